@@ -1,50 +1,663 @@
-use crate::ring::{Trigger, TriggerConsumer};
+use crate::capture::CaptureBuffer;
+use crate::config::{ExtraOutputChannels, LatencySettings, Limiter, OutputMode, ReverbConfig};
+use crate::cue::ErrorCue;
+use crate::filter::FilterState;
+use crate::latency::LatencyStats;
+use crate::metronome::Metronome;
+use crate::record::RecordProducer;
+use crate::reverb::Reverb;
+use crate::ring::{Trigger, TriggerConsumer, TriggerKind, TriggerSource};
 use crate::samples::{SampleBank, SampleData};
-use anyhow::{Context, Result};
+use crate::stats::EngineStats;
+use anyhow::{Context, Result, bail};
 use arc_swap::ArcSwap;
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use cpal::{BufferSize, StreamConfig};
-use std::sync::Arc;
-
-/// The output sample rate in Hz.
-const OUTPUT_SAMPLE_RATE: u32 = 48_000;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 /// The number of output channels (stereo).
 const OUTPUT_CHANNELS: u16 = 2;
 
-/// Minimum buffer size in frames. The device-reported minimum can be as low
-/// as 1, which causes catastrophic CPU overhead (48k callbacks/sec). 64 frames
-/// at 48kHz ≈ 1.3ms — well within the latency budget and realistic for ALSA.
-const MIN_BUFFER_FRAMES: u32 = 64;
+/// Frames pre-allocated for the reverb send buffer. Larger than any buffer
+/// size we request; a device that delivers more triggers a one-off resize.
+const MAX_CALLBACK_FRAMES: usize = 8192;
+
+/// Length of the fade applied when a looping voice is stopped (50ms at 48kHz).
+const LOOP_FADE_FRAMES: usize = 2400;
+
+/// Length of the release ramp when a voice is choked (2ms at 48kHz), short
+/// enough to sound like a cut but long enough to avoid a click.
+const CHOKE_FADE_FRAMES: usize = 96;
+
+/// Length of the release ramp when a voice is stolen (2.5ms at 48kHz).
+const STEAL_FADE_FRAMES: usize = 120;
+
+/// Voice slots beyond `max_voices` for stolen voices to fade out in. When
+/// more voices are fading than this, the oldest are cut without a fade.
+const STEAL_FADE_VOICES: usize = 4;
+
+/// Length of the release ramp when a gated key is let go (10ms at 48kHz).
+const GATE_RELEASE_FRAMES: usize = 480;
+
+/// Level above which the soft limiter starts compressing. Samples below it
+/// pass through unchanged.
+const SOFT_LIMIT_KNEE: f32 = 0.5;
+
+/// First delay between attempts to reopen a failed audio device. Doubles
+/// after each failed attempt, up to `RECONNECT_MAX_DELAY`.
+const RECONNECT_INITIAL_DELAY: Duration = Duration::from_millis(500);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(8);
+
+/// Master volume shared between the audio callback and runtime controls.
+/// Stored as `f32` bits so it can change without locking.
+#[derive(Debug)]
+pub struct MasterVolume(AtomicU32);
+
+impl MasterVolume {
+    pub fn new(volume: f32) -> Self {
+        Self(AtomicU32::new(volume.clamp(0.0, 1.0).to_bits()))
+    }
+
+    /// Current volume. Real-time safe.
+    pub fn get(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    /// Set the volume, clamped to 0.0..=1.0. The audio callback glides to
+    /// it over the configured volume smoothing time.
+    pub fn set(&self, volume: f32) {
+        self.0
+            .store(volume.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Per-sample mute and solo toggles shared between the mute and solo keys
+/// and the audio callback. They are a view over the samples rather than
+/// part of a bank, so they carry over when the kit changes. Sized once for
+/// the bound samples.
+#[derive(Debug)]
+pub struct SampleToggles {
+    muted: Box<[AtomicBool]>,
+    soloed: Box<[AtomicBool]>,
+
+    /// How many samples are soloed.
+    solo_count: AtomicUsize,
+}
+
+impl SampleToggles {
+    /// Toggles for `sample_count` samples, none muted or soloed.
+    pub fn new(sample_count: usize) -> Self {
+        Self {
+            muted: (0..sample_count).map(|_| AtomicBool::new(false)).collect(),
+            soloed: (0..sample_count).map(|_| AtomicBool::new(false)).collect(),
+            solo_count: AtomicUsize::new(0),
+        }
+    }
+
+    /// Flip whether `sample_id` is muted. Returns whether it now is, or
+    /// None if the sample has no toggle.
+    pub fn toggle_mute(&self, sample_id: usize) -> Option<bool> {
+        let muted = self.muted.get(sample_id)?;
+        Some(!muted.fetch_xor(true, Ordering::Relaxed))
+    }
+
+    /// Flip whether `sample_id` is soloed. Returns whether it now is, or
+    /// None if the sample has no toggle.
+    pub fn toggle_solo(&self, sample_id: usize) -> Option<bool> {
+        let soloed = self.soloed.get(sample_id)?;
+        let now = !soloed.fetch_xor(true, Ordering::Relaxed);
+        if now {
+            self.solo_count.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.solo_count.fetch_sub(1, Ordering::Relaxed);
+        }
+        Some(now)
+    }
+
+    /// Unmute and unsolo every sample.
+    pub fn clear(&self) {
+        for toggle in self.muted.iter().chain(self.soloed.iter()) {
+            toggle.store(false, Ordering::Relaxed);
+        }
+        self.solo_count.store(0, Ordering::Relaxed);
+    }
+
+    /// Whether voices of `sample_id` are heard: it is not muted and, while
+    /// any sample is soloed, it is one of them. Voices that are not bank
+    /// samples, like the error cue, are always heard. Real-time safe.
+    fn is_audible(&self, sample_id: u16) -> bool {
+        let index = sample_id as usize;
+        let Some(muted) = self.muted.get(index) else {
+            return true;
+        };
+        !muted.load(Ordering::Relaxed)
+            && (self.solo_count.load(Ordering::Relaxed) == 0
+                || self.soloed[index].load(Ordering::Relaxed))
+    }
+}
+
+/// One-pole smoothing of the master volume, advanced once per callback.
+/// Within a buffer the gain ramps linearly between the smoothed values at
+/// its start and end, so a volume change never steps and never clicks.
+#[derive(Debug)]
+struct VolumeSmoother {
+    current: f32,
+
+    /// Time constant in frames; 0.0 follows changes within one buffer.
+    time_constant_frames: f32,
+}
+
+impl VolumeSmoother {
+    fn new(volume: f32, smoothing: Duration, sample_rate: u32) -> Self {
+        Self {
+            current: volume,
+            time_constant_frames: (smoothing.as_secs_f64() * sample_rate as f64) as f32,
+        }
+    }
+
+    /// Move toward `target` by one buffer of `frames` frames and return the
+    /// gains at the buffer's start and end.
+    fn advance(&mut self, target: f32, frames: usize) -> (f32, f32) {
+        let start = self.current;
+        let coefficient = if self.time_constant_frames > 0.0 {
+            1.0 - (-(frames as f32) / self.time_constant_frames).exp()
+        } else {
+            1.0
+        };
+        self.current += (target - self.current) * coefficient;
+        // Settle exactly once inaudibly close, so a steady volume costs
+        // nothing and the ramp ends.
+        if (target - self.current).abs() < 1e-5 {
+            self.current = target;
+        }
+        (start, self.current)
+    }
+}
+
+/// Collapses one-shot triggers for the same sample that arrive from
+/// different sources within a short window into the first of them, e.g.
+/// a controller that sends both a key press and a MIDI note per pad.
+#[derive(Debug)]
+struct TriggerDedup {
+    window: Duration,
+
+    /// Source and time of the last one-shot kept for each sample, sized
+    /// for the bank's samples and grown by bank swaps that add some.
+    last_kept: Vec<Option<(TriggerSource, Instant)>>,
+}
+
+impl TriggerDedup {
+    fn new(window: Duration, sample_count: usize) -> Self {
+        Self {
+            window,
+            last_kept: vec![None; sample_count],
+        }
+    }
+
+    /// Whether `trigger` repeats a one-shot just kept from another source.
+    /// Repeats from the same source, like fast rolls, are never dropped.
+    fn is_duplicate(&mut self, trigger: &Trigger) -> bool {
+        if trigger.kind != TriggerKind::OneShot {
+            return false;
+        }
+        let Some(last) = self.last_kept.get_mut(trigger.sample_id as usize) else {
+            return false;
+        };
+        if let Some((source, at)) = *last
+            && source != trigger.source
+            && trigger.enqueued_at.saturating_duration_since(at) <= self.window
+        {
+            return true;
+        }
+        *last = Some((trigger.source, trigger.enqueued_at));
+        false
+    }
+}
+
+/// Highest absolute output sample seen before limiting, shared between the
+/// audio callback and the reporting thread. Stored as `f32` bits: for
+/// non-negative floats, the bit patterns order like the values, so an
+/// atomic integer max tracks the float max.
+#[derive(Debug)]
+pub struct PeakMeter(AtomicU32);
+
+impl PeakMeter {
+    pub fn new() -> Self {
+        Self(AtomicU32::new(0.0f32.to_bits()))
+    }
+
+    /// Raise the peak to `peak` if it is higher. Real-time safe.
+    fn record(&self, peak: f32) {
+        self.0.fetch_max(peak.abs().to_bits(), Ordering::Relaxed);
+    }
+
+    /// The peak since the last call, resetting it for the next window.
+    pub fn take(&self) -> f32 {
+        f32::from_bits(self.0.swap(0.0f32.to_bits(), Ordering::Relaxed))
+    }
+}
 
 /// A single active voice (playing sample instance).
 #[derive(Debug)]
 struct Voice {
     /// Index into the samples array.
-    sample_id: u8,
+    sample_id: u16,
 
     /// Current playback position in frames.
     position: usize,
 
-    /// Combined gain (per-sample gain * master volume * velocity).
+    /// Fraction of a frame between `position` and the next frame (0.0 to
+    /// 1.0), for playback rates other than 1.0.
+    fraction: f32,
+
+    /// Source frames advanced per output frame.
+    rate: f32,
+
+    /// Combined gain (per-sample gain * velocity, floored at the per-sample
+    /// minimum gain, * loudness gain). The master volume is applied to the
+    /// mix, not here.
     gain: f32,
 
+    /// Reverb bus send level (0.0 when the reverb is disabled).
+    reverb_send: f32,
+
+    /// Left and right channel gains from the sample's pan position.
+    pan_gains: [f32; 2],
+
+    /// Protected voices are only stolen when every voice is protected.
+    protected: bool,
+
+    /// Mixed into the bypass buffer, added to the output after the master
+    /// limiter, instead of the main mix.
+    bypass_master_fx: bool,
+
+    /// Whether the voice is within its sample's reserved voices. Refreshed
+    /// just before stealing.
+    reserved: bool,
+
+    /// Stolen voices fade out over `STEAL_FADE_FRAMES` in the spare slots
+    /// and no longer count towards `max_voices`.
+    stolen: bool,
+
+    /// Looping voices wrap from `loop_end` back to `loop_start` until
+    /// stopped.
+    looping: bool,
+
+    /// First frame of the looped part.
+    loop_start: usize,
+
+    /// Frame a looping voice wraps back at (exclusive). Always within the
+    /// sample and after `loop_start`.
+    loop_end: usize,
+
+    /// Frames left in the fade-out once the voice has been stopped.
+    fade_remaining: Option<usize>,
+
+    /// Total length of the current fade-out, in frames.
+    fade_frames: usize,
+
+    /// Frames of the attack ramp still to play, from silence up to `gain`.
+    attack_remaining: usize,
+
+    /// Total length of the attack ramp, in frames (0 = none).
+    attack_frames: usize,
+
+    /// Choke group of the sample (0 = none).
+    choke_group: u8,
+
+    /// The sample's filter and its state, if it has one.
+    filter: Option<FilterState>,
+
     /// Snapshot of the sample data for this voice.
     /// Holds an Arc to the SampleBank that was active when this voice started,
     /// so the sample data stays alive even if the bank is swapped mid-playback.
     sample_data: Arc<SampleData>,
 }
 
+impl Voice {
+    /// Start fading the voice out over `frames`, unless it is already
+    /// fading out at least as quickly.
+    fn fade_out(&mut self, frames: usize) {
+        if self
+            .fade_remaining
+            .is_none_or(|remaining| remaining > frames)
+        {
+            self.fade_remaining = Some(frames);
+            self.fade_frames = frames;
+        }
+    }
+}
+
+/// Xorshift random number generator for the audio thread. Lock-free and
+/// allocation-free; plenty for musical variation, not for anything else.
+#[derive(Debug)]
+struct Rng(u32);
+
+impl Rng {
+    /// Seed from the clock, so each run varies.
+    fn from_time() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.subsec_nanos());
+        // Xorshift gets stuck at zero.
+        Self(nanos | 1)
+    }
+
+    fn next_u32(&mut self) -> u32 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.0 = x;
+        x
+    }
+
+    /// A value in `from..=to`.
+    fn range_inclusive(&mut self, from: usize, to: usize) -> usize {
+        from + self.next_u32() as usize % (to - from + 1)
+    }
+}
+
 /// Configuration for the audio engine.
 pub struct AudioEngineConfig {
     /// Shared sample bank that can be swapped at runtime.
     pub sample_bank: Arc<ArcSwap<SampleBank>>,
 
+    /// Name (or part of it) of the output device, or None for the default.
+    pub device_name: Option<String>,
+
+    /// Output sample rate in Hz. Loaded samples are at this rate.
+    pub sample_rate: u32,
+
     /// Maximum number of simultaneous voices.
     pub max_voices: usize,
 
-    /// Master volume (0.0 to 1.0).
-    pub master_volume: f32,
+    /// Master volume (0.0 to 1.0), adjustable at runtime.
+    pub master_volume: Arc<MasterVolume>,
+
+    /// Time constant for gliding to a new master volume.
+    pub volume_smoothing: Duration,
+
+    /// Shared reverb bus settings, if enabled.
+    pub reverb: Option<ReverbConfig>,
+
+    /// Trigger latency accumulator, if statistics are enabled.
+    pub latency_stats: Option<Arc<LatencyStats>>,
+
+    /// Audible error cue, if enabled.
+    pub error_cue: Option<Arc<ErrorCue>>,
+
+    /// Most triggers turned into voices per callback, if capped.
+    pub max_triggers_per_callback: Option<usize>,
+
+    /// Window within which one-shots of a sample from different sources
+    /// collapse into one, if enabled.
+    pub trigger_dedup: Option<Duration>,
+
+    /// How long voices of a swapped-out bank take to fade, if they do.
+    pub bank_crossfade: Option<Duration>,
+
+    /// Per-sample mute and solo state, changed by the mute and solo keys.
+    pub sample_toggles: Arc<SampleToggles>,
+
+    /// Limiter applied to the final mix.
+    pub limiter: Limiter,
+
+    /// Routing for output channels beyond the front stereo pair.
+    pub extra_output_channels: ExtraOutputChannels,
+
+    /// Whether the mix is stereo or summed to mono.
+    pub output_mode: OutputMode,
+
+    /// Buffer size to request and warm-up silence at stream start.
+    pub latency: LatencySettings,
+
+    /// Recording ring the final mix is copied into, if recording.
+    pub recorder: Option<RecordProducer>,
+
+    /// Rolling buffer the final mix is copied into, if capture is enabled.
+    pub capture: Option<Arc<CaptureBuffer>>,
+
+    /// Click track mixed into the output, if configured.
+    pub metronome: Option<Metronome>,
+
+    /// Meter for the output peak before limiting, if clip reporting is on.
+    pub peak_meter: Option<Arc<PeakMeter>>,
+
+    /// Voice steal, callback timing and voice count metrics, if `--stats`
+    /// or `--tui` is on.
+    pub engine_stats: Option<Arc<EngineStats>>,
+}
+
+/// State owned by the audio callback. Built once before the stream starts
+/// and moved into the callback closure, so mixing never allocates.
+struct Mixer {
+    /// Number of interleaved channels in the output buffer.
+    output_channels: usize,
+
+    /// Shared sample bank that can be swapped at runtime.
+    sample_bank: Arc<ArcSwap<SampleBank>>,
+
+    /// Master volume (0.0 to 1.0), adjustable at runtime.
+    master_volume: Arc<MasterVolume>,
+
+    /// Master gain applied to the mix, smoothed toward `master_volume`.
+    master_gain: VolumeSmoother,
+
+    /// Maximum number of simultaneous voices.
+    max_voices: usize,
+
+    /// Currently playing voices.
+    voices: Vec<Voice>,
+
+    /// Reused buffer for triggers drained from the ring each callback.
+    trigger_buf: Vec<Trigger>,
+
+    /// Shared reverb bus. Allocated up front so a kit can bring its own
+    /// reverb without allocating on the audio thread; only run while
+    /// `active_reverb` is set.
+    reverb: Reverb,
+
+    /// Reverb from the config's `[reverb]`, for kits without their own.
+    default_reverb: Option<ReverbConfig>,
+
+    /// Reverb settings `reverb` currently runs with, or None if it is off.
+    active_reverb: Option<ReverbConfig>,
+
+    /// Mono reverb input accumulated per frame while mixing voices.
+    reverb_buf: Vec<f32>,
+
+    /// Interleaved output of voices that bypass the master limiter, added
+    /// to the mix after it.
+    bypass_buf: Vec<f32>,
+
+    /// Trigger latency accumulator, if statistics are enabled.
+    latency_stats: Option<Arc<LatencyStats>>,
+
+    /// Audible error cue, if enabled.
+    error_cue: Option<Arc<ErrorCue>>,
+
+    /// Most triggers drained per callback, if capped. The rest stay queued
+    /// in the ring, in order, for later callbacks.
+    max_triggers_per_callback: Option<usize>,
+
+    /// Per-sample voice counts used while marking reserved voices, sized
+    /// for the bank's samples and grown by bank swaps that add some.
+    voice_counts: Vec<u16>,
+
+    /// Duplicate one-shots from different sources, if collapsed.
+    trigger_dedup: Option<TriggerDedup>,
+
+    /// Frames over which voices of a swapped-out bank fade, if they do.
+    bank_crossfade_frames: Option<usize>,
+
+    /// Per-sample mute and solo state; silenced voices play on unheard.
+    sample_toggles: Arc<SampleToggles>,
+
+    /// Limiter applied to the final mix.
+    limiter: Limiter,
+
+    /// Routing for output channels beyond the front stereo pair.
+    extra_output_channels: ExtraOutputChannels,
+
+    /// Whether the mix is stereo or summed to mono.
+    output_mode: OutputMode,
+
+    /// Frames of warm-up silence still to play before triggers are mixed.
+    warmup_frames: usize,
+
+    /// Recording ring the final mix is copied into, if recording.
+    recorder: Option<RecordProducer>,
+
+    /// Rolling buffer the final mix is copied into, if capture is enabled.
+    capture: Option<Arc<CaptureBuffer>>,
+
+    /// Click track mixed into the output, if configured.
+    metronome: Option<Metronome>,
+
+    /// Meter for the output peak before limiting, if clip reporting is on.
+    peak_meter: Option<Arc<PeakMeter>>,
+
+    /// Voice steal, callback timing and voice count metrics, if `--stats`
+    /// or `--tui` is on.
+    engine_stats: Option<Arc<EngineStats>>,
+
+    /// Randomness for per-trigger variation, such as start regions.
+    rng: Rng,
+}
+
+impl Mixer {
+    fn new(config: AudioEngineConfig, output_channels: usize) -> Self {
+        let bank = config.sample_bank.load();
+        let active_reverb = bank.reverb.or(config.reverb);
+        let reverb = Reverb::new(&active_reverb.unwrap_or_default(), config.sample_rate);
+
+        let sample_count = bank.samples.len();
+        drop(bank);
+        let voice_counts = vec![0; sample_count];
+        let trigger_dedup = config
+            .trigger_dedup
+            .map(|window| TriggerDedup::new(window, sample_count));
+
+        Self {
+            output_channels,
+            sample_bank: config.sample_bank,
+            master_gain: VolumeSmoother::new(
+                config.master_volume.get(),
+                config.volume_smoothing,
+                config.sample_rate,
+            ),
+            master_volume: config.master_volume,
+            max_voices: config.max_voices,
+            voices: Vec::with_capacity(config.max_voices + STEAL_FADE_VOICES),
+            trigger_buf: Vec::with_capacity(128),
+            reverb,
+            default_reverb: config.reverb,
+            active_reverb,
+            reverb_buf: vec![0.0; MAX_CALLBACK_FRAMES],
+            bypass_buf: vec![0.0; MAX_CALLBACK_FRAMES * output_channels],
+            latency_stats: config.latency_stats,
+            error_cue: config.error_cue,
+            max_triggers_per_callback: config.max_triggers_per_callback,
+            voice_counts,
+            trigger_dedup,
+            bank_crossfade_frames: config
+                .bank_crossfade
+                .map(|fade| (fade.as_secs_f64() * config.sample_rate as f64) as usize),
+            sample_toggles: config.sample_toggles,
+            limiter: config.limiter,
+            extra_output_channels: config.extra_output_channels,
+            output_mode: config.output_mode,
+            warmup_frames: (config.latency.warmup.as_secs_f64() * config.sample_rate as f64)
+                as usize,
+            recorder: config.recorder,
+            capture: config.capture,
+            metronome: config.metronome,
+            peak_meter: config.peak_meter,
+            engine_stats: config.engine_stats,
+            rng: Rng::from_time(),
+        }
+    }
+}
+
+/// Mixer state and trigger consumer, owned by the running stream's
+/// callback or parked while no stream exists.
+type EngineState = (Mixer, TriggerConsumer);
+
+/// Engine state moved into a stream callback. When the stream is dropped,
+/// the state is parked again so a rebuilt stream can pick up the same
+/// voices, trigger ring and settings.
+struct StreamState {
+    state: Option<EngineState>,
+    parked: Arc<Mutex<Option<EngineState>>>,
+}
+
+impl Drop for StreamState {
+    fn drop(&mut self) {
+        if let Ok(mut parked) = self.parked.lock() {
+            *parked = self.state.take();
+        }
+    }
+}
+
+/// Retry schedule for reopening a failed audio device: the first attempt
+/// is immediate, then the delay doubles after each failure up to a cap.
+#[derive(Debug)]
+struct ReconnectBackoff {
+    /// Delay applied after the next failed attempt.
+    delay: Duration,
+
+    /// Earliest time of the next attempt.
+    next_attempt: Instant,
+}
+
+impl ReconnectBackoff {
+    fn new(now: Instant) -> Self {
+        Self {
+            delay: RECONNECT_INITIAL_DELAY,
+            next_attempt: now,
+        }
+    }
+
+    fn should_attempt(&self, now: Instant) -> bool {
+        now >= self.next_attempt
+    }
+
+    /// Schedule the next attempt after a failed one and return its delay.
+    fn attempt_failed(&mut self, now: Instant) -> Duration {
+        let delay = self.delay;
+        self.next_attempt = now + delay;
+        self.delay = (delay * 2).min(RECONNECT_MAX_DELAY);
+        delay
+    }
+}
+
+/// A running audio output stream that can be rebuilt when its device fails.
+///
+/// Playback stops when this is dropped.
+pub struct AudioEngine {
+    stream: Option<cpal::Stream>,
+
+    /// Engine state while no stream owns it.
+    parked: Arc<Mutex<Option<EngineState>>>,
+
+    /// Set by the stream's error callback when the device is gone or the
+    /// stream must be rebuilt.
+    failed: Arc<AtomicBool>,
+
+    /// Retry schedule while reconnecting, None while the stream is healthy.
+    reconnect: Option<ReconnectBackoff>,
+
+    /// Name (or part of it) of the output device, or None for the default.
+    device_name: Option<String>,
+
+    /// Output sample rate requested from the device.
+    sample_rate: u32,
+
+    /// Buffer size requested from the device.
+    latency: LatencySettings,
 }
 
 /// Start the audio output stream and return a handle to it.
@@ -55,72 +668,209 @@ pub struct AudioEngineConfig {
 /// The `sample_bank` is read atomically each time a trigger is received,
 /// allowing runtime sample swapping without locks in the audio callback.
 ///
-/// Returns the cpal Stream handle. The stream plays until the handle is dropped.
+/// Returns an engine handle. The stream plays until the handle is dropped.
 pub fn start_audio_stream(
     config: AudioEngineConfig,
-    mut consumer: TriggerConsumer,
-) -> Result<cpal::Stream> {
+    consumer: TriggerConsumer,
+) -> Result<AudioEngine> {
+    // Pre-allocate voices, the trigger drain buffer and effect state outside
+    // the callback. The mixer is moved into the closure and reused every
+    // callback — no allocations. The channel count is set once the device
+    // config is known.
+    let sample_rate = config.sample_rate;
+    let latency = config.latency;
+    let device_name = config.device_name.clone();
+    let mixer = Mixer::new(config, OUTPUT_CHANNELS as usize);
+
+    let mut engine = AudioEngine {
+        stream: None,
+        parked: Arc::new(Mutex::new(Some((mixer, consumer)))),
+        failed: Arc::new(AtomicBool::new(false)),
+        reconnect: None,
+        device_name,
+        sample_rate,
+        latency,
+    };
+    engine.open_stream()?;
+    Ok(engine)
+}
+
+impl AudioEngine {
+    /// Pick the configured (or default) output device and start a stream on
+    /// it, moving the parked engine state into the callback.
+    fn open_stream(&mut self) -> Result<()> {
+        let device = select_output_device(self.device_name.as_deref())?;
+        log::info!("Using audio output device: {}", output_device_name(&device));
+
+        // Find the best output config: our sample rate, stereo, and the
+        // buffer size the latency settings ask for.
+        let stream_config = find_best_config(&device, self.sample_rate, &self.latency)?;
+
+        log::info!(
+            "Audio stream config: {}Hz, {} channels, buffer: {:?}",
+            stream_config.sample_rate,
+            stream_config.channels,
+            stream_config.buffer_size,
+        );
+
+        let mut state = self
+            .parked
+            .lock()
+            .ok()
+            .and_then(|mut parked| parked.take())
+            .context("Audio engine state is unavailable")?;
+        state.0.output_channels = stream_config.channels as usize;
+
+        // If building fails, cpal drops the callback, which parks the state
+        // again for the next attempt.
+        let mut stream_state = StreamState {
+            state: Some(state),
+            parked: Arc::clone(&self.parked),
+        };
+        let failed = Arc::clone(&self.failed);
+        let stream = device
+            .build_output_stream(
+                &stream_config,
+                move |data: &mut [f32], _info: &cpal::OutputCallbackInfo| {
+                    if let Some((mixer, consumer)) = &mut stream_state.state {
+                        audio_callback(data, consumer, mixer);
+                    }
+                },
+                move |err| {
+                    log::error!("Audio stream error: {}", err);
+                    if matches!(
+                        err,
+                        cpal::StreamError::DeviceNotAvailable
+                            | cpal::StreamError::StreamInvalidated
+                    ) {
+                        failed.store(true, Ordering::Relaxed);
+                    }
+                },
+                None, // No timeout
+            )
+            .context("Failed to build audio output stream")?;
+
+        stream.play().context("Failed to start audio playback")?;
+        log::info!("Audio stream started");
+
+        self.stream = Some(stream);
+        Ok(())
+    }
+
+    /// Rebuild the stream if its device failed, retrying with backoff until
+    /// a device is available again. Call periodically from the thread that
+    /// started the engine.
+    pub fn supervise(&mut self, now: Instant) {
+        if self.failed.swap(false, Ordering::Relaxed) && self.reconnect.is_none() {
+            log::warn!("Audio device lost, reconnecting...");
+            // Dropping the stream parks the engine state for the next one.
+            self.stream = None;
+            self.reconnect = Some(ReconnectBackoff::new(now));
+        }
+
+        let Some(mut backoff) = self.reconnect.take() else {
+            return;
+        };
+        if !backoff.should_attempt(now) {
+            self.reconnect = Some(backoff);
+            return;
+        }
+
+        match self.open_stream() {
+            Ok(()) => log::info!("Audio device reconnected"),
+            Err(e) => {
+                let delay = backoff.attempt_failed(now);
+                log::warn!(
+                    "Audio reconnect failed: {:#}. Retrying in {:.1}s",
+                    e,
+                    delay.as_secs_f64()
+                );
+                self.reconnect = Some(backoff);
+            }
+        }
+    }
+}
+
+/// The output device whose name contains `name`, ignoring case, or the
+/// default output device if `name` is None. Fails listing the available
+/// devices if none matches.
+fn select_output_device(name: Option<&str>) -> Result<cpal::Device> {
     let host = cpal::default_host();
-    let device = host
-        .default_output_device()
-        .context("No audio output device found")?;
+    let Some(name) = name else {
+        return host
+            .default_output_device()
+            .context("No audio output device found");
+    };
+
+    let devices: Vec<cpal::Device> = host
+        .output_devices()
+        .context("Failed to list audio output devices")?
+        .collect();
+    let names: Vec<String> = devices.iter().map(output_device_name).collect();
+    match matching_device(&names, name) {
+        Some(index) => Ok(devices.into_iter().nth(index).expect("index from names")),
+        None if names.is_empty() => bail!("No audio output device matching '{}': none found", name),
+        None => bail!(
+            "No audio output device matching '{}'. Available: {}",
+            name,
+            names.join(", ")
+        ),
+    }
+}
+
+/// Index of the first of `names` that contains `wanted`, ignoring case.
+fn matching_device(names: &[String], wanted: &str) -> Option<usize> {
+    let wanted = wanted.to_lowercase();
+    names
+        .iter()
+        .position(|name| name.to_lowercase().contains(&wanted))
+}
 
-    let device_name = device
+/// Display name of an output device.
+fn output_device_name(device: &cpal::Device) -> String {
+    device
         .description()
         .map(|d| d.name().to_string())
-        .unwrap_or_else(|_| "unknown".to_string());
-    log::info!("Using audio output device: {}", device_name);
-
-    // Find the best output config: 48kHz, stereo, smallest buffer.
-    let stream_config = find_best_config(&device)?;
-
-    log::info!(
-        "Audio stream config: {}Hz, {} channels, buffer: {:?}",
-        stream_config.sample_rate,
-        stream_config.channels,
-        stream_config.buffer_size,
-    );
-
-    let sample_bank = config.sample_bank;
-    let max_voices = config.max_voices;
-    let master_volume = config.master_volume;
-    let output_channels = stream_config.channels as usize;
-
-    // Pre-allocate voice array and trigger drain buffer outside the callback.
-    // These are moved into the closure and reused every callback — no allocations.
-    let mut voices: Vec<Voice> = Vec::with_capacity(max_voices);
-    let mut trigger_buf: Vec<Trigger> = Vec::with_capacity(128);
-
-    let stream = device
-        .build_output_stream(
-            &stream_config,
-            move |data: &mut [f32], _info: &cpal::OutputCallbackInfo| {
-                audio_callback(
-                    data,
-                    output_channels,
-                    &mut consumer,
-                    &mut trigger_buf,
-                    &mut voices,
-                    &sample_bank,
-                    master_volume,
-                    max_voices,
-                );
-            },
-            move |err| {
-                log::error!("Audio stream error: {}", err);
-            },
-            None, // No timeout
-        )
-        .context("Failed to build audio output stream")?;
-
-    stream.play().context("Failed to start audio playback")?;
-    log::info!("Audio stream started");
+        .unwrap_or_else(|_| "unknown".to_string())
+}
 
-    Ok(stream)
+/// List the audio output devices, marking the default one.
+///
+/// Prints to stdout for the `--list-audio-devices` CLI flag.
+pub fn list_output_devices() -> Result<()> {
+    let host = cpal::default_host();
+    let default_name = host.default_output_device().map(|d| output_device_name(&d));
+    let devices = host
+        .output_devices()
+        .context("Failed to list audio output devices")?;
+
+    println!("Available audio output devices:");
+    let mut found = false;
+    for device in devices {
+        found = true;
+        let name = output_device_name(&device);
+        let marker = if default_name.as_ref() == Some(&name) {
+            " (default)"
+        } else {
+            ""
+        };
+        println!("  {}{}", name, marker);
+    }
+    if !found {
+        println!("  (none found)");
+    }
+    println!();
+    println!("Set audio_device to any part of a name to play through that device.");
+    Ok(())
 }
 
-/// Find the best output config targeting 48kHz stereo with the smallest buffer.
-fn find_best_config(device: &cpal::Device) -> Result<StreamConfig> {
+/// Find the best output config targeting `sample_rate` stereo with the
+/// buffer size from `latency`.
+fn find_best_config(
+    device: &cpal::Device,
+    sample_rate: u32,
+    latency: &LatencySettings,
+) -> Result<StreamConfig> {
     let supported = device
         .supported_output_configs()
         .context("Failed to query supported output configs")?;
@@ -130,9 +880,7 @@ fn find_best_config(device: &cpal::Device) -> Result<StreamConfig> {
 
     for config in supported {
         // Must support our sample rate.
-        if config.min_sample_rate() > OUTPUT_SAMPLE_RATE
-            || config.max_sample_rate() < OUTPUT_SAMPLE_RATE
-        {
+        if config.min_sample_rate() > sample_rate || config.max_sample_rate() < sample_rate {
             continue;
         }
 
@@ -154,24 +902,28 @@ fn find_best_config(device: &cpal::Device) -> Result<StreamConfig> {
         }
     }
 
-    let supported_config = best.context(
-        "No supported audio output config found for 48kHz f32. \
-         Check that your audio device supports 48kHz output.",
-    )?;
+    let supported_config = best.with_context(|| {
+        format!(
+            "No supported audio output config found for {}Hz f32. \
+             Check that your audio device supports {}Hz output.",
+            sample_rate, sample_rate,
+        )
+    })?;
 
-    // Request a small buffer size for low latency, but enforce a sane floor.
-    // Device-reported minimums can be as low as 1 frame, which causes the
-    // callback to fire tens of thousands of times per second — overwhelming
-    // the CPU with scheduling overhead and producing no usable audio.
+    // Request the configured buffer size (or the smallest), but enforce a
+    // sane floor. Device-reported minimums can be as low as 1 frame, which
+    // causes the callback to fire tens of thousands of times per second —
+    // overwhelming the CPU with scheduling overhead and producing no usable
+    // audio.
     let buffer_size = match supported_config.buffer_size() {
         cpal::SupportedBufferSize::Range { min, max } => {
-            let target = (*min).max(MIN_BUFFER_FRAMES).min(*max);
+            let target = buffer_target(*min, *max, latency);
             log::info!(
                 "Audio device buffer range: {}-{} frames, requesting {} frames ({:.1}ms)",
                 min,
                 max,
                 target,
-                target as f64 / OUTPUT_SAMPLE_RATE as f64 * 1000.0,
+                target as f64 / sample_rate as f64 * 1000.0,
             );
             BufferSize::Fixed(target)
         }
@@ -183,13 +935,138 @@ fn find_best_config(device: &cpal::Device) -> Result<StreamConfig> {
 
     let config = StreamConfig {
         channels: supported_config.channels(),
-        sample_rate: OUTPUT_SAMPLE_RATE,
+        sample_rate,
         buffer_size,
     };
 
     Ok(config)
 }
 
+/// Buffer size to request from a device supporting `min..=max` frames: the
+/// configured size, or the device's smallest, raised to the configured
+/// floor and kept within the device's range.
+fn buffer_target(min: u32, max: u32, latency: &LatencySettings) -> u32 {
+    latency
+        .buffer_frames
+        .unwrap_or(min)
+        .max(latency.min_buffer_frames)
+        .max(min)
+        .min(max)
+}
+
+/// Soft-knee limiter: identity up to `SOFT_LIMIT_KNEE`, then a tanh curve
+/// that meets it with matching slope and approaches full scale
+/// asymptotically. Branch-free.
+fn soft_limit(sample: f32) -> f32 {
+    let headroom = 1.0 - SOFT_LIMIT_KNEE;
+    let magnitude = sample.abs();
+    let over = (magnitude - SOFT_LIMIT_KNEE).max(0.0);
+    let limited = magnitude.min(SOFT_LIMIT_KNEE) + headroom * (over / headroom).tanh();
+    limited.copysign(sample)
+}
+
+/// Equal-power left/right gains for a pan position (-1.0 left to 1.0
+/// right). Center is exactly unity on both sides, matching unpanned output.
+fn pan_gains(pan: f32) -> [f32; 2] {
+    let pan = pan.clamp(-1.0, 1.0);
+    [(1.0 - pan).sqrt(), (1.0 + pan).sqrt()]
+}
+
+/// Fade out enough voices to leave room for `starts` new ones within
+/// `max_voices`, oldest unprotected voices first, falling back to the
+/// oldest protected voices only if that isn't enough. Voices within their
+/// sample's reserved count are never stolen. Stolen voices ramp down over
+/// STEAL_FADE_FRAMES instead of stopping dead (which clicks), and are
+/// removed by the mixing loop once the ramp ends.
+fn steal_voices(
+    voices: &mut Vec<Voice>,
+    starts: usize,
+    max_voices: usize,
+    reserved_voices: &[u8],
+    counts: &mut [u16],
+    engine_stats: Option<&EngineStats>,
+) {
+    let playing = voices.iter().filter(|v| !v.stolen).count();
+    let available = max_voices.saturating_sub(playing);
+    if starts <= available || playing == 0 {
+        return;
+    }
+
+    mark_reserved_voices(voices, reserved_voices, counts);
+    let wanted = (starts - available).min(playing);
+    let mut to_steal = wanted;
+    for protected_pass in [false, true] {
+        for voice in voices.iter_mut() {
+            if to_steal > 0
+                && !voice.stolen
+                && !voice.reserved
+                && (protected_pass || !voice.protected)
+            {
+                voice.stolen = true;
+                voice.fade_out(STEAL_FADE_FRAMES);
+                to_steal -= 1;
+            }
+        }
+    }
+    if let Some(stats) = engine_stats {
+        stats.record_steals(wanted - to_steal);
+    }
+
+    // Keep the fading voices within the spare slots, so the new ones never
+    // grow the voice list past its capacity.
+    let fading = voices.iter().filter(|v| v.stolen).count();
+    let mut to_cut = fading.saturating_sub(STEAL_FADE_VOICES);
+    if to_cut > 0 {
+        voices.retain(|voice| {
+            if to_cut > 0 && voice.stolen {
+                to_cut -= 1;
+                false
+            } else {
+                true
+            }
+        });
+    }
+}
+
+/// Flag the newest voices of each sample, up to its reserved voice count,
+/// so stealing leaves them alone. `counts` is scratch space indexed
+/// by sample id.
+fn mark_reserved_voices(voices: &mut [Voice], reserved_voices: &[u8], counts: &mut [u16]) {
+    counts.fill(0);
+    for voice in voices.iter_mut().rev() {
+        if voice.stolen {
+            voice.reserved = false;
+            continue;
+        }
+        let sid = voice.sample_id as usize;
+        let quota = reserved_voices.get(sid).copied().unwrap_or(0) as u16;
+        voice.reserved = match counts.get_mut(sid) {
+            Some(count) if *count < quota => {
+                *count += 1;
+                true
+            }
+            _ => false,
+        };
+    }
+}
+
+/// Switch the reverb bus to the current bank's reverb, or the config's if
+/// the kit has none. A reverb turned back on starts without the tail it
+/// had when it was turned off.
+fn adopt_kit_reverb(mixer: &mut Mixer) {
+    let wanted = mixer.sample_bank.load().reverb.or(mixer.default_reverb);
+    if wanted == mixer.active_reverb {
+        return;
+    }
+    if let Some(config) = &wanted {
+        if mixer.active_reverb.is_none() {
+            mixer.reverb.clear();
+        }
+        mixer.reverb.set_params(config);
+    }
+    mixer.active_reverb = wanted;
+}
+
 /// The core audio callback. Called by cpal on the audio thread.
 ///
 /// This function MUST be real-time safe:
@@ -201,71 +1078,319 @@ fn find_best_config(device: &cpal::Device) -> Result<StreamConfig> {
 /// The `ArcSwap::load` is lock-free — it performs an atomic pointer read
 /// and increments a reference count. This is safe for real-time audio.
 #[inline]
-fn audio_callback(
-    data: &mut [f32],
-    output_channels: usize,
-    consumer: &mut TriggerConsumer,
-    trigger_buf: &mut Vec<Trigger>,
-    voices: &mut Vec<Voice>,
-    sample_bank: &Arc<ArcSwap<SampleBank>>,
-    master_volume: f32,
-    max_voices: usize,
-) {
-    // 1. Drain all pending triggers from the ring buffer.
-    consumer.drain(trigger_buf);
+fn audio_callback(data: &mut [f32], consumer: &mut TriggerConsumer, mixer: &mut Mixer) {
+    // While warming up, play silence and leave triggers queued, so the
+    // device settles before the first hit is mixed.
+    if mixer.warmup_frames > 0 {
+        data.fill(0.0);
+        let frames = data.len() / mixer.output_channels.max(1);
+        mixer.warmup_frames = mixer.warmup_frames.saturating_sub(frames);
+        return;
+    }
 
-    // 2. Spawn new voices for each trigger.
-    if !trigger_buf.is_empty() {
-        // Load the current sample bank once per callback (atomic pointer read).
-        let bank = sample_bank.load();
+    let started = mixer.engine_stats.is_some().then(Instant::now);
+    adopt_kit_reverb(mixer);
+    let output_channels = mixer.output_channels;
+    let max_voices = mixer.max_voices;
+    let trigger_buf = &mut mixer.trigger_buf;
+    let voices = &mut mixer.voices;
+    let error_cue = mixer.error_cue.as_deref();
+    let reverb_enabled = mixer.active_reverb.is_some();
+
+    // Channels voices are mixed into: the front pair, or every channel when
+    // the front pair is mirrored onto the rest.
+    let mixed_channels = match mixer.extra_output_channels {
+        ExtraOutputChannels::Silent => output_channels.min(2),
+        ExtraOutputChannels::MirrorFront => output_channels,
+    };
 
-        // Voice stealing: free up slots for incoming triggers by removing
-        // the oldest voices in a single drain() call. The old code used
-        // `while voices.len() > max { voices.remove(0) }` which was O(k*n)
-        // (each remove shifts all elements) and caused audio callback
-        // overruns under sustained playing.
-        let available = max_voices.saturating_sub(voices.len());
-        if trigger_buf.len() > available && !voices.is_empty() {
-            let to_steal = (trigger_buf.len() - available).min(voices.len());
-            voices.drain(..to_steal);
+    // 1. Drain pending triggers from the ring buffer, up to the cap if set.
+    match mixer.max_triggers_per_callback {
+        Some(max) => consumer.drain_at_most(trigger_buf, max),
+        None => consumer.drain(trigger_buf),
+    }
+
+    if let Some(stats) = &mixer.latency_stats
+        && !trigger_buf.is_empty()
+    {
+        let now = Instant::now();
+        for trigger in trigger_buf.iter() {
+            stats.record(trigger.enqueued_at, now);
         }
+    }
 
-        // Track how many voices we can still add without exceeding max_voices.
-        let mut slots_remaining = max_voices.saturating_sub(voices.len());
+    // A config reload can swap in a bank with more samples than the
+    // per-sample state covers. Growing it allocates, but only on the first
+    // swap after such a reload; kit switches keep the sample count.
+    if trigger_buf.iter().any(|t| t.kind == TriggerKind::BankSwap) {
+        let sample_count = mixer.sample_bank.load().samples.len();
+        if mixer.voice_counts.len() < sample_count {
+            mixer.voice_counts.resize(sample_count, 0);
+        }
+        if let Some(dedup) = &mut mixer.trigger_dedup
+            && dedup.last_kept.len() < sample_count
+        {
+            dedup.last_kept.resize(sample_count, None);
+        }
+    }
 
-        for trigger in trigger_buf.iter() {
-            if slots_remaining == 0 {
-                break;
-            }
+    if let Some(dedup) = &mut mixer.trigger_dedup {
+        trigger_buf.retain(|trigger| !dedup.is_duplicate(trigger));
+    }
 
-            let sid = trigger.sample_id as usize;
-            if sid >= bank.samples.len() {
-                continue; // Invalid sample_id, skip.
+    // 2. A panic silences everything playing, along with the triggers that
+    // arrived before it. The short ramp avoids a click.
+    if let Some(last_panic) = trigger_buf
+        .iter()
+        .rposition(|t| t.kind == TriggerKind::StopAll)
+    {
+        for voice in voices.iter_mut() {
+            voice.fade_out(CHOKE_FADE_FRAMES);
+        }
+        trigger_buf.drain(..=last_panic);
+    }
+
+    // A bank swap fades out the voices already playing, which hold the old
+    // bank's samples, while new triggers play the new bank.
+    if trigger_buf.iter().any(|t| t.kind == TriggerKind::BankSwap) {
+        if let Some(frames) = mixer.bank_crossfade_frames {
+            for voice in voices.iter_mut() {
+                voice.fade_out(frames);
             }
+        }
+        trigger_buf.retain(|t| t.kind != TriggerKind::BankSwap);
+    }
+
+    // 3. Spawn new voices for each trigger.
+    if !trigger_buf.is_empty() {
+        // Load the current sample bank once per callback (atomic pointer read).
+        let bank = mixer.sample_bank.load();
+
+        // Voice stealing: free up slots for the incoming triggers.
+        let starts = trigger_buf
+            .iter()
+            .filter(|t| matches!(t.kind, TriggerKind::OneShot | TriggerKind::StartLoop))
+            .count();
+        steal_voices(
+            voices,
+            starts,
+            max_voices,
+            &bank.settings.reserved_voices,
+            &mut mixer.voice_counts,
+            mixer.engine_stats.as_deref(),
+        );
+
+        // Track how many voices we can still add without exceeding max_voices.
+        let mut slots_remaining =
+            max_voices.saturating_sub(voices.iter().filter(|v| !v.stolen).count());
+
+        // Samples with reserved voices go first, so a flood of other
+        // triggers in the same callback cannot take every free slot.
+        for reserved_pass in [true, false] {
+            for trigger in trigger_buf.iter() {
+                let has_reserve = bank
+                    .settings
+                    .reserved_voices
+                    .get(trigger.sample_id as usize)
+                    .is_some_and(|&n| n > 0);
+                if has_reserve != reserved_pass {
+                    continue;
+                }
+
+                if trigger.kind == TriggerKind::StopLoop {
+                    for voice in voices.iter_mut() {
+                        if voice.looping && voice.sample_id == trigger.sample_id {
+                            voice.fade_out(LOOP_FADE_FRAMES);
+                        }
+                    }
+                    continue;
+                }
+
+                // Releasing moves the voices into their release ramp. A voice
+                // already fading faster, e.g. after a choke, keeps that fade.
+                if trigger.kind == TriggerKind::Release {
+                    for voice in voices.iter_mut() {
+                        if voice.sample_id == trigger.sample_id {
+                            voice.fade_out(GATE_RELEASE_FRAMES);
+                        }
+                    }
+                    continue;
+                }
+
+                if slots_remaining == 0 {
+                    continue;
+                }
+
+                let sid = trigger.sample_id as usize;
+                if sid >= bank.samples.len() || bank.samples[sid].data.is_empty() {
+                    // Invalid sample_id, or a sample missing from this variant
+                    // (a silent placeholder). Nothing to play.
+                    if let Some(cue) = error_cue {
+                        cue.request();
+                    }
+                    continue;
+                }
+
+                // A sample bound to a directory plays the take the input
+                // thread picked.
+                let sample = bank.sample_for_turn(sid, trigger.take as usize);
+
+                let settings = &bank.settings;
+                let per_sample_gain = settings.gains.get(sid).copied().unwrap_or(1.0);
+                let min_gain = settings.min_gains.get(sid).copied().unwrap_or(0.0);
+                let loudness_gain = settings.loudness_gains.get(sid).copied().unwrap_or(1.0);
+                let gain = (per_sample_gain * trigger.velocity).max(min_gain) * loudness_gain;
+                let reverb_send = if reverb_enabled {
+                    settings.reverb_sends.get(sid).copied().unwrap_or(0.0)
+                } else {
+                    0.0
+                };
+
+                // Cut off anything still ringing in the same choke group, e.g.
+                // an open hi-hat when the closed hi-hat is hit.
+                let choke_group = settings.choke_groups.get(sid).copied().unwrap_or(0);
+                if choke_group != 0 {
+                    for voice in voices.iter_mut() {
+                        if voice.choke_group == choke_group {
+                            voice.fade_out(CHOKE_FADE_FRAMES);
+                        }
+                    }
+                }
 
-            let per_sample_gain = bank.sample_gains.get(sid).copied().unwrap_or(1.0);
-            let gain = per_sample_gain * trigger.velocity * master_volume;
+                // Start somewhere in the sample's start region, if it has one.
+                let position = match settings.start_regions.get(sid).copied().flatten() {
+                    Some((from, to)) => {
+                        let last_frame = sample.num_frames().saturating_sub(1);
+                        mixer.rng.range_inclusive(from, to).min(last_frame)
+                    }
+                    None => 0,
+                };
 
-            voices.push(Voice {
-                sample_id: trigger.sample_id,
-                position: 0,
-                gain,
-                // Clone the Arc to the sample data so this voice keeps
-                // a reference even if the bank is swapped while playing.
-                sample_data: Arc::clone(&bank.samples[sid]),
-            });
+                // Loop points are clamped to the sample in this variant, falling
+                // back to the whole sample if they don't fit.
+                let looping = trigger.kind == TriggerKind::StartLoop
+                    || settings.loops.get(sid).copied().unwrap_or(false);
+                let sample_frames = sample.num_frames();
+                let loop_end = settings
+                    .loop_ends
+                    .get(sid)
+                    .copied()
+                    .flatten()
+                    .map_or(sample_frames, |end| end.min(sample_frames));
+                let loop_start = settings.loop_starts.get(sid).copied().unwrap_or(0);
+                let (loop_start, loop_end) = if loop_start < loop_end {
+                    (loop_start, loop_end)
+                } else {
+                    (0, sample_frames)
+                };
 
-            slots_remaining -= 1;
+                // Harder hits get a shorter attack ramp. Quieter bindings
+                // don't: the ramp follows the hit, not the gain.
+                let attack_frames = match settings.vel_attacks.get(sid).copied().flatten() {
+                    Some((soft, hard)) => {
+                        let hit = trigger.hit.clamp(0.0, 1.0);
+                        (soft as f32 + (hard as f32 - soft as f32) * hit).round() as usize
+                    }
+                    None => 0,
+                };
+                let filter = settings.filters.get(sid).copied().flatten();
+
+                voices.push(Voice {
+                    sample_id: trigger.sample_id,
+                    position,
+                    fraction: 0.0,
+                    rate: settings.playback_rates.get(sid).copied().unwrap_or(1.0),
+                    gain,
+                    reverb_send,
+                    pan_gains: pan_gains(settings.pans.get(sid).copied().unwrap_or(0.0)),
+                    protected: settings.protected.get(sid).copied().unwrap_or(false),
+                    bypass_master_fx: settings.bypass_master_fx.get(sid).copied().unwrap_or(false),
+                    reserved: false,
+                    stolen: false,
+                    looping,
+                    loop_start,
+                    loop_end,
+                    fade_remaining: None,
+                    fade_frames: 0,
+                    attack_remaining: attack_frames,
+                    attack_frames,
+                    choke_group,
+                    filter: filter.map(FilterState::new),
+                    // Clone the Arc to the sample data so this voice keeps
+                    // a reference even if the bank is swapped while playing.
+                    sample_data: Arc::clone(sample),
+                });
+
+                slots_remaining -= 1;
+            }
         }
     }
 
-    // 3. Zero the output buffer.
+    // Play the error cue if an error path asked for it since the last callback.
+    if let Some(cue) = error_cue
+        && cue.take()
+    {
+        // The cue takes a slot like any other voice, stealing one if the
+        // triggers above filled them all.
+        steal_voices(
+            voices,
+            1,
+            max_voices,
+            &mixer.sample_bank.load().settings.reserved_voices,
+            &mut mixer.voice_counts,
+            mixer.engine_stats.as_deref(),
+        );
+        voices.push(Voice {
+            sample_id: u16::MAX, // Not a bank sample.
+            position: 0,
+            fraction: 0.0,
+            rate: 1.0,
+            gain: 1.0,
+            reverb_send: 0.0,
+            pan_gains: [1.0, 1.0],
+            protected: false,
+            bypass_master_fx: false,
+            reserved: false,
+            stolen: false,
+            looping: false,
+            loop_start: 0,
+            loop_end: cue.blip.num_frames(),
+            fade_remaining: None,
+            fade_frames: 0,
+            attack_remaining: 0,
+            attack_frames: 0,
+            choke_group: 0,
+            filter: None,
+            sample_data: Arc::clone(&cue.blip),
+        });
+    }
+
+    // 4. Zero the output buffer, the reverb send buffer and, if any voice
+    // bypasses the master limiter, the bypass buffer.
     for sample in data.iter_mut() {
         *sample = 0.0;
     }
 
-    // 4. Mix all active voices into the output buffer.
     let num_frames = data.len() / output_channels;
+    let reverb_buf = &mut mixer.reverb_buf;
+    if reverb_enabled {
+        if reverb_buf.len() < num_frames {
+            reverb_buf.resize(num_frames, 0.0);
+        }
+        reverb_buf[..num_frames].fill(0.0);
+    }
+
+    let bypass_used = voices.iter().any(|voice| voice.bypass_master_fx);
+    let bypass_buf = &mut mixer.bypass_buf;
+    if bypass_used {
+        if bypass_buf.len() < data.len() {
+            bypass_buf.resize(data.len(), 0.0);
+        }
+        bypass_buf[..data.len()].fill(0.0);
+    }
+    let bypass_buf = &mut bypass_buf[..if bypass_used { data.len() } else { 0 }];
+
+    // 5. Mix all active voices into the output buffer.
 
     let mut i = 0;
     while i < voices.len() {
@@ -274,225 +1399,1179 @@ fn audio_callback(
         let sample_channels = sample.channels as usize;
         let sample_frames = sample.num_frames();
 
-        if voice.position >= sample_frames {
-            voices.swap_remove(i);
-            continue;
-        }
-
-        let gain = voice.gain;
-        let frames_to_mix = num_frames.min(sample_frames - voice.position);
+        // Muted voices keep their place, so unmuting picks them up again.
+        let gain = if mixer.sample_toggles.is_audible(voice.sample_id) {
+            voice.gain
+        } else {
+            0.0
+        };
+        let reverb_send = voice.reverb_send;
+        let mut finished = false;
+
+        // Voices bypassing the master limiter mix into their own buffer.
+        let target: &mut [f32] = if voice.bypass_master_fx {
+            &mut *bypass_buf
+        } else {
+            &mut *data
+        };
 
         // Mix sample data into the output buffer.
-        for frame in 0..frames_to_mix {
-            let src_frame = voice.position + frame;
-            let src_offset = src_frame * sample_channels;
+        for frame in 0..num_frames {
+            if voice.looping && voice.position >= voice.loop_end {
+                let loop_len = voice.loop_end - voice.loop_start;
+                voice.position = voice.loop_start + (voice.position - voice.loop_end) % loop_len;
+            } else if voice.position >= sample_frames {
+                finished = true;
+                break;
+            }
 
-            for ch in 0..output_channels {
+            let envelope = match &mut voice.fade_remaining {
+                Some(0) => {
+                    finished = true;
+                    break;
+                }
+                Some(remaining) => {
+                    *remaining -= 1;
+                    *remaining as f32 / voice.fade_frames as f32
+                }
+                None => 1.0,
+            };
+            let envelope = if voice.attack_remaining > 0 {
+                let attack = 1.0 - voice.attack_remaining as f32 / voice.attack_frames as f32;
+                voice.attack_remaining -= 1;
+                envelope * attack
+            } else {
+                envelope
+            };
+
+            // Frame to interpolate towards when between frames: the next
+            // one, wrapping for loops and holding the last frame otherwise.
+            let src_offset = voice.position * sample_channels;
+            let next_offset = if voice.fraction == 0.0 {
+                src_offset
+            } else if voice.looping && voice.position + 1 >= voice.loop_end {
+                voice.loop_start * sample_channels
+            } else if voice.position + 1 < sample_frames {
+                src_offset + sample_channels
+            } else {
+                src_offset
+            };
+            let mut frame_sum = 0.0;
+            let mut filtered = [0.0; 2];
+
+            for ch in 0..mixed_channels {
                 let dst_idx = frame * output_channels + ch;
 
-                // Map output channel to source channel.
+                // Map output channel to its front channel (left or right),
+                // then to source channel.
                 // Mono: duplicate to both channels.
                 // Stereo: direct mapping.
-                let src_ch = if sample_channels == 1 {
-                    0
-                } else {
-                    ch.min(sample_channels - 1)
-                };
+                let front = ch % 2;
+                let src_ch = front.min(sample_channels - 1);
                 let src_idx = src_offset + src_ch;
+                let next_idx = next_offset + src_ch;
+
+                if next_idx < sample.data.len()
+                    && src_idx < sample.data.len()
+                    && dst_idx < target.len()
+                {
+                    let current = sample.data[src_idx];
+                    let mut source = current + (sample.data[next_idx] - current) * voice.fraction;
+                    if let Some(filter) = &mut voice.filter {
+                        // Each front channel is filtered once per frame;
+                        // further output channels reuse its result.
+                        if ch < 2 {
+                            filtered[front] = filter.process(front, source);
+                        }
+                        source = filtered[front];
+                    }
+                    let pan_gain = voice.pan_gains[front];
+                    let value = source * gain * pan_gain * envelope;
+                    target[dst_idx] += value;
+                    frame_sum += value;
+                }
+            }
+
+            if reverb_send > 0.0
+                && let Some(send) = reverb_buf.get_mut(frame)
+            {
+                *send += frame_sum / mixed_channels as f32 * reverb_send;
+            }
 
-                if src_idx < sample.data.len() && dst_idx < data.len() {
-                    data[dst_idx] += sample.data[src_idx] * gain;
+            // At rate 1.0 the fraction stays 0.0 and this is a plain +1.
+            voice.fraction += voice.rate;
+            let whole = voice.fraction.floor();
+            voice.position += whole as usize;
+            voice.fraction -= whole;
+        }
+
+        // Remove voices that reached the end of a one-shot or of a fade.
+        finished |=
+            voice.fade_remaining == Some(0) || (!voice.looping && voice.position >= sample_frames);
+        if finished {
+            voices.swap_remove(i);
+        } else {
+            i += 1;
+        }
+    }
+
+    // 6. Run the reverb bus over the accumulated sends and add its return.
+    // This runs even without new sends so existing tails keep ringing out.
+    if reverb_enabled {
+        for (frame, &input) in reverb_buf[..num_frames].iter().enumerate() {
+            let (wet_l, wet_r) = mixer.reverb.process(input);
+            let base = frame * output_channels;
+            if output_channels == 1 {
+                data[base] += (wet_l + wet_r) * 0.5;
+            } else {
+                for ch in 0..mixed_channels {
+                    data[base + ch] += if ch % 2 == 0 { wet_l } else { wet_r };
                 }
             }
         }
+    }
+
+    // Add the metronome click before the master volume and the limiter, so
+    // it goes through them like the voices.
+    if let Some(metronome) = &mut mixer.metronome {
+        metronome.render(data, output_channels, mixed_channels);
+    }
+
+    // Sum the front pair to mono, in the bypass buffer too, so the mix
+    // reaches the master volume and limiter already collapsed.
+    if mixer.output_mode == OutputMode::Mono && output_channels > 1 {
+        for buffer in [&mut *data, &mut *bypass_buf] {
+            for samples in buffer.chunks_exact_mut(output_channels) {
+                let mono = (samples[0] + samples[1]) * 0.5;
+                for sample in samples.iter_mut().take(mixed_channels) {
+                    *sample = mono;
+                }
+            }
+        }
+    }
+
+    // 7. Apply the master volume, ramping across the buffer toward its
+    // smoothed value so runtime volume changes are click-free.
+    let (from, to) = mixer
+        .master_gain
+        .advance(mixer.master_volume.get(), num_frames);
+    if from != 1.0 || to != 1.0 {
+        let step = (to - from) / num_frames.max(1) as f32;
+        for buffer in [&mut *data, &mut *bypass_buf] {
+            for (frame, samples) in buffer.chunks_exact_mut(output_channels).enumerate() {
+                let gain = from + step * (frame + 1) as f32;
+                for sample in samples {
+                    *sample *= gain;
+                }
+            }
+        }
+    }
+
+    // 8. Limit output to [-1.0, 1.0] to prevent clipping, metering how far
+    // over full scale the mix went first.
+    if let Some(meter) = &mixer.peak_meter {
+        meter.record(data.iter().fold(0.0f32, |peak, s| peak.max(s.abs())));
+    }
+    match mixer.limiter {
+        Limiter::Hard => {
+            for sample in data.iter_mut() {
+                *sample = sample.clamp(-1.0, 1.0);
+            }
+        }
+        Limiter::SoftTanh => {
+            for sample in data.iter_mut() {
+                *sample = soft_limit(*sample);
+            }
+        }
+    }
+
+    // Voices bypassing the limiter join the mix only now.
+    for (sample, &bypassed) in data.iter_mut().zip(bypass_buf.iter()) {
+        *sample += bypassed;
+    }
+
+    // 9. Copy the final mix to the recording and the capture buffer, if
+    // enabled.
+    if let Some(recorder) = &mut mixer.recorder {
+        recorder.push(data, output_channels);
+    }
+    if let Some(capture) = &mixer.capture {
+        capture.push(data, output_channels);
+    }
+
+    if let (Some(stats), Some(started)) = (&mixer.engine_stats, started) {
+        stats.set_active_voices(mixer.voices.len());
+        stats.record_callback(data.len() / output_channels.max(1), started.elapsed());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::LatencyMode;
+    use crate::ring;
+    use crate::samples::SampleSettings;
+
+    /// Create a simple test sample: a mono sine-like ramp.
+    fn make_test_sample(num_frames: usize, channels: u16) -> Arc<SampleData> {
+        let total_samples = num_frames * channels as usize;
+        let mut data = Vec::with_capacity(total_samples);
+        for i in 0..total_samples {
+            data.push((i as f32 / total_samples as f32) * 0.5);
+        }
+        Arc::new(SampleData {
+            data,
+            channels,
+            sample_rate: 48000,
+        })
+    }
+
+    /// Create a SampleBank wrapped in ArcSwap for testing.
+    fn make_test_bank(samples: Vec<Arc<SampleData>>) -> Arc<ArcSwap<SampleBank>> {
+        let gains = vec![1.0f32; samples.len()];
+        Arc::new(ArcSwap::from_pointee(SampleBank {
+            samples,
+            settings: SampleSettings {
+                gains,
+                ..Default::default()
+            },
+            pools: Vec::new(),
+            reverb: None,
+            kit_name: "test".to_string(),
+            variant_name: "v1".to_string(),
+        }))
+    }
+
+    /// Create a stereo mixer with no reverb for testing.
+    fn make_mixer(bank: &Arc<ArcSwap<SampleBank>>, master_volume: f32, max_voices: usize) -> Mixer {
+        Mixer::new(
+            AudioEngineConfig {
+                sample_bank: Arc::clone(bank),
+                device_name: None,
+                sample_rate: 48000,
+                max_voices,
+                master_volume: Arc::new(MasterVolume::new(master_volume)),
+                volume_smoothing: Duration::from_millis(20),
+                reverb: None,
+                latency_stats: None,
+                error_cue: None,
+                max_triggers_per_callback: None,
+                trigger_dedup: None,
+                bank_crossfade: None,
+                sample_toggles: Arc::new(SampleToggles::new(0)),
+                limiter: Limiter::Hard,
+                extra_output_channels: ExtraOutputChannels::Silent,
+                output_mode: OutputMode::Stereo,
+                latency: LatencyMode::Balanced.settings(),
+                recorder: None,
+                capture: None,
+                metronome: None,
+                peak_meter: None,
+                engine_stats: None,
+            },
+            2,
+        )
+    }
+
+    #[test]
+    fn test_audio_callback_silence_when_no_triggers() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (_prod, mut cons) = ring::default_trigger_channel();
+        let bank = make_test_bank(vec![make_test_sample(100, 1)]);
+        let mut mixer = make_mixer(&bank, 1.0, 32);
+        let mut output = vec![0.5f32; 256]; // Pre-fill with non-zero to verify it's zeroed.
+
+        audio_callback(&mut output, &mut cons, &mut mixer);
+
+        // Output should be all zeros (no voices playing).
+        for &s in &output {
+            assert_eq!(s, 0.0);
+        }
+    }
+
+    #[test]
+    fn test_audio_callback_plays_triggered_sample() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let bank = make_test_bank(vec![make_test_sample(100, 1)]);
+        let mut mixer = make_mixer(&bank, 1.0, 32);
+        let mut output = vec![0.0f32; 20]; // 10 frames stereo
+
+        // Send a trigger.
+        prod.send(Trigger::new(0, 1.0));
+
+        audio_callback(&mut output, &mut cons, &mut mixer);
+
+        // Output should have non-zero values (sample was mixed in).
+        let has_nonzero = output.iter().any(|&s| s != 0.0);
+        assert!(has_nonzero, "Expected non-zero output after trigger");
+    }
+
+    #[test]
+    fn test_voice_finishes_and_is_removed() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let bank = make_test_bank(vec![make_test_sample(5, 1)]);
+        let mut mixer = make_mixer(&bank, 1.0, 32);
+
+        prod.send(Trigger::new(0, 1.0));
+
+        // First callback: 10 frames output, but sample is only 5 frames.
+        let mut output = vec![0.0f32; 20]; // 10 stereo frames
+        audio_callback(&mut output, &mut cons, &mut mixer);
+
+        // Voice should be removed after finishing.
+        assert_eq!(
+            mixer.voices.len(),
+            0,
+            "Voice should be removed after sample ends"
+        );
+    }
+
+    #[test]
+    fn test_voice_stealing() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let bank = make_test_bank(vec![make_test_sample(1000, 1)]);
+        let max_voices = 2;
+        let mut mixer = make_mixer(&bank, 1.0, max_voices);
+
+        // Send 4 triggers but max_voices is 2.
+        for _ in 0..4 {
+            prod.send(Trigger::new(0, 1.0));
+        }
+
+        let mut output = vec![0.0f32; 20];
+        audio_callback(&mut output, &mut cons, &mut mixer);
+
+        let playing = mixer.voices.iter().filter(|v| !v.stolen).count();
+        assert!(
+            playing <= max_voices,
+            "Voice count {} exceeds max {}",
+            playing,
+            max_voices
+        );
+        assert!(mixer.voices.len() <= max_voices + STEAL_FADE_VOICES);
+    }
+
+    #[test]
+    fn test_stolen_voice_fades_out_instead_of_cutting() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let constant = Arc::new(SampleData {
+            data: vec![0.5; 4800],
+            channels: 1,
+            sample_rate: 48000,
+        });
+        let silent = Arc::new(SampleData {
+            data: vec![0.0; 4800],
+            channels: 1,
+            sample_rate: 48000,
+        });
+        let bank = make_test_bank(vec![constant, silent]);
+        let mut mixer = make_mixer(&bank, 1.0, 1);
+        let mut output = vec![0.0f32; 20];
+
+        // The only slot holds a steady voice; a silent hit steals it.
+        prod.send(Trigger::new(0, 1.0));
+        audio_callback(&mut output, &mut cons, &mut mixer);
+        let steady = output[0];
+        assert!(steady > 0.0);
+        prod.send(Trigger::new(1, 1.0));
+        let mut output = vec![0.0f32; (STEAL_FADE_FRAMES + 10) * 2];
+        audio_callback(&mut output, &mut cons, &mut mixer);
+
+        // The stolen voice ramps down rather than dropping to silence.
+        let left: Vec<f32> = output.iter().step_by(2).copied().collect();
+        assert!(left[0] > 0.0 && left[0] <= steady);
+        assert!(left[STEAL_FADE_FRAMES / 2] > 0.0);
+        assert!(left[STEAL_FADE_FRAMES / 2] < left[0]);
+        assert!(left.windows(2).all(|w| w[1] <= w[0]));
+        assert!(left[STEAL_FADE_FRAMES..].iter().all(|&s| s == 0.0));
+
+        // Once the ramp ends only the new voice is left.
+        assert_eq!(mixer.voices.len(), 1);
+        assert_eq!(mixer.voices[0].sample_id, 1);
+    }
+
+    #[test]
+    fn test_engine_stats_count_steals_when_oversubscribed() {
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let bank = make_test_bank(vec![make_test_sample(1000, 1)]);
+        let mut mixer = make_mixer(&bank, 1.0, 2);
+        let stats = Arc::new(EngineStats::new(48000));
+        mixer.engine_stats = Some(Arc::clone(&stats));
+
+        // Two voices fit; three more hits steal both of them.
+        let mut output = vec![0.0f32; 20];
+        for _ in 0..2 {
+            prod.send(Trigger::new(0, 1.0));
+        }
+        audio_callback(&mut output, &mut cons, &mut mixer);
+        for _ in 0..3 {
+            prod.send(Trigger::new(0, 1.0));
+        }
+        audio_callback(&mut output, &mut cons, &mut mixer);
+
+        let summary = stats.take_summary();
+        assert_eq!(summary.voice_steals, 2);
+        assert_eq!(summary.callbacks, 2);
+        // The two new voices, and the two stolen ones still fading out.
+        assert_eq!(stats.active_voices(), 4);
+    }
+
+    #[test]
+    fn test_voice_stealing_skips_protected_voices() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+
+        // Sample 0 (a cymbal) is protected, sample 1 (a snare) is not.
+        let bank = Arc::new(ArcSwap::from_pointee(SampleBank {
+            samples: vec![make_test_sample(1000, 1), make_test_sample(1000, 1)],
+            settings: SampleSettings {
+                gains: vec![1.0, 1.0],
+                protected: vec![true, false],
+                ..Default::default()
+            },
+            pools: Vec::new(),
+            reverb: None,
+            kit_name: "test".to_string(),
+            variant_name: "v1".to_string(),
+        }));
+        let mut mixer = make_mixer(&bank, 1.0, 3);
+        let mut output = vec![0.0f32; 20];
+
+        // Fill every slot: protected voices are the oldest.
+        prod.send(Trigger::new(0, 1.0));
+        prod.send(Trigger::new(0, 1.0));
+        prod.send(Trigger::new(1, 1.0));
+        audio_callback(&mut output, &mut cons, &mut mixer);
+        assert_eq!(mixer.voices.len(), 3);
+
+        // A new hit must steal the unprotected snare, not an older cymbal.
+        let playing = |mixer: &Mixer| -> Vec<bool> {
+            mixer
+                .voices
+                .iter()
+                .filter(|v| !v.stolen)
+                .map(|v| v.protected)
+                .collect()
+        };
+        prod.send(Trigger::new(1, 1.0));
+        audio_callback(&mut output, &mut cons, &mut mixer);
+        assert_eq!(playing(&mixer), vec![true, true, false]);
+
+        // With only protected voices left to take, they are stolen as a last resort.
+        prod.send(Trigger::new(1, 1.0));
+        prod.send(Trigger::new(1, 1.0));
+        audio_callback(&mut output, &mut cons, &mut mixer);
+        assert_eq!(playing(&mixer), vec![true, false, false]);
+    }
+
+    #[test]
+    fn test_toggle_loop_plays_until_stopped_then_fades() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let sample = Arc::new(SampleData {
+            data: vec![0.5; 10],
+            channels: 1,
+            sample_rate: 48000,
+        });
+        let bank = make_test_bank(vec![sample]);
+        let mut mixer = make_mixer(&bank, 1.0, 32);
+
+        // A 10-frame loop keeps playing through a 100-frame callback.
+        prod.send(Trigger::with_kind(0, 1.0, TriggerKind::StartLoop));
+        let mut output = vec![0.0f32; 200];
+        audio_callback(&mut output, &mut cons, &mut mixer);
+        assert_eq!(mixer.voices.len(), 1);
+        assert!(output.iter().all(|&s| (s - 0.5).abs() < 1e-6));
+
+        // Stopping fades it out over LOOP_FADE_FRAMES, then removes it.
+        prod.send(Trigger::with_kind(0, 0.0, TriggerKind::StopLoop));
+        let mut output = vec![0.0f32; (LOOP_FADE_FRAMES + 10) * 2];
+        audio_callback(&mut output, &mut cons, &mut mixer);
+        assert!(output[0] > 0.49);
+        assert!(output[LOOP_FADE_FRAMES] < output[0]);
+        assert!(output[LOOP_FADE_FRAMES * 2..].iter().all(|&s| s == 0.0));
+        assert!(mixer.voices.is_empty());
+    }
+
+    #[test]
+    fn test_panic_fades_all_voices_and_drops_earlier_triggers() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let sample = make_test_sample(10_000, 1);
+        let bank = make_test_bank(vec![Arc::clone(&sample), sample]);
+        let mut mixer = make_mixer(&bank, 1.0, 32);
+
+        prod.send(Trigger::new(0, 1.0));
+        prod.send(Trigger::with_kind(1, 1.0, TriggerKind::StartLoop));
+        let mut output = vec![0.0f32; 20];
+        audio_callback(&mut output, &mut cons, &mut mixer);
+        assert_eq!(mixer.voices.len(), 2);
+
+        // A trigger queued before the panic is dropped; one after it plays.
+        prod.send(Trigger::new(0, 1.0));
+        prod.send(Trigger::with_kind(0, 0.0, TriggerKind::StopAll));
+        prod.send(Trigger::new(1, 1.0));
+        audio_callback(&mut output, &mut cons, &mut mixer);
+        assert_eq!(mixer.voices.len(), 3);
+        assert!(mixer.voices[..2].iter().all(|v| v.fade_remaining.is_some()));
+        assert_eq!(mixer.voices[2].sample_id, 1);
+        assert!(mixer.voices[2].fade_remaining.is_none());
+
+        // After the short ramp only the new voice is left.
+        let mut output = vec![0.0f32; CHOKE_FADE_FRAMES * 2];
+        audio_callback(&mut output, &mut cons, &mut mixer);
+        assert_eq!(mixer.voices.len(), 1);
+        assert!(!mixer.voices[0].looping);
+    }
+
+    #[test]
+    fn test_release_moves_voices_into_release_ramp() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let sample = make_test_sample(10_000, 1);
+        let bank = make_test_bank(vec![Arc::clone(&sample), sample]);
+        let mut mixer = make_mixer(&bank, 1.0, 32);
+
+        prod.send(Trigger::new(0, 1.0));
+        prod.send(Trigger::new(1, 1.0));
+        let mut output = vec![0.0f32; 20];
+        audio_callback(&mut output, &mut cons, &mut mixer);
+        assert!(mixer.voices.iter().all(|v| v.fade_remaining.is_none()));
+
+        // Releasing sample 0 starts its release ramp; sample 1 plays on.
+        prod.send(Trigger::with_kind(0, 0.0, TriggerKind::Release));
+        audio_callback(&mut output, &mut cons, &mut mixer);
+        assert_eq!(
+            mixer.voices[0].fade_remaining,
+            Some(GATE_RELEASE_FRAMES - 10)
+        );
+        assert!(mixer.voices[1].fade_remaining.is_none());
+
+        // A faster fade already under way (e.g. a choke) is kept.
+        mixer.voices[1].fade_out(CHOKE_FADE_FRAMES);
+        prod.send(Trigger::with_kind(1, 0.0, TriggerKind::Release));
+        audio_callback(&mut output, &mut cons, &mut mixer);
+        assert_eq!(mixer.voices[1].fade_remaining, Some(CHOKE_FADE_FRAMES - 10));
+
+        // The released voice is removed at the end of its ramp.
+        let mut output = vec![0.0f32; GATE_RELEASE_FRAMES * 2];
+        audio_callback(&mut output, &mut cons, &mut mixer);
+        assert!(mixer.voices.is_empty());
+    }
+
+    #[test]
+    fn test_trigger_cap_leaves_rest_queued() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let bank = make_test_bank(vec![make_test_sample(1000, 1)]);
+        let mut mixer = make_mixer(&bank, 1.0, 32);
+        mixer.max_triggers_per_callback = Some(2);
+
+        for _ in 0..5 {
+            prod.send(Trigger::new(0, 1.0));
+        }
+
+        // Each callback spawns at most two voices; the remaining triggers
+        // play in the following callbacks.
+        let mut output = vec![0.0f32; 20];
+        for expected_voices in [2, 4, 5, 5] {
+            audio_callback(&mut output, &mut cons, &mut mixer);
+            assert_eq!(mixer.voices.len(), expected_voices);
+        }
+    }
+
+    #[test]
+    fn test_trigger_dedup_collapses_same_sample_from_other_sources() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let bank = make_test_bank(vec![
+            make_test_sample(10_000, 1),
+            make_test_sample(10_000, 1),
+        ]);
+        let mut mixer = make_mixer(&bank, 1.0, 32);
+        mixer.trigger_dedup = Some(TriggerDedup::new(Duration::from_millis(5), 2));
+        let mut output = vec![0.0f32; 20];
+
+        // A key and a MIDI note for the same sample play once.
+        let key = Trigger::new(0, 1.0);
+        prod.send(key);
+        prod.send(Trigger::new(0, 1.0).tagged(TriggerSource::Midi));
+        audio_callback(&mut output, &mut cons, &mut mixer);
+        assert_eq!(mixer.voices.len(), 1);
+
+        // Other samples, and repeats from the same source, still play.
+        prod.send(Trigger::new(1, 1.0).tagged(TriggerSource::Midi));
+        prod.send(Trigger::new(0, 1.0));
+        audio_callback(&mut output, &mut cons, &mut mixer);
+        assert_eq!(mixer.voices.len(), 3);
+
+        // Outside the window, another source plays again.
+        let mut late = Trigger::new(0, 1.0).tagged(TriggerSource::Preview);
+        late.enqueued_at = key.enqueued_at + Duration::from_millis(50);
+        prod.send(late);
+        audio_callback(&mut output, &mut cons, &mut mixer);
+        assert_eq!(mixer.voices.len(), 4);
+    }
+
+    #[test]
+    fn test_bank_swap_with_more_samples_grows_per_sample_state() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let bank = make_test_bank(vec![make_test_sample(10_000, 1)]);
+        let mut mixer = make_mixer(&bank, 1.0, 32);
+        mixer.trigger_dedup = Some(TriggerDedup::new(Duration::from_millis(5), 1));
+        let mut output = vec![0.0f32; 20];
+
+        // A reload adds a sample; its hits are deduplicated like the rest.
+        bank.store(Arc::new(SampleBank {
+            samples: vec![make_test_sample(10_000, 1), make_test_sample(10_000, 1)],
+            settings: SampleSettings {
+                gains: vec![1.0, 1.0],
+                ..Default::default()
+            },
+            pools: Vec::new(),
+            reverb: None,
+            kit_name: "test".to_string(),
+            variant_name: "v2".to_string(),
+        }));
+        prod.send(Trigger::with_kind(0, 0.0, TriggerKind::BankSwap));
+        prod.send(Trigger::new(1, 1.0));
+        prod.send(Trigger::new(1, 1.0).tagged(TriggerSource::Midi));
+        audio_callback(&mut output, &mut cons, &mut mixer);
+
+        assert_eq!(mixer.voices.len(), 1);
+        assert_eq!(mixer.voice_counts.len(), 2);
+    }
+
+    #[test]
+    fn test_choke_group_cuts_ringing_voice() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+
+        // Sample 0 (open hi-hat) and 1 (closed hi-hat) share choke group 1;
+        // sample 2 (a snare) has no group.
+        let bank = Arc::new(ArcSwap::from_pointee(SampleBank {
+            samples: vec![
+                make_test_sample(10_000, 1),
+                make_test_sample(10_000, 1),
+                make_test_sample(10_000, 1),
+            ],
+            settings: SampleSettings {
+                gains: vec![1.0; 3],
+                choke_groups: vec![1, 1, 0],
+                ..Default::default()
+            },
+            pools: Vec::new(),
+            reverb: None,
+            kit_name: "test".to_string(),
+            variant_name: "v1".to_string(),
+        }));
+        let mut mixer = make_mixer(&bank, 1.0, 32);
+        let mut output = vec![0.0f32; 20];
+
+        prod.send(Trigger::new(0, 1.0));
+        prod.send(Trigger::new(2, 1.0));
+        audio_callback(&mut output, &mut cons, &mut mixer);
+        assert_eq!(mixer.voices.len(), 2);
+
+        // The closed hi-hat chokes the open one after a short release; the
+        // snare keeps ringing.
+        prod.send(Trigger::new(1, 1.0));
+        let mut output = vec![0.0f32; (CHOKE_FADE_FRAMES + 10) * 2];
+        audio_callback(&mut output, &mut cons, &mut mixer);
+        let mut playing: Vec<u16> = mixer.voices.iter().map(|v| v.sample_id).collect();
+        playing.sort();
+        assert_eq!(playing, [1, 2]);
+    }
+
+    #[test]
+    fn test_reserved_voice_survives_hihat_flood() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+
+        // Sample 0 (kick) reserves one voice, sample 1 (hi-hat) none.
+        let bank = Arc::new(ArcSwap::from_pointee(SampleBank {
+            samples: vec![make_test_sample(10_000, 1), make_test_sample(10_000, 1)],
+            settings: SampleSettings {
+                gains: vec![1.0, 1.0],
+                reserved_voices: vec![1, 0],
+                ..Default::default()
+            },
+            pools: Vec::new(),
+            reverb: None,
+            kit_name: "test".to_string(),
+            variant_name: "v1".to_string(),
+        }));
+        let mut mixer = make_mixer(&bank, 1.0, 4);
+        let mut output = vec![0.0f32; 20];
+        let kicks = |mixer: &Mixer| mixer.voices.iter().filter(|v| v.sample_id == 0).count();
+
+        // A ringing kick is not stolen by later hi-hats.
+        prod.send(Trigger::new(0, 1.0));
+        audio_callback(&mut output, &mut cons, &mut mixer);
+        for _ in 0..10 {
+            prod.send(Trigger::new(1, 1.0));
+        }
+        audio_callback(&mut output, &mut cons, &mut mixer);
+        assert_eq!(mixer.voices.len(), 4);
+        assert_eq!(kicks(&mixer), 1);
+
+        // A kick arriving behind a flood of hi-hats in the same callback
+        // still gets a voice.
+        let mut mixer = make_mixer(&bank, 1.0, 4);
+        for _ in 0..10 {
+            prod.send(Trigger::new(1, 1.0));
+        }
+        prod.send(Trigger::new(0, 1.0));
+        audio_callback(&mut output, &mut cons, &mut mixer);
+        assert_eq!(mixer.voices.len(), 4);
+        assert_eq!(kicks(&mixer), 1);
+    }
+
+    #[test]
+    fn test_hard_left_pan_silences_right_channel() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let bank = Arc::new(ArcSwap::from_pointee(SampleBank {
+            samples: vec![make_test_sample(100, 1)],
+            settings: SampleSettings {
+                gains: vec![1.0],
+                pans: vec![-1.0],
+                ..Default::default()
+            },
+            pools: Vec::new(),
+            reverb: None,
+            kit_name: "test".to_string(),
+            variant_name: "v1".to_string(),
+        }));
+        let mut mixer = make_mixer(&bank, 1.0, 32);
+
+        prod.send(Trigger::new(0, 1.0));
+        let mut output = vec![0.0f32; 40];
+        audio_callback(&mut output, &mut cons, &mut mixer);
+
+        assert!(output.iter().step_by(2).any(|&s| s > 0.0));
+        assert!(output.iter().skip(1).step_by(2).all(|&s| s == 0.0));
+        assert_eq!(pan_gains(0.0), [1.0, 1.0]);
+    }
+
+    #[test]
+    fn test_mono_output_mode_sums_hard_pan_to_both_channels() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let bank = Arc::new(ArcSwap::from_pointee(SampleBank {
+            samples: vec![make_test_sample(100, 1)],
+            settings: SampleSettings {
+                gains: vec![1.0],
+                pans: vec![-1.0],
+                ..Default::default()
+            },
+            pools: Vec::new(),
+            reverb: None,
+            kit_name: "test".to_string(),
+            variant_name: "v1".to_string(),
+        }));
+        let mut mixer = make_mixer(&bank, 1.0, 32);
+        mixer.output_mode = OutputMode::Mono;
+
+        prod.send(Trigger::new(0, 1.0));
+        let mut output = vec![0.0f32; 40];
+        audio_callback(&mut output, &mut cons, &mut mixer);
+
+        // The hard-left voice plays at half level on both channels.
+        assert!(output.iter().any(|&s| s > 0.0));
+        for frame in output.chunks_exact(2) {
+            assert_eq!(frame[0], frame[1]);
+        }
+        let mut stereo = vec![0.0f32; 40];
+        let mut mixer = make_mixer(&bank, 1.0, 32);
+        prod.send(Trigger::new(0, 1.0));
+        audio_callback(&mut stereo, &mut cons, &mut mixer);
+        assert!((output[10] - stereo[10] * 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_latency_presets_pick_buffer_targets() {
+        // A device that offers anything from 16 to 4096 frames.
+        let target = |mode: LatencyMode| buffer_target(16, 4096, &mode.settings());
+        assert_eq!(target(LatencyMode::Safe), 512);
+        assert_eq!(target(LatencyMode::Balanced), 64);
+        assert_eq!(target(LatencyMode::Aggressive), 32);
+
+        // The device's own limits still win.
+        let balanced = LatencyMode::Balanced.settings();
+        assert_eq!(buffer_target(128, 4096, &balanced), 128);
+        assert_eq!(buffer_target(16, 256, &LatencyMode::Safe.settings()), 256);
+
+        // Only the safe preset warms up.
+        assert!(LatencyMode::Safe.settings().warmup > Duration::ZERO);
+        assert_eq!(balanced.warmup, Duration::ZERO);
+    }
+
+    #[test]
+    fn test_warmup_plays_silence_and_keeps_triggers_queued() {
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let bank = make_test_bank(vec![make_test_sample(1000, 1)]);
+        let mut mixer = make_mixer(&bank, 1.0, 4);
+        mixer.warmup_frames = 15;
 
-        voice.position += frames_to_mix;
+        prod.send(Trigger::new(0, 1.0));
+        let mut output = vec![0.0f32; 20];
+        audio_callback(&mut output, &mut cons, &mut mixer);
+        assert!(output.iter().all(|&s| s == 0.0));
+        assert!(mixer.voices.is_empty());
+
+        // The second buffer finishes the warm-up; the trigger plays after it.
+        audio_callback(&mut output, &mut cons, &mut mixer);
+        assert!(mixer.voices.is_empty());
+        audio_callback(&mut output, &mut cons, &mut mixer);
+        assert_eq!(mixer.voices.len(), 1);
+    }
 
-        // If the voice has finished, remove it.
-        if voice.position >= sample_frames {
-            voices.swap_remove(i);
-        } else {
-            i += 1;
+    #[test]
+    fn test_reconnect_backoff_doubles_up_to_cap() {
+        let start = Instant::now();
+        let mut backoff = ReconnectBackoff::new(start);
+
+        // The first attempt is immediate.
+        assert!(backoff.should_attempt(start));
+
+        let mut now = start;
+        let mut delays = Vec::new();
+        for _ in 0..6 {
+            let delay = backoff.attempt_failed(now);
+            assert!(!backoff.should_attempt(now));
+            assert!(!backoff.should_attempt(now + delay - Duration::from_millis(1)));
+            now += delay;
+            assert!(backoff.should_attempt(now));
+            delays.push(delay.as_millis());
         }
+        assert_eq!(delays, vec![500, 1000, 2000, 4000, 8000, 8000]);
     }
 
-    // 5. Clamp output to [-1.0, 1.0] to prevent clipping.
-    for sample in data.iter_mut() {
-        *sample = sample.clamp(-1.0, 1.0);
-    }
-}
+    #[test]
+    fn test_double_playback_rate_finishes_in_half_the_frames() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let bank = Arc::new(ArcSwap::from_pointee(SampleBank {
+            samples: vec![make_test_sample(100, 1)],
+            settings: SampleSettings {
+                gains: vec![1.0],
+                playback_rates: vec![2.0],
+                ..Default::default()
+            },
+            pools: Vec::new(),
+            reverb: None,
+            kit_name: "test".to_string(),
+            variant_name: "v1".to_string(),
+        }));
+        let mut mixer = make_mixer(&bank, 1.0, 32);
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::ring;
+        prod.send(Trigger::new(0, 1.0));
+        let mut output = vec![0.0f32; 200]; // 100 stereo frames
+        audio_callback(&mut output, &mut cons, &mut mixer);
 
-    /// Create a simple test sample: a mono sine-like ramp.
-    fn make_test_sample(num_frames: usize, channels: u16) -> Arc<SampleData> {
-        let total_samples = num_frames * channels as usize;
-        let mut data = Vec::with_capacity(total_samples);
-        for i in 0..total_samples {
-            data.push((i as f32 / total_samples as f32) * 0.5);
-        }
-        Arc::new(SampleData {
-            data,
-            channels,
-            sample_rate: 48000,
-        })
+        assert!(mixer.voices.is_empty());
+        let last_audible = output.iter().rposition(|&s| s != 0.0).unwrap() / 2;
+        assert!(
+            (49..=51).contains(&last_audible),
+            "last frame {}",
+            last_audible
+        );
+
+        // Every other source frame is played.
+        assert_eq!(output[2], bank.load().samples[0].data[2]);
     }
 
-    /// Create a SampleBank wrapped in ArcSwap for testing.
-    fn make_test_bank(samples: Vec<Arc<SampleData>>) -> Arc<ArcSwap<SampleBank>> {
-        let gains = vec![1.0f32; samples.len()];
-        Arc::new(ArcSwap::from_pointee(SampleBank {
-            samples,
-            sample_gains: gains,
+    #[test]
+    fn test_loop_points_wrap_within_the_sample() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+
+        // Stereo frames (0.1, 0.2), (0.3, 0.4), (0.5, 0.6), (0.7, 0.8),
+        // looping over the middle two.
+        let bank = Arc::new(ArcSwap::from_pointee(SampleBank {
+            samples: vec![Arc::new(SampleData {
+                data: vec![0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8],
+                channels: 2,
+                sample_rate: 48000,
+            })],
+            settings: SampleSettings {
+                gains: vec![1.0],
+                loops: vec![true],
+                loop_starts: vec![1],
+                loop_ends: vec![Some(3)],
+                ..Default::default()
+            },
+            pools: Vec::new(),
+            reverb: None,
             kit_name: "test".to_string(),
             variant_name: "v1".to_string(),
-        }))
+        }));
+        let mut mixer = make_mixer(&bank, 1.0, 32);
+
+        // A one-shot hit keeps playing far past the sample's length.
+        prod.send(Trigger::new(0, 1.0));
+        let mut output = vec![0.0f32; 200];
+        audio_callback(&mut output, &mut cons, &mut mixer);
+        assert_eq!(mixer.voices.len(), 1);
+        assert!(output.iter().all(|&s| s != 0.0));
+
+        // Channels stay paired across the wrap.
+        let expected = [0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.3, 0.4, 0.5, 0.6, 0.3, 0.4];
+        for (sample, expected) in output.iter().zip(expected) {
+            assert!((sample - expected).abs() < 1e-6);
+        }
     }
 
-    fn make_test_bank_with_gains(
-        samples: Vec<Arc<SampleData>>,
-        gains: Vec<f32>,
-    ) -> Arc<ArcSwap<SampleBank>> {
-        Arc::new(ArcSwap::from_pointee(SampleBank {
-            samples,
-            sample_gains: gains,
+    #[test]
+    fn test_vel_attack_is_shorter_for_harder_hits() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let bank = Arc::new(ArcSwap::from_pointee(SampleBank {
+            samples: vec![Arc::new(SampleData {
+                data: vec![0.5; 1000],
+                channels: 1,
+                sample_rate: 48000,
+            })],
+            settings: SampleSettings {
+                gains: vec![1.0],
+                vel_attacks: vec![Some((480, 48))],
+                ..Default::default()
+            },
+            pools: Vec::new(),
+            reverb: None,
             kit_name: "test".to_string(),
             variant_name: "v1".to_string(),
-        }))
+        }));
+
+        // Frames until `trigger` reaches its full level.
+        let frames_to_full = |trigger: Trigger| {
+            let (mut prod, mut cons) = ring::default_trigger_channel();
+            let mut mixer = make_mixer(&bank, 1.0, 32);
+            prod.send(trigger);
+            let mut output = vec![0.0f32; 2000];
+            audio_callback(&mut output, &mut cons, &mut mixer);
+            let full = 0.5 * trigger.velocity;
+            assert!(output[0] < full);
+            output
+                .iter()
+                .step_by(2)
+                .position(|&s| (s - full).abs() < 1e-6)
+                .unwrap()
+        };
+
+        let hard = frames_to_full(Trigger::new(0, 1.0));
+        let soft = frames_to_full(Trigger::new(0, 0.25));
+        assert_eq!(hard, 48);
+        assert_eq!(soft, 372);
+
+        // A full-strength hit on a binding with gain 0.25 is still hard.
+        let quiet = frames_to_full(Trigger::new(0, 0.25).with_hit(1.0));
+        assert_eq!(quiet, 48);
     }
 
     #[test]
-    fn test_audio_callback_silence_when_no_triggers() {
+    fn test_start_region_randomizes_start_position() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let (_prod, mut cons) = ring::create_trigger_channel();
-        let bank = make_test_bank(vec![make_test_sample(100, 1)]);
-        let mut voices = Vec::with_capacity(32);
-        let mut trigger_buf = Vec::with_capacity(128);
-        let mut output = vec![0.5f32; 256]; // Pre-fill with non-zero to verify it's zeroed.
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let bank = Arc::new(ArcSwap::from_pointee(SampleBank {
+            samples: vec![make_test_sample(2000, 1)],
+            settings: SampleSettings {
+                gains: vec![1.0],
+                start_regions: vec![Some((480, 960))],
+                ..Default::default()
+            },
+            pools: Vec::new(),
+            reverb: None,
+            kit_name: "test".to_string(),
+            variant_name: "v1".to_string(),
+        }));
+        let mut mixer = make_mixer(&bank, 1.0, 32);
 
-        audio_callback(
-            &mut output,
-            2,
-            &mut cons,
-            &mut trigger_buf,
-            &mut voices,
-            &bank,
-            1.0,
-            32,
-        );
+        for _ in 0..32 {
+            prod.send(Trigger::new(0, 1.0));
+        }
+        // One frame, so each voice has advanced exactly one frame.
+        let mut output = vec![0.0f32; 2];
+        audio_callback(&mut output, &mut cons, &mut mixer);
 
-        // Output should be all zeros (no voices playing).
-        for &s in &output {
-            assert_eq!(s, 0.0);
+        assert_eq!(mixer.voices.len(), 32);
+        for voice in &mixer.voices {
+            assert!(
+                (481..=961).contains(&voice.position),
+                "position {}",
+                voice.position
+            );
         }
+        let first = mixer.voices[0].position;
+        assert!(mixer.voices.iter().any(|v| v.position != first));
     }
 
     #[test]
-    fn test_audio_callback_plays_triggered_sample() {
+    fn test_soft_limiter_stays_below_full_scale() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let (mut prod, mut cons) = ring::create_trigger_channel();
-        let bank = make_test_bank(vec![make_test_sample(100, 1)]);
-        let mut voices = Vec::with_capacity(32);
-        let mut trigger_buf = Vec::with_capacity(128);
-        let mut output = vec![0.0f32; 20]; // 10 frames stereo
-
-        // Send a trigger.
-        prod.send(Trigger {
-            sample_id: 0,
-            velocity: 1.0,
+        let loud = Arc::new(SampleData {
+            data: vec![0.9; 100],
+            channels: 1,
+            sample_rate: 48000,
         });
-
-        audio_callback(
-            &mut output,
-            2,
-            &mut cons,
-            &mut trigger_buf,
-            &mut voices,
-            &bank,
-            1.0,
-            32,
-        );
-
-        // Output should have non-zero values (sample was mixed in).
-        let has_nonzero = output.iter().any(|&s| s != 0.0);
-        assert!(has_nonzero, "Expected non-zero output after trigger");
+        let bank = make_test_bank(vec![loud.clone(), loud.clone(), loud]);
+
+        // Three 0.9 voices stacked sum to 2.7.
+        let render = |limiter| {
+            let (mut prod, mut cons) = ring::default_trigger_channel();
+            let mut mixer = make_mixer(&bank, 1.0, 32);
+            mixer.limiter = limiter;
+            for sample_id in 0..3 {
+                prod.send(Trigger::new(sample_id, 1.0));
+            }
+            let mut output = vec![0.0f32; 20];
+            audio_callback(&mut output, &mut cons, &mut mixer);
+            output
+        };
+
+        assert!(render(Limiter::Hard).iter().all(|&s| s == 1.0));
+        let soft = render(Limiter::SoftTanh);
+        assert!(soft.iter().all(|&s| s > 0.99 && s < 1.0));
+
+        // The curve keeps ordering near the top instead of flattening, and
+        // quiet passages pass through untouched.
+        assert!(soft_limit(1.5) < soft_limit(2.7));
+        assert!(soft_limit(-2.7) > -1.0);
+        assert_eq!(soft_limit(0.3), 0.3);
+        assert_eq!(soft_limit(-0.5), -0.5);
     }
 
     #[test]
-    fn test_voice_finishes_and_is_removed() {
+    fn test_bypassed_sample_is_added_after_the_limiter() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let (mut prod, mut cons) = ring::create_trigger_channel();
-        let bank = make_test_bank(vec![make_test_sample(5, 1)]);
-        let mut voices = Vec::with_capacity(32);
-        let mut trigger_buf = Vec::with_capacity(128);
+        let constant = |value| {
+            Arc::new(SampleData {
+                data: vec![value; 100],
+                channels: 1,
+                sample_rate: 48000,
+            })
+        };
+        let bank = Arc::new(ArcSwap::from_pointee(SampleBank {
+            samples: vec![constant(0.9), constant(0.9), constant(0.5)],
+            settings: SampleSettings {
+                gains: vec![1.0; 3],
+                bypass_master_fx: vec![false, false, true],
+                ..Default::default()
+            },
+            pools: Vec::new(),
+            reverb: None,
+            kit_name: "test".to_string(),
+            variant_name: "v1".to_string(),
+        }));
 
-        prod.send(Trigger {
-            sample_id: 0,
-            velocity: 1.0,
-        });
+        // Two 0.9 voices hit the limiter; the 0.5 voice skips it.
+        let render = |limiter| {
+            let (mut prod, mut cons) = ring::default_trigger_channel();
+            let mut mixer = make_mixer(&bank, 1.0, 32);
+            mixer.limiter = limiter;
+            for sample_id in 0..3 {
+                prod.send(Trigger::new(sample_id, 1.0));
+            }
+            let mut output = vec![0.0f32; 20];
+            audio_callback(&mut output, &mut cons, &mut mixer);
+            output
+        };
 
-        // First callback: 10 frames output, but sample is only 5 frames.
-        let mut output = vec![0.0f32; 20]; // 10 stereo frames
-        audio_callback(
-            &mut output,
-            2,
-            &mut cons,
-            &mut trigger_buf,
-            &mut voices,
-            &bank,
-            1.0,
-            32,
+        assert!(render(Limiter::Hard).iter().all(|&s| s == 1.5));
+        let soft = soft_limit(1.8) + 0.5;
+        assert!(
+            render(Limiter::SoftTanh)
+                .iter()
+                .all(|&s| (s - soft).abs() < 1e-6)
         );
-
-        // Voice should be removed after finishing.
-        assert_eq!(voices.len(), 0, "Voice should be removed after sample ends");
     }
 
     #[test]
-    fn test_voice_stealing() {
+    fn test_peak_meter_records_peak_before_limiting() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let (mut prod, mut cons) = ring::create_trigger_channel();
-        let bank = make_test_bank(vec![make_test_sample(1000, 1)]);
-        let mut voices = Vec::with_capacity(4);
-        let mut trigger_buf = Vec::with_capacity(128);
-        let max_voices = 2;
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let bank = make_test_bank(vec![Arc::new(SampleData {
+            data: vec![0.9; 100],
+            channels: 1,
+            sample_rate: 48000,
+        })]);
+        let meter = Arc::new(PeakMeter::new());
+        let mut mixer = make_mixer(&bank, 1.0, 32);
+        mixer.peak_meter = Some(Arc::clone(&meter));
 
-        // Send 4 triggers but max_voices is 2.
-        for _ in 0..4 {
-            prod.send(Trigger {
-                sample_id: 0,
-                velocity: 1.0,
-            });
+        // Three stacked voices reach 2.7 before the limiter clamps them.
+        for _ in 0..3 {
+            prod.send(Trigger::new(0, 1.0));
         }
-
         let mut output = vec![0.0f32; 20];
-        audio_callback(
-            &mut output,
-            2,
-            &mut cons,
-            &mut trigger_buf,
-            &mut voices,
-            &bank,
-            1.0,
-            max_voices,
-        );
+        audio_callback(&mut output, &mut cons, &mut mixer);
 
-        assert!(
-            voices.len() <= max_voices,
-            "Voice count {} exceeds max {}",
-            voices.len(),
-            max_voices
-        );
+        assert!(output.iter().all(|&s| s <= 1.0));
+        assert!((meter.take() - 2.7).abs() < 1e-5);
+        assert_eq!(meter.take(), 0.0);
+    }
+
+    #[test]
+    fn test_extra_output_channels_silent_by_default() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let bank = make_test_bank(vec![make_test_sample(100, 2)]);
+
+        let render = |extra_output_channels| {
+            let (mut prod, mut cons) = ring::default_trigger_channel();
+            let mut mixer = Mixer::new(
+                AudioEngineConfig {
+                    sample_bank: Arc::clone(&bank),
+                    device_name: None,
+                    sample_rate: 48000,
+                    max_voices: 32,
+                    master_volume: Arc::new(MasterVolume::new(1.0)),
+                    volume_smoothing: Duration::from_millis(20),
+                    reverb: None,
+                    latency_stats: None,
+                    error_cue: None,
+                    max_triggers_per_callback: None,
+                    trigger_dedup: None,
+                    bank_crossfade: None,
+                    sample_toggles: Arc::new(SampleToggles::new(0)),
+                    limiter: Limiter::Hard,
+                    extra_output_channels,
+                    output_mode: OutputMode::Stereo,
+                    latency: LatencyMode::Balanced.settings(),
+                    recorder: None,
+                    capture: None,
+                    metronome: None,
+                    peak_meter: None,
+                    engine_stats: None,
+                },
+                4,
+            );
+            prod.send(Trigger::new(0, 1.0));
+            let mut output = vec![0.0f32; 4 * 20];
+            audio_callback(&mut output, &mut cons, &mut mixer);
+            output
+        };
+
+        // Default: the front pair plays, channels 2 and 3 stay silent.
+        let output = render(ExtraOutputChannels::Silent);
+        let frames: Vec<&[f32]> = output.chunks_exact(4).collect();
+        assert!(frames.iter().any(|f| f[0] > 0.0 && f[1] > 0.0));
+        assert!(frames.iter().all(|f| f[2] == 0.0 && f[3] == 0.0));
+
+        // Mirrored: the rear pair repeats the front pair.
+        let output = render(ExtraOutputChannels::MirrorFront);
+        assert!(output.chunks_exact(4).all(|f| f[2] == f[0] && f[3] == f[1]));
     }
 
     #[test]
     fn test_master_volume() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let (mut prod_full, mut cons_full) = ring::create_trigger_channel();
-        let (mut prod_half, mut cons_half) = ring::create_trigger_channel();
+        let (mut prod_full, mut cons_full) = ring::default_trigger_channel();
+        let (mut prod_half, mut cons_half) = ring::default_trigger_channel();
 
         // Use a constant sample (all 0.5).
         let sample = Arc::new(SampleData {
@@ -503,42 +2582,16 @@ mod tests {
         let bank = make_test_bank(vec![sample]);
 
         // Full volume.
-        prod_full.send(Trigger {
-            sample_id: 0,
-            velocity: 1.0,
-        });
+        prod_full.send(Trigger::new(0, 1.0));
         let mut output_full = vec![0.0f32; 20];
-        let mut voices_full = Vec::with_capacity(32);
-        let mut trigger_buf_full = Vec::with_capacity(128);
-        audio_callback(
-            &mut output_full,
-            2,
-            &mut cons_full,
-            &mut trigger_buf_full,
-            &mut voices_full,
-            &bank,
-            1.0,
-            32,
-        );
+        let mut mixer_full = make_mixer(&bank, 1.0, 32);
+        audio_callback(&mut output_full, &mut cons_full, &mut mixer_full);
 
         // Half volume.
-        prod_half.send(Trigger {
-            sample_id: 0,
-            velocity: 1.0,
-        });
+        prod_half.send(Trigger::new(0, 1.0));
         let mut output_half = vec![0.0f32; 20];
-        let mut voices_half = Vec::with_capacity(32);
-        let mut trigger_buf_half = Vec::with_capacity(128);
-        audio_callback(
-            &mut output_half,
-            2,
-            &mut cons_half,
-            &mut trigger_buf_half,
-            &mut voices_half,
-            &bank,
-            0.5,
-            32,
-        );
+        let mut mixer_half = make_mixer(&bank, 0.5, 32);
+        audio_callback(&mut output_half, &mut cons_half, &mut mixer_half);
 
         // Half-volume output should be half of full-volume output.
         for i in 0..output_full.len() {
@@ -554,10 +2607,51 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_master_volume_change_glides_to_new_target() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+
+        // A constant sample long enough to keep playing throughout.
+        let sample = Arc::new(SampleData {
+            data: vec![0.5; 48000],
+            channels: 1,
+            sample_rate: 48000,
+        });
+        let bank = make_test_bank(vec![sample]);
+        let mut mixer = make_mixer(&bank, 1.0, 32);
+        let mut output = vec![0.0f32; 256];
+
+        prod.send(Trigger::new(0, 1.0));
+        audio_callback(&mut output, &mut cons, &mut mixer);
+        assert_eq!(output[254], 0.5);
+
+        // Turn the volume down: the playing voice follows, smoothly.
+        mixer.master_volume.set(0.25);
+        let target = 0.5 * 0.25;
+        let mut previous = output[254];
+        for _ in 0..100 {
+            audio_callback(&mut output, &mut cons, &mut mixer);
+            let (first, last) = (output[0], output[254]);
+            assert!(previous - first < 0.01, "volume stepped");
+            assert!(first <= previous);
+            assert!(last <= first && last >= target);
+            previous = last;
+        }
+        assert!((previous - target).abs() < 1e-4, "did not converge");
+
+        // The first buffer after the change only moved part of the way.
+        let mut mixer = make_mixer(&bank, 1.0, 32);
+        prod.send(Trigger::new(0, 1.0));
+        mixer.master_volume.set(0.25);
+        audio_callback(&mut output, &mut cons, &mut mixer);
+        assert!(output[254] > target && output[254] < 0.5);
+    }
+
     #[test]
     fn test_output_clamping() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let (mut prod, mut cons) = ring::create_trigger_channel();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
 
         // Create a sample with values > 1.0 when summed.
         let loud_sample = Arc::new(SampleData {
@@ -569,30 +2663,17 @@ mod tests {
 
         // Send 3 triggers — they'll stack and sum to ~2.7.
         for _ in 0..3 {
-            prod.send(Trigger {
-                sample_id: 0,
-                velocity: 1.0,
-            });
+            prod.send(Trigger::new(0, 1.0));
         }
 
         let mut output = vec![0.0f32; 20];
-        let mut voices = Vec::with_capacity(32);
-        let mut trigger_buf = Vec::with_capacity(128);
-        audio_callback(
-            &mut output,
-            2,
-            &mut cons,
-            &mut trigger_buf,
-            &mut voices,
-            &bank,
-            1.0,
-            32,
-        );
+        let mut mixer = make_mixer(&bank, 1.0, 32);
+        audio_callback(&mut output, &mut cons, &mut mixer);
 
         // All values should be clamped to [-1.0, 1.0].
         for &s in &output {
             assert!(
-                s >= -1.0 && s <= 1.0,
+                (-1.0..=1.0).contains(&s),
                 "Output sample {} exceeds [-1.0, 1.0]",
                 s
             );
@@ -602,7 +2683,7 @@ mod tests {
     #[test]
     fn test_mono_to_stereo_upmix() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let (mut prod, mut cons) = ring::create_trigger_channel();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
 
         let mono_sample = Arc::new(SampleData {
             data: vec![0.5; 10],
@@ -611,25 +2692,12 @@ mod tests {
         });
         let bank = make_test_bank(vec![mono_sample]);
 
-        prod.send(Trigger {
-            sample_id: 0,
-            velocity: 1.0,
-        });
+        prod.send(Trigger::new(0, 1.0));
 
         // 5 frames stereo = 10 output samples.
         let mut output = vec![0.0f32; 10];
-        let mut voices = Vec::with_capacity(32);
-        let mut trigger_buf = Vec::with_capacity(128);
-        audio_callback(
-            &mut output,
-            2,
-            &mut cons,
-            &mut trigger_buf,
-            &mut voices,
-            &bank,
-            1.0,
-            32,
-        );
+        let mut mixer = make_mixer(&bank, 1.0, 32);
+        audio_callback(&mut output, &mut cons, &mut mixer);
 
         // Mono should be duplicated to both L and R channels.
         for frame in 0..5 {
@@ -647,8 +2715,8 @@ mod tests {
     #[test]
     fn test_polyphonic_stacking() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let (mut prod_single, mut cons_single) = ring::create_trigger_channel();
-        let (mut prod_double, mut cons_double) = ring::create_trigger_channel();
+        let (mut prod_single, mut cons_single) = ring::default_trigger_channel();
+        let (mut prod_double, mut cons_double) = ring::default_trigger_channel();
 
         let sample = Arc::new(SampleData {
             data: vec![0.3; 100],
@@ -658,46 +2726,17 @@ mod tests {
         let bank = make_test_bank(vec![sample]);
 
         // Single trigger.
-        prod_single.send(Trigger {
-            sample_id: 0,
-            velocity: 1.0,
-        });
+        prod_single.send(Trigger::new(0, 1.0));
         let mut out_single = vec![0.0f32; 20];
-        let mut voices_single = Vec::with_capacity(32);
-        let mut tb_single = Vec::with_capacity(128);
-        audio_callback(
-            &mut out_single,
-            2,
-            &mut cons_single,
-            &mut tb_single,
-            &mut voices_single,
-            &bank,
-            1.0,
-            32,
-        );
+        let mut mixer_single = make_mixer(&bank, 1.0, 32);
+        audio_callback(&mut out_single, &mut cons_single, &mut mixer_single);
 
         // Double trigger (two stacked voices).
-        prod_double.send(Trigger {
-            sample_id: 0,
-            velocity: 1.0,
-        });
-        prod_double.send(Trigger {
-            sample_id: 0,
-            velocity: 1.0,
-        });
+        prod_double.send(Trigger::new(0, 1.0));
+        prod_double.send(Trigger::new(0, 1.0));
         let mut out_double = vec![0.0f32; 20];
-        let mut voices_double = Vec::with_capacity(32);
-        let mut tb_double = Vec::with_capacity(128);
-        audio_callback(
-            &mut out_double,
-            2,
-            &mut cons_double,
-            &mut tb_double,
-            &mut voices_double,
-            &bank,
-            1.0,
-            32,
-        );
+        let mut mixer_double = make_mixer(&bank, 1.0, 32);
+        audio_callback(&mut out_double, &mut cons_double, &mut mixer_double);
 
         // Double should be approximately 2x single.
         for i in 0..out_single.len() {
@@ -716,7 +2755,7 @@ mod tests {
     #[test]
     fn test_bank_swap_mid_playback() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let (mut prod, mut cons) = ring::create_trigger_channel();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
 
         // Start with a long sample.
         let sample_a = Arc::new(SampleData {
@@ -726,32 +2765,21 @@ mod tests {
         });
         let bank = Arc::new(ArcSwap::from_pointee(SampleBank {
             samples: vec![sample_a],
-            sample_gains: vec![1.0],
+            settings: SampleSettings::default(),
+            pools: Vec::new(),
+            reverb: None,
             kit_name: "kit_a".to_string(),
             variant_name: "v1".to_string(),
         }));
 
-        prod.send(Trigger {
-            sample_id: 0,
-            velocity: 1.0,
-        });
+        prod.send(Trigger::new(0, 1.0));
 
-        let mut voices = Vec::with_capacity(32);
-        let mut trigger_buf = Vec::with_capacity(128);
+        let mut mixer = make_mixer(&bank, 1.0, 32);
 
         // First callback — voice starts playing sample_a.
         let mut output1 = vec![0.0f32; 20];
-        audio_callback(
-            &mut output1,
-            2,
-            &mut cons,
-            &mut trigger_buf,
-            &mut voices,
-            &bank,
-            1.0,
-            32,
-        );
-        assert_eq!(voices.len(), 1, "Should have 1 playing voice");
+        audio_callback(&mut output1, &mut cons, &mut mixer);
+        assert_eq!(mixer.voices.len(), 1, "Should have 1 playing voice");
         let has_audio = output1.iter().any(|&s| s != 0.0);
         assert!(has_audio, "Should produce audio");
 
@@ -763,24 +2791,17 @@ mod tests {
         });
         bank.store(Arc::new(SampleBank {
             samples: vec![sample_b],
-            sample_gains: vec![1.0],
+            settings: SampleSettings::default(),
+            pools: Vec::new(),
+            reverb: None,
             kit_name: "kit_b".to_string(),
             variant_name: "v1".to_string(),
         }));
 
         // Second callback — voice should still play sample_a (its Arc snapshot).
         let mut output2 = vec![0.0f32; 20];
-        audio_callback(
-            &mut output2,
-            2,
-            &mut cons,
-            &mut trigger_buf,
-            &mut voices,
-            &bank,
-            1.0,
-            32,
-        );
-        assert_eq!(voices.len(), 1, "Voice should still be playing");
+        audio_callback(&mut output2, &mut cons, &mut mixer);
+        assert_eq!(mixer.voices.len(), 1, "Voice should still be playing");
 
         // The output should be from sample_a (0.5), not sample_b (0.9).
         // With gain=1.0, the output value should be 0.5.
@@ -790,4 +2811,313 @@ mod tests {
             output2[0],
         );
     }
+
+    #[test]
+    fn test_bank_swap_crossfade_fades_playing_voices() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let bank = make_test_bank(vec![Arc::new(SampleData {
+            data: vec![0.5; 1000],
+            channels: 1,
+            sample_rate: 48000,
+        })]);
+
+        // Without a crossfade the swap leaves the voice alone and starts
+        // nothing.
+        let mut mixer = make_mixer(&bank, 1.0, 32);
+        prod.send(Trigger::new(0, 1.0));
+        prod.send(Trigger::with_kind(0, 0.0, TriggerKind::BankSwap));
+        let mut output = vec![0.0f32; 20];
+        audio_callback(&mut output, &mut cons, &mut mixer);
+        assert_eq!(mixer.voices.len(), 1);
+        assert!(mixer.voices[0].fade_remaining.is_none());
+
+        // With one, the voice ramps down over the crossfade, then ends.
+        let mut mixer = make_mixer(&bank, 1.0, 32);
+        mixer.bank_crossfade_frames = Some(100);
+        prod.send(Trigger::new(0, 1.0));
+        let mut output = vec![0.0f32; 20];
+        audio_callback(&mut output, &mut cons, &mut mixer);
+        assert!((output[0] - 0.5).abs() < 0.01);
+
+        prod.send(Trigger::with_kind(0, 0.0, TriggerKind::BankSwap));
+        let mut output = vec![0.0f32; 200 * 2];
+        audio_callback(&mut output, &mut cons, &mut mixer);
+        assert!(output[0] > 0.49);
+        assert!((output[50 * 2] - 0.25).abs() < 0.01);
+        assert!(output[100 * 2..].iter().all(|&s| s == 0.0));
+        assert!(mixer.voices.is_empty());
+    }
+
+    #[test]
+    fn test_muted_sample_is_silent_while_others_play() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let constant = |value: f32| {
+            Arc::new(SampleData {
+                data: vec![value; 1000],
+                channels: 1,
+                sample_rate: 48000,
+            })
+        };
+        let bank = make_test_bank(vec![constant(0.25), constant(0.125)]);
+        let mut mixer = make_mixer(&bank, 1.0, 32);
+        let toggles = Arc::new(SampleToggles::new(2));
+        mixer.sample_toggles = Arc::clone(&toggles);
+
+        prod.send(Trigger::new(0, 1.0));
+        prod.send(Trigger::new(1, 1.0));
+        let mut output = vec![0.0f32; 20];
+        audio_callback(&mut output, &mut cons, &mut mixer);
+        assert!((output[0] - 0.375).abs() < 1e-6);
+
+        // Muting sample 0 leaves only sample 1, with both voices kept.
+        assert_eq!(toggles.toggle_mute(0), Some(true));
+        audio_callback(&mut output, &mut cons, &mut mixer);
+        assert!((output[0] - 0.125).abs() < 1e-6);
+        assert_eq!(mixer.voices.len(), 2);
+
+        // Soloing sample 0 while it is muted silences everything; unmuting
+        // it then leaves only the soloed sample.
+        assert_eq!(toggles.toggle_solo(0), Some(true));
+        audio_callback(&mut output, &mut cons, &mut mixer);
+        assert!(output.iter().all(|&s| s == 0.0));
+        assert_eq!(toggles.toggle_mute(0), Some(false));
+        audio_callback(&mut output, &mut cons, &mut mixer);
+        assert!((output[0] - 0.25).abs() < 1e-6);
+
+        toggles.clear();
+        audio_callback(&mut output, &mut cons, &mut mixer);
+        assert!((output[0] - 0.375).abs() < 1e-6);
+        assert_eq!(toggles.toggle_mute(2), None);
+    }
+
+    #[test]
+    fn test_audio_device_matches_any_part_of_the_name() {
+        let names = vec![
+            "HDA Intel PCH, ALC257 Analog".to_string(),
+            "Scarlett 2i2 USB".to_string(),
+            "Scarlett 2i2 USB, Direct".to_string(),
+        ];
+        assert_eq!(matching_device(&names, "scarlett"), Some(1));
+        assert_eq!(matching_device(&names, "USB, direct"), Some(2));
+        assert_eq!(matching_device(&names, "hdmi"), None);
+    }
+
+    #[test]
+    fn test_sample_id_above_u8_range() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+
+        // 300 silent samples, except index 299 which is a constant 0.25.
+        let mut samples: Vec<Arc<SampleData>> = (0..300)
+            .map(|_| {
+                Arc::new(SampleData {
+                    data: vec![0.0; 100],
+                    channels: 1,
+                    sample_rate: 48000,
+                })
+            })
+            .collect();
+        samples[299] = Arc::new(SampleData {
+            data: vec![0.25; 100],
+            channels: 1,
+            sample_rate: 48000,
+        });
+        let bank = make_test_bank(samples);
+
+        prod.send(Trigger::new(299, 1.0));
+
+        let mut output = vec![0.0f32; 20];
+        let mut mixer = make_mixer(&bank, 1.0, 32);
+        audio_callback(&mut output, &mut cons, &mut mixer);
+
+        // Index 299 must not wrap to 43 (299 % 256), which is silent.
+        assert_eq!(mixer.voices.len(), 1);
+        assert_eq!(mixer.voices[0].sample_id, 299);
+        assert!((output[0] - 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_min_gain_floor_for_soft_trigger() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+
+        let sample = Arc::new(SampleData {
+            data: vec![1.0; 100],
+            channels: 1,
+            sample_rate: 48000,
+        });
+        let bank = Arc::new(ArcSwap::from_pointee(SampleBank {
+            samples: vec![sample],
+            settings: SampleSettings {
+                gains: vec![1.0],
+                min_gains: vec![0.2],
+                ..Default::default()
+            },
+            pools: Vec::new(),
+            reverb: None,
+            kit_name: "test".to_string(),
+            variant_name: "v1".to_string(),
+        }));
+
+        // A nearly silent hit is lifted to the floor.
+        prod.send(Trigger::new(0, 0.01));
+
+        let mut output = vec![0.0f32; 20];
+        let mut mixer = make_mixer(&bank, 1.0, 32);
+        audio_callback(&mut output, &mut cons, &mut mixer);
+
+        assert!((mixer.voices[0].gain - 0.2).abs() < 1e-6);
+        assert!(
+            output[0] >= 0.2 - 1e-6,
+            "Expected at least the floor, got {}",
+            output[0]
+        );
+    }
+
+    #[test]
+    fn test_missing_sample_plays_error_cue() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+
+        // A silent placeholder, as loaded for a sample missing from a variant.
+        let missing = Arc::new(SampleData {
+            data: vec![],
+            channels: 1,
+            sample_rate: 48000,
+        });
+        let bank = make_test_bank(vec![missing]);
+        let mut mixer = make_mixer(&bank, 1.0, 32);
+        mixer.error_cue = Some(Arc::new(ErrorCue::new(48000)));
+
+        prod.send(Trigger::new(0, 1.0));
+        let mut output = vec![0.0f32; 512];
+        audio_callback(&mut output, &mut cons, &mut mixer);
+
+        assert_eq!(mixer.voices.len(), 1, "Only the error cue should play");
+        assert_eq!(mixer.voices[0].sample_id, u16::MAX);
+        assert!(output.iter().any(|&s| s != 0.0));
+    }
+
+    #[test]
+    fn test_error_cue_at_full_polyphony_steals_by_fading() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let missing = Arc::new(SampleData {
+            data: vec![],
+            channels: 1,
+            sample_rate: 48000,
+        });
+        let bank = make_test_bank(vec![make_test_sample(1000, 1), missing]);
+        let mut mixer = make_mixer(&bank, 1.0, 1);
+        mixer.error_cue = Some(Arc::new(ErrorCue::new(48000)));
+        let stats = Arc::new(EngineStats::new(48000));
+        mixer.engine_stats = Some(Arc::clone(&stats));
+
+        // The only slot is taken when the missing sample asks for the cue.
+        let mut output = vec![0.0f32; 20];
+        prod.send(Trigger::new(0, 1.0));
+        audio_callback(&mut output, &mut cons, &mut mixer);
+        prod.send(Trigger::new(1, 1.0));
+        audio_callback(&mut output, &mut cons, &mut mixer);
+
+        // The playing voice fades out beside the cue rather than vanishing.
+        assert_eq!(mixer.voices.len(), 2);
+        assert_eq!(mixer.voices[0].sample_id, 0);
+        assert!(mixer.voices[0].stolen);
+        assert!(mixer.voices[0].fade_remaining.is_some());
+        assert_eq!(mixer.voices[1].sample_id, u16::MAX);
+        assert!(!mixer.voices[1].stolen);
+        assert_eq!(stats.take_summary().voice_steals, 1);
+    }
+
+    /// Trigger a single-frame impulse through a mixer with reverb enabled and
+    /// return the energy of each 256-frame block rendered afterwards.
+    fn render_impulse_tail(reverb_send: f32) -> Vec<f32> {
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let impulse = Arc::new(SampleData {
+            data: vec![1.0],
+            channels: 1,
+            sample_rate: 48000,
+        });
+        let bank = Arc::new(ArcSwap::from_pointee(SampleBank {
+            samples: vec![impulse],
+            settings: SampleSettings {
+                gains: vec![1.0],
+                reverb_sends: vec![reverb_send],
+                ..Default::default()
+            },
+            pools: Vec::new(),
+            reverb: None,
+            kit_name: "test".to_string(),
+            variant_name: "v1".to_string(),
+        }));
+        let mut mixer = Mixer::new(
+            AudioEngineConfig {
+                sample_bank: bank,
+                device_name: None,
+                sample_rate: 48000,
+                max_voices: 32,
+                master_volume: Arc::new(MasterVolume::new(1.0)),
+                volume_smoothing: Duration::from_millis(20),
+                reverb: Some(ReverbConfig {
+                    room_size: 0.8,
+                    damping: 0.2,
+                    wet: 1.0,
+                }),
+                latency_stats: None,
+                error_cue: None,
+                max_triggers_per_callback: None,
+                trigger_dedup: None,
+                bank_crossfade: None,
+                sample_toggles: Arc::new(SampleToggles::new(0)),
+                limiter: Limiter::Hard,
+                extra_output_channels: ExtraOutputChannels::Silent,
+                output_mode: OutputMode::Stereo,
+                latency: LatencyMode::Balanced.settings(),
+                recorder: None,
+                capture: None,
+                metronome: None,
+                peak_meter: None,
+                engine_stats: None,
+            },
+            2,
+        );
+
+        prod.send(Trigger::new(0, 1.0));
+
+        // The first block holds the dry hit; everything after is tail.
+        let mut output = vec![0.0f32; 512];
+        audio_callback(&mut output, &mut cons, &mut mixer);
+
+        (0..200)
+            .map(|_| {
+                audio_callback(&mut output, &mut cons, &mut mixer);
+                output.iter().map(|s| s * s).sum()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_reverb_send_produces_decaying_tail() {
+        let _ = env_logger::builder().is_test(true).try_init();
+
+        let tail = render_impulse_tail(1.0);
+        let early: f32 = tail[..50].iter().sum();
+        let late: f32 = tail[150..].iter().sum();
+        assert!(early > 0.0, "Expected a reverb tail after the impulse");
+        assert!(
+            late < early * 0.5,
+            "Tail should decay: early {}, late {}",
+            early,
+            late
+        );
+
+        let dry = render_impulse_tail(0.0);
+        assert!(
+            dry.iter().all(|&e| e == 0.0),
+            "Zero send should leave no tail after the impulse"
+        );
+    }
 }
@@ -0,0 +1,166 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Channel count of captures.
+const CAPTURE_CHANNELS: usize = 2;
+
+/// Rolling buffer of the most recent mixed output, as interleaved stereo.
+///
+/// The audio callback keeps overwriting its oldest frames; the input thread
+/// copies out a snapshot when the capture key is pressed. Samples are kept
+/// as f32 bits in atomics so both sides share the buffer without a lock.
+pub struct CaptureBuffer {
+    samples: Box<[AtomicU32]>,
+
+    /// Frames written since the start. The next frame goes at this
+    /// position modulo the buffer's length in frames.
+    written: AtomicU64,
+}
+
+impl CaptureBuffer {
+    /// A silent buffer holding the last `frames` frames of output.
+    pub fn new(frames: usize) -> Self {
+        let samples = (0..frames.max(1) * CAPTURE_CHANNELS)
+            .map(|_| AtomicU32::new(0))
+            .collect();
+        Self {
+            samples,
+            written: AtomicU64::new(0),
+        }
+    }
+
+    fn frames(&self) -> u64 {
+        (self.samples.len() / CAPTURE_CHANNELS) as u64
+    }
+
+    /// Write one callback's interleaved output over the oldest frames.
+    /// Extra output channels are dropped and mono is duplicated.
+    ///
+    /// Called from the audio callback: lock-free and allocation-free.
+    pub fn push(&self, data: &[f32], channels: usize) {
+        let frames = self.frames();
+        let mut position = self.written.load(Ordering::Relaxed);
+        for frame in data.chunks_exact(channels) {
+            let index = (position % frames) as usize * CAPTURE_CHANNELS;
+            self.samples[index].store(frame[0].to_bits(), Ordering::Relaxed);
+            self.samples[index + 1].store(frame[1.min(channels - 1)].to_bits(), Ordering::Relaxed);
+            position += 1;
+        }
+        self.written.store(position, Ordering::Release);
+    }
+
+    /// The buffered frames, oldest first, as interleaved stereo. Shorter
+    /// than the buffer until it has filled once.
+    ///
+    /// A callback running during the copy may already have replaced the
+    /// first frames with newer output, at most one buffer's worth.
+    pub fn snapshot(&self) -> Vec<f32> {
+        let frames = self.frames();
+        let written = self.written.load(Ordering::Acquire);
+        let start = written - written.min(frames);
+        let mut samples = Vec::with_capacity((written - start) as usize * CAPTURE_CHANNELS);
+        for position in start..written {
+            let index = (position % frames) as usize * CAPTURE_CHANNELS;
+            for sample in &self.samples[index..index + CAPTURE_CHANNELS] {
+                samples.push(f32::from_bits(sample.load(Ordering::Relaxed)));
+            }
+        }
+        samples
+    }
+}
+
+/// The capture buffer and where the capture key saves its snapshots.
+pub struct OutputCapture {
+    pub buffer: Arc<CaptureBuffer>,
+    pub dir: PathBuf,
+    pub sample_rate: u32,
+}
+
+impl OutputCapture {
+    /// Copy out the buffered output and write it to a new WAV file in
+    /// `dir` on a separate thread, so the input loop never waits on disk.
+    pub fn save(&self) {
+        let samples = self.buffer.snapshot();
+        if samples.is_empty() {
+            log::info!("Capture: nothing has played yet");
+            return;
+        }
+        let dir = self.dir.clone();
+        let sample_rate = self.sample_rate;
+        std::thread::spawn(move || match write_capture(&dir, &samples, sample_rate) {
+            Ok(path) => log::info!(
+                "Captured {:.1}s of output to {}",
+                (samples.len() / CAPTURE_CHANNELS) as f64 / sample_rate as f64,
+                path.display()
+            ),
+            Err(e) => log::error!("Failed to save capture: {:#}", e),
+        });
+    }
+}
+
+/// Write interleaved stereo `samples` to a new, timestamped WAV file in
+/// `dir`, creating the directory if needed. Returns the file's path.
+fn write_capture(dir: &Path, samples: &[f32], sample_rate: u32) -> Result<PathBuf> {
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create capture directory: {}", dir.display()))?;
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis())
+        .unwrap_or_default();
+    let path = dir.join(format!("capture-{}.wav", millis));
+
+    let spec = hound::WavSpec {
+        channels: CAPTURE_CHANNELS as u16,
+        sample_rate,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+    };
+    let mut writer = hound::WavWriter::create(&path, spec)
+        .with_context(|| format!("Failed to create capture: {}", path.display()))?;
+    for &sample in samples {
+        writer
+            .write_sample(sample)
+            .context("Failed to write capture")?;
+    }
+    writer.finalize().context("Failed to finalize capture")?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_holds_last_frames_in_order() {
+        let buffer = CaptureBuffer::new(4);
+        assert!(buffer.snapshot().is_empty());
+
+        // Two frames, not yet full: only what was written.
+        buffer.push(&[1.0, -1.0, 2.0, -2.0], 2);
+        assert_eq!(buffer.snapshot(), vec![1.0, -1.0, 2.0, -2.0]);
+
+        // Wrap around: a mono buffer is duplicated, then a 4-channel one is
+        // reduced to its front pair. Only the last 4 frames remain.
+        buffer.push(&[3.0, 4.0], 1);
+        buffer.push(&[5.0, -5.0, 9.0, 9.0, 6.0, -6.0, 9.0, 9.0], 4);
+        assert_eq!(
+            buffer.snapshot(),
+            vec![3.0, 3.0, 4.0, 4.0, 5.0, -5.0, 6.0, -6.0]
+        );
+    }
+
+    #[test]
+    fn test_capture_written_as_stereo_wav() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_capture(&dir.path().join("captures"), &[0.5, -0.5], 48_000).unwrap();
+
+        let mut reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.spec().channels, 2);
+        assert_eq!(reader.spec().sample_rate, 48_000);
+        let samples: Vec<f32> = reader.samples::<f32>().map(|s| s.unwrap()).collect();
+        assert_eq!(samples, vec![0.5, -0.5]);
+    }
+}
@@ -0,0 +1,182 @@
+use crate::config::{self, InputKind, ResolvedConfig};
+use crate::samples::{self, KitLibrary};
+use anyhow::{Result, anyhow};
+use std::path::Path;
+
+/// `--check` exit code for a config that fails to load, parse or resolve.
+pub const EXIT_CONFIG_ERROR: i32 = 2;
+
+/// `--check` exit code for sample problems: no usable kits, or bound
+/// samples that no variant provides.
+pub const EXIT_SAMPLE_ERROR: i32 = 3;
+
+/// `--check` exit code for a configured input device that does not exist.
+pub const EXIT_DEVICE_ERROR: i32 = 4;
+
+/// A failed check: the exit code for its kind and what went wrong.
+#[derive(Debug)]
+pub struct CheckError {
+    pub exit_code: i32,
+    pub error: anyhow::Error,
+}
+
+impl CheckError {
+    fn new(exit_code: i32, error: anyhow::Error) -> Self {
+        Self { exit_code, error }
+    }
+}
+
+/// Load and fully resolve the config, discover its kits and verify every
+/// bound sample exists in at least one variant, without opening any audio
+/// or input device. Prints a summary and returns the process exit code.
+pub fn run_check(
+    config_path: &Path,
+    system_config_path: &Path,
+    profile: Option<&str>,
+    devices: &[String],
+) -> i32 {
+    match check(config_path, system_config_path, profile, devices) {
+        Ok((resolved, library)) => {
+            print_summary(config_path, &resolved, &library);
+            0
+        }
+        Err(failure) => {
+            eprintln!("Config check failed: {:#}", failure.error);
+            failure.exit_code
+        }
+    }
+}
+
+fn check(
+    config_path: &Path,
+    system_config_path: &Path,
+    profile: Option<&str>,
+    devices: &[String],
+) -> Result<(ResolvedConfig, KitLibrary), CheckError> {
+    let mut resolved = config::load_config(config_path, system_config_path, profile)
+        .map_err(|e| CheckError::new(EXIT_CONFIG_ERROR, e))?;
+    if !devices.is_empty() {
+        resolved.devices = devices.to_vec();
+    }
+
+    let library = samples::discover_kits(
+        &resolved.samples_dir,
+        &resolved.sample_names,
+        &resolved.sample_settings(),
+    )
+    .map_err(|e| CheckError::new(EXIT_SAMPLE_ERROR, e))?;
+    let missing = library
+        .missing_samples()
+        .map_err(|e| CheckError::new(EXIT_SAMPLE_ERROR, e))?;
+    if !missing.is_empty() {
+        return Err(CheckError::new(
+            EXIT_SAMPLE_ERROR,
+            anyhow!(
+                "Bound samples not found in any kit variant under {}: {}",
+                resolved.samples_dir.display(),
+                missing.join(", "),
+            ),
+        ));
+    }
+
+    if resolved.input == InputKind::Evdev
+        && let Some(device) = resolved
+            .devices
+            .iter()
+            .find(|device| !Path::new(device).exists())
+    {
+        return Err(CheckError::new(
+            EXIT_DEVICE_ERROR,
+            anyhow!(
+                "Input device {} does not exist (see --list-devices)",
+                device
+            ),
+        ));
+    }
+
+    Ok((resolved, library))
+}
+
+fn print_summary(config_path: &Path, resolved: &ResolvedConfig, library: &KitLibrary) {
+    let variants: usize = library.kits.iter().map(|kit| kit.variants.len()).sum();
+    println!("Config OK: {}", config_path.display());
+    println!(
+        "  Kits:     {} with {} variants in {}",
+        library.kit_count(),
+        variants,
+        resolved.samples_dir.display(),
+    );
+    println!(
+        "  Bindings: {} keys, {} samples, {} banks",
+        resolved.key_map.len(),
+        resolved.sample_names.len(),
+        resolved.banks.len(),
+    );
+    let device = match resolved.input {
+        InputKind::Midi => format!(
+            "MIDI port {}",
+            resolved.midi.port.as_deref().unwrap_or("(first available)")
+        ),
+        InputKind::Evdev if resolved.devices.is_empty() => {
+            "none set, picked interactively at startup".to_string()
+        }
+        InputKind::Evdev => resolved.devices.join(", "),
+    };
+    println!("  Device:   {}", device);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Write `config` to a config file in `dir`, with `{samples}` replaced
+    /// by a samples directory holding kit/variant/kick.wav.
+    fn write_config(dir: &Path, config: &str) -> PathBuf {
+        let samples_dir = dir.join("samples");
+        let variant_dir = samples_dir.join("acoustic").join("dry");
+        std::fs::create_dir_all(&variant_dir).unwrap();
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 48000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(variant_dir.join("kick.wav"), spec).unwrap();
+        writer.write_sample(0i16).unwrap();
+        writer.finalize().unwrap();
+
+        let path = dir.join("config.toml");
+        let config = config.replace("{samples}", &samples_dir.display().to_string());
+        std::fs::write(&path, config).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_check_exit_codes_by_failure_kind() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = tempfile::tempdir().unwrap();
+        let no_system = dir.path().join("none.toml");
+        let kick = "samples_dir = \"{samples}\"\n\
+                    [[bindings]]\nkey = \"KEY_A\"\nsample = \"kick.wav\"\n";
+
+        let path = write_config(dir.path(), kick);
+        assert_eq!(run_check(&path, &no_system, None, &[]), 0);
+
+        let path = write_config(dir.path(), "samples_dir = [");
+        assert_eq!(run_check(&path, &no_system, None, &[]), EXIT_CONFIG_ERROR);
+
+        let path = write_config(
+            dir.path(),
+            &format!("{kick}[[bindings]]\nkey = \"KEY_S\"\nsample = \"snare.wav\"\n"),
+        );
+        assert_eq!(run_check(&path, &no_system, None, &[]), EXIT_SAMPLE_ERROR);
+
+        let path = write_config(dir.path(), kick);
+        let missing_device = dir.path().join("event99").display().to_string();
+        assert_eq!(
+            run_check(&path, &no_system, None, &[missing_device]),
+            EXIT_DEVICE_ERROR
+        );
+    }
+}
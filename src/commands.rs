@@ -0,0 +1,143 @@
+use anyhow::{Context, Result, bail};
+use std::io::BufRead;
+use std::sync::mpsc::Sender;
+
+/// Help text logged when a command is not understood.
+const COMMAND_HELP: &str = "Commands: next, prev, kit N, vol X (0.0 to 1.0), quit";
+
+/// A change of kit or variant. Cycling keys, terminal commands and OSC
+/// messages all ask for one of these, so they behave the same.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KitAction {
+    NextKit,
+    PrevKit,
+    NextVariant,
+    PrevVariant,
+
+    /// Select a kit by zero-based index.
+    SelectKit(usize),
+}
+
+/// A runtime control action typed at the terminal or sent by the preview
+/// server or an OSC controller. Applied by the input thread alongside
+/// cycling keys.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ControlAction {
+    Kit(KitAction),
+
+    /// Set the master volume (0.0 to 1.0).
+    SetVolume(f32),
+
+    /// Play a sample once at full velocity, by name. The name is looked up
+    /// when the action is applied, so a config reload that moves sample ids
+    /// in the meantime still plays the right sample.
+    TriggerSampleNamed(String),
+
+    /// Stop every playing voice, like the panic key.
+    Panic,
+
+    Quit,
+}
+
+/// Parse a master volume argument (0.0 to 1.0).
+pub fn parse_volume(text: &str) -> Result<f32> {
+    let volume: f32 = text
+        .parse()
+        .with_context(|| format!("Invalid volume '{}'", text))?;
+    if !(0.0..=1.0).contains(&volume) {
+        bail!("Volume must be between 0.0 and 1.0, got {}", volume);
+    }
+    Ok(volume)
+}
+
+/// Parse one line of terminal input. Returns None for a blank line.
+///
+/// Kit numbers are one-based, as shown in the kit switching log lines.
+pub fn parse_command(line: &str) -> Result<Option<ControlAction>> {
+    let mut words = line.split_whitespace();
+    let Some(command) = words.next() else {
+        return Ok(None);
+    };
+    let argument = words.next();
+    if words.next().is_some() {
+        bail!("Too many arguments in '{}'", line.trim());
+    }
+
+    let action = match (command.to_ascii_lowercase().as_str(), argument) {
+        ("next", None) => ControlAction::Kit(KitAction::NextKit),
+        ("prev", None) => ControlAction::Kit(KitAction::PrevKit),
+        ("quit", None) => ControlAction::Quit,
+        ("kit", Some(n)) => {
+            let n: usize = n
+                .parse()
+                .with_context(|| format!("Invalid kit number '{}'", n))?;
+            if n == 0 {
+                bail!("Kit numbers start at 1");
+            }
+            ControlAction::Kit(KitAction::SelectKit(n - 1))
+        }
+        ("vol", Some(x)) => ControlAction::SetVolume(parse_volume(x)?),
+        _ => bail!("Unknown command '{}'", line.trim()),
+    };
+    Ok(Some(action))
+}
+
+/// Read commands from `input` line by line and send them to the input
+/// thread. Returns at end of input or once the receiver is gone.
+pub fn run_command_reader(input: impl BufRead, sender: Sender<ControlAction>) {
+    log::info!("Terminal commands enabled. {}", COMMAND_HELP);
+
+    for line in input.lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                log::warn!("Failed to read terminal input: {}", e);
+                return;
+            }
+        };
+        match parse_command(&line) {
+            Ok(Some(action)) => {
+                if sender.send(action).is_err() {
+                    return;
+                }
+            }
+            Ok(None) => {}
+            Err(e) => log::warn!("{:#}. {}", e, COMMAND_HELP),
+        }
+    }
+    log::debug!("Terminal input closed, command reader stopped");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(
+            parse_command("next").unwrap(),
+            Some(ControlAction::Kit(KitAction::NextKit))
+        );
+        assert_eq!(
+            parse_command("  PREV \n").unwrap(),
+            Some(ControlAction::Kit(KitAction::PrevKit))
+        );
+        assert_eq!(
+            parse_command("kit 3").unwrap(),
+            Some(ControlAction::Kit(KitAction::SelectKit(2)))
+        );
+        assert_eq!(
+            parse_command("vol 0.5").unwrap(),
+            Some(ControlAction::SetVolume(0.5))
+        );
+        assert_eq!(parse_command("quit").unwrap(), Some(ControlAction::Quit));
+        assert_eq!(parse_command("   ").unwrap(), None);
+
+        assert!(parse_command("kit").is_err());
+        assert!(parse_command("kit 0").is_err());
+        assert!(parse_command("kit two").is_err());
+        assert!(parse_command("vol 1.5").is_err());
+        assert!(parse_command("next 2").is_err());
+        assert!(parse_command("louder").is_err());
+    }
+}
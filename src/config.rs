@@ -1,9 +1,14 @@
+use crate::filter::OnePole;
+use crate::samples::{SampleSettings, SilenceTrim};
+use crate::synth;
 use anyhow::{bail, Context, Result};
-use evdev::KeyCode;
+use evdev::{AbsoluteAxisCode, BusType, KeyCode};
 use serde::Deserialize;
 use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::Duration;
 
 /// Top-level configuration loaded from TOML.
 #[derive(Debug, Deserialize)]
@@ -12,9 +17,31 @@ pub struct Config {
     /// Can be overridden by the --device CLI argument.
     pub device: Option<String>,
 
+    /// More evdev keyboards to read alongside `device`. Every device plays
+    /// the same bindings. Default: none.
+    #[serde(default)]
+    pub devices: Vec<String>,
+
+    /// Where key presses come from: "evdev" (a keyboard) or "midi" (pads
+    /// or keys on a MIDI port). Default: "evdev".
+    #[serde(default)]
+    pub input: InputKind,
+
+    /// MIDI port and note mapping, used with `input = "midi"`.
+    #[serde(default)]
+    pub midi: MidiConfig,
+
     /// Master volume multiplier (0.0 to 1.0). Default: 0.8.
-    #[serde(default = "default_master_volume")]
-    pub master_volume: f32,
+    pub master_volume: Option<f32>,
+
+    /// Master volume in decibels (0 or below), instead of `master_volume`.
+    pub master_volume_db: Option<f32>,
+
+    /// Time constant, in milliseconds, over which the output glides to a
+    /// new master volume, so runtime changes don't click. 0 follows changes
+    /// within one audio buffer. Default: 20.
+    #[serde(default = "default_volume_smoothing_ms")]
+    pub volume_smoothing_ms: f64,
 
     /// Maximum number of simultaneous voices. Default: 32.
     #[serde(default = "default_max_voices")]
@@ -25,11 +52,257 @@ pub struct Config {
     pub samples_dir: String,
 
     /// Keybindings mapping evdev key names to sample filenames.
+    /// These form the default binding bank.
     pub bindings: Vec<BindingConfig>,
 
+    /// Additional binding banks, switchable at runtime with the
+    /// `next_bank` / `prev_bank` cycling keys.
+    #[serde(default)]
+    pub banks: Vec<BankConfig>,
+
     /// Optional keybindings for cycling through kits and variants.
     #[serde(default)]
     pub cycling_keys: CyclingKeysConfig,
+
+    /// Kit and variant the audition cycling key switches to while held.
+    pub audition: Option<AuditionConfig>,
+
+    /// Keys that switch to a kit while held and back on release, e.g.
+    /// for a quick fill on another kit.
+    #[serde(default)]
+    pub momentary: Vec<KitKeyConfig>,
+
+    /// Keys that jump straight to a kit, alongside the cycling keys.
+    #[serde(default)]
+    pub kit_select: Vec<KitKeyConfig>,
+
+    /// Keys that mute or unmute a bound sample while playing.
+    #[serde(default)]
+    pub mute: Vec<SampleKeyConfig>,
+
+    /// Keys that solo or unsolo a bound sample while playing. While any
+    /// sample is soloed, only soloed samples are heard.
+    #[serde(default)]
+    pub solo: Vec<SampleKeyConfig>,
+
+    /// Length and destination of the output captures the capture cycling
+    /// key saves.
+    #[serde(default)]
+    pub capture: CaptureConfig,
+
+    /// Optional built-in metronome click, mixed into the output.
+    #[serde(default)]
+    pub metronome: Option<MetronomeConfig>,
+
+    /// Optional absolute axis (e.g. an expression pedal) that scales the
+    /// velocity of every trigger.
+    #[serde(default)]
+    pub expression: Option<ExpressionConfig>,
+
+    /// Optional key-travel axis on analog keyboards, used to derive
+    /// velocity from how quickly each key is pressed.
+    #[serde(default)]
+    pub key_travel: Option<KeyTravelConfig>,
+
+    /// Repeat window for `velocity_mode = "timing"` bindings, in
+    /// milliseconds. Default: 40.
+    #[serde(default = "default_velocity_timing_window_ms")]
+    pub velocity_timing_window_ms: f64,
+
+    /// Skip the debug logs written for every key press and trigger, so
+    /// fast playing with --verbose stays cheap and the log readable.
+    /// Warnings and errors still appear. Default: false.
+    #[serde(default)]
+    pub quiet_trigger_logs: bool,
+
+    /// What happens when key-downs arriving in the same input batch (struck
+    /// at the same instant) trigger the same sample: "all" plays it for
+    /// each key, "coalesce" plays it once. Default: "all".
+    #[serde(default)]
+    pub simultaneous_hits: SimultaneousHits,
+
+    /// When set, automatically advance to the next variant (and then the
+    /// next kit) every this many seconds for hands-free auditioning.
+    pub auto_advance_secs: Option<f64>,
+
+    /// Optional shared reverb bus fed by each binding's `reverb_send`. A
+    /// kit manifest's `[reverb]` replaces it while that kit plays.
+    #[serde(default)]
+    pub reverb: Option<ReverbConfig>,
+
+    /// Periodically log trigger latency and jitter statistics. Default: false.
+    #[serde(default)]
+    pub latency_stats: bool,
+
+    /// Periodically log the loudest output peak the limiter had to bring
+    /// back to full scale. Default: false.
+    #[serde(default)]
+    pub report_clipping: bool,
+
+    /// When set, release the keyboard grab if the input loop stops
+    /// responding for this many seconds.
+    pub watchdog_timeout_secs: Option<f64>,
+
+    /// Also exit the process when the watchdog fires. Default: false.
+    #[serde(default)]
+    pub watchdog_exit: bool,
+
+    /// Play a short error tone when a kit fails to load or a trigger hits a
+    /// missing sample. Default: false.
+    #[serde(default)]
+    pub audible_errors: bool,
+
+    /// Accept simple commands (next, prev, kit N, vol X, quit) typed into
+    /// the terminal. Ignored when stdin is not a TTY. Default: false.
+    #[serde(default)]
+    pub terminal_commands: bool,
+
+    /// When set, the audio callback starts at most this many triggers per
+    /// buffer, leaving the rest queued for the next buffers.
+    pub max_triggers_per_callback: Option<usize>,
+
+    /// When set, one-shot hits of the same sample from different sources
+    /// (keyboard, MIDI, preview server) within this many milliseconds of
+    /// each other play once, as the first of them.
+    pub trigger_dedup_ms: Option<f64>,
+
+    /// When set, voices still playing when the kit, variant or samples are
+    /// swapped fade out over this many milliseconds instead of ringing on,
+    /// while new hits play the new samples.
+    pub bank_crossfade_ms: Option<f64>,
+
+    /// Number of slots in the ring that carries triggers from the input
+    /// thread to the audio thread. Default: 128.
+    #[serde(default = "default_ring_buffer_size")]
+    pub ring_buffer_size: usize,
+
+    /// How the final mix is kept within full scale: "hard" (clip) or
+    /// "soft_tanh" (soft-knee limiter). Default: "hard".
+    #[serde(default)]
+    pub limiter: Limiter,
+
+    /// What output channels beyond the front stereo pair play on devices
+    /// with more than two channels: "silent" or "mirror_front".
+    /// Default: "silent".
+    #[serde(default)]
+    pub extra_output_channels: ExtraOutputChannels,
+
+    /// Channel layout of the mix: "stereo" or "mono" (left and right
+    /// summed). Default: "stereo".
+    #[serde(default)]
+    pub output_mode: OutputMode,
+
+    /// Preset for the audio buffer size and startup warm-up: "safe",
+    /// "balanced" or "aggressive". Default: "balanced".
+    #[serde(default)]
+    pub latency_mode: LatencyMode,
+
+    /// Smallest audio buffer requested, in frames, overriding the preset.
+    pub min_buffer_frames: Option<u32>,
+
+    /// Audio buffer size requested, in frames, overriding the preset.
+    pub buffer_frames: Option<u32>,
+
+    /// Silence played when the stream starts, in milliseconds, before
+    /// triggers are mixed. Overrides the preset.
+    pub warmup_ms: Option<u64>,
+
+    /// Audio output device to play through: the first whose name contains
+    /// this, ignoring case (see `--list-audio-devices`). Default: the
+    /// system's default output device.
+    pub audio_device: Option<String>,
+
+    /// Audio output sample rate in Hz. Samples must match it unless
+    /// `resample` is set. Default: 48000.
+    #[serde(default = "default_output_sample_rate")]
+    pub output_sample_rate: u32,
+
+    /// Resample samples that are not at the output sample rate at load
+    /// time instead of rejecting them. Default: false.
+    #[serde(default)]
+    pub resample: bool,
+
+    /// Warn about samples with at least this many consecutive full-scale
+    /// samples on a channel when they load, as they were probably clipped
+    /// on export. 0 disables the check. Default: 4.
+    #[serde(default = "default_clip_warning_run")]
+    pub clip_warning_run: usize,
+
+    /// Cut leading silence from samples when they load, so the transient
+    /// plays as soon as the key is pressed. Default: false.
+    #[serde(default)]
+    pub trim_silence: bool,
+
+    /// With `trim_silence`, cut trailing silence as well. Default: false.
+    #[serde(default)]
+    pub trim_trailing_silence: bool,
+
+    /// Level in dBFS below which `trim_silence` treats a frame as silent.
+    /// Default: -60.
+    #[serde(default = "default_trim_silence_threshold_db")]
+    pub trim_silence_threshold_db: f32,
+
+    /// Scale every sample when it loads so its peak sits at
+    /// `normalize_peak_db`, evening out kits from different sources.
+    /// Binding gains apply on top. Default: false.
+    #[serde(default)]
+    pub normalize: bool,
+
+    /// Peak level in dBFS that `normalize` scales samples to. Default: -1.
+    #[serde(default = "default_normalize_peak_db")]
+    pub normalize_peak_db: f32,
+
+    /// Add triangular (TPDF) dither of up to one least significant bit
+    /// when decoding integer samples. Float samples are unchanged.
+    /// Default: false.
+    #[serde(default)]
+    pub dither: bool,
+
+    /// Reopen the audio output when its device disappears (e.g. a USB
+    /// interface is unplugged), retrying until it is back. Default: false.
+    #[serde(default)]
+    pub reconnect_audio: bool,
+
+    /// Measure each sample's loudness when a kit loads and scale its
+    /// velocity-to-gain mapping so equal velocities sound equally loud
+    /// across the kit. Default: false.
+    #[serde(default)]
+    pub loudness_normalize_velocity: bool,
+
+    /// Load every kit and variant at startup and keep them in memory, so
+    /// switching kits reads no files. Default: false (load on switch).
+    #[serde(default)]
+    pub preload_all: bool,
+
+    /// Load kits on a background thread when switching, so key presses are
+    /// never held up by disk reads. The previous kit plays until the new
+    /// one is ready. Default: false.
+    #[serde(default)]
+    pub background_loading: bool,
+
+    /// Watch the config file and apply changes to bindings and master
+    /// volume without restarting. Default: false.
+    #[serde(default)]
+    pub watch_config: bool,
+
+    /// Save the kit, variant and master volume on shutdown and restore them
+    /// on the next start, instead of starting on the first kit. Default:
+    /// false.
+    #[serde(default)]
+    pub remember_state: bool,
+
+    /// File the remembered state is kept in. Default:
+    /// "~/.local/state/keyboard-drums/state.toml".
+    pub state_file: Option<String>,
+
+    /// Name and IDs the passthrough virtual device presents to the system.
+    #[serde(default)]
+    pub virtual_device: VirtualDeviceConfig,
+
+    /// Optional HTTP server listing kits and triggering samples, for
+    /// external kit browsers.
+    #[serde(default)]
+    pub preview_server: Option<PreviewServerConfig>,
 }
 
 /// A single keybinding entry from config.
@@ -38,293 +311,3036 @@ pub struct BindingConfig {
     /// evdev key name (e.g. "KEY_A", "KEY_SPACE").
     pub key: String,
 
-    /// WAV filename that must exist in every variant folder (e.g. "kick.wav").
-    pub sample: String,
+    /// Sample filename that must exist in every variant folder (e.g.
+    /// "kick.wav") or a folder of takes played in rotation, a list of
+    /// filenames played in rotation on successive presses (round-robin), or
+    /// a list of velocity layers. Required unless `samples` is set.
+    #[serde(default)]
+    pub sample: Option<SampleNames>,
+
+    /// Samples played together on every press (e.g. a kick layered with a
+    /// sub), each with its own gain. Used instead of `sample`.
+    #[serde(default)]
+    pub samples: Vec<StackedSampleConfig>,
 
     /// Per-sample gain multiplier (0.0 to 1.0). Default: 1.0.
-    #[serde(default = "default_gain")]
-    pub gain: f32,
-}
+    pub gain: Option<f32>,
 
-/// Keybindings for cycling through drum kits and variants at runtime.
-#[derive(Debug, Deserialize, Default)]
-pub struct CyclingKeysConfig {
-    /// Key to cycle forward through drum kits.
-    pub next_kit: Option<String>,
+    /// Per-sample gain in decibels (0 or below), instead of `gain`.
+    pub gain_db: Option<f32>,
 
-    /// Key to cycle backward through drum kits.
-    pub prev_kit: Option<String>,
+    /// Amount of this sample sent to the reverb bus (0.0 to 1.0). Default: 0.0.
+    #[serde(default)]
+    pub reverb_send: f32,
 
-    /// Key to cycle forward through variants within the current kit.
-    pub next_variant: Option<String>,
+    /// Lowest gain a trigger can play at after velocity scaling (0.0 to 1.0).
+    /// Default: 0.0 (no floor).
+    #[serde(default)]
+    pub min_gain: f32,
 
-    /// Key to cycle backward through variants within the current kit.
-    pub prev_variant: Option<String>,
-}
+    /// Stereo position (-1.0 left to 1.0 right). Default: 0.0 (center).
+    #[serde(default)]
+    pub pan: f32,
 
-/// Resolved cycling key codes (validated evdev key codes).
-#[derive(Debug, Clone)]
-pub struct ResolvedCyclingKeys {
-    pub next_kit: Option<u16>,
-    pub prev_kit: Option<u16>,
-    pub next_variant: Option<u16>,
-    pub prev_variant: Option<u16>,
-}
+    /// Pitch shift in semitones (-24.0 to 24.0), applied by changing the
+    /// playback rate. Default: 0.0.
+    #[serde(default)]
+    pub pitch: f32,
 
-/// A validated and resolved keybinding ready for use.
-#[derive(Debug, Clone)]
-pub struct ResolvedBinding {
-    /// evdev key code for this binding.
-    pub key_code: KeyCode,
+    /// Keep this sample's voices playing when voices must be stolen, unless
+    /// every playing voice is protected. Default: false.
+    #[serde(default)]
+    pub protect_from_steal: bool,
 
-    /// Index into the loaded samples array.
-    pub sample_index: usize,
+    /// Number of this sample's newest voices that are never stolen, so it
+    /// keeps polyphony under heavy playing. Default: 0.
+    #[serde(default)]
+    pub reserved_voices: u8,
 
-    /// Per-sample gain (already clamped to 0.0..=1.0).
-    pub gain: f32,
-}
+    /// How the sample responds to presses. Default: "one_shot".
+    #[serde(default)]
+    pub mode: BindingMode,
 
-/// Validated configuration with resolved key codes and sample paths.
-#[derive(Debug)]
-pub struct ResolvedConfig {
-    /// Device path (may be None if to be provided by CLI).
-    pub device: Option<String>,
+    /// Release the sample with a short fade when the key is let go instead
+    /// of letting it play to the end. Default: false.
+    #[serde(default)]
+    pub gate: bool,
 
-    /// Master volume (clamped to 0.0..=1.0).
-    pub master_volume: f32,
+    /// Loop the sample until it is released (gate), choked or silenced by
+    /// the panic key, instead of playing it once. Default: false.
+    #[serde(default, rename = "loop")]
+    pub looping: bool,
 
-    /// Maximum simultaneous voices.
-    pub max_voices: usize,
+    /// First frame of the looped part, at the output sample rate.
+    /// Default: 0.
+    #[serde(default)]
+    pub loop_start: usize,
 
-    /// Root directory containing drum kit folders.
-    pub samples_dir: PathBuf,
+    /// Frame the loop wraps back at (exclusive), at the output sample rate.
+    /// Default: the end of the sample.
+    #[serde(default)]
+    pub loop_end: Option<usize>,
 
-    /// Unique sample filenames in load order (index = sample_index).
-    /// These are just the filenames (e.g. "kick.wav"), not full paths.
-    pub sample_names: Vec<String>,
+    /// Choke group (1 to 255). Starting this sample stops other playing
+    /// samples in the same group. Default: 0 (none).
+    #[serde(default)]
+    pub choke_group: u8,
 
-    /// Map from evdev key code to resolved binding.
-    pub key_map: HashMap<u16, ResolvedBinding>,
+    /// How trigger velocity is derived. Default: "fixed".
+    #[serde(default)]
+    pub velocity_mode: VelocityMode,
 
-    /// Resolved cycling keybindings.
-    pub cycling_keys: ResolvedCyclingKeys,
-}
+    /// Start each trigger at a random point within this region of the
+    /// sample instead of at its beginning. Default: none.
+    #[serde(default)]
+    pub start_region: Option<StartRegionConfig>,
 
-fn default_master_volume() -> f32 {
-    0.8
-}
+    /// Fade each hit in over a time that shortens as velocity rises, so
+    /// hard hits have a sharper attack than soft ones. Default: none.
+    #[serde(default)]
+    pub vel_attack: Option<VelAttackConfig>,
 
-fn default_max_voices() -> usize {
-    32
-}
+    /// Play the sample files at their own sample rate as-is, at the output
+    /// rate, instead of rejecting or resampling them. A file at another
+    /// rate then plays faster or slower, and so higher or lower in pitch.
+    /// Default: false.
+    #[serde(default)]
+    pub ignore_sample_rate: bool,
 
-fn default_gain() -> f32 {
-    1.0
+    /// Add this sample to the output after the master limiter, so it is
+    /// neither clamped nor soft-limited and leaves the limiter to the rest
+    /// of the mix. Its reverb send still goes through the limiter.
+    /// Default: false.
+    #[serde(default)]
+    pub bypass_master_fx: bool,
+
+    /// One-pole low-pass or high-pass filter the sample plays through.
+    /// Default: none.
+    #[serde(default)]
+    pub filter: Option<FilterConfig>,
 }
 
-/// Expand a leading `~` or `~/` to the user's home directory.
-pub fn expand_tilde(path: &str) -> PathBuf {
-    if path == "~" || path.starts_with("~/") {
-        if let Ok(home) = std::env::var("HOME") {
-            return PathBuf::from(home).join(&path[2..]);
+impl BindingConfig {
+    /// Every sample filename the binding plays.
+    pub fn sample_names(&self) -> Vec<&String> {
+        match &self.sample {
+            Some(sample) => sample.names(),
+            None => self.samples.iter().map(|stacked| &stacked.sample).collect(),
         }
     }
-    PathBuf::from(path)
 }
 
-/// Resolve an optional evdev key name string to a key code.
-fn resolve_optional_key(name: &Option<String>, field: &str) -> Result<Option<u16>> {
-    match name {
-        None => Ok(None),
-        Some(key_name) => {
-            let key_code = KeyCode::from_str(key_name).map_err(|_| {
-                anyhow::anyhow!(
-                    "Unknown evdev key name for {}: '{}'. Use names like KEY_A, KEY_SPACE, etc.",
-                    field,
-                    key_name,
-                )
-            })?;
-            Ok(Some(key_code.code()))
+/// The sample(s) a binding plays: one filename, several for round-robin, or
+/// velocity layers.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum SampleNames {
+    One(String),
+    RoundRobin(Vec<String>),
+    Layers(Vec<VelocityLayerConfig>),
+}
+
+impl SampleNames {
+    /// Filenames in rotation order, or softest layer first.
+    pub fn names(&self) -> Vec<&String> {
+        match self {
+            SampleNames::One(name) => vec![name],
+            SampleNames::RoundRobin(names) => names.iter().collect(),
+            SampleNames::Layers(layers) => layers.iter().map(|layer| &layer.sample).collect(),
         }
     }
 }
 
-/// Load and validate configuration from a TOML file.
-pub fn load_config(path: &Path) -> Result<ResolvedConfig> {
-    let content = std::fs::read_to_string(path)
-        .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+/// One of the samples a stacked binding plays together.
+#[derive(Debug, Clone, Deserialize)]
+pub struct StackedSampleConfig {
+    /// Sample filename, as for a single-sample binding.
+    pub sample: String,
 
-    let config: Config = toml::from_str(&content)
-        .with_context(|| format!("Failed to parse config file: {}", path.display()))?;
+    /// Gain of this sample within the stack (0.0 to 1.0), applied on top of
+    /// the binding's gain. Default: 1.0.
+    #[serde(default = "default_gain")]
+    pub gain: f32,
+}
 
-    resolve_config(config)
+/// One recording of a velocity-layered binding.
+#[derive(Debug, Clone, Deserialize)]
+pub struct VelocityLayerConfig {
+    /// Sample filename, as for a single-sample binding.
+    pub sample: String,
+
+    /// Lowest velocity (0.0 to 1.0) that plays this layer. A hit plays the
+    /// layer with the highest `min_velocity` at or below its velocity.
+    pub min_velocity: f32,
 }
 
-/// Validate raw config and resolve key names to key codes.
-fn resolve_config(config: Config) -> Result<ResolvedConfig> {
-    let master_volume = config.master_volume.clamp(0.0, 1.0);
-    if (master_volume - config.master_volume).abs() > f32::EPSILON {
-        log::warn!(
-            "master_volume {} clamped to {}",
-            config.master_volume,
-            master_volume
-        );
-    }
+/// A region of a sample, in milliseconds from its start.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct StartRegionConfig {
+    pub from_ms: f64,
+    pub to_ms: f64,
+}
 
-    let max_voices = if config.max_voices == 0 {
-        log::warn!("max_voices was 0, defaulting to 32");
-        32
-    } else {
-        config.max_voices
-    };
+/// Attack ramp lengths in milliseconds for the softest and hardest hits.
+/// Velocities in between get a ramp in between.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct VelAttackConfig {
+    pub soft_ms: f64,
+    pub hard_ms: f64,
+}
 
-    let samples_dir = expand_tilde(&config.samples_dir);
-    if !samples_dir.is_dir() {
-        bail!(
-            "samples_dir does not exist or is not a directory: {}",
-            samples_dir.display()
-        );
-    }
+/// A binding's filter: its type and cutoff frequency.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct FilterConfig {
+    #[serde(rename = "type")]
+    pub kind: FilterKind,
+    pub cutoff_hz: f32,
+}
 
-    // Deduplicate sample names and build index map.
-    // Multiple bindings can reference the same sample — we only load it once.
-    let mut sample_names: Vec<String> = Vec::new();
-    let mut sample_name_to_index: HashMap<String, usize> = HashMap::new();
-    let mut key_map: HashMap<u16, ResolvedBinding> = HashMap::new();
+/// Which side of the cutoff frequency a filter lets through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterKind {
+    LowPass,
+    HighPass,
+}
 
-    if config.bindings.is_empty() {
-        bail!("No keybindings defined in config");
-    }
+/// How a binding's sample responds to key presses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BindingMode {
+    /// Each press plays the sample once.
+    #[default]
+    OneShot,
 
-    for binding in &config.bindings {
-        // Resolve evdev key name to key code.
-        let key_code = KeyCode::from_str(&binding.key).map_err(|_| {
-            anyhow::anyhow!(
-                "Unknown evdev key name: '{}'. Use names like KEY_A, KEY_SPACE, etc.",
-                binding.key
-            )
-        })?;
+    /// The first press starts the sample looping; the next fades it out.
+    ToggleLoop,
+}
 
-        // Get or create sample index by filename.
-        let sample_index = if let Some(&idx) = sample_name_to_index.get(&binding.sample) {
-            idx
-        } else {
-            let idx = sample_names.len();
-            sample_names.push(binding.sample.clone());
-            sample_name_to_index.insert(binding.sample.clone(), idx);
-            idx
-        };
+/// How a binding derives trigger velocity.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VelocityMode {
+    /// Every press plays at the binding's gain.
+    #[default]
+    Fixed,
+
+    /// Presses repeated quickly on the same key play louder than isolated
+    /// ones, for flams and accents.
+    Timing,
+}
 
-        let gain = binding.gain.clamp(0.0, 1.0);
-        if (gain - binding.gain).abs() > f32::EPSILON {
-            log::warn!(
-                "gain for key {} clamped from {} to {}",
-                binding.key,
-                binding.gain,
-                gain
-            );
-        }
+/// Where the input thread reads key presses from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InputKind {
+    /// A keyboard evdev device, grabbed with unbound keys passed through.
+    #[default]
+    Evdev,
 
-        let code = key_code.code();
-        if key_map.contains_key(&code) {
-            log::warn!(
-                "Duplicate keybinding for {}: overwriting previous binding",
-                binding.key
-            );
-        }
+    /// Note-on messages from a MIDI input port.
+    Midi,
+}
 
-        key_map.insert(
-            code,
-            ResolvedBinding {
-                key_code,
-                sample_index,
-                gain,
+/// How per-user bindings combine with system config bindings, set by
+/// `bindings_merge` in the per-user config (or in a profile, for how its
+/// bindings combine with the defaults). Consumed while merging, so it is
+/// not a `Config` field.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BindingsMerge {
+    /// The user's bindings replace the system's entirely.
+    #[default]
+    Replace,
+
+    /// The user's bindings are added after the system's. Later bindings
+    /// win when both bind the same key.
+    Append,
+}
+
+/// How key-downs from the same input batch that trigger the same sample
+/// are played.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SimultaneousHits {
+    /// Play the sample once for every key, stacking the voices.
+    #[default]
+    All,
+
+    /// Play the sample once per batch, e.g. for two pads bound to the same
+    /// sample that register a single strike.
+    Coalesce,
+}
+
+/// How the final mix is kept within -1.0..=1.0.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Limiter {
+    /// Clamp samples to full scale. Loud stacks clip harshly.
+    #[default]
+    Hard,
+
+    /// Pass quiet samples through unchanged and compress peaks above a knee
+    /// with tanh, approaching but never reaching full scale.
+    SoftTanh,
+}
+
+/// Presets trading audio buffer safety against latency.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LatencyMode {
+    /// Large buffers and a short warm-up, for busy or slow machines where
+    /// underruns (crackles) matter more than a few milliseconds.
+    Safe,
+
+    /// The smallest buffer the device offers, but no fewer than 64 frames.
+    #[default]
+    Balanced,
+
+    /// Buffers down to 32 frames for the lowest latency, at a much higher
+    /// callback rate. Needs a quiet, well-tuned system.
+    Aggressive,
+}
+
+impl LatencyMode {
+    /// The buffer and warm-up settings this preset stands for.
+    pub fn settings(self) -> LatencySettings {
+        match self {
+            LatencyMode::Safe => LatencySettings {
+                min_buffer_frames: 256,
+                buffer_frames: Some(512),
+                warmup: Duration::from_millis(200),
+            },
+            // 64 frames at 48kHz is about 1.3ms. Device-reported minimums
+            // can be as low as 1 frame, which means tens of thousands of
+            // callbacks per second and no usable audio.
+            LatencyMode::Balanced => LatencySettings {
+                min_buffer_frames: 64,
+                buffer_frames: None,
+                warmup: Duration::ZERO,
+            },
+            LatencyMode::Aggressive => LatencySettings {
+                min_buffer_frames: 32,
+                buffer_frames: None,
+                warmup: Duration::ZERO,
             },
+        }
+    }
+}
+
+/// Audio buffer size and warm-up, from `latency_mode` and any overrides.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencySettings {
+    /// Floor for the requested buffer size in frames, whatever the device
+    /// reports as its minimum.
+    pub min_buffer_frames: u32,
+
+    /// Buffer size to request in frames, or None for the device's smallest.
+    pub buffer_frames: Option<u32>,
+
+    /// Silence played when the stream starts before triggers are mixed.
+    pub warmup: Duration,
+}
+
+/// Routing for output channels beyond the front stereo pair.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExtraOutputChannels {
+    /// Mix only to the front left/right pair; other channels stay silent.
+    #[default]
+    Silent,
+
+    /// Repeat the front pair on every further pair of channels (even
+    /// channels get left, odd channels right).
+    MirrorFront,
+}
+
+/// Channel layout of the final mix.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputMode {
+    /// Keep the mix in stereo.
+    #[default]
+    Stereo,
+
+    /// Sum left and right and play the average on both, e.g. for a single
+    /// speaker or checking mono compatibility.
+    Mono,
+}
+
+/// A named alternative set of keybindings.
+#[derive(Debug, Deserialize)]
+pub struct BankConfig {
+    /// Display name used in logs when switching to this bank.
+    pub name: String,
+
+    /// Keybindings active while this bank is selected.
+    pub bindings: Vec<BindingConfig>,
+}
+
+/// Keybindings for cycling through drum kits and variants at runtime.
+#[derive(Debug, Deserialize, Default)]
+pub struct CyclingKeysConfig {
+    /// Key to cycle forward through drum kits.
+    pub next_kit: Option<String>,
+
+    /// Key to cycle backward through drum kits.
+    pub prev_kit: Option<String>,
+
+    /// Key to cycle forward through variants within the current kit.
+    pub next_variant: Option<String>,
+
+    /// Key to cycle backward through variants within the current kit.
+    pub prev_variant: Option<String>,
+
+    /// Key to pause and resume automatic advancement.
+    pub toggle_auto_advance: Option<String>,
+
+    /// Key to switch to the next binding bank.
+    pub next_bank: Option<String>,
+
+    /// Key to switch to the previous binding bank.
+    pub prev_bank: Option<String>,
+
+    /// Key to silence every playing voice at once.
+    pub panic: Option<String>,
+
+    /// Key that plays the [audition] kit while held.
+    pub audition: Option<String>,
+
+    /// Key that saves the last few seconds of output as a WAV file.
+    pub capture: Option<String>,
+
+    /// Key to switch the metronome on and off.
+    pub toggle_metronome: Option<String>,
+}
+
+/// The kit the audition key switches to while it is held.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AuditionConfig {
+    /// Kit folder name.
+    pub kit: String,
+
+    /// Variant folder name within the kit. Default: its first variant.
+    pub variant: Option<String>,
+}
+
+/// A key tied to a kit, in `[[momentary]]` or `[[kit_select]]`.
+#[derive(Debug, Deserialize)]
+pub struct KitKeyConfig {
+    /// evdev key name (e.g. "KEY_F1").
+    pub key: String,
+
+    /// Kit folder name.
+    pub kit: String,
+
+    /// Variant folder name within the kit. Default: its first variant.
+    pub variant: Option<String>,
+}
+
+/// A key tied to a bound sample, in `[[mute]]` or `[[solo]]`.
+#[derive(Debug, Deserialize)]
+pub struct SampleKeyConfig {
+    /// evdev key name (e.g. "KEY_F9").
+    pub key: String,
+
+    /// Sample filename, as bound in `[[bindings]]` or `[[banks]]`.
+    pub sample: String,
+}
+
+/// Rolling capture of the mixed output, saved by the capture key.
+#[derive(Debug, Default, Deserialize)]
+pub struct CaptureConfig {
+    /// Seconds of output each capture holds (up to 120). Default: 10.
+    pub seconds: Option<f64>,
+
+    /// Directory captures are written to. Default:
+    /// "~/keyboard-drums-captures".
+    pub dir: Option<String>,
+}
+
+/// Click track played by the audio engine.
+#[derive(Debug, Default, Deserialize)]
+pub struct MetronomeConfig {
+    /// Beats per minute (20 to 400). Default: 120.
+    pub tempo: Option<f64>,
+
+    /// Beats per bar over the note value that gets one beat, e.g. "3/4"
+    /// or "6/8". The first beat of each bar is accented. Default: "4/4".
+    pub time_signature: Option<String>,
+
+    /// Level of the clicks (0.0 to 1.0). Default: 0.5.
+    pub volume: Option<f32>,
+
+    /// Click from startup. Set to false to start it with the
+    /// toggle_metronome key instead. Default: true.
+    pub enabled: Option<bool>,
+}
+
+/// An absolute axis used as a global velocity modifier.
+#[derive(Debug, Deserialize)]
+pub struct ExpressionConfig {
+    /// evdev absolute axis name (e.g. "ABS_Z", "ABS_THROTTLE").
+    pub axis: String,
+
+    /// Axis value that maps to silence (velocity scale 0.0).
+    pub min: i32,
+
+    /// Axis value that maps to full velocity (velocity scale 1.0).
+    /// May be lower than `min` to invert the axis.
+    pub max: i32,
+}
+
+/// An absolute axis reporting key depth on an analog (e.g. Hall-effect)
+/// keyboard, used to derive velocity from press speed.
+#[derive(Debug, Deserialize)]
+pub struct KeyTravelConfig {
+    /// evdev absolute axis name (e.g. "ABS_MISC").
+    pub axis: String,
+
+    /// Axis value with the key at rest.
+    pub rest: i32,
+
+    /// Axis value with the key fully pressed. May be lower than `rest`.
+    pub bottom: i32,
+
+    /// Press time, in milliseconds, at or below which a hit plays at full
+    /// velocity. Default: 5.
+    #[serde(default = "default_travel_fast_ms")]
+    pub fast_ms: f64,
+
+    /// Press time, in milliseconds, at or above which a hit plays at the
+    /// quietest velocity. Default: 50.
+    #[serde(default = "default_travel_slow_ms")]
+    pub slow_ms: f64,
+}
+
+/// MIDI input settings.
+#[derive(Debug, Default, Deserialize)]
+pub struct MidiConfig {
+    /// Use the first input port whose name contains this text. Default:
+    /// the first port found.
+    pub port: Option<String>,
+
+    /// MIDI note number (0 to 127) -> evdev key name. A note-on plays the
+    /// note as a press of that key, so it uses the key's binding.
+    #[serde(default)]
+    pub notes: HashMap<String, String>,
+}
+
+/// Identity of the passthrough virtual device. IDs left unset are copied
+/// from the physical keyboard when any ID is set.
+#[derive(Debug, Default, Deserialize)]
+pub struct VirtualDeviceConfig {
+    /// Device name. Default: "keyboard-drums passthrough".
+    pub name: Option<String>,
+
+    /// evdev bus type name (e.g. "BUS_USB", "BUS_I8042").
+    pub bus_type: Option<String>,
+
+    pub vendor: Option<u16>,
+    pub product: Option<u16>,
+    pub version: Option<u16>,
+}
+
+/// Settings for the shared reverb bus.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct ReverbConfig {
+    /// Size of the simulated room (0.0 to 1.0). Default: 0.5.
+    #[serde(default = "default_reverb_room_size")]
+    pub room_size: f32,
+
+    /// High-frequency damping of the tail (0.0 to 1.0). Default: 0.5.
+    #[serde(default = "default_reverb_damping")]
+    pub damping: f32,
+
+    /// Level of the reverb return mixed into the output (0.0 to 1.0). Default: 0.3.
+    #[serde(default = "default_reverb_wet")]
+    pub wet: f32,
+}
+
+impl Default for ReverbConfig {
+    fn default() -> Self {
+        Self {
+            room_size: default_reverb_room_size(),
+            damping: default_reverb_damping(),
+            wet: default_reverb_wet(),
+        }
+    }
+}
+
+impl ReverbConfig {
+    /// These settings with each one clamped to its 0.0 to 1.0 range.
+    pub fn clamped(self) -> Self {
+        Self {
+            room_size: self.room_size.clamp(0.0, 1.0),
+            damping: self.damping.clamp(0.0, 1.0),
+            wet: self.wet.clamp(0.0, 1.0),
+        }
+    }
+}
+
+/// Settings for the HTTP preview server.
+#[derive(Debug, Deserialize)]
+pub struct PreviewServerConfig {
+    /// Address and port to listen on. Default: "127.0.0.1:8765".
+    #[serde(default = "default_preview_server_bind")]
+    pub bind: String,
+}
+
+/// Resolved cycling key codes (validated evdev key codes).
+#[derive(Debug, Clone, Default)]
+pub struct ResolvedCyclingKeys {
+    pub next_kit: Option<u16>,
+    pub prev_kit: Option<u16>,
+    pub next_variant: Option<u16>,
+    pub prev_variant: Option<u16>,
+    pub toggle_auto_advance: Option<u16>,
+    pub next_bank: Option<u16>,
+    pub prev_bank: Option<u16>,
+    pub panic: Option<u16>,
+    pub audition: Option<u16>,
+    pub capture: Option<u16>,
+    pub toggle_metronome: Option<u16>,
+}
+
+/// Resolved kit key (validated key code). The kit and variant are looked
+/// up in the kit library when the key is pressed.
+#[derive(Debug, Clone)]
+pub struct ResolvedKitKey {
+    pub key: u16,
+    pub kit: String,
+    pub variant: Option<String>,
+}
+
+/// Resolved mute or solo key (validated key code, sample looked up in
+/// `sample_names`).
+#[derive(Debug, Clone)]
+pub struct ResolvedSampleKey {
+    pub key: u16,
+    pub sample: String,
+    pub sample_index: usize,
+}
+
+/// Resolved output capture (validated length, expanded directory).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedCapture {
+    /// How much of the most recent output each capture holds.
+    pub length: Duration,
+
+    /// Directory captures are written to.
+    pub dir: PathBuf,
+}
+
+/// Resolved metronome (validated tempo, time signature and volume).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedMetronome {
+    /// Beats per minute.
+    pub tempo: f64,
+
+    /// Beats per bar; the first of each is accented.
+    pub beats_per_bar: u32,
+
+    /// Level of the clicks (0.0 to 1.0).
+    pub volume: f32,
+
+    /// Whether the metronome clicks from startup.
+    pub enabled: bool,
+}
+
+impl Default for ResolvedMetronome {
+    fn default() -> Self {
+        Self {
+            tempo: DEFAULT_METRONOME_TEMPO,
+            beats_per_bar: DEFAULT_BEATS_PER_BAR,
+            volume: DEFAULT_METRONOME_VOLUME,
+            enabled: true,
+        }
+    }
+}
+
+/// Resolved expression axis (validated evdev axis code and range).
+#[derive(Debug, Clone)]
+pub struct ResolvedExpression {
+    pub axis: u16,
+    pub min: i32,
+    pub max: i32,
+}
+
+/// Resolved key-travel axis (validated evdev axis code, range and timings).
+#[derive(Debug, Clone)]
+pub struct ResolvedKeyTravel {
+    pub axis: u16,
+    pub rest: i32,
+    pub bottom: i32,
+    pub fast: Duration,
+    pub slow: Duration,
+}
+
+/// Resolved MIDI input settings.
+#[derive(Debug, Clone, Default)]
+pub struct ResolvedMidi {
+    pub port: Option<String>,
+
+    /// MIDI note number -> evdev key code it presses.
+    pub note_keys: HashMap<u8, u16>,
+}
+
+/// Resolved virtual device identity (validated bus type).
+#[derive(Debug, Clone)]
+pub struct ResolvedVirtualDevice {
+    pub name: String,
+    pub bus_type: Option<u16>,
+    pub vendor: Option<u16>,
+    pub product: Option<u16>,
+    pub version: Option<u16>,
+}
+
+/// A validated and resolved keybinding ready for use.
+#[derive(Debug, Clone)]
+pub struct ResolvedBinding {
+    /// Indices into the loaded samples array, played in rotation. Holds a
+    /// single index unless the binding is round-robin or velocity-layered.
+    pub sample_indices: Vec<usize>,
+
+    /// Lowest velocity of each layer, ascending and parallel to
+    /// `sample_indices`. Empty unless the binding is velocity-layered.
+    pub layer_velocities: Vec<f32>,
+
+    /// Gain of each sample played together, parallel to `sample_indices`
+    /// (already clamped to 0.0..=1.0). Empty unless the binding stacks
+    /// samples.
+    pub stack_gains: Vec<f32>,
+
+    /// Per-sample gain (already clamped to 0.0..=1.0).
+    pub gain: f32,
+
+    /// Reverb bus send level (already clamped to 0.0..=1.0).
+    pub reverb_send: f32,
+
+    /// Gain floor after velocity scaling (already clamped to 0.0..=1.0).
+    pub min_gain: f32,
+
+    /// Stereo position (already clamped to -1.0..=1.0).
+    pub pan: f32,
+
+    /// Playback rate from the pitch shift (1.0 = original pitch).
+    pub playback_rate: f32,
+
+    /// Whether this sample's voices resist voice stealing.
+    pub protect_from_steal: bool,
+
+    /// Newest voices of this sample that are never stolen.
+    pub reserved_voices: u8,
+
+    /// How the sample responds to presses.
+    pub mode: BindingMode,
+
+    /// Whether releasing the key releases the sample's voices.
+    pub gate: bool,
+
+    /// Whether one-shot hits loop instead of playing once.
+    pub looping: bool,
+
+    /// Loop start frame, and the end frame if not the sample end. Used by
+    /// looping hits and toggle loops.
+    pub loop_start: usize,
+    pub loop_end: Option<usize>,
+
+    /// Choke group (0 = none).
+    pub choke_group: u8,
+
+    /// How trigger velocity is derived.
+    pub velocity_mode: VelocityMode,
+
+    /// Random start region as (first, last) frame at the output sample
+    /// rate, if set.
+    pub start_region: Option<(usize, usize)>,
+
+    /// Attack ramp as (softest, hardest) hit length in frames at the output
+    /// sample rate, if set.
+    pub vel_attack: Option<(usize, usize)>,
+
+    /// Whether the sample files load at their own rate, unchecked.
+    pub ignore_sample_rate: bool,
+
+    /// Whether the sample is mixed in after the master limiter.
+    pub bypass_master_fx: bool,
+
+    /// Filter coefficients at the output sample rate, if set.
+    pub filter: Option<OnePole>,
+}
+
+/// A resolved additional binding bank.
+#[derive(Debug, Clone)]
+pub struct ResolvedBank {
+    pub name: String,
+    pub key_map: HashMap<u16, ResolvedBinding>,
+}
+
+/// Validated configuration with resolved key codes and sample paths.
+#[derive(Debug)]
+pub struct ResolvedConfig {
+    /// Evdev device paths, `device` first (may be empty if to be provided
+    /// by CLI).
+    pub devices: Vec<String>,
+
+    /// Where key presses come from.
+    pub input: InputKind,
+
+    /// MIDI port and note mapping (used with `InputKind::Midi`).
+    pub midi: ResolvedMidi,
+
+    /// Master volume (clamped to 0.0..=1.0).
+    pub master_volume: f32,
+
+    /// Time constant for gliding to a new master volume.
+    pub volume_smoothing: Duration,
+
+    /// Maximum simultaneous voices.
+    pub max_voices: usize,
+
+    /// Root directory containing drum kit folders.
+    pub samples_dir: PathBuf,
+
+    /// Unique sample filenames in load order (index = sample_index).
+    /// These are just the filenames (e.g. "kick.wav"), not full paths.
+    pub sample_names: Vec<String>,
+
+    /// Map from evdev key code to resolved binding (the default bank).
+    pub key_map: HashMap<u16, ResolvedBinding>,
+
+    /// Additional binding banks, in config order after the default bank.
+    pub banks: Vec<ResolvedBank>,
+
+    /// Resolved cycling keybindings.
+    pub cycling_keys: ResolvedCyclingKeys,
+
+    /// Kit the audition key plays, set whenever the key is.
+    pub audition: Option<AuditionConfig>,
+
+    /// Output capture the capture key saves, set whenever the key is.
+    pub capture: Option<ResolvedCapture>,
+
+    /// Metronome, set if `[metronome]` or its toggle key is.
+    pub metronome: Option<ResolvedMetronome>,
+
+    /// Keys that switch to a kit while held.
+    pub momentary: Vec<ResolvedKitKey>,
+
+    /// Keys that jump straight to a kit.
+    pub kit_select: Vec<ResolvedKitKey>,
+
+    /// Keys that mute a sample.
+    pub mute: Vec<ResolvedSampleKey>,
+
+    /// Keys that solo a sample.
+    pub solo: Vec<ResolvedSampleKey>,
+
+    /// Resolved expression axis, if configured.
+    pub expression: Option<ResolvedExpression>,
+
+    /// Resolved key-travel axis, if configured.
+    pub key_travel: Option<ResolvedKeyTravel>,
+
+    /// Repeat window for timing-velocity bindings.
+    pub velocity_timing_window: Duration,
+
+    /// Whether the per-trigger debug logs are silenced.
+    pub quiet_trigger_logs: bool,
+
+    /// Handling of a sample triggered more than once in one input batch.
+    pub simultaneous_hits: SimultaneousHits,
+
+    /// Interval for automatic kit/variant advancement, if enabled.
+    pub auto_advance: Option<Duration>,
+
+    /// Reverb bus settings (values clamped to 0.0..=1.0), if enabled.
+    pub reverb: Option<ReverbConfig>,
+
+    /// Whether to log trigger latency and jitter statistics.
+    pub latency_stats: bool,
+
+    /// Whether to log output peaks that went over full scale.
+    pub report_clipping: bool,
+
+    /// Input loop stall timeout for the watchdog, if enabled.
+    pub watchdog_timeout: Option<Duration>,
+
+    /// Whether the watchdog exits the process after releasing the grab.
+    pub watchdog_exit: bool,
+
+    /// Whether to play an error tone on load/trigger failures.
+    pub audible_errors: bool,
+
+    /// Whether to read runtime commands from the terminal.
+    pub terminal_commands: bool,
+
+    /// Cap on triggers started per audio callback, if set.
+    pub max_triggers_per_callback: Option<usize>,
+
+    /// Window collapsing same-sample hits from different sources, if set.
+    pub trigger_dedup: Option<Duration>,
+
+    /// Fade for voices of a swapped-out sample bank, if set.
+    pub bank_crossfade: Option<Duration>,
+
+    /// Number of slots in the trigger ring buffer.
+    pub ring_buffer_size: usize,
+
+    /// Output limiter applied to the final mix.
+    pub limiter: Limiter,
+
+    /// Routing for output channels beyond the front stereo pair.
+    pub extra_output_channels: ExtraOutputChannels,
+
+    /// Whether the mix is stereo or summed to mono.
+    pub output_mode: OutputMode,
+
+    /// Audio buffer size and warm-up.
+    pub latency: LatencySettings,
+
+    /// Name (or part of it) of the audio output device, if not the default.
+    pub audio_device: Option<String>,
+
+    /// Audio output sample rate in Hz.
+    pub output_sample_rate: u32,
+
+    /// Resample samples at other rates instead of rejecting them.
+    pub resample: bool,
+
+    /// Full-scale run length that flags a loaded sample as clipped (0 = off).
+    pub clip_warning_run: usize,
+
+    /// Silence cut from samples when they load, if trimming is enabled.
+    pub trim_silence: Option<SilenceTrim>,
+
+    /// Linear peak level samples are scaled to when they load, if
+    /// normalizing.
+    pub normalize_peak: Option<f32>,
+
+    /// Whether integer samples are dithered when they load.
+    pub dither: bool,
+
+    /// Whether a failed audio device is reopened automatically.
+    pub reconnect_audio: bool,
+
+    /// Whether velocity is compensated for per-sample loudness.
+    pub loudness_normalize_velocity: bool,
+
+    /// Whether every kit and variant is loaded at startup.
+    pub preload_all: bool,
+
+    /// Whether kits are loaded on a background thread when switching.
+    pub background_loading: bool,
+
+    /// Whether config file changes are applied while running.
+    pub watch_config: bool,
+
+    /// File the kit, variant and master volume are saved to and restored
+    /// from, if `remember_state` is on.
+    pub state_file: Option<PathBuf>,
+
+    /// Name and IDs for the passthrough virtual device.
+    pub virtual_device: ResolvedVirtualDevice,
+
+    /// Listen address of the HTTP preview server, if enabled.
+    pub preview_server: Option<SocketAddr>,
+}
+
+impl ResolvedConfig {
+    /// Per-sample settings from the bindings of every bank, indexed like
+    /// `sample_names`.
+    pub fn sample_settings(&self) -> SampleSettings {
+        let sample_count = self.sample_names.len();
+        let mut settings = SampleSettings {
+            gains: vec![1.0; sample_count],
+            reverb_sends: vec![0.0; sample_count],
+            min_gains: vec![0.0; sample_count],
+            protected: vec![false; sample_count],
+            choke_groups: vec![0; sample_count],
+            reserved_voices: vec![0; sample_count],
+            pans: vec![0.0; sample_count],
+            playback_rates: vec![1.0; sample_count],
+            start_regions: vec![None; sample_count],
+            vel_attacks: vec![None; sample_count],
+            loops: vec![false; sample_count],
+            loop_starts: vec![0; sample_count],
+            loop_ends: vec![None; sample_count],
+            ignore_sample_rates: vec![false; sample_count],
+            bypass_master_fx: vec![false; sample_count],
+            filters: vec![None; sample_count],
+            // Measured from the samples each time a kit loads.
+            loudness_gains: Vec::new(),
+        };
+        let all_bindings = self
+            .key_map
+            .values()
+            .chain(self.banks.iter().flat_map(|bank| bank.key_map.values()));
+        for binding in all_bindings {
+            for &index in binding.sample_indices.iter().filter(|&&i| i < sample_count) {
+                settings.gains[index] = binding.gain;
+                settings.reverb_sends[index] = binding.reverb_send;
+                settings.min_gains[index] = binding.min_gain;
+                settings.protected[index] = binding.protect_from_steal;
+                settings.choke_groups[index] = binding.choke_group;
+                settings.reserved_voices[index] = binding.reserved_voices;
+                settings.pans[index] = binding.pan;
+                settings.playback_rates[index] = binding.playback_rate;
+                settings.start_regions[index] = binding.start_region;
+                settings.vel_attacks[index] = binding.vel_attack;
+                settings.loops[index] = binding.looping;
+                settings.loop_starts[index] = binding.loop_start;
+                settings.loop_ends[index] = binding.loop_end;
+                settings.ignore_sample_rates[index] = binding.ignore_sample_rate;
+                settings.bypass_master_fx[index] = binding.bypass_master_fx;
+                settings.filters[index] = binding.filter;
+            }
+        }
+        settings
+    }
+}
+
+fn default_master_volume() -> f32 {
+    0.8
+}
+
+fn default_volume_smoothing_ms() -> f64 {
+    20.0
+}
+
+fn default_ring_buffer_size() -> usize {
+    crate::ring::DEFAULT_RING_BUFFER_SIZE
+}
+
+fn default_output_sample_rate() -> u32 {
+    crate::samples::DEFAULT_SAMPLE_RATE
+}
+
+fn default_trim_silence_threshold_db() -> f32 {
+    -60.0
+}
+
+fn default_normalize_peak_db() -> f32 {
+    -1.0
+}
+
+fn default_clip_warning_run() -> usize {
+    crate::samples::DEFAULT_CLIP_WARNING_RUN
+}
+
+fn default_max_voices() -> usize {
+    32
+}
+
+fn default_gain() -> f32 {
+    1.0
+}
+
+fn default_velocity_timing_window_ms() -> f64 {
+    40.0
+}
+
+fn default_travel_fast_ms() -> f64 {
+    5.0
+}
+
+fn default_travel_slow_ms() -> f64 {
+    50.0
+}
+
+fn default_reverb_room_size() -> f32 {
+    0.5
+}
+
+fn default_reverb_damping() -> f32 {
+    0.5
+}
+
+fn default_reverb_wet() -> f32 {
+    0.3
+}
+
+fn default_preview_server_bind() -> String {
+    "127.0.0.1:8765".to_string()
+}
+
+/// Expand a leading `~` or `~/` to the user's home directory.
+pub fn expand_tilde(path: &str) -> PathBuf {
+    if (path == "~" || path.starts_with("~/"))
+        && let Ok(home) = std::env::var("HOME")
+    {
+        return PathBuf::from(home).join(&path[2..]);
+    }
+    PathBuf::from(path)
+}
+
+/// `device` followed by `devices`, tilde-expanded, without repeats.
+fn resolve_devices(device: Option<String>, devices: Vec<String>) -> Vec<String> {
+    let mut resolved: Vec<String> = Vec::new();
+    for path in device.into_iter().chain(devices) {
+        let path = expand_tilde(&path).to_string_lossy().into_owned();
+        if !resolved.contains(&path) {
+            resolved.push(path);
+        }
+    }
+    resolved
+}
+
+/// System-wide config that a per-user config is merged over.
+pub const SYSTEM_CONFIG_PATH: &str = "/etc/keyboard-drums/config.toml";
+
+/// The annotated example config, written by `--init-config`.
+const EXAMPLE_CONFIG: &str = include_str!("../config.example.toml");
+
+/// Write the example config to `path` for a first run, creating its
+/// directory. Refuses to overwrite an existing file.
+pub fn write_example_config(path: &Path) -> Result<()> {
+    if ConfigFormat::of(path) != ConfigFormat::Toml {
+        bail!(
+            "The example config is TOML; write it to a .toml path, e.g. with --config {}",
+            path.with_extension("toml").display()
+        );
+    }
+    if path.exists() {
+        bail!(
+            "{} already exists; remove it first to write a fresh example config",
+            path.display()
+        );
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    std::fs::write(path, EXAMPLE_CONFIG)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Range of accepted `output_sample_rate` values, in Hz.
+const MIN_OUTPUT_SAMPLE_RATE: u32 = 8_000;
+const MAX_OUTPUT_SAMPLE_RATE: u32 = 192_000;
+
+/// Name of the passthrough virtual device unless configured otherwise.
+const DEFAULT_VIRTUAL_DEVICE_NAME: &str = "keyboard-drums passthrough";
+
+/// Capture length unless configured otherwise, and the longest accepted,
+/// in seconds. The whole capture is kept in memory.
+const DEFAULT_CAPTURE_SECONDS: f64 = 10.0;
+const MAX_CAPTURE_SECONDS: f64 = 120.0;
+
+/// Directory captures are written to unless configured otherwise.
+const DEFAULT_CAPTURE_DIR: &str = "~/keyboard-drums-captures";
+
+/// Quietest level accepted in decibels; anything at or below it is silent.
+const MIN_GAIN_DB: f32 = -96.0;
+
+/// Metronome tempo unless configured otherwise, and the accepted range,
+/// in beats per minute.
+const DEFAULT_METRONOME_TEMPO: f64 = 120.0;
+const MIN_METRONOME_TEMPO: f64 = 20.0;
+const MAX_METRONOME_TEMPO: f64 = 400.0;
+
+/// Metronome beats per bar and click level unless configured otherwise.
+const DEFAULT_BEATS_PER_BAR: u32 = 4;
+const DEFAULT_METRONOME_VOLUME: f32 = 0.5;
+
+/// File `remember_state` keeps its state in unless configured otherwise.
+const DEFAULT_STATE_FILE: &str = "~/.local/state/keyboard-drums/state.toml";
+
+/// Friendly key names that don't match an evdev name once uppercased and
+/// prefixed with `KEY_`.
+const KEY_ALIASES: &[(&str, &str)] = &[
+    ("RETURN", "KEY_ENTER"),
+    ("ESCAPE", "KEY_ESC"),
+    ("DEL", "KEY_DELETE"),
+    ("PGUP", "KEY_PAGEUP"),
+    ("PGDN", "KEY_PAGEDOWN"),
+    ("CTRL", "KEY_LEFTCTRL"),
+    ("SHIFT", "KEY_LEFTSHIFT"),
+    ("ALT", "KEY_LEFTALT"),
+    (";", "KEY_SEMICOLON"),
+    ("'", "KEY_APOSTROPHE"),
+    (",", "KEY_COMMA"),
+    (".", "KEY_DOT"),
+    ("/", "KEY_SLASH"),
+    ("\\", "KEY_BACKSLASH"),
+    ("[", "KEY_LEFTBRACE"),
+    ("]", "KEY_RIGHTBRACE"),
+    ("-", "KEY_MINUS"),
+    ("=", "KEY_EQUAL"),
+    ("`", "KEY_GRAVE"),
+];
+
+/// Parse a key name leniently: case-insensitive, with the `KEY_` prefix
+/// optional and a few aliases, so `a`, `A`, `key_a` and `KEY_A` all work.
+fn parse_key_name(name: &str) -> Option<KeyCode> {
+    let upper = name.trim().to_ascii_uppercase();
+    let normalized = match KEY_ALIASES.iter().find(|(alias, _)| *alias == upper) {
+        Some((_, evdev_name)) => evdev_name.to_string(),
+        None if upper.starts_with("KEY_") => upper,
+        None => format!("KEY_{}", upper),
+    };
+    KeyCode::from_str(&normalized).ok()
+}
+
+/// Resolve an optional evdev key name string to a key code.
+fn resolve_optional_key(name: &Option<String>, field: &str) -> Result<Option<u16>> {
+    match name {
+        None => Ok(None),
+        Some(key_name) => {
+            let key_code = parse_key_name(key_name).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Unknown evdev key name for {}: '{}'. Use names like KEY_A, KEY_SPACE, etc.",
+                    field,
+                    key_name,
+                )
+            })?;
+            Ok(Some(key_code.code()))
+        }
+    }
+}
+
+/// Validate an expression axis config and resolve its axis name.
+fn resolve_expression(expression: &ExpressionConfig) -> Result<ResolvedExpression> {
+    let axis = AbsoluteAxisCode::from_str(&expression.axis).map_err(|_| {
+        anyhow::anyhow!(
+            "Unknown evdev axis name for expression: '{}'. Use names like ABS_Z, ABS_THROTTLE, etc.",
+            expression.axis,
+        )
+    })?;
+
+    if expression.min == expression.max {
+        bail!(
+            "Expression axis range is empty: min and max are both {}",
+            expression.min,
+        );
+    }
+
+    Ok(ResolvedExpression {
+        axis: axis.0,
+        min: expression.min,
+        max: expression.max,
+    })
+}
+
+/// Validate a key-travel config and resolve its axis name and timings.
+fn resolve_key_travel(key_travel: &KeyTravelConfig) -> Result<ResolvedKeyTravel> {
+    let axis = AbsoluteAxisCode::from_str(&key_travel.axis).map_err(|_| {
+        anyhow::anyhow!(
+            "Unknown evdev axis name for key_travel: '{}'. Use names like ABS_MISC, ABS_Z, etc.",
+            key_travel.axis,
+        )
+    })?;
+
+    if key_travel.rest == key_travel.bottom {
+        bail!(
+            "Key travel axis range is empty: rest and bottom are both {}",
+            key_travel.rest,
+        );
+    }
+
+    let (fast_ms, slow_ms) = (key_travel.fast_ms, key_travel.slow_ms);
+    if !(fast_ms >= 0.0 && fast_ms.is_finite() && slow_ms.is_finite() && fast_ms < slow_ms) {
+        bail!(
+            "key_travel fast_ms must be at least 0 and less than slow_ms, got fast_ms={} slow_ms={}",
+            fast_ms,
+            slow_ms,
+        );
+    }
+
+    Ok(ResolvedKeyTravel {
+        axis: axis.0,
+        rest: key_travel.rest,
+        bottom: key_travel.bottom,
+        fast: Duration::from_secs_f64(fast_ms / 1000.0),
+        slow: Duration::from_secs_f64(slow_ms / 1000.0),
+    })
+}
+
+/// Resolve the key names of a `[[momentary]]` or `[[kit_select]]` list
+/// (`section`). Keys must be unique within it and not appear in `taken`.
+fn resolve_kit_keys(
+    entries: &[KitKeyConfig],
+    section: &str,
+    taken: &[ResolvedKitKey],
+) -> Result<Vec<ResolvedKitKey>> {
+    let mut resolved: Vec<ResolvedKitKey> = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let key = parse_key_name(&entry.key).ok_or_else(|| {
+            anyhow::anyhow!(
+                "Unknown evdev key name in [[{}]]: '{}'. Use names like KEY_A, KEY_SPACE, etc.",
+                section,
+                entry.key,
+            )
+        })?;
+        if resolved.iter().chain(taken).any(|k| k.key == key.code()) {
+            bail!(
+                "Key {} is used by more than one [[momentary]] or [[kit_select]] entry",
+                entry.key
+            );
+        }
+        resolved.push(ResolvedKitKey {
+            key: key.code(),
+            kit: entry.kit.clone(),
+            variant: entry.variant.clone(),
+        });
+    }
+    Ok(resolved)
+}
+
+/// Resolve the key names and samples of a `[[mute]]` or `[[solo]]` list
+/// (`section`). Keys must be unique within it and not appear in `taken`;
+/// samples must be bound somewhere.
+fn resolve_sample_keys(
+    entries: &[SampleKeyConfig],
+    section: &str,
+    sample_names: &[String],
+    taken: &[ResolvedSampleKey],
+) -> Result<Vec<ResolvedSampleKey>> {
+    let mut resolved: Vec<ResolvedSampleKey> = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let key = parse_key_name(&entry.key).ok_or_else(|| {
+            anyhow::anyhow!(
+                "Unknown evdev key name in [[{}]]: '{}'. Use names like KEY_A, KEY_SPACE, etc.",
+                section,
+                entry.key,
+            )
+        })?;
+        if resolved.iter().chain(taken).any(|k| k.key == key.code()) {
+            bail!(
+                "Key {} is used by more than one [[mute]] or [[solo]] entry",
+                entry.key
+            );
+        }
+        let Some(sample_index) = sample_names.iter().position(|name| *name == entry.sample) else {
+            bail!(
+                "[[{}]] sample '{}' is not bound to any key",
+                section,
+                entry.sample
+            );
+        };
+        resolved.push(ResolvedSampleKey {
+            key: key.code(),
+            sample: entry.sample.clone(),
+            sample_index,
+        });
+    }
+    Ok(resolved)
+}
+
+/// Validate the capture length and expand the capture directory.
+fn resolve_capture(config: &CaptureConfig) -> Result<ResolvedCapture> {
+    let seconds = config.seconds.unwrap_or(DEFAULT_CAPTURE_SECONDS);
+    if !(seconds > 0.0 && seconds <= MAX_CAPTURE_SECONDS) {
+        bail!(
+            "capture.seconds must be greater than 0 and at most {}, got {}",
+            MAX_CAPTURE_SECONDS,
+            seconds
+        );
+    }
+    let dir = config.dir.as_deref().unwrap_or(DEFAULT_CAPTURE_DIR);
+    Ok(ResolvedCapture {
+        length: Duration::from_secs_f64(seconds),
+        dir: expand_tilde(dir),
+    })
+}
+
+/// Convert a level in decibels to a linear gain. Levels at or below
+/// `MIN_GAIN_DB` are silence.
+fn db_to_gain(db: f32) -> f32 {
+    if db <= MIN_GAIN_DB {
+        0.0
+    } else {
+        10f32.powf(db / 20.0)
+    }
+}
+
+/// The linear gain set by either `field` (`linear`) or `<field>_db` (`db`),
+/// or `default` if neither is. Setting both is an error.
+fn resolve_gain(linear: Option<f32>, db: Option<f32>, field: &str, default: f32) -> Result<f32> {
+    match (linear, db) {
+        (Some(_), Some(_)) => bail!("{} and {}_db are both set; use one", field, field),
+        (Some(gain), None) => Ok(gain),
+        (None, Some(db)) => Ok(db_to_gain(db)),
+        (None, None) => Ok(default),
+    }
+}
+
+/// Check that a metronome tempo, in beats per minute, is in range.
+pub fn check_metronome_tempo(tempo: f64) -> Result<()> {
+    if !(MIN_METRONOME_TEMPO..=MAX_METRONOME_TEMPO).contains(&tempo) {
+        bail!(
+            "Metronome tempo must be from {} to {} BPM, got {}",
+            MIN_METRONOME_TEMPO,
+            MAX_METRONOME_TEMPO,
+            tempo
+        );
+    }
+    Ok(())
+}
+
+/// Validate the metronome settings and read the beats per bar from its
+/// time signature.
+fn resolve_metronome(config: &MetronomeConfig) -> Result<ResolvedMetronome> {
+    let tempo = config.tempo.unwrap_or(DEFAULT_METRONOME_TEMPO);
+    check_metronome_tempo(tempo)?;
+
+    let beats_per_bar = match config.time_signature.as_deref() {
+        None => DEFAULT_BEATS_PER_BAR,
+        Some(signature) => parse_time_signature(signature)
+            .with_context(|| format!("Invalid metronome time_signature '{}'", signature))?,
+    };
+
+    let volume = config.volume.unwrap_or(DEFAULT_METRONOME_VOLUME);
+    if !(0.0..=1.0).contains(&volume) {
+        bail!("Metronome volume must be from 0.0 to 1.0, got {}", volume);
+    }
+
+    Ok(ResolvedMetronome {
+        tempo,
+        beats_per_bar,
+        volume,
+        enabled: config.enabled.unwrap_or(true),
+    })
+}
+
+/// Beats per bar of a time signature such as "3/4" or "6/8".
+fn parse_time_signature(signature: &str) -> Result<u32> {
+    let (beats, unit) = signature
+        .split_once('/')
+        .context("expected e.g. \"4/4\" or \"6/8\"")?;
+    let beats: u32 = beats
+        .trim()
+        .parse()
+        .context("beats per bar must be a whole number")?;
+    let unit: u32 = unit
+        .trim()
+        .parse()
+        .context("the note value must be a whole number")?;
+    if !(1..=32).contains(&beats) {
+        bail!("beats per bar must be from 1 to 32, got {}", beats);
+    }
+    if !(unit.is_power_of_two() && unit <= 32) {
+        bail!("the note value must be 1, 2, 4, 8, 16 or 32, got {}", unit);
+    }
+    Ok(beats)
+}
+
+/// Validate a MIDI config and resolve its note numbers and key names.
+fn resolve_midi(config: &MidiConfig) -> Result<ResolvedMidi> {
+    let mut note_keys = HashMap::new();
+    for (note, key) in &config.notes {
+        let note_number = match note.parse::<u8>() {
+            Ok(n) if n <= 127 => n,
+            _ => bail!(
+                "Invalid MIDI note '{}': expected a number from 0 to 127",
+                note
+            ),
+        };
+        let key_code = parse_key_name(key).ok_or_else(|| {
+            anyhow::anyhow!(
+                "Unknown evdev key name for MIDI note {}: '{}'. Use names like KEY_A, KEY_SPACE, etc.",
+                note,
+                key
+            )
+        })?;
+        note_keys.insert(note_number, key_code.code());
+    }
+
+    Ok(ResolvedMidi {
+        port: config.port.clone(),
+        note_keys,
+    })
+}
+
+/// Apply the individual buffer options over the `latency_mode` preset.
+fn resolve_latency(config: &Config) -> Result<LatencySettings> {
+    let preset = config.latency_mode.settings();
+    if config.min_buffer_frames == Some(0) || config.buffer_frames == Some(0) {
+        bail!("min_buffer_frames and buffer_frames must be at least 1");
+    }
+
+    let settings = LatencySettings {
+        min_buffer_frames: config.min_buffer_frames.unwrap_or(preset.min_buffer_frames),
+        buffer_frames: config.buffer_frames.or(preset.buffer_frames),
+        warmup: config
+            .warmup_ms
+            .map_or(preset.warmup, Duration::from_millis),
+    };
+    if let Some(frames) = settings.buffer_frames
+        && frames < settings.min_buffer_frames
+    {
+        log::warn!(
+            "buffer_frames ({}) is below min_buffer_frames ({}); {} frames will be requested",
+            frames,
+            settings.min_buffer_frames,
+            settings.min_buffer_frames
+        );
+    }
+    Ok(settings)
+}
+
+/// Validate a virtual device config and resolve its bus type name.
+fn resolve_virtual_device(config: &VirtualDeviceConfig) -> Result<ResolvedVirtualDevice> {
+    let bus_type = config
+        .bus_type
+        .as_ref()
+        .map(|name| {
+            BusType::from_str(name).map_err(|_| {
+                anyhow::anyhow!(
+                    "Unknown bus type for virtual_device: '{}'. Use names like BUS_USB, BUS_I8042, etc.",
+                    name,
+                )
+            })
+        })
+        .transpose()?;
+
+    Ok(ResolvedVirtualDevice {
+        name: config
+            .name
+            .clone()
+            .unwrap_or_else(|| DEFAULT_VIRTUAL_DEVICE_NAME.to_string()),
+        bus_type: bus_type.map(|b| b.0),
+        vendor: config.vendor,
+        product: config.product,
+        version: config.version,
+    })
+}
+
+/// Syntax of a config file, chosen by its extension.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ConfigFormat {
+    Toml,
+    Json,
+    Yaml,
+}
+
+impl ConfigFormat {
+    /// `.json` is JSON and `.yaml` or `.yml` YAML; anything else is TOML.
+    fn of(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => ConfigFormat::Json,
+            Some(ext) if ext.eq_ignore_ascii_case("yaml") || ext.eq_ignore_ascii_case("yml") => {
+                ConfigFormat::Yaml
+            }
+            _ => ConfigFormat::Toml,
+        }
+    }
+}
+
+/// Read a config file as a raw TOML table, before merging and validation.
+/// JSON and YAML files are read into the same table, so merging, profiles
+/// and validation work the same for every format.
+fn read_config_table(path: &Path) -> Result<toml::Table> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+
+    let table = match ConfigFormat::of(path) {
+        ConfigFormat::Toml => toml::from_str(&content).map_err(anyhow::Error::from),
+        ConfigFormat::Json => serde_json::from_str(&content)
+            .map_err(anyhow::Error::from)
+            .and_then(value_to_table),
+        ConfigFormat::Yaml => serde_norway::from_str(&content)
+            .map_err(anyhow::Error::from)
+            .and_then(value_to_table),
+    };
+    table.with_context(|| format!("Failed to parse config file: {}", path.display()))
+}
+
+/// Convert a parsed JSON or YAML document to a TOML table. TOML has no
+/// null, so a field set to null is left out, as if it were not set; a null
+/// inside a list is an error.
+fn value_to_table(mut value: serde_json::Value) -> Result<toml::Table> {
+    strip_nulls(&mut value);
+    Ok(serde_json::from_value(value)?)
+}
+
+/// Remove the null fields of every object in `value`.
+fn strip_nulls(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(fields) => {
+            fields.retain(|_, field| !field.is_null());
+            fields.values_mut().for_each(strip_nulls);
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(strip_nulls),
+        _ => {}
+    }
+}
+
+/// Recursively overlay `overlay` onto `base`: nested tables merge key by
+/// key, any other value in `overlay` replaces the one in `base`.
+fn merge_tables(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(overlay_table)) => {
+                merge_tables(base_table, overlay_table);
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Merge a per-user config over the system config. The user's fields
+/// override the system's; `bindings` are replaced or appended according to
+/// the user's `bindings_merge`.
+fn merge_config_tables(mut system: toml::Table, mut user: toml::Table) -> Result<toml::Table> {
+    let bindings_merge: BindingsMerge = match user.remove("bindings_merge") {
+        Some(value) => value.try_into().context("Invalid bindings_merge")?,
+        None => BindingsMerge::default(),
+    };
+
+    if bindings_merge == BindingsMerge::Append
+        && let Some(toml::Value::Array(user_bindings)) = user.remove("bindings")
+    {
+        match system.get_mut("bindings") {
+            Some(toml::Value::Array(system_bindings)) => system_bindings.extend(user_bindings),
+            _ => {
+                system.insert("bindings".to_string(), toml::Value::Array(user_bindings));
+            }
+        }
+    }
+
+    // A volume given in one form replaces the other form underneath it,
+    // rather than clashing with it.
+    if user.contains_key("master_volume") {
+        system.remove("master_volume_db");
+    }
+    if user.contains_key("master_volume_db") {
+        system.remove("master_volume");
+    }
+
+    merge_tables(&mut system, user);
+    Ok(system)
+}
+
+/// Merge the `[profiles.<name>]` table selected by `profile` over the rest
+/// of the config, the same way a per-user config merges over the system
+/// config. The `profiles` section is dropped whether or not one is selected.
+fn apply_profile(mut table: toml::Table, profile: Option<&str>) -> Result<toml::Table> {
+    let profiles = match table.remove("profiles") {
+        Some(toml::Value::Table(profiles)) => profiles,
+        Some(_) => bail!("profiles must be a table of [profiles.<name>] sections"),
+        None => toml::Table::new(),
+    };
+    let Some(name) = profile else {
+        return Ok(table);
+    };
+
+    match profiles.get(name) {
+        Some(toml::Value::Table(selected)) => {
+            log::info!("Using profile '{}'", name);
+            merge_config_tables(table, selected.clone())
+        }
+        Some(_) => bail!("Profile '{}' must be a [profiles.{}] table", name, name),
+        None if profiles.is_empty() => {
+            bail!("Unknown profile '{}': the config defines no profiles", name)
+        }
+        None => bail!(
+            "Unknown profile '{}'. Available profiles: {}",
+            name,
+            profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+        ),
+    }
+}
+
+/// Load and validate configuration from a config file, merged over the
+/// system-wide config at `system_path` if that file exists, with the named
+/// `profile` (if any) merged over both. Each file is read as TOML, JSON or
+/// YAML by its extension (see `ConfigFormat`).
+///
+/// When only the system config exists it is used on its own.
+pub fn load_config(
+    path: &Path,
+    system_path: &Path,
+    profile: Option<&str>,
+) -> Result<ResolvedConfig> {
+    if !path.exists() && !system_path.is_file() {
+        bail!(
+            "No config file found at {}.\n\
+             To get started:\n  \
+             1. keyboard-drums --init-config    writes an annotated example config there\n  \
+             2. keyboard-drums --list-devices   shows your keyboard's device path\n  \
+             3. set device, samples_dir and bindings in the config, then run keyboard-drums\n\
+             (Pass the same --config to --init-config if you use a custom path.)",
+            path.display()
+        );
+    }
+
+    let table = if system_path.is_file() {
+        log::info!("Loading system config from: {}", system_path.display());
+        let system = read_config_table(system_path)?;
+        if path.exists() {
+            merge_config_tables(system, read_config_table(path)?)?
+        } else {
+            log::info!("No config at {}, using system config only", path.display());
+            system
+        }
+    } else {
+        read_config_table(path)?
+    };
+    let table = apply_profile(table, profile)?;
+
+    let config: Config = table
+        .try_into()
+        .with_context(|| format!("Failed to parse config file: {}", path.display()))?;
+
+    resolve_config(config)
+}
+
+/// Resolve a list of keybindings to key codes and sample indices.
+///
+/// New sample filenames are appended to `sample_names`; filenames already
+/// present (from earlier bindings or banks) reuse their existing index.
+fn resolve_bindings(
+    bindings: &[BindingConfig],
+    sample_rate: u32,
+    sample_names: &mut Vec<String>,
+    sample_name_to_index: &mut HashMap<String, usize>,
+) -> Result<HashMap<u16, ResolvedBinding>> {
+    let mut key_map: HashMap<u16, ResolvedBinding> = HashMap::new();
+
+    for binding in bindings {
+        // Resolve evdev key name to key code.
+        let key_code = parse_key_name(&binding.key).ok_or_else(|| {
+            anyhow::anyhow!(
+                "Unknown evdev key name: '{}'. Use names like KEY_A, KEY_SPACE, etc.",
+                binding.key
+            )
+        })?;
+
+        match (&binding.sample, binding.samples.is_empty()) {
+            (Some(_), false) => bail!(
+                "Binding for key {} sets both sample and samples; use one",
+                binding.key
+            ),
+            (None, true) => bail!("Binding for key {} has no sample or samples", binding.key),
+            _ => {}
+        }
+        if binding.sample_names().is_empty() {
+            bail!("Binding for key {} has an empty sample list", binding.key);
+        }
+        if !binding.samples.is_empty() && binding.mode == BindingMode::ToggleLoop {
+            bail!(
+                "Binding for key {} plays several samples at once, which toggle_loop does not support",
+                binding.key
+            );
+        }
+        if let Some(loop_end) = binding.loop_end
+            && loop_end <= binding.loop_start
+        {
+            bail!(
+                "Binding for key {} has loop_end {} at or before loop_start {}",
+                binding.key,
+                loop_end,
+                binding.loop_start
+            );
+        }
+        if binding.looping && !binding.gate && binding.choke_group == 0 {
+            log::warn!(
+                "Binding for key {} loops without gate or choke_group; only the panic key stops it",
+                binding.key
+            );
+        }
+        if binding.gate && binding.mode == BindingMode::ToggleLoop {
+            bail!(
+                "Binding for key {} sets gate with toggle_loop; a toggle loop is stopped by its next press",
+                binding.key
+            );
+        }
+
+        let layer_velocities = match &binding.sample {
+            Some(SampleNames::Layers(layers)) => resolve_layer_velocities(&binding.key, layers)?,
+            _ => Vec::new(),
+        };
+
+        let stack_gains = binding
+            .samples
+            .iter()
+            .map(|stacked| {
+                let gain = stacked.gain.clamp(0.0, 1.0);
+                if (gain - stacked.gain).abs() > f32::EPSILON {
+                    log::warn!(
+                        "gain of {} for key {} clamped from {} to {}",
+                        stacked.sample,
+                        binding.key,
+                        stacked.gain,
+                        gain
+                    );
+                }
+                gain
+            })
+            .collect();
+
+        // Get or create sample indices by filename.
+        let sample_indices = binding
+            .sample_names()
+            .into_iter()
+            .map(|name| {
+                if let Some(&idx) = sample_name_to_index.get(name) {
+                    idx
+                } else {
+                    let idx = sample_names.len();
+                    sample_names.push(name.clone());
+                    sample_name_to_index.insert(name.clone(), idx);
+                    idx
+                }
+            })
+            .collect();
+
+        let requested_gain = resolve_gain(binding.gain, binding.gain_db, "gain", 1.0)
+            .with_context(|| format!("Binding for key {}", binding.key))?;
+        let gain = requested_gain.clamp(0.0, 1.0);
+        if (gain - requested_gain).abs() > f32::EPSILON {
+            log::warn!(
+                "gain for key {} clamped from {} to {}",
+                binding.key,
+                requested_gain,
+                gain
+            );
+        }
+
+        let reverb_send = binding.reverb_send.clamp(0.0, 1.0);
+        if (reverb_send - binding.reverb_send).abs() > f32::EPSILON {
+            log::warn!(
+                "reverb_send for key {} clamped from {} to {}",
+                binding.key,
+                binding.reverb_send,
+                reverb_send
+            );
+        }
+
+        let min_gain = binding.min_gain.clamp(0.0, 1.0);
+        if (min_gain - binding.min_gain).abs() > f32::EPSILON {
+            log::warn!(
+                "min_gain for key {} clamped from {} to {}",
+                binding.key,
+                binding.min_gain,
+                min_gain
+            );
+        }
+
+        let pan = binding.pan.clamp(-1.0, 1.0);
+        if (pan - binding.pan).abs() > f32::EPSILON {
+            log::warn!(
+                "pan for key {} clamped from {} to {}",
+                binding.key,
+                binding.pan,
+                pan
+            );
+        }
+
+        let pitch = binding.pitch.clamp(-24.0, 24.0);
+        if (pitch - binding.pitch).abs() > f32::EPSILON {
+            log::warn!(
+                "pitch for key {} clamped from {} to {}",
+                binding.key,
+                binding.pitch,
+                pitch
+            );
+        }
+        let playback_rate = 2f32.powf(pitch / 12.0);
+
+        let start_region = binding
+            .start_region
+            .map(|region| resolve_start_region(&binding.key, region, sample_rate))
+            .transpose()?;
+        let vel_attack = binding
+            .vel_attack
+            .map(|attack| resolve_vel_attack(&binding.key, attack, sample_rate))
+            .transpose()?;
+        let filter = binding
+            .filter
+            .map(|filter| resolve_filter(&binding.key, filter, sample_rate))
+            .transpose()?;
+
+        let code = key_code.code();
+        if key_map.contains_key(&code) {
+            log::warn!(
+                "Duplicate keybinding for {}: overwriting previous binding",
+                binding.key
+            );
+        }
+
+        key_map.insert(
+            code,
+            ResolvedBinding {
+                sample_indices,
+                layer_velocities,
+                stack_gains,
+                gain,
+                reverb_send,
+                min_gain,
+                pan,
+                playback_rate,
+                protect_from_steal: binding.protect_from_steal,
+                reserved_voices: binding.reserved_voices,
+                mode: binding.mode,
+                gate: binding.gate,
+                looping: binding.looping,
+                loop_start: binding.loop_start,
+                loop_end: binding.loop_end,
+                choke_group: binding.choke_group,
+                velocity_mode: binding.velocity_mode,
+                start_region,
+                vel_attack,
+                ignore_sample_rate: binding.ignore_sample_rate,
+                bypass_master_fx: binding.bypass_master_fx,
+                filter,
+            },
+        );
+    }
+
+    Ok(key_map)
+}
+
+/// Validate a binding's velocity layers and return their lowest velocities.
+fn resolve_layer_velocities(key: &str, layers: &[VelocityLayerConfig]) -> Result<Vec<f32>> {
+    let velocities: Vec<f32> = layers.iter().map(|layer| layer.min_velocity).collect();
+    if let Some(velocity) = velocities.iter().find(|v| !(0.0..=1.0).contains(*v)) {
+        bail!(
+            "Velocity layer for key {} has min_velocity {}, expected 0.0 to 1.0",
+            key,
+            velocity
+        );
+    }
+    if velocities.windows(2).any(|pair| pair[0] >= pair[1]) {
+        bail!(
+            "Velocity layers for key {} must be listed softest first, with increasing min_velocity",
+            key
+        );
+    }
+    Ok(velocities)
+}
+
+/// Validate a binding's start region and convert it to frames at
+/// `sample_rate`.
+fn resolve_start_region(
+    key: &str,
+    region: StartRegionConfig,
+    sample_rate: u32,
+) -> Result<(usize, usize)> {
+    let (from_ms, to_ms) = (region.from_ms, region.to_ms);
+    if !(from_ms >= 0.0 && to_ms.is_finite() && from_ms <= to_ms) {
+        bail!(
+            "start_region for key {} must have 0 <= from_ms <= to_ms, got from_ms={} to_ms={}",
+            key,
+            from_ms,
+            to_ms,
+        );
+    }
+    let to_frames = |ms: f64| (ms * sample_rate as f64 / 1000.0).round() as usize;
+    Ok((to_frames(from_ms), to_frames(to_ms)))
+}
+
+/// Validate a binding's velocity attack and convert it to frames at
+/// `sample_rate`.
+fn resolve_vel_attack(
+    key: &str,
+    attack: VelAttackConfig,
+    sample_rate: u32,
+) -> Result<(usize, usize)> {
+    let (soft_ms, hard_ms) = (attack.soft_ms, attack.hard_ms);
+    if !(soft_ms >= 0.0 && hard_ms >= 0.0 && soft_ms.is_finite() && hard_ms.is_finite()) {
+        bail!(
+            "vel_attack for key {} must have soft_ms and hard_ms of 0 or more, got soft_ms={} hard_ms={}",
+            key,
+            soft_ms,
+            hard_ms,
+        );
+    }
+    if hard_ms > soft_ms {
+        log::warn!(
+            "vel_attack for key {}: hard_ms ({}) is longer than soft_ms ({}), so harder hits attack more slowly",
+            key,
+            hard_ms,
+            soft_ms,
+        );
+    }
+    let to_frames = |ms: f64| (ms * sample_rate as f64 / 1000.0).round() as usize;
+    Ok((to_frames(soft_ms), to_frames(hard_ms)))
+}
+
+/// Validate a binding's filter and compute its coefficients at
+/// `sample_rate`.
+fn resolve_filter(key: &str, filter: FilterConfig, sample_rate: u32) -> Result<OnePole> {
+    let nyquist = sample_rate as f32 / 2.0;
+    if !(filter.cutoff_hz > 0.0 && filter.cutoff_hz < nyquist) {
+        bail!(
+            "filter for key {} must have cutoff_hz above 0 and below {} (half the output sample rate), got {}",
+            key,
+            nyquist,
+            filter.cutoff_hz,
+        );
+    }
+    Ok(OnePole::new(filter.kind, filter.cutoff_hz, sample_rate))
+}
+
+/// Validate raw config and resolve key names to key codes.
+fn resolve_config(config: Config) -> Result<ResolvedConfig> {
+    let requested_volume = resolve_gain(
+        config.master_volume,
+        config.master_volume_db,
+        "master_volume",
+        default_master_volume(),
+    )?;
+    let master_volume = requested_volume.clamp(0.0, 1.0);
+    if (master_volume - requested_volume).abs() > f32::EPSILON {
+        log::warn!(
+            "master_volume {} clamped to {}",
+            requested_volume,
+            master_volume
+        );
+    }
+
+    let max_voices = if config.max_voices == 0 {
+        log::warn!("max_voices was 0, defaulting to 32");
+        32
+    } else {
+        config.max_voices
+    };
+
+    let samples_dir = expand_tilde(&config.samples_dir);
+    if !samples_dir.is_dir() {
+        bail!(
+            "samples_dir does not exist or is not a directory: {}",
+            samples_dir.display()
+        );
+    }
+
+    if config
+        .audio_device
+        .as_deref()
+        .is_some_and(|name| name.trim().is_empty())
+    {
+        bail!("audio_device must not be empty; leave it out to use the default output device");
+    }
+    if !(MIN_OUTPUT_SAMPLE_RATE..=MAX_OUTPUT_SAMPLE_RATE).contains(&config.output_sample_rate) {
+        bail!(
+            "output_sample_rate must be between {} and {}Hz, got {}",
+            MIN_OUTPUT_SAMPLE_RATE,
+            MAX_OUTPUT_SAMPLE_RATE,
+            config.output_sample_rate,
+        );
+    }
+    let trim_silence = if config.trim_silence {
+        let db = config.trim_silence_threshold_db;
+        if !(-120.0..0.0).contains(&db) {
+            bail!(
+                "trim_silence_threshold_db must be below 0 and at least -120, got {}",
+                db
+            );
+        }
+        Some(SilenceTrim {
+            threshold: 10f32.powf(db / 20.0),
+            trailing: config.trim_trailing_silence,
+        })
+    } else {
+        if config.trim_trailing_silence {
+            log::warn!("trim_trailing_silence is set but trim_silence is not; it is ignored");
+        }
+        None
+    };
+
+    let normalize_peak = if config.normalize {
+        let db = config.normalize_peak_db;
+        if !(-60.0..=0.0).contains(&db) {
+            bail!("normalize_peak_db must be from -60 to 0, got {}", db);
+        }
+        Some(10f32.powf(db / 20.0))
+    } else {
+        None
+    };
+
+    // Deduplicate sample names and build index map.
+    // Multiple bindings can reference the same sample — we only load it once.
+    let mut sample_names: Vec<String> = Vec::new();
+    let mut sample_name_to_index: HashMap<String, usize> = HashMap::new();
+
+    if config.bindings.is_empty() {
+        bail!("No keybindings defined in config");
+    }
+
+    let key_map = resolve_bindings(
+        &config.bindings,
+        config.output_sample_rate,
+        &mut sample_names,
+        &mut sample_name_to_index,
+    )?;
+
+    // Additional binding banks share the same sample list.
+    let mut banks = Vec::with_capacity(config.banks.len());
+    for bank in &config.banks {
+        if bank.bindings.is_empty() {
+            bail!("Binding bank '{}' has no bindings", bank.name);
+        }
+        banks.push(ResolvedBank {
+            name: bank.name.clone(),
+            key_map: resolve_bindings(
+                &bank.bindings,
+                config.output_sample_rate,
+                &mut sample_names,
+                &mut sample_name_to_index,
+            )?,
+        });
+    }
+
+    // Synth sounds are rendered when a kit loads; reject bad specs now.
+    for name in sample_names.iter().filter(|name| synth::is_synth(name)) {
+        synth::parse_synth_spec(name)?;
+    }
+
+    // Sample indices travel to the audio thread as a u16 in each Trigger.
+    if sample_names.len() > u16::MAX as usize + 1 {
+        bail!(
+            "Too many unique samples: {} (maximum is {})",
+            sample_names.len(),
+            u16::MAX as usize + 1,
+        );
+    }
+
+    // Resolve cycling keybindings.
+    let cycling_keys = ResolvedCyclingKeys {
+        next_kit: resolve_optional_key(&config.cycling_keys.next_kit, "next_kit")?,
+        prev_kit: resolve_optional_key(&config.cycling_keys.prev_kit, "prev_kit")?,
+        next_variant: resolve_optional_key(&config.cycling_keys.next_variant, "next_variant")?,
+        prev_variant: resolve_optional_key(&config.cycling_keys.prev_variant, "prev_variant")?,
+        toggle_auto_advance: resolve_optional_key(
+            &config.cycling_keys.toggle_auto_advance,
+            "toggle_auto_advance",
+        )?,
+        next_bank: resolve_optional_key(&config.cycling_keys.next_bank, "next_bank")?,
+        prev_bank: resolve_optional_key(&config.cycling_keys.prev_bank, "prev_bank")?,
+        panic: resolve_optional_key(&config.cycling_keys.panic, "panic")?,
+        audition: resolve_optional_key(&config.cycling_keys.audition, "audition")?,
+        capture: resolve_optional_key(&config.cycling_keys.capture, "capture")?,
+        toggle_metronome: resolve_optional_key(
+            &config.cycling_keys.toggle_metronome,
+            "toggle_metronome",
+        )?,
+    };
+
+    let audition = match (cycling_keys.audition, &config.audition) {
+        (Some(_), Some(audition)) => Some(audition.clone()),
+        (Some(_), None) => {
+            bail!("The audition cycling key needs an [audition] table naming the kit to play")
+        }
+        (None, Some(_)) => {
+            log::warn!("[audition] is set but cycling_keys.audition is not; it is ignored");
+            None
+        }
+        (None, None) => None,
+    };
+
+    let capture = match cycling_keys.capture {
+        Some(_) => Some(resolve_capture(&config.capture)?),
+        None => {
+            if config.capture.seconds.is_some() || config.capture.dir.is_some() {
+                log::warn!("[capture] is set but cycling_keys.capture is not; it is ignored");
+            }
+            None
+        }
+    };
+
+    let metronome = match (&config.metronome, cycling_keys.toggle_metronome) {
+        (Some(metronome), toggle) => {
+            let metronome = resolve_metronome(metronome)?;
+            if !metronome.enabled && toggle.is_none() {
+                log::warn!(
+                    "[metronome] is disabled and cycling_keys.toggle_metronome is not set; it never plays"
+                );
+            }
+            Some(metronome)
+        }
+        // The toggle key alone gives a default metronome, off at startup.
+        (None, Some(_)) => Some(ResolvedMetronome {
+            enabled: false,
+            ..ResolvedMetronome::default()
+        }),
+        (None, None) => None,
+    };
+
+    let state_file = if config.remember_state {
+        Some(expand_tilde(
+            config.state_file.as_deref().unwrap_or(DEFAULT_STATE_FILE),
+        ))
+    } else {
+        if config.state_file.is_some() {
+            log::warn!("state_file is set but remember_state is not; it is ignored");
+        }
+        None
+    };
+
+    let momentary = resolve_kit_keys(&config.momentary, "momentary", &[])?;
+    let kit_select = resolve_kit_keys(&config.kit_select, "kit_select", &momentary)?;
+    let mute = resolve_sample_keys(&config.mute, "mute", &sample_names, &[])?;
+    let solo = resolve_sample_keys(&config.solo, "solo", &sample_names, &mute)?;
+
+    // Ensure cycling keys don't collide with sample bindings.
+    let cycling_codes: Vec<(u16, &str)> = [
+        (cycling_keys.next_kit, "next_kit"),
+        (cycling_keys.prev_kit, "prev_kit"),
+        (cycling_keys.next_variant, "next_variant"),
+        (cycling_keys.prev_variant, "prev_variant"),
+        (cycling_keys.toggle_auto_advance, "toggle_auto_advance"),
+        (cycling_keys.next_bank, "next_bank"),
+        (cycling_keys.prev_bank, "prev_bank"),
+        (cycling_keys.panic, "panic"),
+        (cycling_keys.audition, "audition"),
+        (cycling_keys.capture, "capture"),
+        (cycling_keys.toggle_metronome, "toggle_metronome"),
+    ]
+    .iter()
+    .filter_map(|(code, name)| code.map(|c| (c, *name)))
+    .collect();
+
+    for (code, name) in &cycling_codes {
+        if key_map.contains_key(code) || banks.iter().any(|b| b.key_map.contains_key(code)) {
+            bail!(
+                "Cycling key '{}' conflicts with a sample keybinding. \
+                 Use a different key for cycling.",
+                name,
+            );
+        }
+    }
+
+    let kit_keys = [("momentary", &momentary), ("kit_select", &kit_select)];
+    for (section, entries) in kit_keys {
+        for entry in entries {
+            let code = entry.key;
+            if key_map.contains_key(&code) || banks.iter().any(|b| b.key_map.contains_key(&code)) {
+                bail!(
+                    "[[{}]] key for kit '{}' conflicts with a sample keybinding",
+                    section,
+                    entry.kit,
+                );
+            }
+            if let Some((_, name)) = cycling_codes.iter().find(|(c, _)| *c == code) {
+                bail!(
+                    "[[{}]] key for kit '{}' is also the '{}' cycling key",
+                    section,
+                    entry.kit,
+                    name,
+                );
+            }
+        }
+    }
+
+    let sample_keys = [("mute", &mute), ("solo", &solo)];
+    for (section, entries) in sample_keys {
+        for entry in entries {
+            let code = entry.key;
+            if key_map.contains_key(&code) || banks.iter().any(|b| b.key_map.contains_key(&code)) {
+                bail!(
+                    "[[{}]] key for sample '{}' conflicts with a sample keybinding",
+                    section,
+                    entry.sample,
+                );
+            }
+            if let Some((_, name)) = cycling_codes.iter().find(|(c, _)| *c == code) {
+                bail!(
+                    "[[{}]] key for sample '{}' is also the '{}' cycling key",
+                    section,
+                    entry.sample,
+                    name,
+                );
+            }
+            if momentary.iter().chain(&kit_select).any(|k| k.key == code) {
+                bail!(
+                    "[[{}]] key for sample '{}' is also a [[momentary]] or [[kit_select]] key",
+                    section,
+                    entry.sample,
+                );
+            }
+        }
+    }
+
+    let expression = config
+        .expression
+        .as_ref()
+        .map(resolve_expression)
+        .transpose()?;
+
+    let key_travel = config
+        .key_travel
+        .as_ref()
+        .map(resolve_key_travel)
+        .transpose()?;
+
+    let volume_smoothing = match config.volume_smoothing_ms {
+        ms if ms >= 0.0 && ms.is_finite() => Duration::from_secs_f64(ms / 1000.0),
+        ms => bail!("volume_smoothing_ms must be 0 or more, got {}", ms),
+    };
+
+    let velocity_timing_window = match config.velocity_timing_window_ms {
+        ms if ms > 0.0 && ms.is_finite() => Duration::from_secs_f64(ms / 1000.0),
+        ms => bail!(
+            "velocity_timing_window_ms must be a positive number, got {}",
+            ms
+        ),
+    };
+
+    let auto_advance = match config.auto_advance_secs {
+        None => None,
+        Some(secs) if secs > 0.0 && secs.is_finite() => Some(Duration::from_secs_f64(secs)),
+        Some(secs) => bail!("auto_advance_secs must be a positive number, got {}", secs),
+    };
+    if cycling_keys.toggle_auto_advance.is_some() && auto_advance.is_none() {
+        log::warn!(
+            "toggle_auto_advance key is set but auto_advance_secs is not; the key does nothing"
+        );
+    }
+
+    let watchdog_timeout = match config.watchdog_timeout_secs {
+        None => None,
+        Some(secs) if secs > 0.0 && secs.is_finite() => Some(Duration::from_secs_f64(secs)),
+        Some(secs) => bail!(
+            "watchdog_timeout_secs must be a positive number, got {}",
+            secs
+        ),
+    };
+    if config.watchdog_exit && watchdog_timeout.is_none() {
+        log::warn!("watchdog_exit is set but watchdog_timeout_secs is not; the watchdog is off");
+    }
+
+    // A cap of zero would leave every trigger queued forever.
+    if config.max_triggers_per_callback == Some(0) {
+        bail!("max_triggers_per_callback must be at least 1");
+    }
+    if config.ring_buffer_size == 0 {
+        bail!("ring_buffer_size must be at least 1");
+    }
+    let trigger_dedup = match config.trigger_dedup_ms {
+        None => None,
+        Some(ms) if ms > 0.0 && ms.is_finite() => Some(Duration::from_secs_f64(ms / 1000.0)),
+        Some(ms) => bail!("trigger_dedup_ms must be a positive number, got {}", ms),
+    };
+    let bank_crossfade = match config.bank_crossfade_ms {
+        None => None,
+        Some(ms) if ms > 0.0 && ms.is_finite() => Some(Duration::from_secs_f64(ms / 1000.0)),
+        Some(ms) => bail!("bank_crossfade_ms must be a positive number, got {}", ms),
+    };
+
+    let latency = resolve_latency(&config)?;
+
+    let virtual_device = resolve_virtual_device(&config.virtual_device)?;
+
+    let midi = resolve_midi(&config.midi)?;
+    if config.input == InputKind::Midi && midi.note_keys.is_empty() {
+        bail!("input = \"midi\" needs a [midi.notes] table mapping notes to keys");
+    }
+
+    let preview_server = config
+        .preview_server
+        .map(|p| {
+            p.bind
+                .parse::<SocketAddr>()
+                .with_context(|| format!("Invalid preview_server bind address '{}'", p.bind))
+        })
+        .transpose()?;
+
+    let reverb = config.reverb.map(ReverbConfig::clamped);
+    if reverb.is_none() && key_map.values().any(|b| b.reverb_send > 0.0) {
+        log::warn!(
+            "reverb_send is set on a binding but no [reverb] table is configured; \
+             sends are ignored unless a kit's manifest sets one"
+        );
+    }
+
+    log::info!(
+        "Config loaded: {} bindings, {} unique samples, master_volume={}, max_voices={}",
+        key_map.len(),
+        sample_names.len(),
+        master_volume,
+        max_voices,
+    );
+
+    Ok(ResolvedConfig {
+        devices: resolve_devices(config.device, config.devices),
+        input: config.input,
+        midi,
+        master_volume,
+        volume_smoothing,
+        max_voices,
+        samples_dir,
+        sample_names,
+        key_map,
+        banks,
+        cycling_keys,
+        audition,
+        capture,
+        metronome,
+        momentary,
+        kit_select,
+        mute,
+        solo,
+        expression,
+        key_travel,
+        velocity_timing_window,
+        quiet_trigger_logs: config.quiet_trigger_logs,
+        simultaneous_hits: config.simultaneous_hits,
+        auto_advance,
+        reverb,
+        latency_stats: config.latency_stats,
+        report_clipping: config.report_clipping,
+        watchdog_timeout,
+        watchdog_exit: config.watchdog_exit,
+        audible_errors: config.audible_errors,
+        terminal_commands: config.terminal_commands,
+        max_triggers_per_callback: config.max_triggers_per_callback,
+        trigger_dedup,
+        bank_crossfade,
+        ring_buffer_size: config.ring_buffer_size,
+        limiter: config.limiter,
+        extra_output_channels: config.extra_output_channels,
+        output_mode: config.output_mode,
+        latency,
+        audio_device: config.audio_device,
+        output_sample_rate: config.output_sample_rate,
+        resample: config.resample,
+        clip_warning_run: config.clip_warning_run,
+        trim_silence,
+        normalize_peak,
+        dither: config.dither,
+        reconnect_audio: config.reconnect_audio,
+        loudness_normalize_velocity: config.loudness_normalize_velocity,
+        preload_all: config.preload_all,
+        background_loading: config.background_loading,
+        watch_config: config.watch_config,
+        state_file,
+        virtual_device,
+        preview_server,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// Helper to create a minimal valid config test directory with the
+    /// new kit/variant folder structure.
+    fn setup_test_dir() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let samples_dir = dir.path().join("samples");
+        fs::create_dir(&samples_dir).unwrap();
+
+        // Create: samples/acoustic/variant1/kick.wav
+        let variant_dir = samples_dir.join("acoustic").join("variant1");
+        fs::create_dir_all(&variant_dir).unwrap();
+
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 48000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let path = variant_dir.join("kick.wav");
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        writer.write_sample(0i16).unwrap();
+        writer.finalize().unwrap();
+
+        dir
+    }
+
+    #[test]
+    fn test_parse_minimal_config() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = setup_test_dir();
+        let samples_dir = dir.path().join("samples");
+
+        let config_str = format!(
+            r#"
+            samples_dir = "{}"
+            master_volume = 0.8
+            max_voices = 16
+
+            [[bindings]]
+            key = "KEY_A"
+            sample = "kick.wav"
+            gain = 1.0
+            "#,
+            samples_dir.display()
+        );
+
+        let config: Config = toml::from_str(&config_str).unwrap();
+        let resolved = resolve_config(config).unwrap();
+
+        assert_eq!(resolved.master_volume, 0.8);
+        assert_eq!(resolved.max_voices, 16);
+        assert_eq!(resolved.sample_names.len(), 1);
+        assert_eq!(resolved.sample_names[0], "kick.wav");
+        assert_eq!(resolved.key_map.len(), 1);
+        assert!(resolved.key_map.contains_key(&KeyCode::KEY_A.code()));
+    }
+
+    #[test]
+    fn test_device_and_devices_combined_without_repeats() {
+        let dir = setup_test_dir();
+        let config_str = format!(
+            r#"
+            device = "/dev/input/event3"
+            devices = ["/dev/input/event5", "/dev/input/event3", "/dev/input/event5"]
+            samples_dir = "{}"
+
+            [[bindings]]
+            key = "KEY_A"
+            sample = "kick.wav"
+            "#,
+            dir.path().join("samples").display()
+        );
+
+        let config: Config = toml::from_str(&config_str).unwrap();
+        let resolved = resolve_config(config).unwrap();
+        assert_eq!(
+            resolved.devices,
+            vec!["/dev/input/event3", "/dev/input/event5"]
+        );
+    }
+
+    #[test]
+    fn test_gain_defaults_to_one() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = setup_test_dir();
+        let samples_dir = dir.path().join("samples");
+
+        let config_str = format!(
+            r#"
+            samples_dir = "{}"
+
+            [[bindings]]
+            key = "KEY_A"
+            sample = "kick.wav"
+            "#,
+            samples_dir.display()
+        );
+
+        let config: Config = toml::from_str(&config_str).unwrap();
+        let resolved = resolve_config(config).unwrap();
+
+        let binding = resolved.key_map.get(&KeyCode::KEY_A.code()).unwrap();
+        assert_eq!(binding.gain, 1.0);
+    }
+
+    #[test]
+    fn test_gain_clamped() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = setup_test_dir();
+        let samples_dir = dir.path().join("samples");
+
+        let config_str = format!(
+            r#"
+            samples_dir = "{}"
+
+            [[bindings]]
+            key = "KEY_A"
+            sample = "kick.wav"
+            gain = 5.0
+            "#,
+            samples_dir.display()
+        );
+
+        let config: Config = toml::from_str(&config_str).unwrap();
+        let resolved = resolve_config(config).unwrap();
+
+        let binding = resolved.key_map.get(&KeyCode::KEY_A.code()).unwrap();
+        assert_eq!(binding.gain, 1.0);
+    }
+
+    #[test]
+    fn test_gain_db_converted_to_linear() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = setup_test_dir();
+        let samples_dir = dir.path().join("samples");
+
+        let config_str = format!(
+            r#"
+            samples_dir = "{}"
+            master_volume_db = -6.0
+
+            [[bindings]]
+            key = "KEY_A"
+            sample = "kick.wav"
+            gain_db = 0.0
+
+            [[bindings]]
+            key = "KEY_S"
+            sample = "snare.wav"
+            gain_db = -6.0
+
+            [[bindings]]
+            key = "KEY_D"
+            sample = "hihat.wav"
+            gain_db = -120.0
+            "#,
+            samples_dir.display()
+        );
+
+        let config: Config = toml::from_str(&config_str).unwrap();
+        let resolved = resolve_config(config).unwrap();
+
+        let gain = |key: KeyCode| resolved.key_map[&key.code()].gain;
+        assert_eq!(gain(KeyCode::KEY_A), 1.0);
+        assert!((gain(KeyCode::KEY_S) - 0.501).abs() < 0.001);
+        assert_eq!(gain(KeyCode::KEY_D), 0.0);
+        assert!((resolved.master_volume - 0.501).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_gain_and_gain_db_together_rejected() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = setup_test_dir();
+        let samples_dir = dir.path().join("samples");
+
+        let binding = format!(
+            r#"
+            samples_dir = "{}"
+
+            [[bindings]]
+            key = "KEY_A"
+            sample = "kick.wav"
+            gain = 0.5
+            gain_db = -6.0
+            "#,
+            samples_dir.display()
+        );
+        let config: Config = toml::from_str(&binding).unwrap();
+        let err = format!("{:#}", resolve_config(config).unwrap_err());
+        assert!(err.contains("KEY_A"), "{}", err);
+        assert!(err.contains("gain and gain_db are both set"), "{}", err);
+
+        let master = format!(
+            r#"
+            samples_dir = "{}"
+            master_volume = 0.5
+            master_volume_db = -6.0
+
+            [[bindings]]
+            key = "KEY_A"
+            sample = "kick.wav"
+            "#,
+            samples_dir.display()
+        );
+        let config: Config = toml::from_str(&master).unwrap();
+        let err = format!("{:#}", resolve_config(config).unwrap_err());
+        assert!(err.contains("master_volume_db are both set"), "{}", err);
+
+        // A user config in dB replaces the system config's linear volume.
+        let system: toml::Table = toml::from_str("master_volume = 0.9").unwrap();
+        let user: toml::Table = toml::from_str("master_volume_db = -6.0").unwrap();
+        let merged = merge_config_tables(system, user).unwrap();
+        assert!(!merged.contains_key("master_volume"));
+    }
+
+    #[test]
+    fn test_invalid_key_name() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = setup_test_dir();
+        let samples_dir = dir.path().join("samples");
+
+        let config_str = format!(
+            r#"
+            samples_dir = "{}"
+
+            [[bindings]]
+            key = "KEY_FOOBAR"
+            sample = "kick.wav"
+            "#,
+            samples_dir.display()
+        );
+
+        let config: Config = toml::from_str(&config_str).unwrap();
+        let result = resolve_config(config);
+        assert!(result.is_err());
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Unknown evdev key name"));
+    }
+
+    #[test]
+    fn test_key_names_normalized() {
+        for name in ["a", "A", "key_a", "KEY_A", " Key_A "] {
+            assert_eq!(parse_key_name(name), Some(KeyCode::KEY_A), "{}", name);
+        }
+        assert_eq!(parse_key_name("Space"), Some(KeyCode::KEY_SPACE));
+        assert_eq!(parse_key_name("Return"), Some(KeyCode::KEY_ENTER));
+        assert_eq!(parse_key_name(";"), Some(KeyCode::KEY_SEMICOLON));
+        assert_eq!(parse_key_name("foobar"), None);
+    }
+
+    #[test]
+    fn test_key_aliases_resolve_and_unknown_ones_error() {
+        for name in ["A", "a", "KEY_A"] {
+            let key = resolve_optional_key(&Some(name.to_string()), "next_kit").unwrap();
+            assert_eq!(key, Some(KeyCode::KEY_A.code()), "{}", name);
+        }
+        let key = resolve_optional_key(&Some("5".to_string()), "next_kit").unwrap();
+        assert_eq!(key, Some(KeyCode::KEY_5.code()));
+        let key = resolve_optional_key(&Some("]".to_string()), "next_kit").unwrap();
+        assert_eq!(key, Some(KeyCode::KEY_RIGHTBRACE.code()));
+
+        let err = resolve_optional_key(&Some("Spacebar".to_string()), "next_kit").unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("next_kit"), "{}", message);
+        assert!(message.contains("'Spacebar'"), "{}", message);
+    }
+
+    #[test]
+    fn test_binding_and_cycling_keys_accept_lenient_names() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = setup_test_dir();
+        let samples_dir = dir.path().join("samples");
+
+        let config_str = format!(
+            r#"
+            samples_dir = "{}"
+
+            [cycling_keys]
+            next_kit = "pgdn"
+
+            [[bindings]]
+            key = "a"
+            sample = "kick.wav"
+            "#,
+            samples_dir.display()
+        );
+
+        let config: Config = toml::from_str(&config_str).unwrap();
+        let resolved = resolve_config(config).unwrap();
+
+        assert!(resolved.key_map.contains_key(&KeyCode::KEY_A.code()));
+        assert_eq!(
+            resolved.cycling_keys.next_kit,
+            Some(KeyCode::KEY_PAGEDOWN.code())
+        );
+    }
+
+    #[test]
+    fn test_no_bindings() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = setup_test_dir();
+        let samples_dir = dir.path().join("samples");
+
+        let config_str = format!(
+            r#"
+            samples_dir = "{}"
+            bindings = []
+            "#,
+            samples_dir.display()
+        );
+
+        let config: Config = toml::from_str(&config_str).unwrap();
+        let result = resolve_config(config);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("No keybindings"));
+    }
+
+    #[test]
+    fn test_duplicate_sample_deduplication() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = setup_test_dir();
+        let samples_dir = dir.path().join("samples");
+
+        let config_str = format!(
+            r#"
+            samples_dir = "{}"
+
+            [[bindings]]
+            key = "KEY_A"
+            sample = "kick.wav"
+
+            [[bindings]]
+            key = "KEY_S"
+            sample = "kick.wav"
+            "#,
+            samples_dir.display()
         );
+
+        let config: Config = toml::from_str(&config_str).unwrap();
+        let resolved = resolve_config(config).unwrap();
+
+        // Two bindings but only one unique sample.
+        assert_eq!(resolved.key_map.len(), 2);
+        assert_eq!(resolved.sample_names.len(), 1);
+
+        let binding_a = resolved.key_map.get(&KeyCode::KEY_A.code()).unwrap();
+        let binding_s = resolved.key_map.get(&KeyCode::KEY_S.code()).unwrap();
+        assert_eq!(binding_a.sample_indices, binding_s.sample_indices);
     }
 
-    // Resolve cycling keybindings.
-    let cycling_keys = ResolvedCyclingKeys {
-        next_kit: resolve_optional_key(&config.cycling_keys.next_kit, "next_kit")?,
-        prev_kit: resolve_optional_key(&config.cycling_keys.prev_kit, "prev_kit")?,
-        next_variant: resolve_optional_key(&config.cycling_keys.next_variant, "next_variant")?,
-        prev_variant: resolve_optional_key(&config.cycling_keys.prev_variant, "prev_variant")?,
-    };
+    #[test]
+    fn test_velocity_layers_parsed_and_validated() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = setup_test_dir();
+        let samples_dir = dir.path().join("samples");
 
-    // Ensure cycling keys don't collide with sample bindings.
-    let cycling_codes: Vec<(u16, &str)> = [
-        (cycling_keys.next_kit, "next_kit"),
-        (cycling_keys.prev_kit, "prev_kit"),
-        (cycling_keys.next_variant, "next_variant"),
-        (cycling_keys.prev_variant, "prev_variant"),
-    ]
-    .iter()
-    .filter_map(|(code, name)| code.map(|c| (c, *name)))
-    .collect();
+        let config_str = format!(
+            r#"
+            samples_dir = "{}"
 
-    for (code, name) in &cycling_codes {
-        if key_map.contains_key(code) {
-            bail!(
-                "Cycling key '{}' conflicts with a sample keybinding. \
-                 Use a different key for cycling.",
-                name,
+            [[bindings]]
+            key = "KEY_A"
+            sample = [
+                {{ sample = "kick1.wav", min_velocity = 0.0 }},
+                {{ sample = "kick2.wav", min_velocity = 0.6 }},
+            ]
+            "#,
+            samples_dir.display()
+        );
+
+        let config: Config = toml::from_str(&config_str).unwrap();
+        let resolved = resolve_config(config).unwrap();
+        let binding = &resolved.key_map[&KeyCode::KEY_A.code()];
+        assert_eq!(binding.sample_indices, [0, 1]);
+        assert_eq!(binding.layer_velocities, [0.0, 0.6]);
+
+        // Layers out of order, or thresholds outside 0.0..=1.0, are rejected.
+        for layers in [
+            r#"{ sample = "kick1.wav", min_velocity = 0.6 }, { sample = "kick2.wav", min_velocity = 0.6 }"#,
+            r#"{ sample = "kick1.wav", min_velocity = 0.0 }, { sample = "kick2.wav", min_velocity = 1.5 }"#,
+        ] {
+            let config_str = format!(
+                "samples_dir = \"{}\"\n[[bindings]]\nkey = \"KEY_A\"\nsample = [{}]\n",
+                samples_dir.display(),
+                layers
             );
+            let config: Config = toml::from_str(&config_str).unwrap();
+            assert!(resolve_config(config).is_err());
         }
     }
 
-    log::info!(
-        "Config loaded: {} bindings, {} unique samples, master_volume={}, max_voices={}",
-        key_map.len(),
-        sample_names.len(),
-        master_volume,
-        max_voices,
-    );
+    #[test]
+    fn test_stacked_samples_parsed_and_validated() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = setup_test_dir();
+        let samples_dir = dir.path().join("samples");
 
-    Ok(ResolvedConfig {
-        device: config
-            .device
-            .map(|d| expand_tilde(&d).to_string_lossy().into_owned()),
-        master_volume,
-        max_voices,
-        samples_dir,
-        sample_names,
-        key_map,
-        cycling_keys,
-    })
-}
+        let config_str = format!(
+            r#"
+            samples_dir = "{}"
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs;
+            [[bindings]]
+            key = "KEY_A"
+            samples = [
+                {{ sample = "kick1.wav" }},
+                {{ sample = "kick2.wav", gain = 0.5 }},
+            ]
+            "#,
+            samples_dir.display()
+        );
 
-    /// Helper to create a minimal valid config test directory with the
-    /// new kit/variant folder structure.
-    fn setup_test_dir() -> tempfile::TempDir {
-        let dir = tempfile::tempdir().unwrap();
+        let config: Config = toml::from_str(&config_str).unwrap();
+        let resolved = resolve_config(config).unwrap();
+        let binding = &resolved.key_map[&KeyCode::KEY_A.code()];
+        assert_eq!(binding.sample_indices, [0, 1]);
+        assert_eq!(binding.stack_gains, [1.0, 0.5]);
+
+        // A binding needs exactly one of sample and samples.
+        let mut config: Config = toml::from_str(&config_str).unwrap();
+        config.bindings[0].sample = Some(SampleNames::One("kick1.wav".to_string()));
+        assert!(resolve_config(config).is_err());
+        let mut config: Config = toml::from_str(&config_str).unwrap();
+        config.bindings[0].samples.clear();
+        assert!(resolve_config(config).is_err());
+    }
+
+    #[test]
+    fn test_more_than_256_samples_keep_distinct_indices() {
+        let dir = setup_test_dir();
         let samples_dir = dir.path().join("samples");
-        fs::create_dir(&samples_dir).unwrap();
 
-        // Create: samples/acoustic/variant1/kick.wav
-        let variant_dir = samples_dir.join("acoustic").join("variant1");
-        fs::create_dir_all(&variant_dir).unwrap();
+        // 256 takes in rotation on KEY_A, then one more sample on KEY_B:
+        // the 257th unique sample, past the old u8 limit.
+        let takes: Vec<String> = (0..256).map(|i| format!("\"take{}.wav\"", i)).collect();
+        let config_str = format!(
+            "samples_dir = \"{}\"\n\
+             [[bindings]]\nkey = \"KEY_A\"\nsample = [{}]\n\
+             [[bindings]]\nkey = \"KEY_B\"\nsample = \"crash.wav\"\n",
+            samples_dir.display(),
+            takes.join(", ")
+        );
 
-        let spec = hound::WavSpec {
-            channels: 1,
-            sample_rate: 48000,
-            bits_per_sample: 16,
-            sample_format: hound::SampleFormat::Int,
-        };
-        let path = variant_dir.join("kick.wav");
-        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
-        writer.write_sample(0i16).unwrap();
-        writer.finalize().unwrap();
+        let config: Config = toml::from_str(&config_str).unwrap();
+        let resolved = resolve_config(config).unwrap();
+        assert_eq!(resolved.sample_names.len(), 257);
+        let rotation = &resolved.key_map[&KeyCode::KEY_A.code()].sample_indices;
+        assert_eq!(*rotation, (0..256).collect::<Vec<usize>>());
+        let crash = &resolved.key_map[&KeyCode::KEY_B.code()].sample_indices;
+        assert_eq!(*crash, [256]);
+        assert_eq!(resolved.sample_names[256], "crash.wav");
+    }
+
+    #[test]
+    fn test_round_robin_sample_list() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = setup_test_dir();
+        let samples_dir = dir.path().join("samples");
+
+        let config_str = format!(
+            r#"
+            samples_dir = "{}"
+
+            [[bindings]]
+            key = "KEY_A"
+            sample = ["kick1.wav", "kick2.wav", "kick3.wav"]
+
+            [[bindings]]
+            key = "KEY_S"
+            sample = "kick2.wav"
+
+            [[bindings]]
+            key = "KEY_D"
+            sample = []
+            "#,
+            samples_dir.display()
+        );
+
+        let mut config: Config = toml::from_str(&config_str).unwrap();
+        let empty = config.bindings.pop().unwrap();
+        let resolved = resolve_config(config).unwrap();
+        assert_eq!(
+            resolved.sample_names,
+            vec!["kick1.wav", "kick2.wav", "kick3.wav"]
+        );
+        assert_eq!(
+            resolved.key_map[&KeyCode::KEY_A.code()].sample_indices,
+            [0, 1, 2]
+        );
+        assert_eq!(resolved.key_map[&KeyCode::KEY_S.code()].sample_indices, [1]);
+
+        // An empty list is rejected.
+        let mut config: Config = toml::from_str(&config_str).unwrap();
+        config.bindings = vec![empty];
+        let err = resolve_config(config).unwrap_err().to_string();
+        assert!(err.contains("empty sample list"));
+    }
+
+    #[test]
+    fn test_cycling_keys_parsed() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = setup_test_dir();
+        let samples_dir = dir.path().join("samples");
+
+        let config_str = format!(
+            r#"
+            samples_dir = "{}"
+
+            [cycling_keys]
+            next_kit = "KEY_RIGHT"
+            prev_kit = "KEY_LEFT"
+            next_variant = "KEY_UP"
+            prev_variant = "KEY_DOWN"
+
+            [[bindings]]
+            key = "KEY_A"
+            sample = "kick.wav"
+            "#,
+            samples_dir.display()
+        );
+
+        let config: Config = toml::from_str(&config_str).unwrap();
+        let resolved = resolve_config(config).unwrap();
+
+        assert_eq!(
+            resolved.cycling_keys.next_kit,
+            Some(KeyCode::KEY_RIGHT.code())
+        );
+        assert_eq!(
+            resolved.cycling_keys.prev_kit,
+            Some(KeyCode::KEY_LEFT.code())
+        );
+        assert_eq!(
+            resolved.cycling_keys.next_variant,
+            Some(KeyCode::KEY_UP.code())
+        );
+        assert_eq!(
+            resolved.cycling_keys.prev_variant,
+            Some(KeyCode::KEY_DOWN.code())
+        );
+    }
+
+    #[test]
+    fn test_cycling_keys_optional() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = setup_test_dir();
+        let samples_dir = dir.path().join("samples");
+
+        let config_str = format!(
+            r#"
+            samples_dir = "{}"
+
+            [[bindings]]
+            key = "KEY_A"
+            sample = "kick.wav"
+            "#,
+            samples_dir.display()
+        );
+
+        let config: Config = toml::from_str(&config_str).unwrap();
+        let resolved = resolve_config(config).unwrap();
+
+        assert!(resolved.cycling_keys.next_kit.is_none());
+        assert!(resolved.cycling_keys.prev_kit.is_none());
+        assert!(resolved.cycling_keys.next_variant.is_none());
+        assert!(resolved.cycling_keys.prev_variant.is_none());
+    }
+
+    #[test]
+    fn test_cycling_key_conflicts_with_binding() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = setup_test_dir();
+        let samples_dir = dir.path().join("samples");
+
+        let config_str = format!(
+            r#"
+            samples_dir = "{}"
+
+            [cycling_keys]
+            next_kit = "KEY_A"
+
+            [[bindings]]
+            key = "KEY_A"
+            sample = "kick.wav"
+            "#,
+            samples_dir.display()
+        );
+
+        let config: Config = toml::from_str(&config_str).unwrap();
+        let result = resolve_config(config);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("conflicts"));
+    }
+
+    #[test]
+    fn test_audition_key_requires_audition_table() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = setup_test_dir();
+        let samples_dir = dir.path().join("samples");
+
+        let config_str = format!(
+            r#"
+            samples_dir = "{}"
+
+            [cycling_keys]
+            audition = "KEY_F12"
+
+            [[bindings]]
+            key = "KEY_A"
+            sample = "kick.wav"
+            "#,
+            samples_dir.display()
+        );
 
-        dir
+        let config: Config = toml::from_str(&config_str).unwrap();
+        let result = resolve_config(config);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("[audition]"));
     }
 
     #[test]
-    fn test_parse_minimal_config() {
+    fn test_invalid_cycling_key_name() {
         let _ = env_logger::builder().is_test(true).try_init();
         let dir = setup_test_dir();
         let samples_dir = dir.path().join("samples");
@@ -332,30 +3348,28 @@ mod tests {
         let config_str = format!(
             r#"
             samples_dir = "{}"
-            master_volume = 0.8
-            max_voices = 16
+
+            [cycling_keys]
+            next_kit = "KEY_DOESNOTEXIST"
 
             [[bindings]]
             key = "KEY_A"
             sample = "kick.wav"
-            gain = 1.0
             "#,
             samples_dir.display()
         );
 
         let config: Config = toml::from_str(&config_str).unwrap();
-        let resolved = resolve_config(config).unwrap();
-
-        assert_eq!(resolved.master_volume, 0.8);
-        assert_eq!(resolved.max_voices, 16);
-        assert_eq!(resolved.sample_names.len(), 1);
-        assert_eq!(resolved.sample_names[0], "kick.wav");
-        assert_eq!(resolved.key_map.len(), 1);
-        assert!(resolved.key_map.contains_key(&KeyCode::KEY_A.code()));
+        let result = resolve_config(config);
+        assert!(result.is_err());
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Unknown evdev key name"));
     }
 
     #[test]
-    fn test_gain_defaults_to_one() {
+    fn test_expression_axis_parsed() {
         let _ = env_logger::builder().is_test(true).try_init();
         let dir = setup_test_dir();
         let samples_dir = dir.path().join("samples");
@@ -364,6 +3378,11 @@ mod tests {
             r#"
             samples_dir = "{}"
 
+            [expression]
+            axis = "ABS_Z"
+            min = 0
+            max = 255
+
             [[bindings]]
             key = "KEY_A"
             sample = "kick.wav"
@@ -374,12 +3393,14 @@ mod tests {
         let config: Config = toml::from_str(&config_str).unwrap();
         let resolved = resolve_config(config).unwrap();
 
-        let binding = resolved.key_map.get(&KeyCode::KEY_A.code()).unwrap();
-        assert_eq!(binding.gain, 1.0);
+        let expression = resolved.expression.unwrap();
+        assert_eq!(expression.axis, AbsoluteAxisCode::ABS_Z.0);
+        assert_eq!(expression.min, 0);
+        assert_eq!(expression.max, 255);
     }
 
     #[test]
-    fn test_gain_clamped() {
+    fn test_key_travel_parsed_with_default_timings() {
         let _ = env_logger::builder().is_test(true).try_init();
         let dir = setup_test_dir();
         let samples_dir = dir.path().join("samples");
@@ -388,10 +3409,14 @@ mod tests {
             r#"
             samples_dir = "{}"
 
+            [key_travel]
+            axis = "ABS_MISC"
+            rest = 0
+            bottom = 1023
+
             [[bindings]]
             key = "KEY_A"
             sample = "kick.wav"
-            gain = 5.0
             "#,
             samples_dir.display()
         );
@@ -399,12 +3424,16 @@ mod tests {
         let config: Config = toml::from_str(&config_str).unwrap();
         let resolved = resolve_config(config).unwrap();
 
-        let binding = resolved.key_map.get(&KeyCode::KEY_A.code()).unwrap();
-        assert_eq!(binding.gain, 1.0);
+        let key_travel = resolved.key_travel.unwrap();
+        assert_eq!(key_travel.axis, AbsoluteAxisCode::ABS_MISC.0);
+        assert_eq!(key_travel.rest, 0);
+        assert_eq!(key_travel.bottom, 1023);
+        assert_eq!(key_travel.fast, Duration::from_millis(5));
+        assert_eq!(key_travel.slow, Duration::from_millis(50));
     }
 
     #[test]
-    fn test_invalid_key_name() {
+    fn test_expression_axis_empty_range() {
         let _ = env_logger::builder().is_test(true).try_init();
         let dir = setup_test_dir();
         let samples_dir = dir.path().join("samples");
@@ -413,8 +3442,13 @@ mod tests {
             r#"
             samples_dir = "{}"
 
+            [expression]
+            axis = "ABS_Z"
+            min = 100
+            max = 100
+
             [[bindings]]
-            key = "KEY_FOOBAR"
+            key = "KEY_A"
             sample = "kick.wav"
             "#,
             samples_dir.display()
@@ -423,14 +3457,11 @@ mod tests {
         let config: Config = toml::from_str(&config_str).unwrap();
         let result = resolve_config(config);
         assert!(result.is_err());
-        assert!(result
-            .unwrap_err()
-            .to_string()
-            .contains("Unknown evdev key name"));
+        assert!(result.unwrap_err().to_string().contains("range is empty"));
     }
 
     #[test]
-    fn test_no_bindings() {
+    fn test_reverb_send_parsed_and_clamped() {
         let _ = env_logger::builder().is_test(true).try_init();
         let dir = setup_test_dir();
         let samples_dir = dir.path().join("samples");
@@ -438,52 +3469,293 @@ mod tests {
         let config_str = format!(
             r#"
             samples_dir = "{}"
-            bindings = []
+
+            [reverb]
+            room_size = 0.8
+
+            [[bindings]]
+            key = "KEY_A"
+            sample = "kick.wav"
+            reverb_send = 1.5
             "#,
             samples_dir.display()
         );
 
         let config: Config = toml::from_str(&config_str).unwrap();
-        let result = resolve_config(config);
-        assert!(result.is_err());
-        assert!(result.unwrap_err().to_string().contains("No keybindings"));
+        let resolved = resolve_config(config).unwrap();
+
+        let reverb = resolved.reverb.unwrap();
+        assert!((reverb.room_size - 0.8).abs() < f32::EPSILON);
+        assert!((reverb.wet - 0.3).abs() < f32::EPSILON);
+        let binding = &resolved.key_map[&KeyCode::KEY_A.code()];
+        assert!((binding.reverb_send - 1.0).abs() < f32::EPSILON);
     }
 
     #[test]
-    fn test_duplicate_sample_deduplication() {
+    fn test_start_region_parsed_and_validated() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = setup_test_dir();
+        let samples_dir = dir.path().join("samples");
+
+        let config_str = |region: &str| {
+            format!(
+                r#"
+                samples_dir = "{}"
+
+                [[bindings]]
+                key = "KEY_A"
+                sample = "kick.wav"
+                start_region = {}
+                "#,
+                samples_dir.display(),
+                region
+            )
+        };
+
+        let config: Config =
+            toml::from_str(&config_str("{ from_ms = 10, to_ms = 250.5 }")).unwrap();
+        let resolved = resolve_config(config).unwrap();
+        let binding = &resolved.key_map[&KeyCode::KEY_A.code()];
+        assert_eq!(binding.start_region, Some((480, 12024)));
+
+        let config: Config = toml::from_str(&config_str("{ from_ms = 20, to_ms = 10 }")).unwrap();
+        let err = resolve_config(config).unwrap_err().to_string();
+        assert!(err.contains("start_region for key KEY_A"));
+    }
+
+    #[test]
+    fn test_gate_parsed_and_rejected_with_toggle_loop() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = setup_test_dir();
+        let samples_dir = dir.path().join("samples");
+
+        let config_str = |mode: &str| {
+            format!(
+                r#"
+                samples_dir = "{}"
+
+                [[bindings]]
+                key = "KEY_A"
+                sample = "kick.wav"
+                gate = true
+                mode = "{}"
+                "#,
+                samples_dir.display(),
+                mode
+            )
+        };
+
+        let config: Config = toml::from_str(&config_str("one_shot")).unwrap();
+        let resolved = resolve_config(config).unwrap();
+        assert!(resolved.key_map[&KeyCode::KEY_A.code()].gate);
+
+        let config: Config = toml::from_str(&config_str("toggle_loop")).unwrap();
+        let err = resolve_config(config).unwrap_err().to_string();
+        assert!(err.contains("sets gate with toggle_loop"));
+    }
+
+    #[test]
+    fn test_loop_points_parsed_and_validated() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = setup_test_dir();
+        let samples_dir = dir.path().join("samples");
+
+        let config_str = |loop_points: &str| {
+            format!(
+                r#"
+                samples_dir = "{}"
+
+                [[bindings]]
+                key = "KEY_A"
+                sample = "kick.wav"
+                loop = true
+                gate = true
+                {}
+                "#,
+                samples_dir.display(),
+                loop_points
+            )
+        };
+
+        let config: Config =
+            toml::from_str(&config_str("loop_start = 100\nloop_end = 4800")).unwrap();
+        let resolved = resolve_config(config).unwrap();
+        let binding = &resolved.key_map[&KeyCode::KEY_A.code()];
+        assert!(binding.looping);
+        assert_eq!((binding.loop_start, binding.loop_end), (100, Some(4800)));
+        let settings = resolved.sample_settings();
+        assert_eq!(settings.loops, vec![true]);
+        assert_eq!(settings.loop_ends, vec![Some(4800)]);
+
+        let config: Config =
+            toml::from_str(&config_str("loop_start = 100\nloop_end = 100")).unwrap();
+        let err = resolve_config(config).unwrap_err().to_string();
+        assert!(err.contains("loop_end 100 at or before loop_start 100"));
+    }
+
+    #[test]
+    fn test_vel_attack_parsed_and_validated() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = setup_test_dir();
+        let samples_dir = dir.path().join("samples");
+
+        let config_str = |attack: &str| {
+            format!(
+                r#"
+                samples_dir = "{}"
+
+                [[bindings]]
+                key = "KEY_A"
+                sample = "kick.wav"
+                vel_attack = {}
+                "#,
+                samples_dir.display(),
+                attack
+            )
+        };
+
+        let config: Config =
+            toml::from_str(&config_str("{ soft_ms = 10, hard_ms = 0.5 }")).unwrap();
+        let resolved = resolve_config(config).unwrap();
+        let binding = &resolved.key_map[&KeyCode::KEY_A.code()];
+        assert_eq!(binding.vel_attack, Some((480, 24)));
+        assert_eq!(
+            resolved.sample_settings().vel_attacks,
+            vec![Some((480, 24))]
+        );
+
+        let config: Config = toml::from_str(&config_str("{ soft_ms = -1, hard_ms = 0 }")).unwrap();
+        let err = resolve_config(config).unwrap_err().to_string();
+        assert!(err.contains("vel_attack for key KEY_A"));
+    }
+
+    #[test]
+    fn test_filter_parsed_and_validated() {
+        let dir = setup_test_dir();
+        let samples_dir = dir.path().join("samples");
+
+        let config_str = |filter: &str| {
+            format!(
+                r#"
+                samples_dir = "{}"
+
+                [[bindings]]
+                key = "KEY_A"
+                sample = "kick.wav"
+                filter = {}
+                "#,
+                samples_dir.display(),
+                filter
+            )
+        };
+
+        let config: Config =
+            toml::from_str(&config_str(r#"{ type = "high_pass", cutoff_hz = 80 }"#)).unwrap();
+        let resolved = resolve_config(config).unwrap();
+        let filter = OnePole::new(FilterKind::HighPass, 80.0, 48000);
+        let binding = &resolved.key_map[&KeyCode::KEY_A.code()];
+        assert_eq!(binding.filter, Some(filter));
+        assert_eq!(resolved.sample_settings().filters, vec![Some(filter)]);
+
+        let config: Config =
+            toml::from_str(&config_str(r#"{ type = "low_pass", cutoff_hz = 30000 }"#)).unwrap();
+        let err = resolve_config(config).unwrap_err().to_string();
+        assert!(err.contains("filter for key KEY_A"));
+    }
+
+    #[test]
+    fn test_latency_mode_overridden_by_buffer_options() {
+        let dir = setup_test_dir();
+        let samples_dir = dir.path().join("samples");
+
+        let config_str = |options: &str| {
+            format!(
+                r#"
+                samples_dir = "{}"
+                latency_mode = "safe"
+                {}
+
+                [[bindings]]
+                key = "KEY_A"
+                sample = "kick.wav"
+                "#,
+                samples_dir.display(),
+                options
+            )
+        };
+
+        let config: Config = toml::from_str(&config_str("")).unwrap();
+        let resolved = resolve_config(config).unwrap();
+        assert_eq!(resolved.latency, LatencyMode::Safe.settings());
+
+        let overrides = config_str("buffer_frames = 128\nwarmup_ms = 0");
+        let config: Config = toml::from_str(&overrides).unwrap();
+        let resolved = resolve_config(config).unwrap();
+        assert_eq!(
+            resolved.latency,
+            LatencySettings {
+                min_buffer_frames: 256,
+                buffer_frames: Some(128),
+                warmup: Duration::ZERO,
+            }
+        );
+
+        let config: Config = toml::from_str(&config_str("min_buffer_frames = 0")).unwrap();
+        assert!(resolve_config(config).is_err());
+    }
+
+    #[test]
+    fn test_output_sample_rate_44100_accepts_matching_samples() {
         let _ = env_logger::builder().is_test(true).try_init();
         let dir = setup_test_dir();
         let samples_dir = dir.path().join("samples");
 
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 44100,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let wav_path = dir.path().join("cd_rate.wav");
+        let mut writer = hound::WavWriter::create(&wav_path, spec).unwrap();
+        writer.write_sample(0i16).unwrap();
+        writer.finalize().unwrap();
+
         let config_str = format!(
             r#"
             samples_dir = "{}"
+            output_sample_rate = 44100
 
             [[bindings]]
             key = "KEY_A"
             sample = "kick.wav"
-
-            [[bindings]]
-            key = "KEY_S"
-            sample = "kick.wav"
+            start_region = {{ from_ms = 10, to_ms = 20 }}
             "#,
             samples_dir.display()
         );
 
         let config: Config = toml::from_str(&config_str).unwrap();
         let resolved = resolve_config(config).unwrap();
+        assert_eq!(resolved.output_sample_rate, 44100);
+        let binding = &resolved.key_map[&KeyCode::KEY_A.code()];
+        assert_eq!(binding.start_region, Some((441, 882)));
 
-        // Two bindings but only one unique sample.
-        assert_eq!(resolved.key_map.len(), 2);
-        assert_eq!(resolved.sample_names.len(), 1);
+        let options = crate::samples::LoadOptions {
+            sample_rate: resolved.output_sample_rate,
+            ..Default::default()
+        };
+        let sample = crate::samples::load_wav(&wav_path, options).unwrap();
+        assert_eq!(sample.sample_rate, 44100);
 
-        let binding_a = resolved.key_map.get(&KeyCode::KEY_A.code()).unwrap();
-        let binding_s = resolved.key_map.get(&KeyCode::KEY_S.code()).unwrap();
-        assert_eq!(binding_a.sample_index, binding_s.sample_index);
+        // The default 48kHz rejects it, naming the expected rate.
+        let err = crate::samples::load_wav(&wav_path, Default::default())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("expected 48000Hz, got 44100Hz"));
     }
 
     #[test]
-    fn test_cycling_keys_parsed() {
+    fn test_binding_banks_share_sample_indices() {
         let _ = env_logger::builder().is_test(true).try_init();
         let dir = setup_test_dir();
         let samples_dir = dir.path().join("samples");
@@ -493,14 +3765,22 @@ mod tests {
             samples_dir = "{}"
 
             [cycling_keys]
-            next_kit = "KEY_RIGHT"
-            prev_kit = "KEY_LEFT"
-            next_variant = "KEY_UP"
-            prev_variant = "KEY_DOWN"
+            next_bank = "KEY_PAGEDOWN"
 
             [[bindings]]
             key = "KEY_A"
             sample = "kick.wav"
+
+            [[banks]]
+            name = "percussion"
+
+            [[banks.bindings]]
+            key = "KEY_A"
+            sample = "conga.wav"
+
+            [[banks.bindings]]
+            key = "KEY_S"
+            sample = "kick.wav"
             "#,
             samples_dir.display()
         );
@@ -508,26 +3788,20 @@ mod tests {
         let config: Config = toml::from_str(&config_str).unwrap();
         let resolved = resolve_config(config).unwrap();
 
+        assert_eq!(resolved.sample_names, vec!["kick.wav", "conga.wav"]);
+        assert_eq!(resolved.banks.len(), 1);
+        let bank = &resolved.banks[0];
+        assert_eq!(bank.name, "percussion");
+        assert_eq!(bank.key_map[&KeyCode::KEY_A.code()].sample_indices, [1]);
+        assert_eq!(bank.key_map[&KeyCode::KEY_S.code()].sample_indices, [0]);
         assert_eq!(
-            resolved.cycling_keys.next_kit,
-            Some(KeyCode::KEY_RIGHT.code())
-        );
-        assert_eq!(
-            resolved.cycling_keys.prev_kit,
-            Some(KeyCode::KEY_LEFT.code())
-        );
-        assert_eq!(
-            resolved.cycling_keys.next_variant,
-            Some(KeyCode::KEY_UP.code())
-        );
-        assert_eq!(
-            resolved.cycling_keys.prev_variant,
-            Some(KeyCode::KEY_DOWN.code())
+            resolved.cycling_keys.next_bank,
+            Some(KeyCode::KEY_PAGEDOWN.code())
         );
     }
 
     #[test]
-    fn test_cycling_keys_optional() {
+    fn test_midi_notes_parsed_and_validated() {
         let _ = env_logger::builder().is_test(true).try_init();
         let dir = setup_test_dir();
         let samples_dir = dir.path().join("samples");
@@ -535,6 +3809,14 @@ mod tests {
         let config_str = format!(
             r#"
             samples_dir = "{}"
+            input = "midi"
+
+            [midi]
+            port = "Drum Pad"
+
+            [midi.notes]
+            36 = "KEY_A"
+            38 = "KEY_S"
 
             [[bindings]]
             key = "KEY_A"
@@ -545,65 +3827,264 @@ mod tests {
 
         let config: Config = toml::from_str(&config_str).unwrap();
         let resolved = resolve_config(config).unwrap();
-
-        assert!(resolved.cycling_keys.next_kit.is_none());
-        assert!(resolved.cycling_keys.prev_kit.is_none());
-        assert!(resolved.cycling_keys.next_variant.is_none());
-        assert!(resolved.cycling_keys.prev_variant.is_none());
+        assert_eq!(resolved.input, InputKind::Midi);
+        assert_eq!(resolved.midi.port.as_deref(), Some("Drum Pad"));
+        assert_eq!(resolved.midi.note_keys[&36], KeyCode::KEY_A.code());
+        assert_eq!(resolved.midi.note_keys[&38], KeyCode::KEY_S.code());
+
+        // Notes must be 0 to 127 and keys must exist.
+        let mut config: Config = toml::from_str(&config_str).unwrap();
+        let notes = &mut config.midi.notes;
+        notes.insert("128".to_string(), "KEY_D".to_string());
+        assert!(resolve_config(config).is_err());
+        let mut config: Config = toml::from_str(&config_str).unwrap();
+        let notes = &mut config.midi.notes;
+        notes.insert("40".to_string(), "KEY_NOPE".to_string());
+        assert!(resolve_config(config).is_err());
+
+        // MIDI input without any mapped notes can't play anything.
+        let mut config: Config = toml::from_str(&config_str).unwrap();
+        config.midi.notes.clear();
+        assert!(resolve_config(config).is_err());
     }
 
     #[test]
-    fn test_cycling_key_conflicts_with_binding() {
+    fn test_profile_merged_over_defaults() {
         let _ = env_logger::builder().is_test(true).try_init();
         let dir = setup_test_dir();
-        let samples_dir = dir.path().join("samples");
-
+        let path = dir.path().join("config.toml");
+        let no_system = dir.path().join("missing-system.toml");
         let config_str = format!(
             r#"
             samples_dir = "{}"
+            master_volume = 0.5
 
             [cycling_keys]
-            next_kit = "KEY_A"
+            next_kit = "KEY_RIGHT"
 
             [[bindings]]
             key = "KEY_A"
             sample = "kick.wav"
+
+            [profiles.macropad]
+            device = "/dev/input/event7"
+
+            [[profiles.macropad.bindings]]
+            key = "KEY_1"
+            sample = "snare.wav"
+
+            [profiles.macropad.cycling_keys]
+            prev_kit = "KEY_2"
+
+            [profiles.laptop]
+            bindings_merge = "append"
+
+            [[profiles.laptop.bindings]]
+            key = "KEY_S"
+            sample = "snare.wav"
             "#,
-            samples_dir.display()
+            dir.path().join("samples").display()
         );
+        fs::write(&path, config_str).unwrap();
 
-        let config: Config = toml::from_str(&config_str).unwrap();
-        let result = resolve_config(config);
-        assert!(result.is_err());
-        assert!(result.unwrap_err().to_string().contains("conflicts"));
+        // No profile: only the defaults.
+        let resolved = load_config(&path, &no_system, None).unwrap();
+        assert!(resolved.devices.is_empty());
+        assert_eq!(resolved.key_map.len(), 1);
+
+        // The profile's bindings replace the defaults; other settings and
+        // tables merge.
+        let resolved = load_config(&path, &no_system, Some("macropad")).unwrap();
+        assert_eq!(resolved.devices, vec!["/dev/input/event7"]);
+        assert_eq!(resolved.master_volume, 0.5);
+        assert_eq!(
+            resolved.key_map.keys().copied().collect::<Vec<_>>(),
+            vec![KeyCode::KEY_1.code()]
+        );
+        assert_eq!(
+            resolved.cycling_keys.next_kit,
+            Some(KeyCode::KEY_RIGHT.code())
+        );
+        assert_eq!(resolved.cycling_keys.prev_kit, Some(KeyCode::KEY_2.code()));
+
+        // A profile can append to the default bindings instead.
+        let resolved = load_config(&path, &no_system, Some("laptop")).unwrap();
+        assert!(resolved.key_map.contains_key(&KeyCode::KEY_A.code()));
+        assert!(resolved.key_map.contains_key(&KeyCode::KEY_S.code()));
+
+        let err = load_config(&path, &no_system, Some("desktop")).unwrap_err();
+        assert!(err.to_string().contains("laptop, macropad"));
     }
 
     #[test]
-    fn test_invalid_cycling_key_name() {
+    fn test_json_and_yaml_configs_resolve_like_toml() {
         let _ = env_logger::builder().is_test(true).try_init();
         let dir = setup_test_dir();
+        let no_system = dir.path().join("missing-system.toml");
         let samples_dir = dir.path().join("samples");
-
-        let config_str = format!(
+        let samples_dir = samples_dir.display();
+        let toml = format!(
             r#"
-            samples_dir = "{}"
+            samples_dir = "{samples_dir}"
+            master_volume = 0.5
 
             [cycling_keys]
-            next_kit = "KEY_DOESNOTEXIST"
+            next_kit = "KEY_RIGHT"
 
             [[bindings]]
             key = "KEY_A"
             sample = "kick.wav"
-            "#,
-            samples_dir.display()
+            gain = 1
+
+            [[bindings]]
+            key = "KEY_S"
+            sample = ["snare1.wav", "snare2.wav"]
+            pan = -0.5
+            "#
+        );
+        let json = format!(
+            r#"{{
+                "samples_dir": "{samples_dir}",
+                "master_volume": 0.5,
+                "expression": null,
+                "cycling_keys": {{ "next_kit": "KEY_RIGHT" }},
+                "bindings": [
+                    {{ "key": "KEY_A", "sample": "kick.wav", "gain": 1, "pan": null }},
+                    {{ "key": "KEY_S", "sample": ["snare1.wav", "snare2.wav"], "pan": -0.5 }}
+                ]
+            }}"#
+        );
+        let yaml = format!(
+            "samples_dir: {samples_dir}
+master_volume: 0.5
+expression:
+cycling_keys:
+  next_kit: KEY_RIGHT
+bindings:
+  - key: KEY_A
+    sample: kick.wav
+    gain: 1
+    pan: ~
+  - key: KEY_S
+    sample: [snare1.wav, snare2.wav]
+    pan: -0.5
+"
         );
 
-        let config: Config = toml::from_str(&config_str).unwrap();
-        let result = resolve_config(config);
-        assert!(result.is_err());
-        assert!(result
-            .unwrap_err()
-            .to_string()
-            .contains("Unknown evdev key name"));
+        let mut resolved = Vec::new();
+        for (name, content) in [
+            ("config.toml", toml.clone()),
+            ("config.json", json),
+            ("config.yaml", yaml.clone()),
+            ("config.yml", yaml),
+        ] {
+            let path = dir.path().join(name);
+            fs::write(&path, content).unwrap();
+            // The key map is a HashMap, so compare its bindings sorted.
+            let mut config = load_config(&path, &no_system, None).unwrap();
+            let key_map: std::collections::BTreeMap<_, _> =
+                std::mem::take(&mut config.key_map).into_iter().collect();
+            resolved.push(format!("{:?} {:?}", config, key_map));
+        }
+        assert_eq!(resolved[1], resolved[0]);
+        assert_eq!(resolved[2], resolved[0]);
+        assert_eq!(resolved[3], resolved[0]);
+
+        // A file with another extension is read as TOML.
+        let path = dir.path().join("config.conf");
+        fs::write(&path, toml).unwrap();
+        assert_eq!(ConfigFormat::of(&path), ConfigFormat::Toml);
+        assert!(load_config(&path, &no_system, None).is_ok());
+
+        // Syntax errors name the file.
+        let path = dir.path().join("broken.json");
+        fs::write(&path, "{ \"samples_dir\": ").unwrap();
+        let err = load_config(&path, &no_system, None).unwrap_err();
+        assert!(err.to_string().contains("broken.json"));
+    }
+
+    #[test]
+    fn test_merge_user_config_over_system_config() {
+        let system: toml::Table = toml::from_str(
+            r#"
+            samples_dir = "/usr/share/keyboard-drums/samples"
+            master_volume = 0.5
+            max_voices = 16
+
+            [reverb]
+            room_size = 0.7
+            wet = 0.2
+
+            [[bindings]]
+            key = "KEY_A"
+            sample = "kick.wav"
+
+            [[bindings]]
+            key = "KEY_S"
+            sample = "snare.wav"
+            "#,
+        )
+        .unwrap();
+        let user = r#"
+            master_volume = 0.9
+
+            [reverb]
+            wet = 0.4
+
+            [[bindings]]
+            key = "KEY_S"
+            sample = "clap.wav"
+            "#;
+
+        // Default: the user's bindings replace the system's, other fields
+        // override individually and nested tables merge key by key.
+        let merged = merge_config_tables(system.clone(), toml::from_str(user).unwrap()).unwrap();
+        let config: Config = merged.try_into().unwrap();
+        assert_eq!(config.samples_dir, "/usr/share/keyboard-drums/samples");
+        assert_eq!(config.master_volume, Some(0.9));
+        assert_eq!(config.max_voices, 16);
+        let reverb = config.reverb.unwrap();
+        assert_eq!(reverb.room_size, 0.7);
+        assert_eq!(reverb.wet, 0.4);
+        let samples: Vec<&str> = config
+            .bindings
+            .iter()
+            .flat_map(|b| b.sample_names())
+            .map(String::as_str)
+            .collect();
+        assert_eq!(samples, vec!["clap.wav"]);
+
+        // Append: the user's bindings follow the system's.
+        let user = format!("bindings_merge = \"append\"\n{}", user);
+        let merged = merge_config_tables(system, toml::from_str(&user).unwrap()).unwrap();
+        let config: Config = merged.try_into().unwrap();
+        let samples: Vec<&str> = config
+            .bindings
+            .iter()
+            .flat_map(|b| b.sample_names())
+            .map(String::as_str)
+            .collect();
+        assert_eq!(samples, vec!["kick.wav", "snare.wav", "clap.wav"]);
+        assert_eq!(config.master_volume, Some(0.9));
+    }
+
+    #[test]
+    fn test_missing_config_gives_first_run_guidance() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("keyboard-drums").join("config.toml");
+        let no_system = dir.path().join("missing-system.toml");
+
+        let err = load_config(&path, &no_system, None).unwrap_err();
+        let err = err.to_string();
+        assert!(err.contains("No config file found"), "{}", err);
+        assert!(err.contains("--init-config") && err.contains("--list-devices"));
+        assert!(!err.contains("Failed to read"));
+
+        // --init-config writes the example config, which parses, and then
+        // refuses to overwrite it.
+        write_example_config(&path).unwrap();
+        let config: Config = toml::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert!(!config.bindings.is_empty());
+        assert!(write_example_config(&path).is_err());
     }
 }
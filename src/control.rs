@@ -0,0 +1,237 @@
+use crate::commands::{ControlAction, KitAction, parse_volume};
+use crate::samples::SampleBank;
+use anyhow::{Context, Result, bail};
+use arc_swap::ArcSwap;
+use serde::Serialize;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+/// How long the listener sleeps between checks for new connections and
+/// shutdown.
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Help text sent back when a command is not understood.
+const SOCKET_HELP: &str =
+    "commands: next-kit, prev-kit, select-kit <name>, master <0.0 to 1.0>, panic, status";
+
+/// A line sent to the control socket.
+#[derive(Debug, Clone, PartialEq)]
+enum SocketCommand {
+    /// Forwarded to the input thread like a terminal command.
+    Action(ControlAction),
+
+    /// Reply with the kit and variant playing.
+    Status,
+}
+
+/// Reply to `status`.
+#[derive(Debug, Serialize)]
+struct Status<'a> {
+    kit: &'a str,
+    variant: &'a str,
+}
+
+/// Parse one line sent to the control socket. Returns None for a blank
+/// line. `kit_names` are the discovered kits, in order.
+fn parse_socket_command(line: &str, kit_names: &[String]) -> Result<Option<SocketCommand>> {
+    let line = line.trim();
+    if line.is_empty() {
+        return Ok(None);
+    }
+    // Kit names may contain spaces, so the argument is the rest of the line.
+    let (command, argument) = match line.split_once(char::is_whitespace) {
+        Some((command, argument)) => (command, Some(argument.trim_start())),
+        None => (line, None),
+    };
+
+    let command = match (command, argument) {
+        ("next-kit", None) => SocketCommand::Action(ControlAction::Kit(KitAction::NextKit)),
+        ("prev-kit", None) => SocketCommand::Action(ControlAction::Kit(KitAction::PrevKit)),
+        ("select-kit", Some(name)) => match kit_names.iter().position(|k| k == name) {
+            Some(index) => SocketCommand::Action(ControlAction::Kit(KitAction::SelectKit(index))),
+            None => bail!("Unknown kit '{}'", name),
+        },
+        ("master", Some(x)) => SocketCommand::Action(ControlAction::SetVolume(parse_volume(x)?)),
+        ("panic", None) => SocketCommand::Action(ControlAction::Panic),
+        ("status", None) => SocketCommand::Status,
+        _ => bail!("Unknown command '{}'", line),
+    };
+    Ok(Some(command))
+}
+
+/// A listening control socket. Dropping it removes the socket file.
+pub struct ControlSocket {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+/// Open the control socket at `path`. A socket file left behind by a
+/// previous run that nothing listens on any more is replaced; one in use
+/// is an error.
+pub fn bind(path: &Path) -> Result<ControlSocket> {
+    let listener = match UnixListener::bind(path) {
+        Ok(listener) => listener,
+        Err(e) if e.kind() == ErrorKind::AddrInUse && UnixStream::connect(path).is_err() => {
+            std::fs::remove_file(path).with_context(|| {
+                format!("Failed to remove stale control socket: {}", path.display())
+            })?;
+            UnixListener::bind(path)
+                .with_context(|| format!("Failed to open control socket: {}", path.display()))?
+        }
+        Err(e) => {
+            return Err(e)
+                .with_context(|| format!("Failed to open control socket: {}", path.display()));
+        }
+    };
+    listener
+        .set_nonblocking(true)
+        .context("Failed to set the control socket non-blocking")?;
+    log::info!("Control socket listening at {}", path.display());
+    Ok(ControlSocket {
+        listener,
+        path: path.to_path_buf(),
+    })
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            log::warn!(
+                "Failed to remove control socket {}: {}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+}
+
+/// Accept connections until `shutdown` is set, then remove the socket.
+///
+/// Each connection is served on its own thread, one reply line per
+/// command line. Those threads are not joined, since they may be blocked
+/// reading from a client that stays connected.
+pub fn run_control_socket(
+    socket: ControlSocket,
+    kit_names: Vec<String>,
+    sample_bank: Arc<ArcSwap<SampleBank>>,
+    sender: Sender<ControlAction>,
+    shutdown: &AtomicBool,
+) {
+    let kit_names: Arc<[String]> = kit_names.into();
+    while !shutdown.load(Ordering::Relaxed) {
+        let stream = match socket.listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                std::thread::sleep(SHUTDOWN_POLL_INTERVAL);
+                continue;
+            }
+            Err(e) => {
+                log::warn!("Control socket failed to accept a connection: {}", e);
+                std::thread::sleep(SHUTDOWN_POLL_INTERVAL);
+                continue;
+            }
+        };
+
+        let kit_names = Arc::clone(&kit_names);
+        let sample_bank = Arc::clone(&sample_bank);
+        let sender = sender.clone();
+        let spawned = std::thread::Builder::new()
+            .name("control-client".to_string())
+            .spawn(move || {
+                if let Err(e) = serve_client(stream, &kit_names, &sample_bank, &sender) {
+                    log::debug!("Control socket client disconnected: {}", e);
+                }
+            });
+        if let Err(e) = spawned {
+            log::warn!("Failed to spawn control socket client thread: {}", e);
+        }
+    }
+}
+
+/// Answer the commands of one client until it disconnects.
+fn serve_client(
+    stream: UnixStream,
+    kit_names: &[String],
+    sample_bank: &ArcSwap<SampleBank>,
+    sender: &Sender<ControlAction>,
+) -> std::io::Result<()> {
+    // Accepted sockets inherit non-blocking mode on some platforms.
+    stream.set_nonblocking(false)?;
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        let reply = match parse_socket_command(&line, kit_names) {
+            Ok(Some(SocketCommand::Action(action))) => {
+                log::debug!("Control socket: {:?}", action);
+                if sender.send(action).is_err() {
+                    return writeln!(writer, "error: input thread is not running");
+                }
+                "ok".to_string()
+            }
+            Ok(Some(SocketCommand::Status)) => {
+                let bank = sample_bank.load();
+                let status = Status {
+                    kit: &bank.kit_name,
+                    variant: &bank.variant_name,
+                };
+                serde_json::to_string(&status).expect("status is always serializable")
+            }
+            Ok(None) => continue,
+            Err(e) => format!("error: {:#} ({})", e, SOCKET_HELP),
+        };
+        writeln!(writer, "{}", reply)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_socket_command() {
+        let kit_names = vec!["acoustic".to_string(), "big room".to_string()];
+        let parse = |line: &str| parse_socket_command(line, &kit_names);
+
+        assert_eq!(
+            parse("next-kit\n").unwrap(),
+            Some(SocketCommand::Action(ControlAction::Kit(
+                KitAction::NextKit
+            )))
+        );
+        assert_eq!(
+            parse("prev-kit").unwrap(),
+            Some(SocketCommand::Action(ControlAction::Kit(
+                KitAction::PrevKit
+            )))
+        );
+        assert_eq!(
+            parse("select-kit big room").unwrap(),
+            Some(SocketCommand::Action(ControlAction::Kit(
+                KitAction::SelectKit(1)
+            )))
+        );
+        assert_eq!(
+            parse("master 0.3").unwrap(),
+            Some(SocketCommand::Action(ControlAction::SetVolume(0.3)))
+        );
+        assert_eq!(
+            parse("panic").unwrap(),
+            Some(SocketCommand::Action(ControlAction::Panic))
+        );
+        assert_eq!(parse("status").unwrap(), Some(SocketCommand::Status));
+        assert_eq!(parse("  ").unwrap(), None);
+
+        assert!(parse("select-kit vintage").is_err());
+        assert!(parse("select-kit").is_err());
+        assert!(parse("master loud").is_err());
+        assert!(parse("master 2").is_err());
+        assert!(parse("status now").is_err());
+        assert!(parse("next").is_err());
+    }
+}
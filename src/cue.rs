@@ -0,0 +1,76 @@
+use crate::samples::SampleData;
+use std::f32::consts::TAU;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Pitches of the two descending tones in the error blip, in Hz.
+const BLIP_TONES: [f32; 2] = [880.0, 440.0];
+
+/// Length of each tone and of the gap between them, in seconds.
+const BLIP_TONE_SECS: f32 = 0.06;
+const BLIP_GAP_SECS: f32 = 0.03;
+
+/// Fade in/out at each tone's edges, in seconds, to avoid clicks.
+const BLIP_FADE_SECS: f32 = 0.005;
+
+/// Peak amplitude of the blip.
+const BLIP_AMPLITUDE: f32 = 0.5;
+
+/// A short synthesized tone played when something goes wrong (a kit fails
+/// to load, a trigger hits a missing sample), so headless users notice.
+///
+/// Error paths on any thread call `request`; the audio callback calls
+/// `take` and starts a voice playing `blip` when it returns true.
+#[derive(Debug)]
+pub struct ErrorCue {
+    requested: AtomicBool,
+
+    /// Pre-rendered mono blip at the output sample rate.
+    pub blip: Arc<SampleData>,
+}
+
+impl ErrorCue {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            requested: AtomicBool::new(false),
+            blip: Arc::new(render_blip(sample_rate)),
+        }
+    }
+
+    /// Ask the audio thread to play the cue. Repeated requests before it
+    /// plays collapse into one.
+    pub fn request(&self) {
+        self.requested.store(true, Ordering::Relaxed);
+    }
+
+    /// Consume a pending request. Real-time safe.
+    pub fn take(&self) -> bool {
+        self.requested.swap(false, Ordering::Relaxed)
+    }
+}
+
+/// Render two short descending sine tones with faded edges.
+fn render_blip(sample_rate: u32) -> SampleData {
+    let rate = sample_rate as f32;
+    let tone_frames = (BLIP_TONE_SECS * rate) as usize;
+    let gap_frames = (BLIP_GAP_SECS * rate) as usize;
+    let fade_frames = ((BLIP_FADE_SECS * rate) as usize).max(1);
+
+    let mut data = Vec::with_capacity(BLIP_TONES.len() * (tone_frames + gap_frames));
+    for (i, &freq) in BLIP_TONES.iter().enumerate() {
+        if i > 0 {
+            data.extend(std::iter::repeat_n(0.0, gap_frames));
+        }
+        for n in 0..tone_frames {
+            let edge = n.min(tone_frames - 1 - n);
+            let envelope = (edge as f32 / fade_frames as f32).min(1.0);
+            data.push((TAU * freq * n as f32 / rate).sin() * BLIP_AMPLITUDE * envelope);
+        }
+    }
+
+    SampleData {
+        data,
+        channels: 1,
+        sample_rate,
+    }
+}
@@ -0,0 +1,98 @@
+use crate::config::FilterKind;
+
+/// One-pole low-pass or high-pass filter coefficients, computed once from
+/// a cutoff frequency at the output sample rate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OnePole {
+    kind: FilterKind,
+
+    /// Fraction of the distance to the input the low-passed value moves
+    /// each frame (0.0 to 1.0).
+    coefficient: f32,
+}
+
+impl OnePole {
+    pub fn new(kind: FilterKind, cutoff_hz: f32, sample_rate: u32) -> Self {
+        let coefficient =
+            1.0 - (-2.0 * std::f64::consts::PI * cutoff_hz as f64 / sample_rate as f64).exp();
+        Self {
+            kind,
+            coefficient: coefficient as f32,
+        }
+    }
+}
+
+/// A voice's filter: its coefficients and the low-passed value of each
+/// front channel so far. Copy-only, so starting a voice never allocates.
+#[derive(Debug, Clone, Copy)]
+pub struct FilterState {
+    filter: OnePole,
+    lows: [f32; 2],
+}
+
+impl FilterState {
+    pub fn new(filter: OnePole) -> Self {
+        Self {
+            filter,
+            lows: [0.0; 2],
+        }
+    }
+
+    /// Filter the next frame's `input` on front channel `channel` (0 or 1).
+    #[inline]
+    pub fn process(&mut self, channel: usize, input: f32) -> f32 {
+        let low = &mut self.lows[channel];
+        *low += self.filter.coefficient * (input - *low);
+        match self.filter.kind {
+            FilterKind::LowPass => *low,
+            // The high-passed signal is what the low-pass leaves out.
+            FilterKind::HighPass => input - *low,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Peak level of a 100 Hz sine at 48kHz through `filter`, after the
+    /// filter has settled.
+    fn filtered_peak(filter: OnePole) -> f32 {
+        let mut state = FilterState::new(filter);
+        (0..48000)
+            .map(|i| {
+                let t = i as f32 / 48000.0;
+                state.process(0, (2.0 * std::f32::consts::PI * 100.0 * t).sin())
+            })
+            .skip(24000)
+            .fold(0.0, |peak, sample| peak.max(sample.abs()))
+    }
+
+    #[test]
+    fn test_cutoff_above_signal_passes_and_below_attenuates() {
+        // A low-pass well above a low-frequency signal leaves it close to
+        // unchanged; one well below it takes most of it out.
+        let open = filtered_peak(OnePole::new(FilterKind::LowPass, 10000.0, 48000));
+        assert!(open > 0.99, "open low-pass peak {}", open);
+        let closed = filtered_peak(OnePole::new(FilterKind::LowPass, 5.0, 48000));
+        assert!(closed < 0.1, "closed low-pass peak {}", closed);
+
+        // A high-pass does the opposite.
+        let open = filtered_peak(OnePole::new(FilterKind::HighPass, 5.0, 48000));
+        assert!(open > 0.99, "open high-pass peak {}", open);
+        let closed = filtered_peak(OnePole::new(FilterKind::HighPass, 10000.0, 48000));
+        assert!(closed < 0.1, "closed high-pass peak {}", closed);
+
+        // A constant signal settles to itself through a low-pass and to
+        // silence through a high-pass.
+        let mut low = FilterState::new(OnePole::new(FilterKind::LowPass, 200.0, 48000));
+        let mut high = FilterState::new(OnePole::new(FilterKind::HighPass, 200.0, 48000));
+        let (mut low_out, mut high_out) = (0.0, 0.0);
+        for _ in 0..4800 {
+            low_out = low.process(1, 0.5);
+            high_out = high.process(1, 0.5);
+        }
+        assert!((low_out - 0.5).abs() < 1e-4);
+        assert!(high_out.abs() < 1e-4);
+    }
+}
@@ -1,29 +1,529 @@
-use crate::config::ResolvedCyclingKeys;
-use crate::ring::{Trigger, TriggerProducer};
-use crate::samples::{KitLibrary, SampleBank};
-use anyhow::{Context, Result};
+use crate::audio::{MasterVolume, SampleToggles};
+use crate::capture::OutputCapture;
+use crate::commands::{ControlAction, KitAction};
+use crate::config::{
+    AuditionConfig, BindingMode, ResolvedConfig, ResolvedCyclingKeys, ResolvedExpression,
+    ResolvedKeyTravel, ResolvedKitKey, ResolvedMidi, ResolvedSampleKey, ResolvedVirtualDevice,
+    SimultaneousHits, VelocityMode,
+};
+use crate::cue::ErrorCue;
+use crate::loader::BankLoader;
+use crate::reload::ConfigReload;
+use crate::ring::{Trigger, TriggerKind, TriggerProducer, TriggerSource, trigger_debug};
+use crate::samples::{KitLibrary, SampleBank, SampleSettings};
+use crate::stats::KeyHits;
+use crate::watchdog::Heartbeat;
+use anyhow::{Context, Result, anyhow, bail};
 use arc_swap::ArcSwap;
 use evdev::uinput::VirtualDevice;
-use evdev::{AttributeSet, Device, EventType, InputEvent, KeyCode, UinputAbsSetup};
+use evdev::{
+    AttributeSet, BusType, Device, EventType, InputEvent, InputId, KeyCode, UinputAbsSetup,
+};
+use midir::{Ignore, MidiInput, MidiInputConnection};
 use std::collections::HashMap;
 use std::collections::HashSet;
-use std::path::Path;
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
 use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+/// First delay between attempts to reopen a disconnected input device.
+/// Doubles after each failed attempt, up to `RECONNECT_MAX_DELAY`.
+const RECONNECT_INITIAL_DELAY: Duration = Duration::from_millis(500);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(8);
+
+/// Upper bound on how long the event loop waits for input before checking
+/// the shutdown flag and timers again.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long the event loop waits for input while a kit loads in the
+/// background, so the new kit is swapped in soon after it is ready.
+const LOADER_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Fraction of full key travel past which a key counts as moving off rest.
+const TRAVEL_START_DEPTH: f32 = 0.1;
+
+/// Velocity of the slowest key-travel presses, so they stay audible.
+const MIN_TRAVEL_VELOCITY: f32 = 0.1;
+
+/// Velocity of isolated presses on timing-velocity bindings. Quick repeats
+/// rise from here towards full velocity.
+const TIMING_BASE_VELOCITY: f32 = 0.7;
+
+/// Keyboard keys a device needs to be listed as a likely keyboard. Power
+/// buttons, lid switches and media remotes report only a handful; a
+/// numpad has around 20.
+const MIN_KEYBOARD_KEYS: usize = 16;
+
+/// What a bound key plays.
+#[derive(Debug, Clone)]
+pub struct KeyBinding {
+    /// Samples played in rotation on successive presses; usually just one.
+    /// For velocity-layered bindings, the layers from softest to loudest.
+    pub sample_indices: Vec<usize>,
+
+    /// Lowest velocity of each layer, parallel to `sample_indices`. Empty
+    /// unless the binding is velocity-layered.
+    pub layer_velocities: Vec<f32>,
+
+    /// Gain of each sample played together, parallel to `sample_indices`.
+    /// Empty unless the binding stacks samples.
+    pub stack_gains: Vec<f32>,
+    pub gain: f32,
+    pub mode: BindingMode,
+
+    /// Release the binding's samples when the key goes up.
+    pub gate: bool,
+    pub velocity_mode: VelocityMode,
+}
 
-/// A key binding mapping: evdev key code -> (sample_index, gain).
-pub type KeyMap = HashMap<u16, (usize, f32)>;
+/// A key binding mapping: evdev key code -> binding.
+pub type KeyMap = HashMap<u16, KeyBinding>;
 
 /// The set of evdev key codes that should be suppressed (not forwarded to
 /// other applications). This includes both sample-bound keys and cycling keys.
 pub type SuppressedKeys = HashSet<u16>;
 
+/// One key-to-sample layout. Switching banks changes which sample each key
+/// plays without changing the loaded kit.
+pub struct BindingBank {
+    /// Display name used in logs.
+    pub name: String,
+
+    /// Sample keybindings.
+    pub key_map: KeyMap,
+
+    /// Keys that are not forwarded to the virtual device while this bank
+    /// is active (its sample keys plus all cycling keys).
+    pub suppressed_keys: SuppressedKeys,
+}
+
+/// Everything the input loop needs to dispatch events, resolved from config.
+pub struct InputBindings {
+    /// Binding banks. The first is the default bank and is active on
+    /// startup; there is always at least one.
+    pub banks: Vec<BindingBank>,
+
+    /// Keys for cycling through kits, variants and binding banks.
+    pub cycling_keys: ResolvedCyclingKeys,
+
+    /// Optional absolute axis that scales trigger velocity.
+    pub expression: Option<ResolvedExpression>,
+
+    /// Optional key-travel axis from which press speed sets velocity.
+    pub key_travel: Option<ResolvedKeyTravel>,
+
+    /// Repeat window for timing-velocity bindings.
+    pub velocity_timing_window: Duration,
+
+    /// Handling of a sample triggered more than once in one input batch.
+    pub simultaneous_hits: SimultaneousHits,
+
+    /// Interval for automatic kit/variant advancement, if enabled.
+    pub auto_advance: Option<Duration>,
+
+    /// Kit played while the audition key is held, if that key is set.
+    pub audition: Option<AuditionConfig>,
+
+    /// Output capture saved by the capture key, if that key is set.
+    pub capture: Option<OutputCapture>,
+
+    /// Keys that switch to a kit while held.
+    pub momentary: Vec<ResolvedKitKey>,
+
+    /// Keys that jump straight to a kit.
+    pub kit_select: Vec<ResolvedKitKey>,
+
+    /// Keys that mute a sample.
+    pub mute: Vec<ResolvedSampleKey>,
+
+    /// Keys that solo a sample.
+    pub solo: Vec<ResolvedSampleKey>,
+
+    /// Mute and solo state shared with the audio thread.
+    pub sample_toggles: Arc<SampleToggles>,
+
+    /// Metronome on/off switch shared with the audio thread, if the
+    /// metronome is set up.
+    pub metronome: Option<Arc<AtomicBool>>,
+
+    /// Audible cue to request when a kit fails to load, if enabled.
+    pub error_cue: Option<Arc<ErrorCue>>,
+
+    /// Per-key press counters reported on exit, unless disabled.
+    pub key_hits: Option<Arc<KeyHits>>,
+
+    /// Master volume shared with the audio thread, set by terminal commands.
+    pub master_volume: Arc<MasterVolume>,
+
+    /// Bound sample names by sample id, republished on config reload so
+    /// the preview server lists and accepts the current ones.
+    pub sample_names: Arc<ArcSwap<Vec<String>>>,
+}
+
+/// Mutable state owned by the input thread while the event loop runs.
+struct InputState {
+    kit_state: KitState,
+
+    /// Index of the active binding bank.
+    bank_index: usize,
+
+    expression: Option<ExpressionAxis>,
+    key_travel: Option<KeyTravel>,
+    auto_advance: Option<AutoAdvance>,
+
+    /// Actions from the terminal or preview server, if either is enabled.
+    commands: Option<Receiver<ControlAction>>,
+
+    /// Bindings from a changed config file, if config watching is enabled.
+    config_reloads: Option<Receiver<ConfigReload>>,
+
+    /// Toggle-loop keys whose loop is running: key code -> sample id.
+    active_loops: HashMap<u16, u16>,
+
+    /// Last press of each timing-velocity key. Pre-sized for every binding
+    /// so recording a press never allocates.
+    last_press: HashMap<u16, Instant>,
+
+    /// Next position in each round-robin key's rotation. Pre-sized like
+    /// `last_press`.
+    round_robin: HashMap<u16, usize>,
+
+    /// Bound keys pressed and samples triggered since the last SYN_REPORT.
+    batch: HitBatch,
+
+    /// The bank that was playing before the audition key was pressed,
+    /// restored when it is released. None while not auditioning.
+    pre_audition: Option<Arc<SampleBank>>,
+
+    /// Source that bound key presses come from.
+    source: TriggerSource,
+}
+
+impl InputState {
+    /// Initial state for an input loop, on the kit and variant playing in
+    /// `sample_bank` and the first binding bank. Velocity axes are left
+    /// unset; they only exist on evdev devices.
+    fn new(
+        bindings: &InputBindings,
+        library: KitLibrary,
+        sample_bank: Arc<ArcSwap<SampleBank>>,
+        commands: Option<Receiver<ControlAction>>,
+        config_reloads: Option<Receiver<ConfigReload>>,
+    ) -> Self {
+        let auto_advance = bindings.auto_advance.map(|interval| {
+            log::info!(
+                "Auto-advance enabled: next variant every {:.1}s",
+                interval.as_secs_f64(),
+            );
+            AutoAdvance::new(interval, Instant::now())
+        });
+
+        let binding_count = bindings.banks.iter().map(|bank| bank.key_map.len()).sum();
+        let playing = sample_bank.load();
+        let (kit_index, variant_index) = library
+            .find_kit(&playing.kit_name, Some(&playing.variant_name))
+            .unwrap_or((0, 0));
+        drop(playing);
+        let loader = if library.background_loading {
+            match BankLoader::new() {
+                Ok(loader) => Some(loader),
+                Err(e) => {
+                    log::warn!("{:#}; loading kits on the input thread", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        Self {
+            kit_state: KitState {
+                library,
+                sample_bank,
+                kit_index,
+                variant_index,
+                error_cue: bindings.error_cue.clone(),
+                momentary_return: None,
+                bank_swapped: false,
+                loader,
+                interrupted_load: false,
+                pool_turns: HashMap::new(),
+            },
+            bank_index: 0,
+            expression: None,
+            key_travel: None,
+            auto_advance,
+            commands,
+            config_reloads,
+            active_loops: HashMap::new(),
+            last_press: HashMap::with_capacity(binding_count),
+            round_robin: HashMap::with_capacity(binding_count),
+            batch: HitBatch::default(),
+            pre_audition: None,
+            source: TriggerSource::Keyboard,
+        }
+    }
+}
+
+/// The bound key-downs of one input batch. Events up to a SYN_REPORT are
+/// reported together, so their keys were struck at the same instant as
+/// far as the device can tell.
+#[derive(Debug, Default)]
+struct HitBatch {
+    /// Bound keys pressed in this batch, in event order.
+    keys: Vec<u16>,
+
+    /// One-shot samples triggered in this batch.
+    samples: Vec<u16>,
+}
+
+impl HitBatch {
+    /// Start a new batch. Keeps the allocations for the next one.
+    fn clear(&mut self) {
+        self.keys.clear();
+        self.samples.clear();
+    }
+
+    /// Record a press of `code` and return the keys pressed before it in
+    /// this batch.
+    fn press(&mut self, code: u16) -> &[u16] {
+        self.keys.push(code);
+        &self.keys[..self.keys.len() - 1]
+    }
+
+    /// Record a one-shot trigger of `sample_id`. Returns false if the
+    /// sample was already triggered in this batch and `mode` coalesces it.
+    fn trigger(&mut self, sample_id: u16, mode: SimultaneousHits) -> bool {
+        if mode == SimultaneousHits::Coalesce && self.samples.contains(&sample_id) {
+            return false;
+        }
+        self.samples.push(sample_id);
+        true
+    }
+}
+
+/// Timer that periodically advances through variants and kits for
+/// hands-free auditioning. Starts running and can be paused by a key.
+struct AutoAdvance {
+    interval: Duration,
+
+    /// When the next advance is due, or None while paused.
+    next_at: Option<Instant>,
+}
+
+impl AutoAdvance {
+    fn new(interval: Duration, now: Instant) -> Self {
+        Self {
+            interval,
+            next_at: Some(now + interval),
+        }
+    }
+
+    /// Pause a running timer, or resume a paused one from `now`.
+    fn toggle(&mut self, now: Instant) {
+        self.next_at = match self.next_at {
+            Some(_) => None,
+            None => Some(now + self.interval),
+        };
+    }
+
+    /// Return true if an advance is due at `now`, scheduling the next one.
+    fn poll(&mut self, now: Instant) -> bool {
+        match self.next_at {
+            Some(at) if now >= at => {
+                self.next_at = Some(now + self.interval);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Time remaining until the next advance, or None while paused.
+    fn time_until_due(&self, now: Instant) -> Option<Duration> {
+        self.next_at.map(|at| at.saturating_duration_since(now))
+    }
+}
+
+/// Tracks the latest value of the expression axis and the velocity scale
+/// derived from it.
+struct ExpressionAxis {
+    axis: u16,
+    min: i32,
+    max: i32,
+    scale: f32,
+}
+
+impl ExpressionAxis {
+    fn new(config: &ResolvedExpression) -> Self {
+        Self {
+            axis: config.axis,
+            min: config.min,
+            max: config.max,
+            scale: 1.0,
+        }
+    }
+
+    /// Seed the scale from the device's current axis position, so triggers
+    /// sent before the axis first moves already use the pedal's real value.
+    fn read_initial(&mut self, device: &Device) {
+        let Ok(mut absinfo) = device.get_absinfo() else {
+            log::warn!("Failed to read initial expression axis value, assuming full velocity");
+            return;
+        };
+        match absinfo.find(|(code, _)| code.0 == self.axis) {
+            Some((_, info)) => {
+                self.update(info.value());
+                log::info!(
+                    "Expression axis initial value {} (velocity scale {:.2})",
+                    info.value(),
+                    self.scale,
+                );
+            }
+            None => log::warn!(
+                "Device does not report expression axis {}, assuming full velocity until it moves",
+                self.axis,
+            ),
+        }
+    }
+
+    fn update(&mut self, value: i32) {
+        self.scale = axis_value_to_scale(value, self.min, self.max);
+    }
+}
+
+/// Derives velocity from how quickly a key was pressed, on analog keyboards
+/// that report key depth on an absolute axis before the key-down event.
+struct KeyTravel {
+    axis: u16,
+    rest: i32,
+    bottom: i32,
+    fast: Duration,
+    slow: Duration,
+
+    /// Whether the axis was last seen at rest.
+    at_rest: bool,
+
+    /// Event time at which the current press left rest, until a key-down
+    /// consumes it.
+    press_started: Option<SystemTime>,
+}
+
+impl KeyTravel {
+    fn new(config: &ResolvedKeyTravel) -> Self {
+        Self {
+            axis: config.axis,
+            rest: config.rest,
+            bottom: config.bottom,
+            fast: config.fast,
+            slow: config.slow,
+            at_rest: true,
+            press_started: None,
+        }
+    }
+
+    /// Record an axis value reported at event time `at`.
+    fn update(&mut self, value: i32, at: SystemTime) {
+        let depth = axis_value_to_scale(value, self.rest, self.bottom);
+        if depth <= TRAVEL_START_DEPTH {
+            self.at_rest = true;
+            self.press_started = None;
+        } else if self.at_rest {
+            self.at_rest = false;
+            self.press_started = Some(at);
+        }
+    }
+
+    /// Velocity for a key-down at event time `at`, from the time since the
+    /// key left rest. Full velocity if no travel was seen for this press.
+    fn key_down(&mut self, at: SystemTime) -> f32 {
+        match self.press_started.take() {
+            Some(started) => {
+                let elapsed = at.duration_since(started).unwrap_or_default();
+                travel_time_to_velocity(elapsed, self.fast, self.slow)
+            }
+            None => 1.0,
+        }
+    }
+}
+
+/// Map a key's travel time to a velocity: `fast` or quicker is full
+/// velocity, `slow` or slower is `MIN_TRAVEL_VELOCITY`, linear in between.
+fn travel_time_to_velocity(elapsed: Duration, fast: Duration, slow: Duration) -> f32 {
+    if elapsed <= fast {
+        return 1.0;
+    }
+    if elapsed >= slow {
+        return MIN_TRAVEL_VELOCITY;
+    }
+    let t = (elapsed - fast).as_secs_f32() / (slow - fast).as_secs_f32();
+    1.0 - t * (1.0 - MIN_TRAVEL_VELOCITY)
+}
+
+/// Velocity for a timing-velocity press `since_last` after the previous
+/// press of the same key: `TIMING_BASE_VELOCITY` outside `window`, rising
+/// linearly to 1.0 as the repeat gets quicker.
+fn timing_velocity(since_last: Option<Duration>, window: Duration) -> f32 {
+    match since_last {
+        Some(elapsed) if elapsed < window => {
+            let closeness = 1.0 - elapsed.as_secs_f32() / window.as_secs_f32();
+            TIMING_BASE_VELOCITY + (1.0 - TIMING_BASE_VELOCITY) * closeness
+        }
+        _ => TIMING_BASE_VELOCITY,
+    }
+}
+
+/// Map an absolute axis value to a velocity scale in `0.0..=1.0`.
+///
+/// `min` maps to 0.0 and `max` to 1.0, with values outside the range
+/// clamped. Passing `min > max` inverts the axis.
+fn axis_value_to_scale(value: i32, min: i32, max: i32) -> f32 {
+    if min == max {
+        return 1.0;
+    }
+    let t = (value as f64 - min as f64) / (max as f64 - min as f64);
+    t.clamp(0.0, 1.0) as f32
+}
+
 /// Tracks the current kit and variant selection for cycling.
 struct KitState {
     library: KitLibrary,
     sample_bank: Arc<ArcSwap<SampleBank>>,
     kit_index: usize,
     variant_index: usize,
+    error_cue: Option<Arc<ErrorCue>>,
+
+    /// Where to go back to when the held momentary key is released. None
+    /// while no momentary key is held.
+    momentary_return: Option<MomentaryReturn>,
+
+    /// Set when a new bank is swapped in, until the audio thread is told.
+    bank_swapped: bool,
+
+    /// Worker that loads kits switched to, with `background_loading`.
+    loader: Option<BankLoader>,
+
+    /// Set when a bank swapped in cut short a background load, so the
+    /// bank it replaced is not the selected kit's.
+    interrupted_load: bool,
+
+    /// Hits so far of each sample bound to a directory, picking its next
+    /// take. Cleared on config reload, since sample ids may move.
+    pool_turns: HashMap<u16, usize>,
+}
+
+/// The kit that was playing before a momentary key was pressed.
+struct MomentaryReturn {
+    /// The momentary key being held.
+    key: u16,
+
+    kit_index: usize,
+    variant_index: usize,
+
+    /// The bank that was playing, swapped back in on release. None if a
+    /// config reload changed the samples, so the kit is loaded again.
+    bank: Option<Arc<SampleBank>>,
 }
 
 impl KitState {
@@ -61,21 +561,300 @@ impl KitState {
         self.reload();
     }
 
-    /// Load the samples for the current kit/variant and swap them in.
-    fn reload(&mut self) {
-        let kit_name = self
+    /// Switch to the kit at `index`, starting from its first variant.
+    fn select_kit(&mut self, index: usize) {
+        let count = self.library.kit_count();
+        if index >= count {
+            log::warn!("No kit {} (found {} kits)", index + 1, count);
+            return;
+        }
+
+        self.kit_index = index;
+        self.variant_index = 0;
+
+        self.reload();
+    }
+
+    /// Change kit or variant as `action` asks, whichever input it came from.
+    fn apply(&mut self, action: KitAction) {
+        match action {
+            KitAction::NextKit => self.cycle_kit(true),
+            KitAction::PrevKit => self.cycle_kit(false),
+            KitAction::NextVariant => self.cycle_variant(true),
+            KitAction::PrevVariant => self.cycle_variant(false),
+            KitAction::SelectKit(index) => self.select_kit(index),
+        }
+    }
+
+    /// Advance to the next variant, moving on to the first variant of the
+    /// next kit after the last one.
+    fn advance(&mut self) {
+        let kit_count = self.library.kit_count();
+        if kit_count == 0 {
+            return;
+        }
+
+        if self.variant_index + 1 < self.library.variant_count(self.kit_index) {
+            self.variant_index += 1;
+        } else {
+            self.kit_index = (self.kit_index + 1) % kit_count;
+            self.variant_index = 0;
+        }
+
+        self.reload();
+    }
+
+    /// The take to play for a hit of `sample_id`: the next one of its
+    /// directory in the playing bank, or 0 for a single sample file.
+    fn next_take(&mut self, sample_id: u16) -> u16 {
+        let bank = self.sample_bank.load();
+        let takes = bank.pools.get(sample_id as usize).map_or(0, Vec::len);
+        if takes <= 1 {
+            return 0;
+        }
+        let turn = self.pool_turns.entry(sample_id).or_insert(0);
+        let take = *turn % takes;
+        *turn = turn.wrapping_add(1);
+        take as u16
+    }
+
+    /// Switch the library to a new sample list and settings and load the
+    /// current kit/variant with them. On failure, the previous list,
+    /// settings and bank are kept.
+    fn swap_samples(&mut self, sample_names: Vec<String>, settings: SampleSettings) -> Result<()> {
+        let old_names = std::mem::replace(&mut self.library.sample_names, sample_names);
+        let old_settings = std::mem::replace(&mut self.library.settings, settings);
+        // Banks preloaded with the old samples are stale. Loading them all
+        // again here would stall input for as long as preloading at startup
+        // takes, so only the current kit is loaded now and the others are
+        // kept again as they are switched to.
+        let old_preloaded = std::mem::take(&mut self.library.preloaded);
+        match self.library.bank(self.kit_index, self.variant_index) {
+            Ok(bank) => {
+                // A kit still loading in the background has the old samples.
+                if let Some(loader) = &mut self.loader {
+                    loader.cancel();
+                }
+                self.pool_turns.clear();
+                self.swap_bank(bank);
+                // The bank to return to has the old samples.
+                if let Some(held) = &mut self.momentary_return {
+                    held.bank = None;
+                }
+                Ok(())
+            }
+            Err(e) => {
+                self.library.sample_names = old_names;
+                self.library.settings = old_settings;
+                self.library.preloaded = old_preloaded;
+                Err(e)
+            }
+        }
+    }
+
+    /// Swap in the audition kit, returning the bank it replaced. The kit
+    /// and variant selection is left alone. On failure the current bank
+    /// keeps playing and None is returned.
+    fn start_audition(&mut self, audition: &AuditionConfig) -> Option<Arc<SampleBank>> {
+        let Some((kit_index, variant_index)) = self
             .library
-            .kits
-            .get(self.kit_index)
-            .map(|k| k.name.as_str())
-            .unwrap_or("?");
-        let variant_name = self
+            .find_kit(&audition.kit, audition.variant.as_deref())
+        else {
+            log::warn!(
+                "Audition kit '{}' variant '{}' not found",
+                audition.kit,
+                audition.variant.as_deref().unwrap_or("(first)"),
+            );
+            return None;
+        };
+
+        match self.library.bank(kit_index, variant_index) {
+            Ok(bank) => {
+                log::info!(
+                    "Auditioning kit '{}' variant '{}'",
+                    bank.kit_name,
+                    bank.variant_name
+                );
+                Some(self.swap_bank(bank))
+            }
+            Err(e) => {
+                log::error!("Failed to load audition kit '{}': {:#}", audition.kit, e);
+                if let Some(cue) = &self.error_cue {
+                    cue.request();
+                }
+                None
+            }
+        }
+    }
+
+    /// Switch to the kit named `kit` and its variant named `variant`, or its
+    /// first variant if None. Logs a warning if there is no such kit or
+    /// variant.
+    fn select_kit_by_name(&mut self, kit: &str, variant: Option<&str>) {
+        match self.library.find_kit(kit, variant) {
+            Some((kit_index, variant_index)) => {
+                self.kit_index = kit_index;
+                self.variant_index = variant_index;
+                self.reload();
+            }
+            None => log::warn!(
+                "Kit '{}' variant '{}' not found",
+                kit,
+                variant.unwrap_or("(first)")
+            ),
+        }
+    }
+
+    /// Switch to the momentary key's kit until the key is released,
+    /// remembering the current kit, variant and bank. Does nothing while
+    /// another momentary key is held. On failure the current kit keeps
+    /// playing.
+    fn start_momentary(&mut self, momentary: &ResolvedKitKey) {
+        if self.momentary_return.is_some() {
+            return;
+        }
+        let Some((kit_index, variant_index)) = self
             .library
-            .kits
-            .get(self.kit_index)
-            .and_then(|k| k.variants.get(self.variant_index))
+            .find_kit(&momentary.kit, momentary.variant.as_deref())
+        else {
+            log::warn!(
+                "Momentary kit '{}' variant '{}' not found",
+                momentary.kit,
+                momentary.variant.as_deref().unwrap_or("(first)"),
+            );
+            return;
+        };
+
+        match self.library.bank(kit_index, variant_index) {
+            Ok(bank) => {
+                log::info!(
+                    "Momentary switch to kit '{}' variant '{}' while held",
+                    bank.kit_name,
+                    bank.variant_name
+                );
+                let previous = self.swap_bank(bank);
+                self.momentary_return = Some(MomentaryReturn {
+                    key: momentary.key,
+                    kit_index: self.kit_index,
+                    variant_index: self.variant_index,
+                    bank: Some(previous),
+                });
+                self.kit_index = kit_index;
+                self.variant_index = variant_index;
+            }
+            Err(e) => {
+                log::error!("Failed to load momentary kit '{}': {:#}", momentary.kit, e);
+                if let Some(cue) = &self.error_cue {
+                    cue.request();
+                }
+            }
+        }
+    }
+
+    /// Go back to the kit that was playing before the momentary key `key`
+    /// was pressed. Does nothing and returns false unless `key` is the one
+    /// being held.
+    fn end_momentary(&mut self, key: u16) -> bool {
+        let Some(previous) = self.momentary_return.take_if(|held| held.key == key) else {
+            return false;
+        };
+        self.kit_index = previous.kit_index;
+        self.variant_index = previous.variant_index;
+        match previous.bank {
+            Some(bank) => {
+                log::info!(
+                    "Momentary switch over, back to kit '{}' variant '{}'",
+                    bank.kit_name,
+                    bank.variant_name
+                );
+                self.restore_bank(bank);
+            }
+            None => self.reload(),
+        }
+        true
+    }
+
+    /// Swap `bank` in for the playing one, returning the bank it replaced.
+    /// A background load still under way is cancelled.
+    fn swap_bank(&mut self, bank: Arc<SampleBank>) -> Arc<SampleBank> {
+        if let Some(loader) = &mut self.loader
+            && loader.cancel()
+        {
+            self.interrupted_load = true;
+        }
+        self.bank_swapped = true;
+        self.sample_bank.swap(bank)
+    }
+
+    /// Swap back `bank`, which was playing before an audition or momentary
+    /// switch. If that switch cut short a background load, `bank` is not
+    /// the selected kit, so the selected kit is loaded again instead.
+    fn restore_bank(&mut self, bank: Arc<SampleBank>) {
+        if std::mem::take(&mut self.interrupted_load) {
+            self.reload();
+        } else {
+            self.swap_bank(bank);
+        }
+    }
+
+    /// Swap in the bank the background loader finished, if any. On
+    /// failure the current bank keeps playing, as in `reload`.
+    fn poll_loader(&mut self) {
+        let Some(loaded) = self.loader.as_mut().and_then(|loader| loader.try_recv()) else {
+            return;
+        };
+        match loaded.bank {
+            Ok(bank) => {
+                log::info!(
+                    "Kit '{}' variant '{}' loaded",
+                    bank.kit_name,
+                    bank.variant_name
+                );
+                self.library
+                    .keep_preloaded(loaded.kit_index, loaded.variant_index, &bank);
+                self.swap_bank(bank);
+            }
+            Err(e) => {
+                let (kit_name, variant_name) =
+                    Self::kit_names(&self.library, loaded.kit_index, loaded.variant_index);
+                log::error!(
+                    "Failed to load kit '{}' variant '{}': {:#}",
+                    kit_name,
+                    variant_name,
+                    e,
+                );
+                if let Some(cue) = &self.error_cue {
+                    cue.request();
+                }
+            }
+        }
+    }
+
+    /// Whether a kit is being loaded in the background.
+    fn is_loading(&self) -> bool {
+        self.loader.as_ref().is_some_and(BankLoader::is_loading)
+    }
+
+    /// Names of a kit and variant in the library, "?" for any missing.
+    fn kit_names(library: &KitLibrary, kit_index: usize, variant_index: usize) -> (&str, &str) {
+        let kit = library.kits.get(kit_index);
+        let kit_name = kit.map(|k| k.name.as_str()).unwrap_or("?");
+        let variant_name = kit
+            .and_then(|k| k.variants.get(variant_index))
             .map(|v| v.as_str())
             .unwrap_or("?");
+        (kit_name, variant_name)
+    }
+
+    /// Load the samples for the current kit/variant and swap them in.
+    ///
+    /// With `background_loading`, a kit not preloaded is handed to the
+    /// loader instead and swapped in by `poll_loader` once it is ready;
+    /// the current bank plays until then.
+    fn reload(&mut self) {
+        self.interrupted_load = false;
+        let (kit_name, variant_name) =
+            Self::kit_names(&self.library, self.kit_index, self.variant_index);
 
         log::info!(
             "Switching to kit '{}' variant '{}' (kit {}/{}, variant {}/{})",
@@ -87,11 +866,21 @@ impl KitState {
             self.library.variant_count(self.kit_index),
         );
 
-        match self.library.load_bank(self.kit_index, self.variant_index) {
+        let key = (self.kit_index, self.variant_index);
+        if let Some(loader) = &mut self.loader
+            && !self.library.preloaded.contains_key(&key)
+        {
+            loader.request(self.library.clone(), self.kit_index, self.variant_index);
+            return;
+        }
+
+        match self.library.bank(self.kit_index, self.variant_index) {
             Ok(bank) => {
-                self.sample_bank.store(Arc::new(bank));
+                self.swap_bank(bank);
             }
             Err(e) => {
+                let (kit_name, variant_name) =
+                    Self::kit_names(&self.library, self.kit_index, self.variant_index);
                 log::error!(
                     "Failed to load kit '{}' variant '{}': {:#}",
                     kit_name,
@@ -99,16 +888,65 @@ impl KitState {
                     e,
                 );
                 // Keep the previous bank — don't crash.
+                if let Some(cue) = &self.error_cue {
+                    cue.request();
+                }
             }
         }
     }
 }
 
-/// List all available evdev input devices with their names and paths.
+/// An evdev device as shown by `--list-devices` and the device picker.
+struct DeviceSummary {
+    path: PathBuf,
+    name: String,
+
+    /// Keyboard keys the device reports (see `keyboard_key_count`).
+    key_count: usize,
+}
+
+impl DeviceSummary {
+    fn is_likely_keyboard(&self) -> bool {
+        self.key_count >= MIN_KEYBOARD_KEYS
+    }
+
+    /// `*` for a likely keyboard, so it stands out in a list.
+    fn marker(&self) -> char {
+        if self.is_likely_keyboard() { '*' } else { ' ' }
+    }
+}
+
+/// How many of `keys` are keyboard keys. Mice, touchpads and gamepads
+/// report their buttons (BTN_*, from BTN_0 up) as keys too; those don't
+/// count.
+fn keyboard_key_count(keys: impl IntoIterator<Item = KeyCode>) -> usize {
+    keys.into_iter()
+        .filter(|key| key.code() < KeyCode::BTN_0.code())
+        .count()
+}
+
+/// Every evdev input device with its key count, sorted by path.
+fn enumerate_devices() -> Vec<DeviceSummary> {
+    let mut devices: Vec<_> = evdev::enumerate()
+        .map(|(path, device)| DeviceSummary {
+            name: device.name().unwrap_or("(unnamed)").to_string(),
+            key_count: device
+                .supported_keys()
+                .map_or(0, |keys| keyboard_key_count(keys.iter())),
+            path,
+        })
+        .collect();
+    devices.sort_by(|a, b| a.path.cmp(&b.path));
+    devices
+}
+
+/// List the evdev input devices that look like keyboards, with their
+/// names, paths and key counts. With `all`, every device is listed, e.g.
+/// to find a macropad with only a few keys.
 ///
 /// Prints device information to stdout for the `--list-devices` CLI flag.
-pub fn list_devices() {
-    let devices: Vec<_> = evdev::enumerate().collect();
+pub fn list_devices(all: bool) {
+    let devices = enumerate_devices();
 
     if devices.is_empty() {
         println!("No input devices found.");
@@ -116,24 +954,100 @@ pub fn list_devices() {
         return;
     }
 
-    println!("{:<30} {}", "PATH", "NAME");
+    let stable_paths = stable_device_paths();
+
+    println!("  {:<30} {:>4}  NAME", "PATH", "KEYS");
     println!("{}", "-".repeat(70));
 
-    for (path, device) in &devices {
-        let name = device.name().unwrap_or("(unnamed)");
-        println!("{:<30} {}", path.display(), name);
+    let mut hidden = 0;
+    for device in &devices {
+        if !all && !device.is_likely_keyboard() {
+            hidden += 1;
+            continue;
+        }
+        println!(
+            "{} {:<30} {:>4}  {}",
+            device.marker(),
+            device.path.display(),
+            device.key_count,
+            device.name
+        );
+        if let Some(stable) = stable_paths.get(&device.path) {
+            println!("  {:<30}       {}", "", stable.display());
+        }
     }
 
+    println!();
+    println!("* likely keyboard ({} or more keys)", MIN_KEYBOARD_KEYS);
+    if hidden > 0 {
+        println!(
+            "{} other device(s) with fewer keys (mice, power buttons...) hidden; \
+             add --verbose to list every device.",
+            hidden
+        );
+    }
     println!();
     println!("Tip: Use the path of your keyboard as the 'device' setting in config.toml");
+    println!("     The /dev/input/by-id paths stay the same across reboots and replugs.");
+    println!("     To play from several keyboards, list them all, e.g.");
+    println!("     devices = [\"/dev/input/event3\", \"/dev/input/event7\"]");
+    println!("     or repeat --device on the command line.");
+
+    list_midi_ports();
 }
 
-/// Interactively prompt the user to pick an input device by number.
-///
-/// Shows a numbered list of available evdev devices and reads a choice
-/// from stdin. Returns the selected device path as a String.
+/// Map each /dev/input/eventN path to its /dev/input/by-id symlink, which
+/// names the device and survives reboots. Devices without one are left out.
+fn stable_device_paths() -> HashMap<PathBuf, PathBuf> {
+    let Ok(entries) = std::fs::read_dir("/dev/input/by-id") else {
+        return HashMap::new();
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let link = entry.path();
+            let target = std::fs::canonicalize(&link).ok()?;
+            Some((target, link))
+        })
+        .collect()
+}
+
+/// List MIDI input ports, for `input = "midi"`.
+fn list_midi_ports() {
+    let midi_in = match MidiInput::new("keyboard-drums") {
+        Ok(midi_in) => midi_in,
+        Err(e) => {
+            println!("\nMIDI input unavailable: {}", e);
+            return;
+        }
+    };
+    let names: Vec<String> = midi_in
+        .ports()
+        .iter()
+        .filter_map(|port| midi_in.port_name(port).ok())
+        .collect();
+
+    println!();
+    if names.is_empty() {
+        println!("No MIDI input ports found.");
+        return;
+    }
+    println!("MIDI INPUT PORTS");
+    println!("{}", "-".repeat(70));
+    for name in &names {
+        println!("{}", name);
+    }
+    println!();
+    println!("Tip: With input = \"midi\", set [midi] port to part of a port name");
+}
+
+/// Interactively prompt the user to pick an input device by number.
+///
+/// Shows a numbered list of available evdev devices, likely keyboards
+/// marked, and reads a choice from stdin. Pressing Enter picks the first
+/// likely keyboard. Returns the selected device path as a String.
 pub fn pick_device_interactive() -> Result<String> {
-    let mut devices: Vec<_> = evdev::enumerate().collect();
+    let devices = enumerate_devices();
 
     if devices.is_empty() {
         anyhow::bail!(
@@ -141,39 +1055,53 @@ pub fn pick_device_interactive() -> Result<String> {
              You may need to run as root or add your user to the 'input' group."
         );
     }
+    let default = devices.iter().position(DeviceSummary::is_likely_keyboard);
 
-    // Sort by path for stable ordering.
-    devices.sort_by(|(a, _), (b, _)| a.cmp(b));
-
-    println!("No device configured. Pick an input device:\n");
-    for (i, (path, device)) in devices.iter().enumerate() {
-        let name = device.name().unwrap_or("(unnamed)");
-        println!("  {:>3})  {:<28} {}", i + 1, path.display(), name);
+    println!("No device configured. Pick an input device (* likely keyboard):\n");
+    for (i, device) in devices.iter().enumerate() {
+        println!(
+            "{} {:>3})  {:<28} {:>4} keys  {}",
+            device.marker(),
+            i + 1,
+            device.path.display(),
+            device.key_count,
+            device.name
+        );
     }
     println!();
 
     loop {
-        eprint!("Enter device number [1-{}]: ", devices.len());
+        match default {
+            Some(d) => eprint!(
+                "Enter device number [1-{}, Enter for {}]: ",
+                devices.len(),
+                d + 1
+            ),
+            None => eprint!("Enter device number [1-{}]: ", devices.len()),
+        }
 
         let mut line = String::new();
-        std::io::stdin()
+        let read = std::io::stdin()
             .read_line(&mut line)
             .context("Failed to read from stdin")?;
 
         let trimmed = line.trim();
 
-        // Handle EOF / empty input (e.g. piped stdin).
-        if trimmed.is_empty() {
-            anyhow::bail!("No device selected (empty input)");
-        }
+        // Handle EOF / empty input (e.g. piped stdin). A blank line takes
+        // the default, if there is one.
+        let choice = match (read, trimmed.is_empty(), default) {
+            (0, _, _) | (_, true, None) => anyhow::bail!("No device selected (empty input)"),
+            (_, true, Some(d)) => Some(d + 1),
+            (_, false, _) => trimmed.parse::<usize>().ok(),
+        };
 
-        if let Ok(n) = trimmed.parse::<usize>() {
-            if n >= 1 && n <= devices.len() {
-                let path = devices[n - 1].0.to_string_lossy().into_owned();
-                let name = devices[n - 1].1.name().unwrap_or("(unnamed)");
-                println!("Selected: {} ({})\n", path, name);
-                return Ok(path);
-            }
+        if let Some(n) = choice
+            && (1..=devices.len()).contains(&n)
+        {
+            let device = &devices[n - 1];
+            let path = device.path.to_string_lossy().into_owned();
+            println!("Selected: {} ({})\n", path, device.name);
+            return Ok(path);
         }
 
         println!(
@@ -207,133 +1135,348 @@ pub fn open_device(path: &Path) -> Result<Device> {
 
 /// Run the input reader loop.
 ///
-/// This function blocks, reading events from the evdev device.
+/// This function blocks, reading events from the evdev devices.
 /// It should be called from a dedicated thread.
 ///
-/// The physical device is grabbed exclusively so that bound key events
-/// do not reach other applications. All other key events are forwarded
-/// through a uinput virtual keyboard.
+/// Every physical device is grabbed exclusively so that bound key events
+/// do not reach other applications. All other events are forwarded
+/// through the device's own uinput virtual device. Bindings, kits and
+/// banks are shared: a key plays the same sample on every device.
 ///
-/// When a key-down event matches a binding in `key_map`, a Trigger is
+/// When a key-down event matches a binding in the key map, a Trigger is
 /// pushed to the ring buffer producer. When a cycling key is pressed,
 /// the sample bank is swapped atomically.
 ///
-/// The loop exits when `shutdown` is set to true.
+/// The loop exits when `shutdown` is set to true. `heartbeat` is beaten on
+/// every iteration so the watchdog can detect a stalled loop. Actions
+/// received on `commands`, and bindings received on `config_reloads`, are
+/// applied between batches of input events.
+///
+/// If a device disappears (unplugged, or reset over USB), its grab is
+/// released and its path is reopened once it comes back, with a new
+/// virtual device built from `virtual_identity`. The other devices and
+/// audio keep running in the meantime.
+#[allow(clippy::too_many_arguments)]
 pub fn run_input_loop(
-    mut device: Device,
-    key_map: &KeyMap,
+    devices: Vec<EvdevInput>,
+    virtual_identity: &ResolvedVirtualDevice,
+    mut bindings: InputBindings,
     mut producer: TriggerProducer,
     shutdown: &AtomicBool,
-    cycling_keys: &ResolvedCyclingKeys,
+    heartbeat: &Heartbeat,
     library: KitLibrary,
     sample_bank: Arc<ArcSwap<SampleBank>>,
-    suppressed_keys: &SuppressedKeys,
-    mut virtual_device: VirtualDevice,
+    commands: Option<Receiver<ControlAction>>,
+    config_reloads: Option<Receiver<ConfigReload>>,
 ) -> Result<()> {
     log::info!(
-        "Input reader started, listening for {} key bindings ({} keys suppressed)",
-        key_map.len(),
-        suppressed_keys.len(),
+        "Input reader started on {} device(s), listening for {} key bindings in {} bank(s) ({} keys suppressed)",
+        devices.len(),
+        bindings.banks[0].key_map.len(),
+        bindings.banks.len(),
+        bindings.banks[0].suppressed_keys.len(),
     );
 
-    // Grab the device exclusively so key events don't reach other apps.
-    device
-        .grab()
-        .context("Failed to grab input device exclusively")?;
-    log::info!("Device grabbed exclusively — bound keys will not reach other applications");
+    // Velocity axes are read from the first device.
+    let expression = bindings.expression.as_ref().map(|config| {
+        let mut axis = ExpressionAxis::new(config);
+        if let Some(first) = devices.first() {
+            axis.read_initial(&first.device);
+        }
+        axis
+    });
 
-    let mut kit_state = KitState {
-        library,
-        sample_bank,
-        kit_index: 0,
-        variant_index: 0,
-    };
+    let key_travel = bindings.key_travel.as_ref().map(|config| {
+        log::info!(
+            "Key-travel velocity enabled on axis {} ({:.0}ms = full, {:.0}ms = softest)",
+            config.axis,
+            config.fast.as_secs_f64() * 1000.0,
+            config.slow.as_secs_f64() * 1000.0,
+        );
+        KeyTravel::new(config)
+    });
+
+    // Grab every device exclusively so key events don't reach other apps.
+    let mut inputs: Vec<InputDevice> = Vec::with_capacity(devices.len());
+    for mut input in devices {
+        if let Err(e) = input.device.grab() {
+            for grabbed in &mut inputs {
+                grabbed.ungrab();
+            }
+            return Err(e).with_context(|| {
+                format!(
+                    "Failed to grab input device {} exclusively",
+                    input.path.display()
+                )
+            });
+        }
+        log::info!(
+            "Device {} grabbed exclusively — bound keys will not reach other applications",
+            input.path.display()
+        );
+        inputs.push(InputDevice::new(input));
+    }
+
+    let mut state = InputState::new(&bindings, library, sample_bank, commands, config_reloads);
+    state.expression = expression;
+    state.key_travel = key_travel;
 
     let result = run_event_loop(
-        &mut device,
-        key_map,
+        &mut inputs,
+        virtual_identity,
+        &mut bindings,
         &mut producer,
         shutdown,
-        cycling_keys,
-        &mut kit_state,
-        suppressed_keys,
-        &mut virtual_device,
+        heartbeat,
+        &mut state,
     );
+    heartbeat.stop();
 
-    // Always ungrab the device on exit so the keyboard works normally again.
-    if let Err(e) = device.ungrab() {
-        log::warn!("Failed to ungrab device: {}", e);
-    } else {
-        log::info!("Device ungrabbed");
+    // Always ungrab the devices on exit so the keyboards work normally again.
+    for input in &mut inputs {
+        input.ungrab();
     }
 
     result
 }
 
+/// An opened evdev keyboard, with the virtual device its unbound events
+/// are forwarded to.
+pub struct EvdevInput {
+    /// Where the device was opened, to reopen it after a disconnect.
+    pub path: PathBuf,
+
+    pub device: Device,
+    pub virtual_device: VirtualDevice,
+}
+
+/// A keyboard in the input loop: grabbed and read, or disconnected and
+/// waiting to be reopened.
+struct InputDevice {
+    path: PathBuf,
+
+    /// The grabbed device and its virtual device, None while disconnected.
+    open: Option<(Device, VirtualDevice)>,
+
+    /// When to next try reopening the device while it is disconnected.
+    retry_at: Instant,
+
+    /// Delay before the attempt after that. Doubles after each failed
+    /// attempt, up to `RECONNECT_MAX_DELAY`.
+    retry_delay: Duration,
+
+    /// Reconnect attempts since the device disconnected.
+    attempts: u32,
+}
+
+impl InputDevice {
+    fn new(input: EvdevInput) -> Self {
+        Self {
+            path: input.path,
+            open: Some((input.device, input.virtual_device)),
+            retry_at: Instant::now(),
+            retry_delay: RECONNECT_INITIAL_DELAY,
+            attempts: 0,
+        }
+    }
+
+    /// Release the grab, if the device is open.
+    fn ungrab(&mut self) {
+        if let Some((device, _)) = &mut self.open {
+            match device.ungrab() {
+                Ok(()) => log::info!("Device {} ungrabbed", self.path.display()),
+                Err(e) => log::warn!("Failed to ungrab device {}: {}", self.path.display(), e),
+            }
+        }
+    }
+
+    /// Drop a device that went away and schedule reopening it.
+    fn disconnect(&mut self, error: &std::io::Error, now: Instant) {
+        log::warn!(
+            "Input device {} disconnected ({}); audio keeps running, waiting for it to come back",
+            self.path.display(),
+            error,
+        );
+        // A disconnected device usually has no grab left to release.
+        if let Some((mut device, _)) = self.open.take()
+            && let Err(e) = device.ungrab()
+        {
+            log::debug!("Ungrabbing the disconnected device: {}", e);
+        }
+        self.retry_at = now + RECONNECT_INITIAL_DELAY;
+        self.retry_delay = RECONNECT_INITIAL_DELAY * 2;
+        self.attempts = 0;
+    }
+
+    /// Try to reopen and grab a disconnected device, with a new virtual
+    /// device. Returns true if it is back.
+    fn reconnect(&mut self, virtual_identity: &ResolvedVirtualDevice, now: Instant) -> bool {
+        self.attempts += 1;
+        log::info!(
+            "Reconnecting to input device {} (attempt {})",
+            self.path.display(),
+            self.attempts
+        );
+        match reopen_device(&self.path, virtual_identity) {
+            Ok(opened) => {
+                self.open = Some(opened);
+                log::info!("Input device {} reconnected", self.path.display());
+                true
+            }
+            Err(e) => {
+                log::info!("Input device not available yet: {:#}", e);
+                self.retry_at = now + self.retry_delay;
+                self.retry_delay = (self.retry_delay * 2).min(RECONNECT_MAX_DELAY);
+                false
+            }
+        }
+    }
+}
+
+/// Whether an input error means the device went away, e.g. it was
+/// unplugged or reset over USB, rather than some other failure.
+fn is_device_gone(error: &std::io::Error) -> bool {
+    matches!(error.raw_os_error(), Some(libc::ENODEV | libc::ENXIO))
+}
+
+/// Open and grab the device at `path` and mirror it with a new virtual
+/// device. The device is left ungrabbed if anything fails.
+fn reopen_device(
+    path: &Path,
+    virtual_identity: &ResolvedVirtualDevice,
+) -> Result<(Device, VirtualDevice)> {
+    let mut device = open_device(path)?;
+    device
+        .grab()
+        .context("Failed to grab input device exclusively")?;
+    match create_virtual_device(&device, virtual_identity) {
+        Ok(virtual_device) => Ok((device, virtual_device)),
+        Err(e) => {
+            let _ = device.ungrab();
+            Err(e)
+        }
+    }
+}
+
+/// Tell the watchdog which grabbed devices to release if the loop stalls.
+fn record_device_fds(heartbeat: &Heartbeat, inputs: &[InputDevice]) {
+    let fds: Vec<_> = inputs
+        .iter()
+        .filter_map(|input| input.open.as_ref())
+        .map(|(device, _)| device.as_raw_fd())
+        .collect();
+    heartbeat.set_device_fds(fds);
+}
+
 /// Inner event loop, separated so that grab/ungrab cleanup is guaranteed
 /// in `run_input_loop` regardless of how this function exits.
 fn run_event_loop(
-    device: &mut Device,
-    key_map: &KeyMap,
+    inputs: &mut [InputDevice],
+    virtual_identity: &ResolvedVirtualDevice,
+    bindings: &mut InputBindings,
     producer: &mut TriggerProducer,
     shutdown: &AtomicBool,
-    cycling_keys: &ResolvedCyclingKeys,
-    kit_state: &mut KitState,
-    suppressed_keys: &SuppressedKeys,
-    virtual_device: &mut VirtualDevice,
+    heartbeat: &Heartbeat,
+    state: &mut InputState,
 ) -> Result<()> {
+    record_device_fds(heartbeat, inputs);
+
     loop {
         if shutdown.load(Ordering::Relaxed) {
             log::info!("Input reader shutting down");
             break;
         }
 
-        // fetch_events() blocks until events are available.
-        let events: Vec<InputEvent> = match device.fetch_events() {
-            Ok(events) => events.collect(),
-            Err(e) => {
-                if shutdown.load(Ordering::Relaxed) {
-                    break;
-                }
-                // EINTR can happen from signal handlers — just retry.
-                if e.kind() == std::io::ErrorKind::Interrupted {
-                    continue;
+        // Tell the watchdog we're alive. The poll timeout below keeps this
+        // ticking even when no input arrives.
+        heartbeat.beat();
+
+        // Wait for input, waking up for the next auto-advance or to
+        // re-check the shutdown flag.
+        let mut timeout = run_pending_work(bindings, producer, shutdown, state);
+
+        // Reopen disconnected devices that are due, waking up for the next
+        // attempt of those still missing.
+        let now = Instant::now();
+        let mut reconnected = false;
+        for (index, input) in inputs.iter_mut().enumerate() {
+            if input.open.is_some() {
+                continue;
+            }
+            if now >= input.retry_at && input.reconnect(virtual_identity, now) {
+                reconnected = true;
+                if index == 0
+                    && let (Some(axis), Some((device, _))) = (&mut state.expression, &input.open)
+                {
+                    axis.read_initial(device);
                 }
-                return Err(e).context("Error reading events from input device");
+                continue;
             }
-        };
+            timeout = timeout.min(input.retry_at.saturating_duration_since(now));
+        }
+        if reconnected {
+            record_device_fds(heartbeat, inputs);
+        }
+
+        // One pollfd per open device, in `inputs` order.
+        let mut fds: Vec<libc::pollfd> = inputs
+            .iter()
+            .filter_map(|input| input.open.as_ref())
+            .map(|(device, _)| libc::pollfd {
+                fd: device.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            })
+            .collect();
+        match wait_for_events(&mut fds, timeout) {
+            Ok(0) => continue,
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e).context("Error waiting for input events"),
+        }
 
-        // Process events and forward non-suppressed ones to the virtual device.
-        //
-        // Physical device events arrive in batches delimited by SYN_REPORT.
-        // A typical key-press batch looks like:
-        //   EV_MSC MSC_SCAN <scancode>
-        //   EV_KEY KEY_A 1
-        //   EV_SYN SYN_REPORT 0
-        //
-        // We must suppress the entire batch for bound keys (including the
-        // accompanying MSC_SCAN), otherwise orphaned non-KEY events cause
-        // spurious input on the virtual device.
-        //
-        // Strategy: collect each batch, then filter and forward.
-        let mut batch: Vec<InputEvent> = Vec::new();
+        let mut ready = fds.iter().map(|fd| fd.revents != 0);
+        let mut disconnected = false;
+        for input in inputs.iter_mut() {
+            let Some((device, _)) = &mut input.open else {
+                continue;
+            };
+            if !ready.next().unwrap_or(false) {
+                continue;
+            }
 
-        for event in &events {
-            // Always run our handler for drum triggering / kit cycling.
-            handle_event(event, key_map, producer, cycling_keys, kit_state);
+            // The device is readable, so fetch_events() will not block.
+            let fetched = device.fetch_events().map(|events| events.collect());
+            let events: Vec<InputEvent> = match fetched {
+                Ok(events) => events,
+                Err(e) => {
+                    if shutdown.load(Ordering::Relaxed) {
+                        return Ok(());
+                    }
+                    // EINTR can happen from signal handlers — just retry.
+                    if e.kind() == std::io::ErrorKind::Interrupted {
+                        continue;
+                    }
+                    if is_device_gone(&e) {
+                        input.disconnect(&e, Instant::now());
+                        disconnected = true;
+                        continue;
+                    }
+                    return Err(e).with_context(|| {
+                        format!(
+                            "Error reading events from input device {}",
+                            input.path.display()
+                        )
+                    });
+                }
+            };
 
-            if event.event_type() == EventType::SYNCHRONIZATION {
-                // End of batch — filter and forward.
-                forward_batch(&batch, suppressed_keys, virtual_device);
-                batch.clear();
-            } else {
-                batch.push(*event);
+            if let Some((_, virtual_device)) = &mut input.open {
+                process_events(&events, bindings, producer, state, virtual_device);
             }
         }
-
-        // Flush any trailing events (shouldn't normally happen, but be safe).
-        if !batch.is_empty() {
-            forward_batch(&batch, suppressed_keys, virtual_device);
+        if disconnected {
+            record_device_fds(heartbeat, inputs);
         }
     }
 
@@ -342,6 +1485,255 @@ fn run_event_loop(
     Ok(())
 }
 
+/// Handle events read from one device and forward the non-suppressed ones
+/// to its virtual device.
+///
+/// Physical device events arrive in batches delimited by SYN_REPORT.
+/// A typical key-press batch looks like:
+///   EV_MSC MSC_SCAN <scancode>
+///   EV_KEY KEY_A 1
+///   EV_SYN SYN_REPORT 0
+///
+/// We must suppress the entire batch for bound keys (including the
+/// accompanying MSC_SCAN), otherwise orphaned non-KEY events cause
+/// spurious input on the virtual device.
+///
+/// Strategy: collect each batch, then filter and forward.
+fn process_events(
+    events: &[InputEvent],
+    bindings: &mut InputBindings,
+    producer: &mut TriggerProducer,
+    state: &mut InputState,
+    virtual_device: &mut VirtualDevice,
+) {
+    let mut batch: Vec<InputEvent> = Vec::new();
+
+    for event in events {
+        // Always run our handler for drum triggering / kit cycling.
+        handle_event(event, bindings, producer, state);
+
+        if event.event_type() == EventType::SYNCHRONIZATION {
+            // End of batch — filter and forward.
+            forward_batch(
+                &batch,
+                &bindings.banks[state.bank_index].suppressed_keys,
+                virtual_device,
+            );
+            batch.clear();
+        } else {
+            batch.push(*event);
+        }
+    }
+
+    // Flush any trailing events (shouldn't normally happen, but be safe).
+    if !batch.is_empty() {
+        forward_batch(
+            &batch,
+            &bindings.banks[state.bank_index].suppressed_keys,
+            virtual_device,
+        );
+    }
+}
+
+/// The opened source the input thread reads key presses from.
+pub enum InputSource {
+    /// Keyboards, each with the virtual device its unbound events go to.
+    Evdev(Vec<EvdevInput>),
+
+    Midi(MidiPort),
+}
+
+/// An open MIDI input port. midir reads it on its own thread and passes
+/// note-ons to the input thread over `notes`.
+pub struct MidiPort {
+    /// Keeps the port open until the input loop exits.
+    _connection: MidiInputConnection<()>,
+
+    /// (note, velocity) of each note-on.
+    notes: Receiver<(u8, u8)>,
+}
+
+/// Connect to the first MIDI input port whose name contains `port`, or to
+/// the first port if `port` is None.
+pub fn open_midi_port(port: Option<&str>) -> Result<MidiPort> {
+    let mut midi_in = MidiInput::new("keyboard-drums")
+        .map_err(|e| anyhow!("Failed to initialize MIDI input: {}", e))?;
+    midi_in.ignore(Ignore::All);
+
+    let found = midi_in.ports().into_iter().find_map(|candidate| {
+        let name = midi_in.port_name(&candidate).ok()?;
+        port.is_none_or(|wanted| name.contains(wanted))
+            .then_some((candidate, name))
+    });
+    let Some((midi_port, name)) = found else {
+        match port {
+            Some(wanted) => bail!("No MIDI input port matching '{}'", wanted),
+            None => bail!("No MIDI input ports found"),
+        }
+    };
+
+    let (sender, notes) = std::sync::mpsc::channel();
+    let connection = midi_in
+        .connect(
+            &midi_port,
+            "keyboard-drums-in",
+            move |_, message, _| {
+                if let Some(note) = parse_note_on(message) {
+                    let _ = sender.send(note);
+                }
+            },
+            (),
+        )
+        .map_err(|e| anyhow!("Failed to connect to MIDI port '{}': {}", name, e))?;
+    log::info!("Connected to MIDI port '{}'", name);
+
+    Ok(MidiPort {
+        _connection: connection,
+        notes,
+    })
+}
+
+/// Decode a note-on message into (note, velocity) on any channel. A
+/// note-on with velocity 0 means note-off and is ignored like other messages.
+fn parse_note_on(message: &[u8]) -> Option<(u8, u8)> {
+    match *message {
+        [status, note, velocity] if status & 0xF0 == 0x90 && velocity > 0 => Some((note, velocity)),
+        _ => None,
+    }
+}
+
+/// Run the input loop on a MIDI port instead of an evdev device.
+///
+/// Each mapped note-on is handled as a press of its key, with the note
+/// velocity (1 to 127) as the hit velocity, so bindings, cycling keys and
+/// banks work as they do for the keyboard. There is nothing to grab or
+/// pass through. Exits when `shutdown` is set, like `run_input_loop`.
+#[allow(clippy::too_many_arguments)]
+pub fn run_midi_loop(
+    port: MidiPort,
+    midi: &ResolvedMidi,
+    mut bindings: InputBindings,
+    mut producer: TriggerProducer,
+    shutdown: &AtomicBool,
+    heartbeat: &Heartbeat,
+    library: KitLibrary,
+    sample_bank: Arc<ArcSwap<SampleBank>>,
+    commands: Option<Receiver<ControlAction>>,
+    config_reloads: Option<Receiver<ConfigReload>>,
+) -> Result<()> {
+    log::info!(
+        "MIDI reader started, {} notes mapped to keys, {} key bindings in {} bank(s)",
+        midi.note_keys.len(),
+        bindings.banks[0].key_map.len(),
+        bindings.banks.len(),
+    );
+    if bindings.expression.is_some() || bindings.key_travel.is_some() {
+        log::warn!("[expression] and [key_travel] read evdev axes and are ignored for MIDI input");
+    }
+
+    let mut state = InputState::new(&bindings, library, sample_bank, commands, config_reloads);
+    state.source = TriggerSource::Midi;
+    let result = loop {
+        if shutdown.load(Ordering::Relaxed) {
+            log::info!("MIDI reader shutting down");
+            break Ok(());
+        }
+        heartbeat.beat();
+
+        let timeout = run_pending_work(&mut bindings, &mut producer, shutdown, &mut state);
+        match port.notes.recv_timeout(timeout) {
+            Ok((note, velocity)) => match midi.note_keys.get(&note) {
+                Some(&code) => {
+                    // Every MIDI message is a batch of its own.
+                    state.batch.clear();
+                    handle_key_down(
+                        code,
+                        Some(velocity as f32 / 127.0),
+                        &bindings,
+                        &mut producer,
+                        &mut state,
+                    );
+                    signal_bank_swap(&mut producer, &mut state.kit_state);
+                }
+                None => log::debug!("MIDI note {} is not mapped to a key", note),
+            },
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break Err(anyhow!("MIDI input port closed")),
+        }
+    };
+    heartbeat.stop();
+
+    result
+}
+
+/// Do the input thread's work besides reading input: due auto-advance
+/// steps, control actions and config reloads. Returns how long the caller
+/// may wait for input before calling this again.
+fn run_pending_work(
+    bindings: &mut InputBindings,
+    producer: &mut TriggerProducer,
+    shutdown: &AtomicBool,
+    state: &mut InputState,
+) -> Duration {
+    let now = Instant::now();
+    if let Some(auto_advance) = &mut state.auto_advance
+        && auto_advance.poll(now)
+    {
+        log::debug!("Auto-advance: next variant");
+        state.kit_state.advance();
+    }
+
+    if let Some(commands) = &state.commands {
+        while let Ok(action) = commands.try_recv() {
+            apply_control_action(
+                action,
+                bindings,
+                producer,
+                &mut state.kit_state,
+                &mut state.active_loops,
+                shutdown,
+            );
+        }
+    }
+
+    while let Some(reload) = state
+        .config_reloads
+        .as_ref()
+        .and_then(|reloads| reloads.try_recv().ok())
+    {
+        apply_config_reload(reload, bindings, producer, state);
+    }
+    state.kit_state.poll_loader();
+    signal_bank_swap(producer, &mut state.kit_state);
+
+    // Check back soon for a kit loading in the background.
+    let poll_interval = if state.kit_state.is_loading() {
+        LOADER_POLL_INTERVAL
+    } else {
+        POLL_INTERVAL
+    };
+    state
+        .auto_advance
+        .as_ref()
+        .and_then(|a| a.time_until_due(now))
+        .map_or(poll_interval, |t| t.min(poll_interval))
+}
+
+/// Wait until any of `fds` has events to read or `timeout` elapses. With
+/// no descriptors this just sleeps for `timeout`.
+///
+/// Returns how many descriptors are ready; their `revents` say which.
+fn wait_for_events(fds: &mut [libc::pollfd], timeout: Duration) -> std::io::Result<usize> {
+    let timeout_ms = timeout.as_millis().min(i32::MAX as u128) as i32;
+
+    // SAFETY: `fds` is a valid slice of pollfds that outlives the call.
+    let ret = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout_ms) };
+    if ret < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(ret as usize)
+}
+
 /// Filter and forward a single batch of events to the virtual device.
 ///
 /// Removes KEY events for suppressed key codes. If removing those KEY
@@ -390,89 +1782,552 @@ fn forward_batch(
 
 /// Process a single input event. If it's a key-down matching a binding,
 /// send a trigger to the audio thread. If it matches a cycling key,
-/// cycle the kit or variant.
+/// cycle the kit or variant. Movement on the expression axis updates the
+/// velocity scale applied to subsequent triggers, and movement on the
+/// key-travel axis is timed to set the velocity of the next key-down.
 #[inline]
 fn handle_event(
     event: &InputEvent,
-    key_map: &KeyMap,
+    bindings: &InputBindings,
     producer: &mut TriggerProducer,
-    cycling_keys: &ResolvedCyclingKeys,
-    kit_state: &mut KitState,
+    state: &mut InputState,
 ) {
-    // Only care about KEY events.
+    if event.event_type() == EventType::ABSOLUTE {
+        if let Some(expression) = &mut state.expression
+            && event.code() == expression.axis
+        {
+            expression.update(event.value());
+        }
+        if let Some(key_travel) = &mut state.key_travel
+            && event.code() == key_travel.axis
+        {
+            key_travel.update(event.value(), event.timestamp());
+        }
+        return;
+    }
+
+    // A SYN_REPORT ends the batch of events reported together.
+    if event.event_type() == EventType::SYNCHRONIZATION {
+        state.batch.clear();
+        return;
+    }
+
+    // Otherwise only care about KEY events.
     if event.event_type() != EventType::KEY {
         return;
     }
 
     // value: 0 = key up, 1 = key down, 2 = key repeat.
-    // We only trigger on key down (1).
-    let value = event.value();
-    if value != 1 {
-        return;
+    // We trigger on key down (1); key up (0) only matters to gated bindings.
+    match event.value() {
+        0 => handle_key_up(event.code(), bindings, producer, state),
+        1 => {
+            let hit_velocity = state
+                .key_travel
+                .as_mut()
+                .map(|t| t.key_down(event.timestamp()));
+            handle_key_down(event.code(), hit_velocity, bindings, producer, state);
+        }
+        _ => return,
+    }
+    signal_bank_swap(producer, &mut state.kit_state);
+}
+
+/// Tell the audio thread when a new sample bank was swapped in, so it can
+/// fade out the voices still playing the old one.
+fn signal_bank_swap(producer: &mut TriggerProducer, kit_state: &mut KitState) {
+    if std::mem::take(&mut kit_state.bank_swapped) {
+        producer.send(Trigger::with_kind(0, 0.0, TriggerKind::BankSwap));
+    }
+}
+
+/// The kit or variant change a cycling key asks for, if `code` is one.
+fn cycling_kit_action(cycling_keys: &ResolvedCyclingKeys, code: u16) -> Option<KitAction> {
+    let code = Some(code);
+    if code == cycling_keys.next_kit {
+        Some(KitAction::NextKit)
+    } else if code == cycling_keys.prev_kit {
+        Some(KitAction::PrevKit)
+    } else if code == cycling_keys.next_variant {
+        Some(KitAction::NextVariant)
+    } else if code == cycling_keys.prev_variant {
+        Some(KitAction::PrevVariant)
+    } else {
+        None
     }
+}
 
-    let code = event.code();
+/// Act on a key press from any input source: cycle kits, variants or banks
+/// for cycling keys, or send the triggers for a sample binding.
+/// `hit_velocity` is how hard the key was hit (0.0 to 1.0), when the source
+/// can tell.
+fn handle_key_down(
+    code: u16,
+    hit_velocity: Option<f32>,
+    bindings: &InputBindings,
+    producer: &mut TriggerProducer,
+    state: &mut InputState,
+) {
+    let cycling_keys = &bindings.cycling_keys;
+    let kit_state = &mut state.kit_state;
 
     // Check cycling keys first.
-    if Some(code) == cycling_keys.next_kit {
-        log::debug!("Cycling: next kit");
-        kit_state.cycle_kit(true);
+    if let Some(action) = cycling_kit_action(cycling_keys, code) {
+        log::debug!("Cycling: {:?}", action);
+        kit_state.apply(action);
+        return;
+    }
+    if Some(code) == cycling_keys.next_bank {
+        cycle_bank(bindings, state, true);
+        return;
+    }
+    if Some(code) == cycling_keys.prev_bank {
+        cycle_bank(bindings, state, false);
+        return;
+    }
+    if Some(code) == cycling_keys.panic {
+        stop_all_voices(producer, &mut state.active_loops);
+        return;
+    }
+    if Some(code) == cycling_keys.audition {
+        if let Some(audition) = &bindings.audition
+            && state.pre_audition.is_none()
+        {
+            state.pre_audition = kit_state.start_audition(audition);
+        }
+        return;
+    }
+    if Some(code) == cycling_keys.capture {
+        if let Some(capture) = &bindings.capture {
+            capture.save();
+        }
+        return;
+    }
+    if let Some(momentary) = bindings.momentary.iter().find(|m| m.key == code) {
+        kit_state.start_momentary(momentary);
+        return;
+    }
+    if let Some(select) = bindings.kit_select.iter().find(|s| s.key == code) {
+        log::debug!("Kit select: '{}'", select.kit);
+        kit_state.select_kit_by_name(&select.kit, select.variant.as_deref());
+        return;
+    }
+    if let Some(mute) = bindings.mute.iter().find(|m| m.key == code) {
+        match bindings.sample_toggles.toggle_mute(mute.sample_index) {
+            Some(true) => log::info!("Muted '{}'", mute.sample),
+            Some(false) => log::info!("Unmuted '{}'", mute.sample),
+            None => log::warn!("Cannot mute '{}' until restarted", mute.sample),
+        }
         return;
     }
-    if Some(code) == cycling_keys.prev_kit {
-        log::debug!("Cycling: previous kit");
-        kit_state.cycle_kit(false);
+    if let Some(solo) = bindings.solo.iter().find(|s| s.key == code) {
+        match bindings.sample_toggles.toggle_solo(solo.sample_index) {
+            Some(true) => log::info!("Soloed '{}'", solo.sample),
+            Some(false) => log::info!("Unsoloed '{}'", solo.sample),
+            None => log::warn!("Cannot solo '{}' until restarted", solo.sample),
+        }
         return;
     }
-    if Some(code) == cycling_keys.next_variant {
-        log::debug!("Cycling: next variant");
-        kit_state.cycle_variant(true);
+    if Some(code) == cycling_keys.toggle_metronome {
+        match &bindings.metronome {
+            Some(switch) => {
+                let on = !switch.fetch_xor(true, Ordering::Relaxed);
+                log::info!("Metronome {}", if on { "on" } else { "off" });
+            }
+            None => log::warn!("Cannot toggle the metronome until restarted"),
+        }
         return;
     }
-    if Some(code) == cycling_keys.prev_variant {
-        log::debug!("Cycling: previous variant");
-        kit_state.cycle_variant(false);
+    if Some(code) == cycling_keys.toggle_auto_advance {
+        match &mut state.auto_advance {
+            Some(auto_advance) => {
+                auto_advance.toggle(Instant::now());
+                let running = auto_advance.next_at.is_some();
+                log::info!(
+                    "Auto-advance {}",
+                    if running { "resumed" } else { "paused" }
+                );
+            }
+            None => log::debug!("Auto-advance toggle pressed but auto_advance_secs is not set"),
+        }
         return;
     }
 
     // Check sample bindings.
-    if let Some(&(sample_index, gain)) = key_map.get(&code) {
-        log::debug!(
-            "Key down: code={}, sample_index={}, gain={:.2}",
+    let bank = &bindings.banks[state.bank_index];
+    let Some(binding) = bank.key_map.get(&code) else {
+        return;
+    };
+    if let Some(hits) = &bindings.key_hits {
+        hits.record(code);
+    }
+    let simultaneous = state.batch.press(code);
+    if !simultaneous.is_empty() {
+        trigger_debug!(
+            "Key down: code={} struck together with {:?}",
             code,
-            sample_index,
-            gain
+            simultaneous
         );
-
-        producer.send(Trigger {
-            sample_id: sample_index as u8,
-            velocity: gain,
-        });
     }
-}
 
-/// Build a KeyMap from the resolved config bindings.
-///
-/// Maps evdev key code (u16) -> (sample_index, gain).
-pub fn build_key_map(key_map: &HashMap<u16, crate::config::ResolvedBinding>) -> KeyMap {
-    key_map
-        .iter()
-        .map(|(&code, binding)| (code, (binding.sample_index, binding.gain)))
-        .collect()
-}
+    // A second press of a toggle-loop key stops the loop it started, even
+    // if the bank has been switched since.
+    if binding.mode == BindingMode::ToggleLoop
+        && let Some(sample_id) = state.active_loops.remove(&code)
+    {
+        trigger_debug!(
+            "Key down: code={}, stopping loop of sample_index={}",
+            code,
+            sample_id,
+        );
+        producer.send(Trigger::with_kind(sample_id, 0.0, TriggerKind::StopLoop));
+        return;
+    }
 
-/// Build the set of key codes that should be suppressed (not forwarded).
-///
-/// This includes all sample-bound keys and all cycling keys.
-pub fn build_suppressed_keys(
-    key_map: &KeyMap,
-    cycling_keys: &ResolvedCyclingKeys,
-) -> SuppressedKeys {
-    let mut suppressed = SuppressedKeys::new();
+    let scale = state.expression.as_ref().map_or(1.0, |e| e.scale);
+    let hit = hit_velocity.unwrap_or(1.0);
+    let timing = match binding.velocity_mode {
+        VelocityMode::Fixed => 1.0,
+        VelocityMode::Timing => {
+            let now = Instant::now();
+            let since_last = state
+                .last_press
+                .insert(code, now)
+                .map(|last| now.saturating_duration_since(last));
+            timing_velocity(since_last, bindings.velocity_timing_window)
+        }
+    };
+    let strength = scale * hit * timing;
+    let velocity = binding.gain * strength;
 
-    // Add all sample-bound keys.
-    for &code in key_map.keys() {
-        suppressed.insert(code);
+    // Stacked bindings play all their samples at once, each at its own gain.
+    if !binding.stack_gains.is_empty() {
+        trigger_debug!(
+            "Key down: code={}, stacked sample_indices={:?}, velocity={:.2}",
+            code,
+            binding.sample_indices,
+            velocity,
+        );
+        for (&index, &gain) in binding.sample_indices.iter().zip(&binding.stack_gains) {
+            let sample_id = index as u16;
+            if state.batch.trigger(sample_id, bindings.simultaneous_hits) {
+                let take = state.kit_state.next_take(sample_id);
+                let trigger = Trigger::new(sample_id, velocity * gain)
+                    .with_hit(strength)
+                    .with_take(take);
+                producer.send(trigger.tagged(state.source));
+            }
+        }
+        return;
+    }
+
+    // Velocity-layered bindings pick a recording by how hard the key was
+    // played; round-robin bindings rotate through theirs, one per press.
+    let sample_index = if !binding.layer_velocities.is_empty() {
+        // Without any velocity source every hit would be full velocity and
+        // land on the loudest layer, so stay on the softest instead.
+        let has_velocity = hit_velocity.is_some()
+            || binding.velocity_mode == VelocityMode::Timing
+            || state.expression.is_some();
+        let layer = if has_velocity {
+            velocity_layer(&binding.layer_velocities, strength)
+        } else {
+            0
+        };
+        binding.sample_indices[layer]
+    } else {
+        match binding.sample_indices.as_slice() {
+            [index] => *index,
+            indices => {
+                let position = state.round_robin.entry(code).or_insert(0);
+                let index = indices[*position % indices.len()];
+                *position = (*position + 1) % indices.len();
+                index
+            }
+        }
+    };
+    let sample_id = sample_index as u16;
+    trigger_debug!(
+        "Key down: code={}, sample_index={}, gain={:.2}, expression={:.2}, hit={:.2}, timing={:.2}",
+        code,
+        sample_index,
+        binding.gain,
+        scale,
+        hit,
+        timing,
+    );
+
+    let trigger = match binding.mode {
+        BindingMode::OneShot => {
+            if !state.batch.trigger(sample_id, bindings.simultaneous_hits) {
+                trigger_debug!(
+                    "Key down: code={}, sample_index={} already played in this batch",
+                    code,
+                    sample_index,
+                );
+                return;
+            }
+            Trigger::new(sample_id, velocity)
+        }
+        BindingMode::ToggleLoop => {
+            state.active_loops.insert(code, sample_id);
+            Trigger::with_kind(sample_id, velocity, TriggerKind::StartLoop)
+        }
+    };
+    let take = state.kit_state.next_take(sample_id);
+    let trigger = trigger
+        .with_hit(strength)
+        .with_take(take)
+        .tagged(state.source);
+    producer.send(trigger);
+}
+
+/// Release the samples of a gated binding when its key goes up. Their
+/// voices fade out quickly, including those of earlier presses. Releasing
+/// the audition key or a momentary key returns to the previous kit.
+fn handle_key_up(
+    code: u16,
+    bindings: &InputBindings,
+    producer: &mut TriggerProducer,
+    state: &mut InputState,
+) {
+    if Some(code) == bindings.cycling_keys.audition {
+        if let Some(previous) = state.pre_audition.take() {
+            log::info!(
+                "Audition over, back to kit '{}' variant '{}'",
+                previous.kit_name,
+                previous.variant_name
+            );
+            state.kit_state.restore_bank(previous);
+        }
+        return;
+    }
+    if state.kit_state.end_momentary(code) {
+        return;
+    }
+
+    let bank = &bindings.banks[state.bank_index];
+    let Some(binding) = bank.key_map.get(&code).filter(|binding| binding.gate) else {
+        return;
+    };
+    trigger_debug!(
+        "Key up: code={}, releasing sample_indices={:?}",
+        code,
+        binding.sample_indices,
+    );
+    for &index in &binding.sample_indices {
+        producer.send(Trigger::with_kind(index as u16, 0.0, TriggerKind::Release));
+    }
+}
+
+/// Index of the velocity layer a hit at `velocity` plays: the last layer
+/// whose lowest velocity is at or below it, or the first (softest) layer
+/// if it is below all of them. `min_velocities` must be ascending.
+fn velocity_layer(min_velocities: &[f32], velocity: f32) -> usize {
+    min_velocities
+        .iter()
+        .rposition(|&min| min <= velocity)
+        .unwrap_or(0)
+}
+
+/// Silence every playing voice, for the panic key and command.
+fn stop_all_voices(producer: &mut TriggerProducer, active_loops: &mut HashMap<u16, u16>) {
+    log::info!("Panic: stopping all voices");
+    producer.send(Trigger::with_kind(0, 0.0, TriggerKind::StopAll));
+    // Loops are silenced too, so their keys start them again.
+    active_loops.clear();
+}
+
+/// Apply an action typed at the terminal or sent by the preview server, an
+/// OSC controller or the control socket.
+fn apply_control_action(
+    action: ControlAction,
+    bindings: &InputBindings,
+    producer: &mut TriggerProducer,
+    kit_state: &mut KitState,
+    active_loops: &mut HashMap<u16, u16>,
+    shutdown: &AtomicBool,
+) {
+    match action {
+        ControlAction::Kit(action) => kit_state.apply(action),
+        ControlAction::Panic => stop_all_voices(producer, active_loops),
+        ControlAction::SetVolume(volume) => {
+            bindings.master_volume.set(volume);
+            log::info!("Master volume set to {:.2}", volume);
+        }
+        ControlAction::TriggerSampleNamed(name) => {
+            let sample_names = &kit_state.library.sample_names;
+            match sample_names.iter().position(|n| *n == name) {
+                Some(index) => {
+                    let take = kit_state.next_take(index as u16);
+                    let trigger = Trigger::new(index as u16, 1.0).with_take(take);
+                    producer.send(trigger.tagged(TriggerSource::Preview));
+                }
+                None => log::warn!("Preview trigger for sample '{}' that is not bound", name),
+            }
+        }
+        ControlAction::Quit => {
+            log::info!("Quit requested from the terminal");
+            shutdown.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Switch to the bindings of a reloaded config file. If the current kit
+/// cannot be loaded with the new sample list, the previous bindings stay.
+fn apply_config_reload(
+    reload: ConfigReload,
+    bindings: &mut InputBindings,
+    producer: &mut TriggerProducer,
+    state: &mut InputState,
+) {
+    if let Err(e) = state
+        .kit_state
+        .swap_samples(reload.sample_names, reload.settings)
+    {
+        log::error!(
+            "Config reload: failed to load the current kit with the new bindings, keeping the previous config: {:#}",
+            e
+        );
+        if let Some(cue) = &bindings.error_cue {
+            cue.request();
+        }
+        return;
+    }
+
+    // Sample ids may have moved, so loops started by the old bindings could
+    // not be stopped by their keys any more.
+    for (_, sample_id) in state.active_loops.drain() {
+        producer.send(Trigger::with_kind(sample_id, 0.0, TriggerKind::StopLoop));
+    }
+    // For the same reason, mutes and solos start over.
+    bindings.sample_toggles.clear();
+    bindings
+        .sample_names
+        .store(Arc::new(state.kit_state.library.sample_names.clone()));
+
+    let binding_count = reload.banks.iter().map(|bank| bank.key_map.len()).sum();
+    bindings.banks = reload.banks;
+    bindings.cycling_keys = reload.cycling_keys;
+    bindings.momentary = reload.momentary;
+    bindings.kit_select = reload.kit_select;
+    bindings.mute = reload.mute;
+    bindings.solo = reload.solo;
+    bindings.velocity_timing_window = reload.velocity_timing_window;
+    if state.bank_index >= bindings.banks.len() {
+        state.bank_index = 0;
+    }
+    state.last_press = HashMap::with_capacity(binding_count);
+    state.round_robin = HashMap::with_capacity(binding_count);
+
+    log::info!(
+        "Config reloaded: {} key bindings in {} bank(s)",
+        bindings.banks[0].key_map.len(),
+        bindings.banks.len(),
+    );
+}
+
+/// Switch to the next or previous binding bank, wrapping around.
+fn cycle_bank(bindings: &InputBindings, state: &mut InputState, forward: bool) {
+    let count = bindings.banks.len();
+    if count <= 1 {
+        log::debug!("Bank switch pressed but only one binding bank is configured");
+        return;
+    }
+    state.bank_index = if forward {
+        (state.bank_index + 1) % count
+    } else {
+        (state.bank_index + count - 1) % count
+    };
+    log::info!(
+        "Switched to binding bank '{}' ({}/{})",
+        bindings.banks[state.bank_index].name,
+        state.bank_index + 1,
+        count,
+    );
+}
+
+/// Build the binding banks for a config: the default bank from `bindings`,
+/// then any `[[banks]]`.
+pub fn build_binding_banks(resolved: &ResolvedConfig) -> Vec<BindingBank> {
+    let kit_keys = resolved.momentary.iter().chain(&resolved.kit_select);
+    let sample_keys = resolved.mute.iter().chain(&resolved.solo);
+    let control_keys: Vec<u16> = kit_keys
+        .map(|entry| entry.key)
+        .chain(sample_keys.map(|entry| entry.key))
+        .collect();
+
+    let mut banks = vec![build_binding_bank(
+        "default",
+        &resolved.key_map,
+        &resolved.cycling_keys,
+        &control_keys,
+    )];
+    for bank in &resolved.banks {
+        banks.push(build_binding_bank(
+            &bank.name,
+            &bank.key_map,
+            &resolved.cycling_keys,
+            &control_keys,
+        ));
+    }
+    banks
+}
+
+/// Build a binding bank from resolved config bindings. `control_keys` are
+/// the momentary, kit select, mute and solo keys.
+pub fn build_binding_bank(
+    name: &str,
+    key_map: &HashMap<u16, crate::config::ResolvedBinding>,
+    cycling_keys: &ResolvedCyclingKeys,
+    control_keys: &[u16],
+) -> BindingBank {
+    let key_map = build_key_map(key_map);
+    let suppressed_keys = build_suppressed_keys(&key_map, cycling_keys, control_keys);
+    BindingBank {
+        name: name.to_string(),
+        key_map,
+        suppressed_keys,
+    }
+}
+
+/// Build a KeyMap from the resolved config bindings.
+///
+/// Maps evdev key code (u16) -> sample index, gain and mode.
+pub fn build_key_map(key_map: &HashMap<u16, crate::config::ResolvedBinding>) -> KeyMap {
+    key_map
+        .iter()
+        .map(|(&code, binding)| {
+            (
+                code,
+                KeyBinding {
+                    sample_indices: binding.sample_indices.clone(),
+                    layer_velocities: binding.layer_velocities.clone(),
+                    stack_gains: binding.stack_gains.clone(),
+                    gain: binding.gain,
+                    mode: binding.mode,
+                    gate: binding.gate,
+                    velocity_mode: binding.velocity_mode,
+                },
+            )
+        })
+        .collect()
+}
+
+/// Build the set of key codes that should be suppressed (not forwarded).
+///
+/// This includes all sample-bound keys, all cycling keys and the
+/// `control_keys`: momentary, kit select, mute and solo keys.
+pub fn build_suppressed_keys(
+    key_map: &KeyMap,
+    cycling_keys: &ResolvedCyclingKeys,
+    control_keys: &[u16],
+) -> SuppressedKeys {
+    let mut suppressed = SuppressedKeys::new();
+
+    // Add all sample-bound keys.
+    for &code in key_map.keys() {
+        suppressed.insert(code);
     }
 
     // Add all configured cycling keys.
@@ -488,6 +2343,32 @@ pub fn build_suppressed_keys(
     if let Some(code) = cycling_keys.prev_variant {
         suppressed.insert(code);
     }
+    if let Some(code) = cycling_keys.toggle_auto_advance {
+        suppressed.insert(code);
+    }
+    if let Some(code) = cycling_keys.next_bank {
+        suppressed.insert(code);
+    }
+    if let Some(code) = cycling_keys.prev_bank {
+        suppressed.insert(code);
+    }
+    if let Some(code) = cycling_keys.panic {
+        suppressed.insert(code);
+    }
+    if let Some(code) = cycling_keys.audition {
+        suppressed.insert(code);
+    }
+    if let Some(code) = cycling_keys.capture {
+        suppressed.insert(code);
+    }
+    if let Some(code) = cycling_keys.toggle_metronome {
+        suppressed.insert(code);
+    }
+
+    // Add all momentary, kit select, mute and solo keys.
+    for &code in control_keys {
+        suppressed.insert(code);
+    }
 
     suppressed
 }
@@ -497,305 +2378,1491 @@ pub fn build_suppressed_keys(
 /// virtual device must be able to emit every event type the physical one
 /// can, so that non-suppressed events (including any relative/absolute
 /// axes, switches, LEDs, etc.) are forwarded transparently.
-pub fn create_virtual_device(device: &Device) -> Result<VirtualDevice> {
+pub fn create_virtual_device(
+    device: &Device,
+    identity: &ResolvedVirtualDevice,
+) -> Result<VirtualDevice> {
     let mut builder = VirtualDevice::builder()
         .map_err(|e| anyhow::anyhow!("Failed to open /dev/uinput: {}", e))?
-        .name(b"keyboard-drums passthrough");
+        .name(identity.name.as_str());
+
+    if let Some(input_id) = virtual_input_id(identity, &device.input_id()) {
+        log::info!(
+            "Virtual device IDs: bus {:?}, vendor {:04x}, product {:04x}, version {:04x}",
+            input_id.bus_type(),
+            input_id.vendor(),
+            input_id.product(),
+            input_id.version(),
+        );
+        builder = builder.input_id(input_id);
+    }
+
+    // Mirror key capabilities.
+    if let Some(keys) = device.supported_keys() {
+        let mut key_set = AttributeSet::<KeyCode>::new();
+        for key in keys.iter() {
+            key_set.insert(key);
+        }
+        builder = builder
+            .with_keys(&key_set)
+            .map_err(|e| anyhow::anyhow!("Failed to set virtual device keys: {}", e))?;
+    }
+
+    // Mirror relative axis capabilities (mouse movement, scroll wheel, etc.).
+    if let Some(rel_axes) = device.supported_relative_axes() {
+        builder = builder
+            .with_relative_axes(rel_axes)
+            .map_err(|e| anyhow::anyhow!("Failed to set virtual device relative axes: {}", e))?;
+    }
+
+    // Mirror absolute axis capabilities (touchpad, etc.).
+    if let Ok(absinfo_iter) = device.get_absinfo() {
+        for (axis_code, abs_info) in absinfo_iter {
+            let setup = UinputAbsSetup::new(axis_code, abs_info);
+            builder = builder.with_absolute_axis(&setup).map_err(|e| {
+                anyhow::anyhow!("Failed to set virtual device absolute axis: {}", e)
+            })?;
+        }
+    }
+
+    // Mirror switch capabilities.
+    if let Some(switches) = device.supported_switches() {
+        builder = builder
+            .with_switches(switches)
+            .map_err(|e| anyhow::anyhow!("Failed to set virtual device switches: {}", e))?;
+    }
+
+    // Mirror LED capabilities.
+    // Note: VirtualDeviceBuilder doesn't have with_leds(), so LEDs are
+    // not mirrored. This is acceptable — LED state is managed by the
+    // kernel and doesn't affect input forwarding.
+
+    let virt = builder
+        .build()
+        .map_err(|e| anyhow::anyhow!("Failed to create virtual device: {}", e))?;
+
+    log::info!("Created virtual device mirroring physical device capabilities");
+
+    Ok(virt)
+}
+
+/// Input ID for the virtual device: configured IDs, with any left unset
+/// copied from the physical device. None if no ID is configured, keeping
+/// the uinput default.
+fn virtual_input_id(identity: &ResolvedVirtualDevice, physical: &InputId) -> Option<InputId> {
+    if identity.bus_type.is_none()
+        && identity.vendor.is_none()
+        && identity.product.is_none()
+        && identity.version.is_none()
+    {
+        return None;
+    }
+    Some(InputId::new(
+        BusType(identity.bus_type.unwrap_or(physical.bus_type().0)),
+        identity.vendor.unwrap_or(physical.vendor()),
+        identity.product.unwrap_or(physical.product()),
+        identity.version.unwrap_or(physical.version()),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ring;
+    use crate::samples::SampleBank;
+
+    fn one_shot(sample_index: usize, gain: f32) -> KeyBinding {
+        KeyBinding {
+            sample_indices: vec![sample_index],
+            layer_velocities: Vec::new(),
+            stack_gains: Vec::new(),
+            gain,
+            mode: BindingMode::OneShot,
+            gate: false,
+            velocity_mode: VelocityMode::Fixed,
+        }
+    }
+
+    fn make_dummy_cycling_keys() -> ResolvedCyclingKeys {
+        ResolvedCyclingKeys::default()
+    }
+
+    fn make_bindings(key_map: KeyMap, cycling_keys: ResolvedCyclingKeys) -> InputBindings {
+        make_banked_bindings(vec![key_map], cycling_keys)
+    }
+
+    fn make_banked_bindings(
+        key_maps: Vec<KeyMap>,
+        cycling_keys: ResolvedCyclingKeys,
+    ) -> InputBindings {
+        let banks = key_maps
+            .into_iter()
+            .enumerate()
+            .map(|(i, key_map)| BindingBank {
+                name: format!("bank{}", i),
+                suppressed_keys: build_suppressed_keys(&key_map, &cycling_keys, &[]),
+                key_map,
+            })
+            .collect();
+        InputBindings {
+            banks,
+            cycling_keys,
+            expression: None,
+            key_travel: None,
+            velocity_timing_window: Duration::from_millis(40),
+            simultaneous_hits: SimultaneousHits::All,
+            auto_advance: None,
+            audition: None,
+            capture: None,
+            momentary: Vec::new(),
+            kit_select: Vec::new(),
+            mute: Vec::new(),
+            solo: Vec::new(),
+            sample_toggles: Arc::new(SampleToggles::new(1)),
+            metronome: None,
+            error_cue: None,
+            key_hits: None,
+            master_volume: Arc::new(MasterVolume::new(1.0)),
+            sample_names: Arc::new(ArcSwap::from_pointee(Vec::new())),
+        }
+    }
+
+    fn make_dummy_state() -> InputState {
+        InputState {
+            kit_state: make_dummy_kit_state(),
+            bank_index: 0,
+            expression: None,
+            key_travel: None,
+            auto_advance: None,
+            commands: None,
+            config_reloads: None,
+            active_loops: HashMap::new(),
+            last_press: HashMap::new(),
+            round_robin: HashMap::new(),
+            batch: HitBatch::default(),
+            pre_audition: None,
+            source: TriggerSource::Keyboard,
+        }
+    }
+
+    fn make_dummy_kit_state() -> KitState {
+        use crate::samples::{KitInfo, KitLibrary, LoadOptions, SampleData, SampleSettings};
+        use std::path::PathBuf;
+
+        let bank = Arc::new(ArcSwap::from_pointee(SampleBank {
+            samples: vec![Arc::new(SampleData {
+                data: vec![0.0],
+                channels: 1,
+                sample_rate: 48000,
+            })],
+            settings: SampleSettings::default(),
+            pools: Vec::new(),
+            reverb: None,
+            kit_name: "test".to_string(),
+            variant_name: "v1".to_string(),
+        }));
+
+        KitState {
+            library: KitLibrary {
+                samples_dir: PathBuf::from("/tmp"),
+                kits: vec![KitInfo {
+                    name: "test".to_string(),
+                    variants: vec!["v1".to_string()],
+                }],
+                sample_names: vec!["kick.wav".to_string()],
+                settings: SampleSettings::default(),
+                load_options: LoadOptions::default(),
+                loudness_normalize_velocity: false,
+                preload_all: false,
+                preloaded: HashMap::new(),
+                background_loading: false,
+            },
+            sample_bank: bank,
+            kit_index: 0,
+            variant_index: 0,
+            error_cue: None,
+            momentary_return: None,
+            bank_swapped: false,
+            loader: None,
+            interrupted_load: false,
+            pool_turns: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_keyboards_are_told_apart_by_key_count() {
+        let summary = |keys: &[KeyCode]| DeviceSummary {
+            path: PathBuf::from("/dev/input/event0"),
+            name: "test".to_string(),
+            key_count: keyboard_key_count(keys.iter().copied()),
+        };
+
+        // A power button has one key; a mouse's buttons are not keys.
+        assert_eq!(summary(&[KeyCode::KEY_POWER]).key_count, 1);
+        let mouse = summary(&[KeyCode::BTN_LEFT, KeyCode::BTN_RIGHT, KeyCode::BTN_MIDDLE]);
+        assert_eq!(mouse.key_count, 0);
+        assert!(!mouse.is_likely_keyboard());
+
+        // Letters and digits make a keyboard.
+        let keys: Vec<KeyCode> = (KeyCode::KEY_1.code()..=KeyCode::KEY_P.code())
+            .map(KeyCode::new)
+            .chain([KeyCode::BTN_LEFT])
+            .collect();
+        let keyboard = summary(&keys);
+        assert_eq!(keyboard.key_count, keys.len() - 1);
+        assert!(keyboard.is_likely_keyboard());
+        assert_eq!(keyboard.marker(), '*');
+    }
+
+    #[test]
+    fn test_handle_event_key_down_match() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let cycling = make_dummy_cycling_keys();
+        let mut state = make_dummy_state();
+
+        let mut key_map = KeyMap::new();
+        key_map.insert(30, one_shot(0, 0.8)); // KEY_A = 30
+
+        // Simulate a KEY_A down event (type=1 EV_KEY, code=30, value=1).
+        let event = InputEvent::new(EventType::KEY.0, 30, 1);
+        let bindings = make_bindings(key_map, cycling);
+        handle_event(&event, &bindings, &mut prod, &mut state);
+
+        let mut buf = Vec::new();
+        cons.drain(&mut buf);
+        assert_eq!(buf.len(), 1);
+        assert_eq!(buf[0].sample_id, 0);
+        assert!((buf[0].velocity - 0.8).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn test_key_down_counts_a_hit() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, _cons) = ring::default_trigger_channel();
+        let mut state = make_dummy_state();
+
+        let mut key_map = KeyMap::new();
+        key_map.insert(30, one_shot(0, 1.0)); // KEY_A = 30
+        key_map.insert(31, one_shot(1, 1.0)); // KEY_S = 31
+        let mut bindings = make_bindings(key_map, make_dummy_cycling_keys());
+        let hits = Arc::new(KeyHits::new([
+            (30, "kick.wav".to_string()),
+            (31, "snare.wav".to_string()),
+        ]));
+        bindings.key_hits = Some(Arc::clone(&hits));
+
+        let down = InputEvent::new(EventType::KEY.0, 30, 1);
+        let up = InputEvent::new(EventType::KEY.0, 30, 0);
+        let repeat = InputEvent::new(EventType::KEY.0, 30, 2);
+        for event in [&down, &repeat, &up, &down] {
+            handle_event(event, &bindings, &mut prod, &mut state);
+        }
+        handle_event(
+            &InputEvent::new(EventType::KEY.0, 31, 1),
+            &bindings,
+            &mut prod,
+            &mut state,
+        );
+
+        // Only key-downs count; unpressed keys are left out.
+        assert_eq!(
+            hits.summary(),
+            vec![(30, "kick.wav", 2), (31, "snare.wav", 1)]
+        );
+    }
+
+    #[test]
+    fn test_handle_event_key_up_ignored() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let cycling = make_dummy_cycling_keys();
+        let mut state = make_dummy_state();
+
+        let mut key_map = KeyMap::new();
+        key_map.insert(30, one_shot(0, 1.0));
+
+        // Key up event (value=0) should be ignored.
+        let event = InputEvent::new(EventType::KEY.0, 30, 0);
+        let bindings = make_bindings(key_map, cycling);
+        handle_event(&event, &bindings, &mut prod, &mut state);
+
+        let mut buf = Vec::new();
+        cons.drain(&mut buf);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_handle_event_key_repeat_ignored() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let cycling = make_dummy_cycling_keys();
+        let mut state = make_dummy_state();
+
+        let mut key_map = KeyMap::new();
+        key_map.insert(30, one_shot(0, 1.0));
+
+        // Key repeat event (value=2) should be ignored.
+        let event = InputEvent::new(EventType::KEY.0, 30, 2);
+        let bindings = make_bindings(key_map, cycling);
+        handle_event(&event, &bindings, &mut prod, &mut state);
+
+        let mut buf = Vec::new();
+        cons.drain(&mut buf);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_handle_event_unbound_key() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let cycling = make_dummy_cycling_keys();
+        let mut state = make_dummy_state();
+
+        let mut key_map = KeyMap::new();
+        key_map.insert(30, one_shot(0, 1.0)); // KEY_A
+
+        // KEY_B (code=48) is not bound.
+        let event = InputEvent::new(EventType::KEY.0, 48, 1);
+        let bindings = make_bindings(key_map, cycling);
+        handle_event(&event, &bindings, &mut prod, &mut state);
+
+        let mut buf = Vec::new();
+        cons.drain(&mut buf);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_handle_event_non_key_event() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let cycling = make_dummy_cycling_keys();
+        let mut state = make_dummy_state();
+
+        let mut key_map = KeyMap::new();
+        key_map.insert(30, one_shot(0, 1.0));
+
+        // A non-KEY event (EV_REL = 2).
+        let event = InputEvent::new(EventType::RELATIVE.0, 0, 1);
+        let bindings = make_bindings(key_map, cycling);
+        handle_event(&event, &bindings, &mut prod, &mut state);
+
+        let mut buf = Vec::new();
+        cons.drain(&mut buf);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_build_key_map() {
+        use crate::config::ResolvedBinding;
+        use evdev::KeyCode;
+
+        let mut config_map = HashMap::new();
+        config_map.insert(
+            KeyCode::KEY_A.code(),
+            ResolvedBinding {
+                sample_indices: vec![0],
+                layer_velocities: Vec::new(),
+                stack_gains: Vec::new(),
+                gain: 0.9,
+                reverb_send: 0.0,
+                min_gain: 0.0,
+                pan: 0.0,
+                playback_rate: 1.0,
+                protect_from_steal: false,
+                reserved_voices: 0,
+                mode: BindingMode::OneShot,
+                gate: false,
+                looping: false,
+                loop_start: 0,
+                loop_end: None,
+                choke_group: 0,
+                velocity_mode: VelocityMode::Fixed,
+                start_region: None,
+                vel_attack: None,
+                ignore_sample_rate: false,
+                bypass_master_fx: false,
+                filter: None,
+            },
+        );
+        config_map.insert(
+            KeyCode::KEY_S.code(),
+            ResolvedBinding {
+                sample_indices: vec![1],
+                layer_velocities: Vec::new(),
+                stack_gains: Vec::new(),
+                gain: 0.7,
+                reverb_send: 0.0,
+                min_gain: 0.0,
+                pan: 0.0,
+                playback_rate: 1.0,
+                protect_from_steal: false,
+                reserved_voices: 0,
+                mode: BindingMode::OneShot,
+                gate: false,
+                looping: false,
+                loop_start: 0,
+                loop_end: None,
+                choke_group: 0,
+                velocity_mode: VelocityMode::Fixed,
+                start_region: None,
+                vel_attack: None,
+                ignore_sample_rate: false,
+                bypass_master_fx: false,
+                filter: None,
+            },
+        );
+
+        let key_map = build_key_map(&config_map);
+        assert_eq!(key_map.len(), 2);
+
+        let binding = &key_map[&KeyCode::KEY_A.code()];
+        assert_eq!(binding.sample_indices, [0]);
+        assert!((binding.gain - 0.9).abs() < f32::EPSILON);
+
+        let binding = &key_map[&KeyCode::KEY_S.code()];
+        assert_eq!(binding.sample_indices, [1]);
+        assert!((binding.gain - 0.7).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn test_cycling_key_does_not_trigger_sample() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let mut state = make_dummy_state();
+
+        // KEY_RIGHT (code=106) is next_kit cycling key.
+        let cycling = ResolvedCyclingKeys {
+            next_kit: Some(106),
+            ..Default::default()
+        };
+
+        // Also bind KEY_RIGHT as a sample key (should be prevented by config,
+        // but verify cycling takes priority).
+        let mut key_map = KeyMap::new();
+        key_map.insert(106, one_shot(0, 1.0));
+
+        let event = InputEvent::new(EventType::KEY.0, 106, 1);
+        let bindings = make_bindings(key_map, cycling);
+        handle_event(&event, &bindings, &mut prod, &mut state);
+
+        // No sample trigger should be sent — cycling takes priority. The
+        // audio thread only hears that the bank was swapped.
+        let mut buf = Vec::new();
+        cons.drain(&mut buf);
+        let kinds: Vec<TriggerKind> = buf.iter().map(|t| t.kind).collect();
+        assert_eq!(kinds, vec![TriggerKind::BankSwap]);
+    }
+
+    #[test]
+    fn test_background_loading_keeps_old_bank_until_new_one_is_ready() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let mut state = make_dummy_state();
+        let kit_state = &mut state.kit_state;
+        kit_state.library.sample_names = vec!["synth:click".to_string()];
+        kit_state.library.kits.push(crate::samples::KitInfo {
+            name: "electronic".to_string(),
+            variants: vec!["dry".to_string()],
+        });
+        kit_state.loader = Some(BankLoader::new().unwrap());
+        let original = kit_state.sample_bank.load_full();
+
+        // The switch returns at once, with the old bank still playing.
+        kit_state.cycle_kit(true);
+        assert_eq!(kit_state.kit_index, 1);
+        assert!(Arc::ptr_eq(&kit_state.sample_bank.load_full(), &original));
+        assert!(!kit_state.bank_swapped);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while kit_state.is_loading() {
+            assert!(Instant::now() < deadline, "kit never loaded");
+            std::thread::sleep(Duration::from_millis(1));
+            kit_state.poll_loader();
+        }
+        assert_eq!(kit_state.sample_bank.load().kit_name, "electronic");
+        assert!(kit_state.bank_swapped);
+    }
+
+    #[test]
+    fn test_holding_audition_key_swaps_kit_until_released() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, _cons) = ring::default_trigger_channel();
+        let mut state = make_dummy_state();
+
+        // A synth sample loads without files, so any kit folder will do.
+        let library = &mut state.kit_state.library;
+        library.sample_names = vec!["synth:click".to_string()];
+        library.kits.push(crate::samples::KitInfo {
+            name: "reference".to_string(),
+            variants: vec!["dry".to_string(), "wet".to_string()],
+        });
+
+        // KEY_F12 (code=88) auditions the reference kit's wet variant.
+        let cycling = ResolvedCyclingKeys {
+            audition: Some(88),
+            ..Default::default()
+        };
+        let mut bindings = make_bindings(KeyMap::new(), cycling);
+        bindings.audition = Some(AuditionConfig {
+            kit: "reference".to_string(),
+            variant: Some("wet".to_string()),
+        });
+        let original = state.kit_state.sample_bank.load_full();
+
+        let down = InputEvent::new(EventType::KEY.0, 88, 1);
+        handle_event(&down, &bindings, &mut prod, &mut state);
+        let auditioned = state.kit_state.sample_bank.load_full();
+        assert_eq!(auditioned.kit_name, "reference");
+        assert_eq!(auditioned.variant_name, "wet");
+        assert_eq!(state.kit_state.kit_index, 0);
+
+        // Key repeat while held changes nothing.
+        let repeat = InputEvent::new(EventType::KEY.0, 88, 2);
+        handle_event(&repeat, &bindings, &mut prod, &mut state);
+        assert!(Arc::ptr_eq(
+            &state.kit_state.sample_bank.load_full(),
+            &auditioned
+        ));
+
+        let up = InputEvent::new(EventType::KEY.0, 88, 0);
+        handle_event(&up, &bindings, &mut prod, &mut state);
+        assert!(Arc::ptr_eq(
+            &state.kit_state.sample_bank.load_full(),
+            &original
+        ));
+        assert!(state.pre_audition.is_none());
+    }
+
+    #[test]
+    fn test_momentary_key_switches_kit_until_released() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, _cons) = ring::default_trigger_channel();
+        let mut state = make_dummy_state();
+
+        // Two kits; a synth sample loads without files.
+        let library = &mut state.kit_state.library;
+        library.sample_names = vec!["synth:click".to_string()];
+        library.kits.push(crate::samples::KitInfo {
+            name: "fills".to_string(),
+            variants: vec!["tight".to_string(), "roomy".to_string()],
+        });
+
+        // KEY_F1 (code=59) holds the fills kit's roomy variant.
+        let mut bindings = make_bindings(KeyMap::new(), make_dummy_cycling_keys());
+        bindings.momentary = vec![ResolvedKitKey {
+            key: 59,
+            kit: "fills".to_string(),
+            variant: Some("roomy".to_string()),
+        }];
+        let original = state.kit_state.sample_bank.load_full();
+
+        for _ in 0..2 {
+            let down = InputEvent::new(EventType::KEY.0, 59, 1);
+            handle_event(&down, &bindings, &mut prod, &mut state);
+            let held = state.kit_state.sample_bank.load_full();
+            assert_eq!(held.kit_name, "fills");
+            assert_eq!(held.variant_name, "roomy");
+            assert_eq!(
+                (state.kit_state.kit_index, state.kit_state.variant_index),
+                (1, 1)
+            );
+
+            let up = InputEvent::new(EventType::KEY.0, 59, 0);
+            handle_event(&up, &bindings, &mut prod, &mut state);
+            assert!(Arc::ptr_eq(
+                &state.kit_state.sample_bank.load_full(),
+                &original
+            ));
+            assert_eq!(
+                (state.kit_state.kit_index, state.kit_state.variant_index),
+                (0, 0)
+            );
+            assert!(state.kit_state.momentary_return.is_none());
+        }
+    }
+
+    #[test]
+    fn test_kit_select_key_jumps_to_named_kit() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, _cons) = ring::default_trigger_channel();
+        let mut state = make_dummy_state();
+
+        let library = &mut state.kit_state.library;
+        library.sample_names = vec!["synth:click".to_string()];
+        for name in ["electronic", "acoustic"] {
+            library.kits.push(crate::samples::KitInfo {
+                name: name.to_string(),
+                variants: vec!["dry".to_string(), "wet".to_string()],
+            });
+        }
+
+        // KEY_F2 (code=60) selects acoustic/wet; KEY_F3 (code=61) a kit
+        // that does not exist.
+        let mut bindings = make_bindings(KeyMap::new(), make_dummy_cycling_keys());
+        bindings.kit_select = vec![
+            ResolvedKitKey {
+                key: 60,
+                kit: "acoustic".to_string(),
+                variant: Some("wet".to_string()),
+            },
+            ResolvedKitKey {
+                key: 61,
+                kit: "missing".to_string(),
+                variant: None,
+            },
+        ];
+
+        let down = InputEvent::new(EventType::KEY.0, 60, 1);
+        handle_event(&down, &bindings, &mut prod, &mut state);
+        assert_eq!(state.kit_state.kit_index, 2);
+        assert_eq!(state.kit_state.variant_index, 1);
+        assert_eq!(state.kit_state.sample_bank.load().kit_name, "acoustic");
+
+        // An unknown kit leaves the selection alone.
+        let down = InputEvent::new(EventType::KEY.0, 61, 1);
+        handle_event(&down, &bindings, &mut prod, &mut state);
+        assert_eq!(
+            (state.kit_state.kit_index, state.kit_state.variant_index),
+            (2, 1)
+        );
+    }
+
+    #[test]
+    fn test_gated_key_up_releases_its_samples() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let mut state = make_dummy_state();
+
+        let mut key_map = KeyMap::new();
+        key_map.insert(
+            30, // KEY_A
+            KeyBinding {
+                gate: true,
+                ..one_shot(0, 1.0)
+            },
+        );
+        key_map.insert(31, one_shot(1, 1.0)); // KEY_S, not gated
+        let bindings = make_bindings(key_map, make_dummy_cycling_keys());
+
+        for code in [30, 31] {
+            for value in [1, 0] {
+                let event = InputEvent::new(EventType::KEY.0, code, value);
+                handle_event(&event, &bindings, &mut prod, &mut state);
+            }
+        }
+
+        let mut buf = Vec::new();
+        cons.drain(&mut buf);
+        let triggers: Vec<(u16, TriggerKind)> = buf.iter().map(|t| (t.sample_id, t.kind)).collect();
+        assert_eq!(
+            triggers,
+            vec![
+                (0, TriggerKind::OneShot),
+                (0, TriggerKind::Release),
+                (1, TriggerKind::OneShot),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_panic_key_stops_all_voices_and_loops() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let mut state = make_dummy_state();
+
+        // KEY_ESC (code=1) is the panic key.
+        let cycling = ResolvedCyclingKeys {
+            panic: Some(1),
+            ..Default::default()
+        };
+        let mut key_map = KeyMap::new();
+        key_map.insert(
+            30, // KEY_A
+            KeyBinding {
+                mode: BindingMode::ToggleLoop,
+                ..one_shot(0, 1.0)
+            },
+        );
+        let bindings = make_bindings(key_map, cycling);
+        assert!(bindings.banks[0].suppressed_keys.contains(&1));
+
+        let a_down = InputEvent::new(EventType::KEY.0, 30, 1);
+        let panic_down = InputEvent::new(EventType::KEY.0, 1, 1);
+        handle_event(&a_down, &bindings, &mut prod, &mut state);
+        handle_event(&panic_down, &bindings, &mut prod, &mut state);
+        assert!(state.active_loops.is_empty());
+
+        // The loop key starts its loop again after a panic.
+        handle_event(&a_down, &bindings, &mut prod, &mut state);
+
+        let mut buf = Vec::new();
+        cons.drain(&mut buf);
+        let kinds: Vec<TriggerKind> = buf.iter().map(|t| t.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TriggerKind::StartLoop,
+                TriggerKind::StopAll,
+                TriggerKind::StartLoop
+            ]
+        );
+    }
+
+    #[test]
+    fn test_switching_bank_changes_sample_but_not_kit() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let mut state = make_dummy_state();
+
+        // KEY_PAGEDOWN (code=109) switches to the next bank.
+        let cycling = ResolvedCyclingKeys {
+            next_bank: Some(109),
+            ..Default::default()
+        };
+
+        // KEY_A plays sample 0 in the first bank and sample 1 in the second.
+        let mut drums = KeyMap::new();
+        drums.insert(30, one_shot(0, 1.0));
+        let mut percussion = KeyMap::new();
+        percussion.insert(30, one_shot(1, 1.0));
+        let bindings = make_banked_bindings(vec![drums, percussion], cycling);
+
+        let key_a = InputEvent::new(EventType::KEY.0, 30, 1);
+        let next_bank = InputEvent::new(EventType::KEY.0, 109, 1);
+        let mut buf = Vec::new();
+
+        handle_event(&key_a, &bindings, &mut prod, &mut state);
+        cons.drain(&mut buf);
+        assert_eq!(buf[0].sample_id, 0);
+
+        handle_event(&next_bank, &bindings, &mut prod, &mut state);
+        handle_event(&key_a, &bindings, &mut prod, &mut state);
+        cons.drain(&mut buf);
+        assert_eq!(buf.len(), 1, "Bank key must not trigger a sample");
+        assert_eq!(buf[0].sample_id, 1);
+
+        // The sample kit is untouched.
+        assert_eq!(state.kit_state.kit_index, 0);
+        assert_eq!(state.kit_state.variant_index, 0);
+        assert_eq!(state.kit_state.sample_bank.load().kit_name, "test");
+
+        // Switching again wraps back to the first bank.
+        handle_event(&next_bank, &bindings, &mut prod, &mut state);
+        assert_eq!(state.bank_index, 0);
+    }
+
+    #[test]
+    fn test_build_suppressed_keys_includes_bindings_and_cycling() {
+        let mut key_map = KeyMap::new();
+        key_map.insert(30, one_shot(0, 1.0)); // KEY_A
+        key_map.insert(31, one_shot(1, 0.9)); // KEY_S
+
+        let cycling = ResolvedCyclingKeys {
+            next_kit: Some(106),     // KEY_RIGHT
+            prev_kit: Some(105),     // KEY_LEFT
+            next_variant: Some(103), // KEY_UP
+            ..Default::default()
+        };
+
+        let suppressed = build_suppressed_keys(&key_map, &cycling, &[]);
+
+        // Should contain both sample keys.
+        assert!(suppressed.contains(&30));
+        assert!(suppressed.contains(&31));
+
+        // Should contain configured cycling keys.
+        assert!(suppressed.contains(&106));
+        assert!(suppressed.contains(&105));
+        assert!(suppressed.contains(&103));
+
+        // Should NOT contain unconfigured cycling key.
+        // Total: 2 sample keys + 3 cycling keys = 5.
+        assert_eq!(suppressed.len(), 5);
+    }
 
-    // Mirror key capabilities.
-    if let Some(keys) = device.supported_keys() {
-        let mut key_set = AttributeSet::<KeyCode>::new();
-        for key in keys.iter() {
-            key_set.insert(key);
+    #[test]
+    fn test_unplugged_device_errors_are_recognized() {
+        for errno in [libc::ENODEV, libc::ENXIO] {
+            assert!(is_device_gone(&std::io::Error::from_raw_os_error(errno)));
         }
-        builder = builder
-            .with_keys(&key_set)
-            .map_err(|e| anyhow::anyhow!("Failed to set virtual device keys: {}", e))?;
+        assert!(!is_device_gone(&std::io::Error::from_raw_os_error(
+            libc::EACCES
+        )));
+        assert!(!is_device_gone(&std::io::Error::other("not an OS error")));
     }
 
-    // Mirror relative axis capabilities (mouse movement, scroll wheel, etc.).
-    if let Some(rel_axes) = device.supported_relative_axes() {
-        builder = builder
-            .with_relative_axes(rel_axes)
-            .map_err(|e| anyhow::anyhow!("Failed to set virtual device relative axes: {}", e))?;
-    }
+    #[test]
+    fn test_build_suppressed_keys_empty_cycling() {
+        let mut key_map = KeyMap::new();
+        key_map.insert(30, one_shot(0, 1.0));
 
-    // Mirror absolute axis capabilities (touchpad, etc.).
-    if let Ok(absinfo_iter) = device.get_absinfo() {
-        for (axis_code, abs_info) in absinfo_iter {
-            let setup = UinputAbsSetup::new(axis_code, abs_info);
-            builder = builder.with_absolute_axis(&setup).map_err(|e| {
-                anyhow::anyhow!("Failed to set virtual device absolute axis: {}", e)
-            })?;
-        }
+        let cycling = make_dummy_cycling_keys(); // all None
+
+        let suppressed = build_suppressed_keys(&key_map, &cycling, &[]);
+
+        assert_eq!(suppressed.len(), 1);
+        assert!(suppressed.contains(&30));
     }
 
-    // Mirror switch capabilities.
-    if let Some(switches) = device.supported_switches() {
-        builder = builder
-            .with_switches(switches)
-            .map_err(|e| anyhow::anyhow!("Failed to set virtual device switches: {}", e))?;
+    #[test]
+    fn test_axis_value_to_scale() {
+        // Linear mapping across the configured range.
+        assert_eq!(axis_value_to_scale(0, 0, 255), 0.0);
+        assert_eq!(axis_value_to_scale(255, 0, 255), 1.0);
+        assert!((axis_value_to_scale(51, 0, 255) - 0.2).abs() < 1e-6);
+
+        // Values outside the range are clamped.
+        assert_eq!(axis_value_to_scale(-10, 0, 255), 0.0);
+        assert_eq!(axis_value_to_scale(300, 0, 255), 1.0);
+
+        // Swapping min and max inverts the axis.
+        assert_eq!(axis_value_to_scale(0, 1023, 0), 1.0);
+        assert_eq!(axis_value_to_scale(1023, 1023, 0), 0.0);
     }
 
-    // Mirror LED capabilities.
-    // Note: VirtualDeviceBuilder doesn't have with_leds(), so LEDs are
-    // not mirrored. This is acceptable — LED state is managed by the
-    // kernel and doesn't affect input forwarding.
+    #[test]
+    fn test_handle_event_expression_scales_velocity() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let mut state = make_dummy_state();
 
-    let virt = builder
-        .build()
-        .map_err(|e| anyhow::anyhow!("Failed to create virtual device: {}", e))?;
+        let mut key_map = KeyMap::new();
+        key_map.insert(30, one_shot(0, 0.8)); // KEY_A
+        let bindings = make_bindings(key_map, make_dummy_cycling_keys());
 
-    log::info!("Created virtual device mirroring physical device capabilities");
+        state.expression = Some(ExpressionAxis::new(&ResolvedExpression {
+            axis: 2, // ABS_Z
+            min: 0,
+            max: 100,
+        }));
 
-    Ok(virt)
-}
+        // Move the pedal to the halfway point, then press KEY_A.
+        let axis_event = InputEvent::new(EventType::ABSOLUTE.0, 2, 50);
+        handle_event(&axis_event, &bindings, &mut prod, &mut state);
+        let key_event = InputEvent::new(EventType::KEY.0, 30, 1);
+        handle_event(&key_event, &bindings, &mut prod, &mut state);
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::ring;
-    use crate::samples::SampleBank;
+        let mut buf = Vec::new();
+        cons.drain(&mut buf);
+        assert_eq!(buf.len(), 1);
+        assert!((buf[0].velocity - 0.4).abs() < 1e-6);
+    }
 
-    fn make_dummy_cycling_keys() -> ResolvedCyclingKeys {
-        ResolvedCyclingKeys {
-            next_kit: None,
-            prev_kit: None,
-            next_variant: None,
-            prev_variant: None,
+    #[test]
+    fn test_key_travel_speed_sets_velocity() {
+        let mut travel = KeyTravel::new(&ResolvedKeyTravel {
+            axis: 40, // ABS_MISC
+            rest: 0,
+            bottom: 1000,
+            fast: Duration::from_millis(5),
+            slow: Duration::from_millis(45),
+        });
+        let t0 = SystemTime::UNIX_EPOCH + Duration::from_secs(100);
+        let ms = |n: u64| t0 + Duration::from_millis(n);
+
+        // Jitter near rest does not start a press. The key leaves rest at
+        // 10ms and goes down at 35ms: 25ms of travel, halfway between fast
+        // and slow.
+        travel.update(50, ms(0));
+        travel.update(300, ms(10));
+        travel.update(700, ms(20));
+        travel.update(1000, ms(30));
+        let velocity = travel.key_down(ms(35));
+        assert!((velocity - (1.0 + MIN_TRAVEL_VELOCITY) / 2.0).abs() < 1e-6);
+
+        // A key-down without travel since the last one plays at full velocity.
+        assert_eq!(travel.key_down(ms(40)), 1.0);
+
+        // Release, then a 3ms press: faster than `fast`.
+        travel.update(0, ms(100));
+        travel.update(500, ms(200));
+        assert_eq!(travel.key_down(ms(203)), 1.0);
+
+        // Release, then a 100ms press: slower than `slow`.
+        travel.update(0, ms(300));
+        travel.update(200, ms(400));
+        assert_eq!(travel.key_down(ms(500)), MIN_TRAVEL_VELOCITY);
+    }
+
+    #[test]
+    fn test_toggle_loop_alternate_presses_start_and_stop() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let mut state = make_dummy_state();
+
+        let mut key_map = KeyMap::new();
+        key_map.insert(
+            30, // KEY_A
+            KeyBinding {
+                sample_indices: vec![0],
+                layer_velocities: Vec::new(),
+                stack_gains: Vec::new(),
+                gain: 1.0,
+                mode: BindingMode::ToggleLoop,
+                gate: false,
+                velocity_mode: VelocityMode::Fixed,
+            },
+        );
+        let bindings = make_bindings(key_map, make_dummy_cycling_keys());
+
+        let key_event = InputEvent::new(EventType::KEY.0, 30, 1);
+        for _ in 0..3 {
+            handle_event(&key_event, &bindings, &mut prod, &mut state);
         }
+
+        let mut buf = Vec::new();
+        cons.drain(&mut buf);
+        let kinds: Vec<TriggerKind> = buf.iter().map(|t| t.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                TriggerKind::StartLoop,
+                TriggerKind::StopLoop,
+                TriggerKind::StartLoop
+            ]
+        );
+        assert!(buf.iter().all(|t| t.sample_id == 0));
     }
 
-    fn make_dummy_kit_state() -> KitState {
-        use crate::samples::{KitInfo, KitLibrary, SampleData};
-        use std::path::PathBuf;
+    #[test]
+    fn test_timing_velocity() {
+        let window = Duration::from_millis(40);
+        assert_eq!(timing_velocity(None, window), TIMING_BASE_VELOCITY);
+        assert_eq!(
+            timing_velocity(Some(Duration::from_millis(100)), window),
+            TIMING_BASE_VELOCITY
+        );
+        assert_eq!(timing_velocity(Some(Duration::ZERO), window), 1.0);
+        let halfway = timing_velocity(Some(Duration::from_millis(20)), window);
+        assert!((halfway - (TIMING_BASE_VELOCITY + 1.0) / 2.0).abs() < 1e-6);
+    }
 
-        let bank = Arc::new(ArcSwap::from_pointee(SampleBank {
-            samples: vec![Arc::new(SampleData {
-                data: vec![0.0],
-                channels: 1,
-                sample_rate: 48000,
-            })],
-            sample_gains: vec![1.0],
-            kit_name: "test".to_string(),
-            variant_name: "v1".to_string(),
-        }));
+    #[test]
+    fn test_timing_velocity_rapid_repeat_is_louder() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let mut state = make_dummy_state();
 
-        KitState {
-            library: KitLibrary {
-                samples_dir: PathBuf::from("/tmp"),
-                kits: vec![KitInfo {
-                    name: "test".to_string(),
-                    variants: vec!["v1".to_string()],
-                }],
-                sample_names: vec!["kick.wav".to_string()],
-                sample_gains: vec![1.0],
+        let mut key_map = KeyMap::new();
+        key_map.insert(
+            30, // KEY_A
+            KeyBinding {
+                sample_indices: vec![0],
+                layer_velocities: Vec::new(),
+                stack_gains: Vec::new(),
+                gain: 1.0,
+                mode: BindingMode::OneShot,
+                gate: false,
+                velocity_mode: VelocityMode::Timing,
             },
-            sample_bank: bank,
-            kit_index: 0,
-            variant_index: 0,
-        }
+        );
+        let mut bindings = make_bindings(key_map, make_dummy_cycling_keys());
+        // Generous window so the test does not depend on scheduling.
+        bindings.velocity_timing_window = Duration::from_secs(10);
+
+        let key_event = InputEvent::new(EventType::KEY.0, 30, 1);
+        handle_event(&key_event, &bindings, &mut prod, &mut state);
+        handle_event(&key_event, &bindings, &mut prod, &mut state);
+
+        let mut buf = Vec::new();
+        cons.drain(&mut buf);
+        assert_eq!(buf.len(), 2);
+        assert_eq!(buf[0].velocity, TIMING_BASE_VELOCITY);
+        assert!(buf[1].velocity > buf[0].velocity);
     }
 
     #[test]
-    fn test_handle_event_key_down_match() {
+    fn test_virtual_input_id_from_config() {
+        let physical = InputId::new(BusType::BUS_I8042, 0x0001, 0x0002, 0x0003);
+        let mut identity = ResolvedVirtualDevice {
+            name: "drums".to_string(),
+            bus_type: None,
+            vendor: None,
+            product: None,
+            version: None,
+        };
+
+        // Nothing configured: keep the uinput default.
+        assert!(virtual_input_id(&identity, &physical).is_none());
+
+        // Configured IDs win; the rest come from the physical device.
+        identity.bus_type = Some(BusType::BUS_USB.0);
+        identity.vendor = Some(0x046d);
+        identity.product = Some(0xc31c);
+        let id = virtual_input_id(&identity, &physical).unwrap();
+        assert_eq!(id.bus_type(), BusType::BUS_USB);
+        assert_eq!(id.vendor(), 0x046d);
+        assert_eq!(id.product(), 0xc31c);
+        assert_eq!(id.version(), 0x0003);
+    }
+
+    #[test]
+    fn test_handle_event_sample_index_above_u8_range() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let (mut prod, mut cons) = ring::create_trigger_channel();
-        let cycling = make_dummy_cycling_keys();
-        let mut kit_state = make_dummy_kit_state();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let mut state = make_dummy_state();
 
         let mut key_map = KeyMap::new();
-        key_map.insert(30, (0, 0.8)); // KEY_A = 30
+        key_map.insert(30, one_shot(300, 1.0)); // KEY_A -> sample 300
+        let bindings = make_bindings(key_map, make_dummy_cycling_keys());
 
-        // Simulate a KEY_A down event (type=1 EV_KEY, code=30, value=1).
         let event = InputEvent::new(EventType::KEY.0, 30, 1);
-        handle_event(&event, &key_map, &mut prod, &cycling, &mut kit_state);
+        handle_event(&event, &bindings, &mut prod, &mut state);
 
         let mut buf = Vec::new();
         cons.drain(&mut buf);
         assert_eq!(buf.len(), 1);
-        assert_eq!(buf[0].sample_id, 0);
-        assert!((buf[0].velocity - 0.8).abs() < f32::EPSILON);
+        assert_eq!(buf[0].sample_id, 300);
     }
 
     #[test]
-    fn test_handle_event_key_up_ignored() {
+    fn test_velocity_layer_boundaries() {
+        let layers = [0.0, 0.4, 0.8];
+        assert_eq!(velocity_layer(&layers, 0.0), 0);
+        assert_eq!(velocity_layer(&layers, 0.39), 0);
+        // A velocity exactly at a threshold plays that layer.
+        assert_eq!(velocity_layer(&layers, 0.4), 1);
+        assert_eq!(velocity_layer(&layers, 0.79), 1);
+        assert_eq!(velocity_layer(&layers, 0.8), 2);
+        assert_eq!(velocity_layer(&layers, 1.0), 2);
+
+        // Below the softest layer's threshold still plays the softest.
+        assert_eq!(velocity_layer(&[0.5, 0.9], 0.2), 0);
+    }
+
+    #[test]
+    fn test_velocity_layers_follow_expression() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let (mut prod, mut cons) = ring::create_trigger_channel();
-        let cycling = make_dummy_cycling_keys();
-        let mut kit_state = make_dummy_kit_state();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let mut state = make_dummy_state();
 
         let mut key_map = KeyMap::new();
-        key_map.insert(30, (0, 1.0));
+        key_map.insert(
+            30, // KEY_A
+            KeyBinding {
+                sample_indices: vec![2, 3],
+                layer_velocities: vec![0.0, 0.5],
+                ..one_shot(0, 1.0)
+            },
+        );
+        let bindings = make_bindings(key_map, make_dummy_cycling_keys());
+        let key_down = InputEvent::new(EventType::KEY.0, 30, 1);
 
-        // Key up event (value=0) should be ignored.
-        let event = InputEvent::new(EventType::KEY.0, 30, 0);
-        handle_event(&event, &key_map, &mut prod, &cycling, &mut kit_state);
+        // No velocity source: always the softest layer.
+        handle_event(&key_down, &bindings, &mut prod, &mut state);
+
+        // Pedal just under, exactly at, and over the loud layer's threshold.
+        state.expression = Some(ExpressionAxis::new(&ResolvedExpression {
+            axis: 2, // ABS_Z
+            min: 0,
+            max: 100,
+        }));
+        for value in [49, 50, 100] {
+            let axis_event = InputEvent::new(EventType::ABSOLUTE.0, 2, value);
+            handle_event(&axis_event, &bindings, &mut prod, &mut state);
+            handle_event(&key_down, &bindings, &mut prod, &mut state);
+        }
 
         let mut buf = Vec::new();
         cons.drain(&mut buf);
-        assert!(buf.is_empty());
+        let ids: Vec<u16> = buf.iter().map(|t| t.sample_id).collect();
+        assert_eq!(ids, vec![2, 2, 3, 3]);
     }
 
     #[test]
-    fn test_handle_event_key_repeat_ignored() {
+    fn test_stacked_samples_trigger_together() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let (mut prod, mut cons) = ring::create_trigger_channel();
-        let cycling = make_dummy_cycling_keys();
-        let mut kit_state = make_dummy_kit_state();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let mut state = make_dummy_state();
 
         let mut key_map = KeyMap::new();
-        key_map.insert(30, (0, 1.0));
+        key_map.insert(
+            30, // KEY_A
+            KeyBinding {
+                sample_indices: vec![0, 1],
+                stack_gains: vec![1.0, 0.5],
+                ..one_shot(0, 0.8)
+            },
+        );
+        let bindings = make_bindings(key_map, make_dummy_cycling_keys());
 
-        // Key repeat event (value=2) should be ignored.
-        let event = InputEvent::new(EventType::KEY.0, 30, 2);
-        handle_event(&event, &key_map, &mut prod, &cycling, &mut kit_state);
+        let key_down = InputEvent::new(EventType::KEY.0, 30, 1);
+        handle_event(&key_down, &bindings, &mut prod, &mut state);
 
         let mut buf = Vec::new();
         cons.drain(&mut buf);
-        assert!(buf.is_empty());
+        assert_eq!(buf.len(), 2);
+        assert_eq!(buf[0].sample_id, 0);
+        assert!((buf[0].velocity - 0.8).abs() < 1e-6);
+        assert_eq!(buf[1].sample_id, 1);
+        assert!((buf[1].velocity - 0.4).abs() < 1e-6);
     }
 
     #[test]
-    fn test_handle_event_unbound_key() {
+    fn test_same_batch_key_downs_are_simultaneous() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let (mut prod, mut cons) = ring::create_trigger_channel();
-        let cycling = make_dummy_cycling_keys();
-        let mut kit_state = make_dummy_kit_state();
+        let (mut prod, _cons) = ring::default_trigger_channel();
+        let mut state = make_dummy_state();
 
         let mut key_map = KeyMap::new();
-        key_map.insert(30, (0, 1.0)); // KEY_A
+        key_map.insert(30, one_shot(0, 1.0)); // KEY_A
+        key_map.insert(31, one_shot(1, 1.0)); // KEY_S
+        let bindings = make_bindings(key_map, make_dummy_cycling_keys());
+
+        let a_down = InputEvent::new(EventType::KEY.0, 30, 1);
+        let s_down = InputEvent::new(EventType::KEY.0, 31, 1);
+        let syn = InputEvent::new(EventType::SYNCHRONIZATION.0, 0, 0);
+
+        handle_event(&a_down, &bindings, &mut prod, &mut state);
+        handle_event(&s_down, &bindings, &mut prod, &mut state);
+        assert_eq!(state.batch.keys, vec![30, 31]);
+
+        // The next batch starts fresh.
+        handle_event(&syn, &bindings, &mut prod, &mut state);
+        handle_event(&a_down, &bindings, &mut prod, &mut state);
+        assert_eq!(state.batch.keys, vec![30]);
+    }
 
-        // KEY_B (code=48) is not bound.
-        let event = InputEvent::new(EventType::KEY.0, 48, 1);
-        handle_event(&event, &key_map, &mut prod, &cycling, &mut kit_state);
+    #[test]
+    fn test_coalesce_plays_shared_sample_once_per_batch() {
+        let _ = env_logger::builder().is_test(true).try_init();
 
-        let mut buf = Vec::new();
-        cons.drain(&mut buf);
-        assert!(buf.is_empty());
+        let a_down = InputEvent::new(EventType::KEY.0, 30, 1);
+        let s_down = InputEvent::new(EventType::KEY.0, 31, 1);
+        let syn = InputEvent::new(EventType::SYNCHRONIZATION.0, 0, 0);
+
+        for (mode, expected) in [(SimultaneousHits::All, 4), (SimultaneousHits::Coalesce, 2)] {
+            let (mut prod, mut cons) = ring::default_trigger_channel();
+            let mut state = make_dummy_state();
+
+            // Both keys play the same sample.
+            let mut key_map = KeyMap::new();
+            key_map.insert(30, one_shot(0, 1.0)); // KEY_A
+            key_map.insert(31, one_shot(0, 1.0)); // KEY_S
+            let mut bindings = make_bindings(key_map, make_dummy_cycling_keys());
+            bindings.simultaneous_hits = mode;
+
+            // Two batches of both keys at once.
+            for _ in 0..2 {
+                handle_event(&a_down, &bindings, &mut prod, &mut state);
+                handle_event(&s_down, &bindings, &mut prod, &mut state);
+                handle_event(&syn, &bindings, &mut prod, &mut state);
+            }
+
+            let mut buf = Vec::new();
+            cons.drain(&mut buf);
+            assert_eq!(buf.len(), expected, "{:?}", mode);
+        }
     }
 
     #[test]
-    fn test_handle_event_non_key_event() {
+    fn test_parse_note_on() {
+        assert_eq!(parse_note_on(&[0x90, 36, 100]), Some((36, 100)));
+        assert_eq!(parse_note_on(&[0x99, 38, 1]), Some((38, 1))); // channel 10
+        // Velocity 0 is a note-off.
+        assert_eq!(parse_note_on(&[0x90, 36, 0]), None);
+        assert_eq!(parse_note_on(&[0x80, 36, 64]), None);
+        assert_eq!(parse_note_on(&[0xB0, 7, 127]), None);
+        assert_eq!(parse_note_on(&[0x90, 36]), None);
+    }
+
+    #[test]
+    fn test_midi_velocity_scales_hits_and_picks_layers() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let (mut prod, mut cons) = ring::create_trigger_channel();
-        let cycling = make_dummy_cycling_keys();
-        let mut kit_state = make_dummy_kit_state();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let mut state = make_dummy_state();
 
         let mut key_map = KeyMap::new();
-        key_map.insert(30, (0, 1.0));
+        key_map.insert(
+            30, // KEY_A
+            KeyBinding {
+                sample_indices: vec![2, 3],
+                layer_velocities: vec![0.0, 0.5],
+                ..one_shot(0, 0.8)
+            },
+        );
+        let bindings = make_bindings(key_map, make_dummy_cycling_keys());
 
-        // A non-KEY event (EV_REL = 2).
-        let event = InputEvent::new(EventType::RELATIVE.0, 0, 1);
-        handle_event(&event, &key_map, &mut prod, &cycling, &mut kit_state);
+        for velocity in [32u8, 127] {
+            let hit = velocity as f32 / 127.0;
+            handle_key_down(30, Some(hit), &bindings, &mut prod, &mut state);
+        }
 
         let mut buf = Vec::new();
         cons.drain(&mut buf);
-        assert!(buf.is_empty());
+        assert_eq!(buf.len(), 2);
+        assert_eq!(buf[0].sample_id, 2);
+        assert!((buf[0].velocity - 0.8 * 32.0 / 127.0).abs() < 1e-6);
+        assert_eq!(buf[1].sample_id, 3);
+        assert!((buf[1].velocity - 0.8).abs() < 1e-6);
+
+        // The hit strength leaves out the binding's gain.
+        assert!((buf[0].hit - 32.0 / 127.0).abs() < 1e-6);
+        assert!((buf[1].hit - 1.0).abs() < 1e-6);
     }
 
     #[test]
-    fn test_build_key_map() {
-        use crate::config::ResolvedBinding;
-        use evdev::KeyCode;
+    fn test_round_robin_rotates_samples_per_press() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let mut state = make_dummy_state();
 
-        let mut config_map = HashMap::new();
-        config_map.insert(
-            KeyCode::KEY_A.code(),
-            ResolvedBinding {
-                key_code: KeyCode::KEY_A,
-                sample_index: 0,
-                gain: 0.9,
-            },
-        );
-        config_map.insert(
-            KeyCode::KEY_S.code(),
-            ResolvedBinding {
-                key_code: KeyCode::KEY_S,
-                sample_index: 1,
-                gain: 0.7,
+        let mut key_map = KeyMap::new();
+        key_map.insert(
+            30, // KEY_A
+            KeyBinding {
+                sample_indices: vec![4, 5, 6],
+                ..one_shot(0, 1.0)
             },
         );
+        key_map.insert(31, one_shot(7, 1.0)); // KEY_S
+        let bindings = make_bindings(key_map, make_dummy_cycling_keys());
+
+        for code in [30, 30, 31, 30, 30] {
+            handle_event(
+                &InputEvent::new(EventType::KEY.0, code, 1),
+                &bindings,
+                &mut prod,
+                &mut state,
+            );
+            handle_event(
+                &InputEvent::new(EventType::KEY.0, code, 0),
+                &bindings,
+                &mut prod,
+                &mut state,
+            );
+        }
 
-        let key_map = build_key_map(&config_map);
-        assert_eq!(key_map.len(), 2);
+        let mut buf = Vec::new();
+        cons.drain(&mut buf);
+        let ids: Vec<u16> = buf.iter().map(|t| t.sample_id).collect();
+        assert_eq!(ids, vec![4, 5, 7, 6, 4]);
+    }
 
-        let (idx, gain) = key_map[&KeyCode::KEY_A.code()];
-        assert_eq!(idx, 0);
-        assert!((gain - 0.9).abs() < f32::EPSILON);
+    #[test]
+    fn test_auto_advance_cycles_at_interval() {
+        use crate::samples::KitInfo;
 
-        let (idx, gain) = key_map[&KeyCode::KEY_S.code()];
-        assert_eq!(idx, 1);
-        assert!((gain - 0.7).abs() < f32::EPSILON);
+        let _ = env_logger::builder().is_test(true).try_init();
+        let mut kit_state = make_dummy_kit_state();
+        kit_state.library.kits = vec![
+            KitInfo {
+                name: "a".to_string(),
+                variants: vec!["v1".to_string(), "v2".to_string()],
+            },
+            KitInfo {
+                name: "b".to_string(),
+                variants: vec!["v1".to_string()],
+            },
+        ];
+
+        let start = Instant::now();
+        let secs = |s: u64| start + Duration::from_secs(s);
+        let mut timer = AutoAdvance::new(Duration::from_secs(5), start);
+
+        // Nothing happens before the interval elapses.
+        assert!(!timer.poll(secs(4)));
+
+        // First tick: next variant within kit "a".
+        assert!(timer.poll(secs(5)));
+        kit_state.advance();
+        assert_eq!((kit_state.kit_index, kit_state.variant_index), (0, 1));
+
+        // The next tick is scheduled one interval later.
+        assert!(!timer.poll(secs(9)));
+        assert!(timer.poll(secs(10)));
+        kit_state.advance();
+        assert_eq!((kit_state.kit_index, kit_state.variant_index), (1, 0));
+
+        // After the last kit it wraps back to the start.
+        assert!(timer.poll(secs(15)));
+        kit_state.advance();
+        assert_eq!((kit_state.kit_index, kit_state.variant_index), (0, 0));
     }
 
     #[test]
-    fn test_cycling_key_does_not_trigger_sample() {
+    fn test_auto_advance_toggle_pauses_and_resumes() {
+        let start = Instant::now();
+        let mut timer = AutoAdvance::new(Duration::from_secs(5), start);
+
+        timer.toggle(start + Duration::from_secs(1));
+        assert!(timer.time_until_due(start).is_none());
+        assert!(!timer.poll(start + Duration::from_secs(60)));
+
+        // Resuming restarts the full interval from the toggle time.
+        timer.toggle(start + Duration::from_secs(60));
+        assert!(!timer.poll(start + Duration::from_secs(64)));
+        assert!(timer.poll(start + Duration::from_secs(65)));
+    }
+
+    #[test]
+    fn test_failed_reload_requests_error_cue() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let (mut prod, mut cons) = ring::create_trigger_channel();
+        let dir = tempfile::tempdir().unwrap();
+
+        // A kit whose only sample is not a valid WAV file.
+        let variant_dir = dir.path().join("broken").join("v1");
+        std::fs::create_dir_all(&variant_dir).unwrap();
+        std::fs::write(variant_dir.join("kick.wav"), b"not a wav file").unwrap();
+
+        let cue = Arc::new(ErrorCue::new(48000));
         let mut kit_state = make_dummy_kit_state();
+        kit_state.library.samples_dir = dir.path().to_path_buf();
+        kit_state.library.kits[0].name = "broken".to_string();
+        kit_state.library.kits[0].variants = vec!["v1".to_string()];
+        kit_state.error_cue = Some(Arc::clone(&cue));
 
-        // KEY_RIGHT (code=106) is next_kit cycling key.
-        let cycling = ResolvedCyclingKeys {
-            next_kit: Some(106),
-            prev_kit: None,
-            next_variant: None,
-            prev_variant: None,
-        };
+        kit_state.reload();
 
-        // Also bind KEY_RIGHT as a sample key (should be prevented by config,
-        // but verify cycling takes priority).
-        let mut key_map = KeyMap::new();
-        key_map.insert(106, (0, 1.0));
+        assert!(cue.take(), "A failed reload should request the error cue");
+        // The previous bank stays loaded.
+        assert_eq!(kit_state.sample_bank.load().kit_name, "test");
+    }
 
-        let event = InputEvent::new(EventType::KEY.0, 106, 1);
-        handle_event(&event, &key_map, &mut prod, &cycling, &mut kit_state);
+    /// A config reload to `key_map` with the bound `sample_names`.
+    fn make_reload(key_map: KeyMap, sample_names: &[&str]) -> ConfigReload {
+        let cycling_keys = make_dummy_cycling_keys();
+        ConfigReload {
+            banks: vec![BindingBank {
+                name: "bank0".to_string(),
+                suppressed_keys: build_suppressed_keys(&key_map, &cycling_keys, &[]),
+                key_map,
+            }],
+            cycling_keys,
+            momentary: Vec::new(),
+            kit_select: Vec::new(),
+            mute: Vec::new(),
+            solo: Vec::new(),
+            velocity_timing_window: Duration::from_millis(40),
+            sample_names: sample_names.iter().map(|name| name.to_string()).collect(),
+            settings: SampleSettings::default(),
+        }
+    }
+
+    #[test]
+    fn test_preview_trigger_by_name_follows_reloaded_sample_ids() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let mut state = make_dummy_state();
+        let mut bindings = make_bindings(KeyMap::new(), make_dummy_cycling_keys());
+        let names = vec!["synth:click".to_string(), "synth:noise".to_string()];
+        state.kit_state.library.sample_names = names.clone();
+        bindings.sample_names.store(Arc::new(names));
+
+        // The reload inserts a sample in front, moving both ids up by one.
+        let reload = make_reload(
+            KeyMap::new(),
+            &["synth:sine:200hz", "synth:click", "synth:noise"],
+        );
+        apply_config_reload(reload, &mut bindings, &mut prod, &mut state);
+        assert_eq!(bindings.sample_names.load()[2], "synth:noise");
+
+        let shutdown = AtomicBool::new(false);
+        for name in ["synth:noise", "synth:click", "kick.wav"] {
+            apply_control_action(
+                ControlAction::TriggerSampleNamed(name.to_string()),
+                &bindings,
+                &mut prod,
+                &mut state.kit_state,
+                &mut state.active_loops,
+                &shutdown,
+            );
+        }
 
-        // No trigger should be sent — cycling takes priority.
+        // An unbound name plays nothing.
         let mut buf = Vec::new();
         cons.drain(&mut buf);
-        assert!(buf.is_empty());
+        let played: Vec<u16> = buf
+            .iter()
+            .filter(|t| t.kind == TriggerKind::OneShot)
+            .map(|t| t.sample_id)
+            .collect();
+        assert_eq!(played, vec![2, 1]);
     }
 
     #[test]
-    fn test_build_suppressed_keys_includes_bindings_and_cycling() {
-        let mut key_map = KeyMap::new();
-        key_map.insert(30, (0, 1.0)); // KEY_A
-        key_map.insert(31, (1, 0.9)); // KEY_S
-
-        let cycling = ResolvedCyclingKeys {
-            next_kit: Some(106),     // KEY_RIGHT
-            prev_kit: Some(105),     // KEY_LEFT
-            next_variant: Some(103), // KEY_UP
-            prev_variant: None,
+    fn test_folder_binding_added_by_reload_rotates_takes() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = tempfile::tempdir().unwrap();
+        let (mut prod, mut cons) = ring::default_trigger_channel();
+        let mut state = make_dummy_state();
+        let mut bindings = make_bindings(KeyMap::new(), make_dummy_cycling_keys());
+        state.kit_state.library.samples_dir = dir.path().to_path_buf();
+        state.kit_state.library.sample_names = vec!["synth:click".to_string()];
+
+        // The kit's "kick" folder holds three takes.
+        let pool_dir = dir.path().join("test").join("v1").join("kick");
+        std::fs::create_dir_all(&pool_dir).unwrap();
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 48000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
         };
+        for take in 1..=3 {
+            let path = pool_dir.join(format!("kick-{}.wav", take));
+            let mut writer = hound::WavWriter::create(path, spec).unwrap();
+            for _ in 0..take * 10 {
+                writer.write_sample(1000i16).unwrap();
+            }
+            writer.finalize().unwrap();
+        }
 
-        let suppressed = build_suppressed_keys(&key_map, &cycling);
-
-        // Should contain both sample keys.
-        assert!(suppressed.contains(&30));
-        assert!(suppressed.contains(&31));
+        // The reload binds KEY_A (code=30) to the folder, as a new sample.
+        let mut key_map = KeyMap::new();
+        key_map.insert(30, one_shot(1, 1.0));
+        let reload = make_reload(key_map, &["synth:click", "kick"]);
+        apply_config_reload(reload, &mut bindings, &mut prod, &mut state);
+        assert_eq!(state.kit_state.sample_bank.load().pools[1].len(), 3);
 
-        // Should contain configured cycling keys.
-        assert!(suppressed.contains(&106));
-        assert!(suppressed.contains(&105));
-        assert!(suppressed.contains(&103));
+        for _ in 0..4 {
+            handle_key_down(30, None, &bindings, &mut prod, &mut state);
+        }
 
-        // Should NOT contain unconfigured cycling key.
-        // Total: 2 sample keys + 3 cycling keys = 5.
-        assert_eq!(suppressed.len(), 5);
+        let mut buf = Vec::new();
+        cons.drain(&mut buf);
+        let takes: Vec<u16> = buf
+            .iter()
+            .filter(|t| t.kind == TriggerKind::OneShot)
+            .map(|t| t.take)
+            .collect();
+        assert_eq!(takes, vec![0, 1, 2, 0]);
+        let bank = state.kit_state.sample_bank.load();
+        assert_eq!(bank.sample_for_turn(1, 1).num_frames(), 20);
     }
 
     #[test]
-    fn test_build_suppressed_keys_empty_cycling() {
-        let mut key_map = KeyMap::new();
-        key_map.insert(30, (0, 1.0));
-
-        let cycling = make_dummy_cycling_keys(); // all None
-
-        let suppressed = build_suppressed_keys(&key_map, &cycling);
+    fn test_config_reload_preloads_only_the_current_kit() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let mut kit_state = make_dummy_kit_state();
 
-        assert_eq!(suppressed.len(), 1);
-        assert!(suppressed.contains(&30));
+        // A synth sample loads without files, so any kit folder will do.
+        let library = &mut kit_state.library;
+        library.sample_names = vec!["synth:click".to_string()];
+        library.kits.push(crate::samples::KitInfo {
+            name: "electronic".to_string(),
+            variants: vec!["dry".to_string(), "wet".to_string()],
+        });
+        library.preload_all = true;
+        library.preload();
+        assert_eq!(library.preloaded.len(), 3);
+
+        // The reload loads the kit playing, not every kit.
+        let sample_names = vec!["synth:noise".to_string(), "synth:click".to_string()];
+        kit_state
+            .swap_samples(sample_names, SampleSettings::default())
+            .unwrap();
+        assert_eq!(kit_state.library.preloaded.len(), 1);
+        assert_eq!(kit_state.library.preloaded[&(0, 0)].samples.len(), 2);
+        assert_eq!(kit_state.sample_bank.load().samples.len(), 2);
+
+        // Kits switched to afterwards are kept with the new samples.
+        kit_state.cycle_kit(true);
+        assert_eq!(kit_state.library.preloaded.len(), 2);
+        assert_eq!(kit_state.library.preloaded[&(1, 0)].samples.len(), 2);
+        assert!(Arc::ptr_eq(
+            &kit_state.sample_bank.load_full(),
+            &kit_state.library.preloaded[&(1, 0)]
+        ));
     }
 }
@@ -0,0 +1,107 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Lock-free accumulator for trigger latency: the time between the input
+/// thread enqueuing a trigger and the audio callback turning it into a voice.
+///
+/// The audio thread records samples with relaxed atomics (no locks, no
+/// allocation); the main thread periodically takes a summary, which also
+/// resets the counters for the next reporting window.
+#[derive(Debug)]
+pub struct LatencyStats {
+    count: AtomicU64,
+    total_us: AtomicU64,
+    min_us: AtomicU64,
+    max_us: AtomicU64,
+}
+
+/// Latency summary over one reporting window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LatencySummary {
+    /// Number of triggers measured.
+    pub count: u64,
+    pub min: Duration,
+    pub avg: Duration,
+    pub max: Duration,
+}
+
+impl LatencySummary {
+    /// Spread between the fastest and slowest trigger in the window.
+    pub fn jitter(&self) -> Duration {
+        self.max - self.min
+    }
+}
+
+impl LatencyStats {
+    pub fn new() -> Self {
+        Self {
+            count: AtomicU64::new(0),
+            total_us: AtomicU64::new(0),
+            min_us: AtomicU64::new(u64::MAX),
+            max_us: AtomicU64::new(0),
+        }
+    }
+
+    /// Record one trigger enqueued at `enqueued_at` and consumed at `consumed_at`.
+    /// Real-time safe.
+    pub fn record(&self, enqueued_at: Instant, consumed_at: Instant) {
+        let us = consumed_at
+            .saturating_duration_since(enqueued_at)
+            .as_micros() as u64;
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.total_us.fetch_add(us, Ordering::Relaxed);
+        self.min_us.fetch_min(us, Ordering::Relaxed);
+        self.max_us.fetch_max(us, Ordering::Relaxed);
+    }
+
+    /// Summarize and reset the counters. Returns None if nothing was recorded.
+    ///
+    /// A trigger recorded concurrently with the reset may be split across
+    /// windows; that is fine for diagnostics.
+    pub fn take_summary(&self) -> Option<LatencySummary> {
+        let count = self.count.swap(0, Ordering::Relaxed);
+        let total_us = self.total_us.swap(0, Ordering::Relaxed);
+        let min_us = self.min_us.swap(u64::MAX, Ordering::Relaxed);
+        let max_us = self.max_us.swap(0, Ordering::Relaxed);
+
+        if count == 0 {
+            return None;
+        }
+
+        Some(LatencySummary {
+            count,
+            min: Duration::from_micros(min_us),
+            avg: Duration::from_micros(total_us / count),
+            max: Duration::from_micros(max_us),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_from_known_timestamps() {
+        let stats = LatencyStats::new();
+        let base = Instant::now();
+
+        // Latencies of 1ms, 2ms and 6ms.
+        for (enqueue_ms, consume_ms) in [(0, 1), (10, 12), (20, 26)] {
+            stats.record(
+                base + Duration::from_millis(enqueue_ms),
+                base + Duration::from_millis(consume_ms),
+            );
+        }
+
+        let summary = stats.take_summary().unwrap();
+        assert_eq!(summary.count, 3);
+        assert_eq!(summary.min, Duration::from_millis(1));
+        assert_eq!(summary.avg, Duration::from_millis(3));
+        assert_eq!(summary.max, Duration::from_millis(6));
+        assert_eq!(summary.jitter(), Duration::from_millis(5));
+
+        // Taking a summary resets the window.
+        assert!(stats.take_summary().is_none());
+    }
+}
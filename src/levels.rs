@@ -0,0 +1,142 @@
+use crate::samples::{KIT_MANIFEST_FILE, KitLibrary, SampleData};
+use anyhow::Result;
+use std::sync::Arc;
+
+/// RMS level of a sample in dBFS (0.0 for a full-scale square wave), over
+/// all channels. Returns None for empty or silent samples.
+pub fn rms_dbfs(sample: &SampleData) -> Option<f32> {
+    if sample.data.is_empty() {
+        return None;
+    }
+    let sum_squares: f64 = sample.data.iter().map(|&s| s as f64 * s as f64).sum();
+    let rms = (sum_squares / sample.data.len() as f64).sqrt();
+    if rms <= 0.0 {
+        return None;
+    }
+    Some((20.0 * rms.log10()) as f32)
+}
+
+/// Per-sample gains that bring every sample in a bank down to the RMS level
+/// of the quietest one, so equal velocities play at equal loudness. Only
+/// attenuates, so compensation can never push a sample into clipping.
+/// Silent samples get 1.0.
+pub fn loudness_compensation(samples: &[Arc<SampleData>]) -> Vec<f32> {
+    let levels: Vec<Option<f32>> = samples.iter().map(|s| rms_dbfs(s)).collect();
+    let Some(target) = levels.iter().filter_map(|&level| level).reduce(f32::min) else {
+        return vec![1.0; samples.len()];
+    };
+    levels
+        .iter()
+        .map(|level| level.map_or(1.0, |level| 10f32.powf((target - level) / 20.0)))
+        .collect()
+}
+
+/// Load every kit/variant, measure the average RMS level of its samples and
+/// print a gain per kit that brings all kits down to the quietest one, to
+/// set as `gain` in the kit's manifest. A variant that fails to load is
+/// logged and left out.
+pub fn analyze_levels(library: &KitLibrary) -> Result<()> {
+    let mut kit_levels: Vec<(&str, Option<f32>)> = Vec::with_capacity(library.kit_count());
+
+    for (kit_index, kit) in library.kits.iter().enumerate() {
+        let mut levels = Vec::new();
+        for variant_index in 0..library.variant_count(kit_index) {
+            match library.load_bank(kit_index, variant_index) {
+                Ok(bank) => levels.extend(bank.samples.iter().filter_map(|s| rms_dbfs(s))),
+                Err(e) => log::error!(
+                    "Failed to load kit '{}' variant '{}': {:#}",
+                    kit.name,
+                    kit.variants[variant_index],
+                    e
+                ),
+            }
+        }
+        let average =
+            (!levels.is_empty()).then(|| levels.iter().sum::<f32>() / levels.len() as f32);
+        kit_levels.push((kit.name.as_str(), average));
+    }
+
+    let Some(target) = kit_levels
+        .iter()
+        .filter_map(|(_, level)| *level)
+        .reduce(f32::min)
+    else {
+        println!("No audible samples found, nothing to analyze.");
+        return Ok(());
+    };
+
+    println!("{:<30} {:>12} {:>10}", "KIT", "RMS (dBFS)", "GAIN");
+    println!("{}", "-".repeat(54));
+    for (name, level) in &kit_levels {
+        match level {
+            Some(level) => {
+                let gain = 10f32.powf((target - level) / 20.0);
+                println!("{:<30} {:>12.1} {:>10.2}", name, level, gain);
+            }
+            None => println!("{:<30} {:>12} {:>10}", name, "silent", "-"),
+        }
+    }
+
+    println!();
+    println!(
+        "Set a kit's gain as `gain = ...` in its {} to bring it down to the quietest kit's level.",
+        KIT_MANIFEST_FILE
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::TAU;
+
+    fn mono(data: Vec<f32>) -> SampleData {
+        SampleData {
+            data,
+            channels: 1,
+            sample_rate: 48000,
+        }
+    }
+
+    #[test]
+    fn test_rms_dbfs_of_known_signals() {
+        // Full-scale square wave: 0 dBFS.
+        let square = mono(
+            (0..480)
+                .map(|i| if i % 2 == 0 { 1.0 } else { -1.0 })
+                .collect(),
+        );
+        assert!(rms_dbfs(&square).unwrap().abs() < 1e-4);
+
+        // Constant half-scale signal: -6.02 dBFS.
+        let half = mono(vec![0.5; 480]);
+        assert!((rms_dbfs(&half).unwrap() + 6.0206).abs() < 1e-3);
+
+        // Full-scale sine over whole periods: -3.01 dBFS.
+        let sine = mono((0..4800).map(|i| (TAU * i as f32 / 48.0).sin()).collect());
+        assert!((rms_dbfs(&sine).unwrap() + 3.0103).abs() < 1e-3);
+
+        // Silence and empty placeholders have no level.
+        assert_eq!(rms_dbfs(&mono(vec![0.0; 480])), None);
+        assert_eq!(rms_dbfs(&mono(vec![])), None);
+    }
+
+    #[test]
+    fn test_loudness_compensation_equalizes_levels() {
+        let loud = Arc::new(mono(vec![0.8; 480]));
+        let quiet = Arc::new(mono(vec![0.2; 480]));
+        let silent = Arc::new(mono(vec![0.0; 480]));
+
+        let gains = loudness_compensation(&[loud.clone(), quiet.clone(), silent]);
+        assert!((gains[0] - 0.25).abs() < 1e-4);
+        assert!((gains[1] - 1.0).abs() < 1e-4);
+        assert_eq!(gains[2], 1.0);
+
+        // At equal velocity, the compensated samples play at the same level.
+        let scaled =
+            |sample: &SampleData, gain: f32| mono(sample.data.iter().map(|s| s * gain).collect());
+        let loud_level = rms_dbfs(&scaled(&loud, gains[0])).unwrap();
+        let quiet_level = rms_dbfs(&scaled(&quiet, gains[1])).unwrap();
+        assert!((loud_level - quiet_level).abs() < 1e-3);
+    }
+}
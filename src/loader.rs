@@ -0,0 +1,224 @@
+use crate::samples::{KitLibrary, SampleBank};
+use anyhow::{Context, Result};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+
+/// A kit/variant to load, with the library to load it from.
+struct LoadRequest {
+    generation: u64,
+    library: KitLibrary,
+    kit_index: usize,
+    variant_index: usize,
+}
+
+/// A bank the worker finished loading, or the error it failed with.
+pub struct LoadedBank {
+    generation: u64,
+    pub kit_index: usize,
+    pub variant_index: usize,
+    pub bank: Result<Arc<SampleBank>>,
+}
+
+/// What the worker waits on: the next request to load, and whether to exit.
+#[derive(Default)]
+struct Pending {
+    request: Option<LoadRequest>,
+    stop: bool,
+}
+
+/// Loads sample banks on a worker thread, so the input loop never waits
+/// on disk while changing kits.
+///
+/// Only the latest request counts: a request made while another waits
+/// replaces it, so cycling quickly through kits loads the one it stops on
+/// (and the one already loading when it started). Results of replaced or
+/// cancelled requests are dropped by `try_recv`. Dropping the loader
+/// joins the worker, after the load in progress finishes.
+pub struct BankLoader {
+    pending: Arc<(Mutex<Pending>, Condvar)>,
+    sender: Sender<LoadedBank>,
+    loaded: Receiver<LoadedBank>,
+
+    /// Number of the latest request; results of any other are stale.
+    generation: u64,
+
+    /// Whether the latest request's result is still to come.
+    waiting: bool,
+
+    thread: Option<JoinHandle<()>>,
+}
+
+impl BankLoader {
+    /// Start a loader with its worker thread.
+    pub fn new() -> Result<Self> {
+        let mut loader = Self::unstarted();
+        loader.start()?;
+        Ok(loader)
+    }
+
+    /// A loader whose worker is not running yet. Requests queue up (and
+    /// replace each other) until `start`.
+    fn unstarted() -> Self {
+        let (sender, loaded) = mpsc::channel();
+        Self {
+            pending: Arc::new((Mutex::new(Pending::default()), Condvar::new())),
+            sender,
+            loaded,
+            generation: 0,
+            waiting: false,
+            thread: None,
+        }
+    }
+
+    fn start(&mut self) -> Result<()> {
+        let pending = Arc::clone(&self.pending);
+        let sender = self.sender.clone();
+        let thread = std::thread::Builder::new()
+            .name("bank-loader".to_string())
+            .spawn(move || run_worker(&pending, &sender))
+            .context("Failed to spawn bank loader thread")?;
+        self.thread = Some(thread);
+        Ok(())
+    }
+
+    /// Ask for a kit/variant of `library` to be loaded, replacing any
+    /// request the worker has not started on.
+    pub fn request(&mut self, library: KitLibrary, kit_index: usize, variant_index: usize) {
+        self.generation += 1;
+        self.waiting = true;
+        let (lock, wake) = &*self.pending;
+        lock.lock().unwrap().request = Some(LoadRequest {
+            generation: self.generation,
+            library,
+            kit_index,
+            variant_index,
+        });
+        wake.notify_one();
+    }
+
+    /// Forget the latest request: it is not started if it has not been,
+    /// and its result is dropped if it has. Returns whether one was still
+    /// waiting for its result.
+    pub fn cancel(&mut self) -> bool {
+        self.generation += 1;
+        self.pending.0.lock().unwrap().request = None;
+        std::mem::take(&mut self.waiting)
+    }
+
+    /// Whether the latest request's result is still to come.
+    pub fn is_loading(&self) -> bool {
+        self.waiting
+    }
+
+    /// The latest request's result, once the worker has finished it.
+    pub fn try_recv(&mut self) -> Option<LoadedBank> {
+        while let Ok(loaded) = self.loaded.try_recv() {
+            if loaded.generation == self.generation {
+                self.waiting = false;
+                return Some(loaded);
+            }
+        }
+        None
+    }
+}
+
+impl Drop for BankLoader {
+    fn drop(&mut self) {
+        let (lock, wake) = &*self.pending;
+        lock.lock().unwrap().stop = true;
+        wake.notify_one();
+        if let Some(thread) = self.thread.take()
+            && thread.join().is_err()
+        {
+            log::error!("Bank loader thread panicked");
+        }
+    }
+}
+
+/// Load requests as they come until the loader is dropped.
+fn run_worker(pending: &(Mutex<Pending>, Condvar), sender: &Sender<LoadedBank>) {
+    let (lock, wake) = pending;
+    loop {
+        let request = {
+            let mut pending = lock.lock().unwrap();
+            loop {
+                if pending.stop {
+                    return;
+                }
+                if let Some(request) = pending.request.take() {
+                    break request;
+                }
+                pending = wake.wait(pending).unwrap();
+            }
+        };
+        let bank = request
+            .library
+            .load_bank(request.kit_index, request.variant_index)
+            .map(Arc::new);
+        let loaded = LoadedBank {
+            generation: request.generation,
+            kit_index: request.kit_index,
+            variant_index: request.variant_index,
+            bank,
+        };
+        if sender.send(loaded).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::samples::{KitInfo, SampleSettings, discover_kits};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_rapid_requests_coalesce_to_the_latest() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = tempfile::tempdir().unwrap();
+
+        // A synth sample loads without files, so any kit folder will do.
+        let sample_names = vec!["synth:click".to_string()];
+        let mut library =
+            discover_kits(dir.path(), &sample_names, &SampleSettings::default()).unwrap();
+        for name in ["acoustic", "electronic", "vintage"] {
+            library.kits.push(KitInfo {
+                name: name.to_string(),
+                variants: vec!["dry".to_string()],
+            });
+        }
+
+        // Cycle through three kits before the worker gets to any of them.
+        let mut loader = BankLoader::unstarted();
+        for kit_index in 1..=3 {
+            loader.request(library.clone(), kit_index, 0);
+        }
+        assert!(loader.is_loading());
+        loader.start().unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let loaded = loop {
+            if let Some(loaded) = loader.try_recv() {
+                break loaded;
+            }
+            assert!(Instant::now() < deadline, "bank never loaded");
+            std::thread::sleep(Duration::from_millis(1));
+        };
+        assert!(!loader.is_loading());
+        assert_eq!((loaded.kit_index, loaded.variant_index), (3, 0));
+        assert_eq!(loaded.bank.unwrap().kit_name, "vintage");
+
+        // Only the latest kit was loaded; nothing else is on its way.
+        assert!(loader.pending.0.lock().unwrap().request.is_none());
+        assert!(loader.loaded.try_recv().is_err());
+
+        // A cancelled request's result never arrives.
+        loader.request(library.clone(), 1, 0);
+        assert!(loader.cancel());
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(loader.try_recv().is_none());
+        assert!(!loader.is_loading());
+    }
+}
@@ -1,14 +1,50 @@
 mod audio;
+mod capture;
+mod check;
+mod commands;
 mod config;
+mod control;
+mod cue;
+mod filter;
 mod input;
+mod latency;
+mod levels;
+mod loader;
+mod metronome;
+mod osc;
+mod preview;
+mod record;
+mod reload;
+mod resample;
+mod reverb;
 mod ring;
 mod samples;
+mod state;
+mod stats;
+mod synth;
+mod tui;
+mod watchdog;
 
 use anyhow::{Context, Result};
 use arc_swap::ArcSwap;
 use clap::Parser;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::collections::HashMap;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How often trigger latency statistics are logged when enabled.
+const LATENCY_REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often clipped output peaks are reported, when enabled.
+const CLIP_REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often engine statistics are logged, with `--stats`.
+const STATS_REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often the config file is checked for changes, when watched.
+const CONFIG_CHECK_INTERVAL: Duration = Duration::from_secs(1);
 
 /// Ultra-low latency console drum sampler using keyboard input via evdev.
 #[derive(Parser, Debug)]
@@ -18,14 +54,83 @@ struct Cli {
     #[arg(short, long, default_value = "~/.config/keyboard-drums/config.toml")]
     config: String,
 
-    /// Override the evdev device path from config.
+    /// System-wide config merged underneath the user config, if present.
+    #[arg(long, default_value = config::SYSTEM_CONFIG_PATH)]
+    system_config: String,
+
+    /// Use the named [profiles.<name>] section of the config, merged over
+    /// the rest of it.
     #[arg(short, long)]
-    device: Option<String>,
+    profile: Option<String>,
 
-    /// List available input devices and exit.
+    /// Override the evdev device paths from config. Repeat to read from
+    /// several keyboards at once.
+    #[arg(short, long)]
+    device: Vec<String>,
+
+    /// List available keyboards and MIDI ports and exit. With --verbose,
+    /// every input device is listed.
     #[arg(long)]
     list_devices: bool,
 
+    /// Override the audio output device from config. Any part of the
+    /// device name works.
+    #[arg(long, value_name = "NAME")]
+    audio_device: Option<String>,
+
+    /// List available audio output devices and exit.
+    #[arg(long)]
+    list_audio_devices: bool,
+
+    /// Play a metronome click at this tempo, in beats per minute.
+    #[arg(long, value_name = "BPM")]
+    metronome: Option<f64>,
+
+    /// Write an annotated example config to the config path and exit.
+    #[arg(long)]
+    init_config: bool,
+
+    /// Load and resolve the config, check its kits and bound samples and
+    /// exit, without opening any audio or input device.
+    ///
+    /// Exit codes: 0 the config is valid, 2 the config could not be loaded
+    /// or is invalid, 3 no usable kits or a bound sample is in no kit
+    /// variant, 4 the configured input device does not exist.
+    #[arg(long)]
+    check: bool,
+
+    /// Measure the loudness of every kit, print suggested per-kit gains and exit.
+    #[arg(long)]
+    analyze_levels: bool,
+
+    /// Record the mixed output to a stereo WAV file at the output sample rate.
+    #[arg(long, value_name = "PATH")]
+    record: Option<String>,
+
+    /// Log dropped triggers, voice steals and audio callback timing every
+    /// 10 seconds.
+    #[arg(long)]
+    stats: bool,
+
+    /// Don't print how many times each bound key was hit when exiting.
+    #[arg(long)]
+    no_stats: bool,
+
+    /// Accept OSC messages on this UDP port to switch kits and variants and
+    /// set the master volume, e.g. from a foot controller.
+    #[arg(long, value_name = "PORT")]
+    osc: Option<u16>,
+
+    /// Accept newline-separated commands (next-kit, prev-kit, select-kit,
+    /// master, panic, status) on a Unix socket at this path.
+    #[arg(long, value_name = "PATH")]
+    control_socket: Option<String>,
+
+    /// Show the current kit, bindings and active voices in a terminal UI
+    /// instead of plain log output.
+    #[arg(long)]
+    tui: bool,
+
     /// Enable verbose (debug) logging.
     #[arg(short, long)]
     verbose: bool,
@@ -36,55 +141,151 @@ fn main() {
 
     // Initialize logging.
     let log_level = if cli.verbose { "debug" } else { "info" };
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(log_level))
-        .format_timestamp_millis()
-        .init();
+    // With --tui, logs are shown in its log panel while it is on screen.
+    let log_buffer = tui::LogBuffer::default();
+    let mut logger =
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(log_level));
+    logger.format_timestamp_millis();
+    if cli.tui {
+        logger.target(env_logger::Target::Pipe(Box::new(log_buffer.clone())));
+    }
+    logger.init();
 
-    if let Err(e) = run(cli) {
+    if let Err(e) = run(cli, log_buffer) {
         log::error!("{:#}", e);
         std::process::exit(1);
     }
 }
 
-fn run(cli: Cli) -> Result<()> {
+fn run(cli: Cli, log_buffer: tui::LogBuffer) -> Result<()> {
     // Handle --list-devices.
     if cli.list_devices {
-        input::list_devices();
+        input::list_devices(cli.verbose);
         return Ok(());
     }
 
+    // Handle --list-audio-devices.
+    if cli.list_audio_devices {
+        return audio::list_output_devices();
+    }
+
     // Resolve config path (expand ~ to home dir).
     let config_path = config::expand_tilde(&cli.config);
+
+    // Handle --init-config.
+    if cli.init_config {
+        config::write_example_config(&config_path)?;
+        println!("Wrote an example config to {}", config_path.display());
+        println!("Find your keyboard with: keyboard-drums --list-devices");
+        println!("Then set device, samples_dir and bindings in it to taste.");
+        return Ok(());
+    }
+
+    let system_config_path = config::expand_tilde(&cli.system_config);
+
+    // Handle --check.
+    if cli.check {
+        std::process::exit(check::run_check(
+            &config_path,
+            &system_config_path,
+            cli.profile.as_deref(),
+            &cli.device,
+        ));
+    }
+
     log::info!("Loading config from: {}", config_path.display());
 
-    let mut resolved = config::load_config(&config_path)?;
+    let mut resolved =
+        config::load_config(&config_path, &system_config_path, cli.profile.as_deref())?;
 
     // CLI --device overrides config.
-    if let Some(ref device) = cli.device {
-        resolved.device = Some(device.clone());
+    if !cli.device.is_empty() {
+        resolved.devices = cli.device.clone();
     }
 
-    // Determine device path: CLI flag > config file > interactive picker.
-    let device_path = if let Some(ref path) = resolved.device {
-        path.clone()
-    } else {
-        input::pick_device_interactive()?
-    };
+    // CLI --audio-device overrides config.
+    if let Some(name) = &cli.audio_device {
+        resolved.audio_device = Some(name.clone());
+    }
 
-    // Build per-sample gain array from config bindings.
-    let mut sample_gains = vec![1.0f32; resolved.sample_names.len()];
-    for binding in resolved.key_map.values() {
-        if binding.sample_index < sample_gains.len() {
-            sample_gains[binding.sample_index] = binding.gain;
-        }
+    // CLI --metronome starts the metronome at that tempo.
+    if let Some(tempo) = cli.metronome {
+        config::check_metronome_tempo(tempo)?;
+        let metronome = resolved.metronome.get_or_insert_default();
+        metronome.tempo = tempo;
+        metronome.enabled = true;
+    }
+
+    if resolved.quiet_trigger_logs {
+        ring::set_quiet_trigger_logs(true);
+        log::info!("Per-trigger debug logs silenced (quiet_trigger_logs)");
     }
 
+    // Build per-sample settings from config bindings.
+    let sample_settings = resolved.sample_settings();
+
     // Discover drum kits and variants in the samples directory.
-    let library =
-        samples::discover_kits(&resolved.samples_dir, &resolved.sample_names, &sample_gains)?;
+    let mut library = samples::discover_kits(
+        &resolved.samples_dir,
+        &resolved.sample_names,
+        &sample_settings,
+    )?;
+    library.load_options = samples::LoadOptions {
+        sample_rate: resolved.output_sample_rate,
+        resample: resolved.resample,
+        clip_warning_run: resolved.clip_warning_run,
+        trim_silence: resolved.trim_silence,
+        normalize_peak: resolved.normalize_peak,
+        dither: resolved.dither,
+        ..Default::default()
+    };
+    library.loudness_normalize_velocity = resolved.loudness_normalize_velocity;
+    library.preload_all = resolved.preload_all;
+    library.background_loading = resolved.background_loading;
+
+    // Handle --analyze-levels.
+    if cli.analyze_levels {
+        return levels::analyze_levels(&library);
+    }
+
+    // Determine device paths: CLI flags > config file > interactive picker.
+    // MIDI input needs no evdev device.
+    let device_paths = match resolved.input {
+        config::InputKind::Midi => Vec::new(),
+        config::InputKind::Evdev if resolved.devices.is_empty() => {
+            vec![input::pick_device_interactive()?]
+        }
+        config::InputKind::Evdev => resolved.devices.clone(),
+    };
 
-    // Load the initial sample bank (first kit, first variant).
-    let initial_bank = library.load_bank(0, 0)?;
+    // With remember_state, start where the last run stopped.
+    let saved_state = match resolved.state_file.as_deref().map(state::load_state) {
+        Some(Ok(saved)) => saved,
+        Some(Err(e)) => {
+            log::warn!("Ignoring the saved state: {:#}", e);
+            None
+        }
+        None => None,
+    };
+    let (kit_index, variant_index) = match &saved_state {
+        Some(saved) => saved.kit_indices(&library).unwrap_or_else(|| {
+            log::warn!(
+                "Saved kit '{}' variant '{}' not found, starting on the first kit",
+                saved.kit,
+                saved.variant
+            );
+            (0, 0)
+        }),
+        None => (0, 0),
+    };
+
+    // With preload_all, load every kit now so switching reads no files.
+    if library.preload_all {
+        library.preload();
+    }
+
+    // Load the initial sample bank (first kit and variant unless restored).
+    let initial_bank = library.bank(kit_index, variant_index)?;
     log::info!(
         "Initial kit: '{}' variant '{}'",
         initial_bank.kit_name,
@@ -92,21 +293,134 @@ fn run(cli: Cli) -> Result<()> {
     );
 
     // Create the shared, atomically-swappable sample bank.
-    let sample_bank = Arc::new(ArcSwap::from_pointee(initial_bank));
+    let sample_bank = Arc::new(ArcSwap::new(initial_bank));
 
     // Create trigger ring buffer.
-    let (producer, consumer) = ring::create_trigger_channel();
-
-    // Build key map for the input thread.
-    let key_map = input::build_key_map(&resolved.key_map);
-
-    // Build the set of keys to suppress (sample bindings + cycling keys).
-    let suppressed_keys = input::build_suppressed_keys(&key_map, &resolved.cycling_keys);
+    let (producer, consumer) = ring::create_trigger_channel(resolved.ring_buffer_size)?;
+    let dropped_triggers = producer.drop_counter();
+
+    // Build the binding banks for the input thread. Each bank carries its
+    // own set of keys to suppress (its sample bindings + cycling keys).
+    let banks = input::build_binding_banks(&resolved);
+    let bound_keys = if cli.tui {
+        tui_bindings(&resolved)
+    } else {
+        Vec::new()
+    };
     log::info!(
         "Suppressing {} bound keys from reaching other applications",
-        suppressed_keys.len(),
+        banks[0].suppressed_keys.len(),
     );
 
+    // Master volume, shared so terminal commands can change it while playing.
+    let master_volume = Arc::new(audio::MasterVolume::new(
+        saved_state.map_or(resolved.master_volume, |saved| {
+            saved.master_volume.clamp(0.0, 1.0)
+        }),
+    ));
+
+    // Config watching: the main loop re-resolves a changed config file and
+    // hands its bindings to the input thread.
+    let (mut config_watcher, config_reloads) = if resolved.watch_config {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let watcher = reload::ConfigWatcher::new(
+            &config_path,
+            &system_config_path,
+            cli.profile.clone(),
+            cli.device.clone(),
+            &resolved,
+            Arc::clone(&master_volume),
+            sender,
+        );
+        log::info!("Watching {} for changes", config_path.display());
+        (Some(watcher), Some(receiver))
+    } else {
+        (None, None)
+    };
+
+    // Error tone shared by the input thread (kit load failures) and the
+    // audio thread (missing samples).
+    let error_cue = resolved
+        .audible_errors
+        .then(|| Arc::new(cue::ErrorCue::new(resolved.output_sample_rate)));
+
+    // Output capture: the audio callback keeps the last few seconds of the
+    // mix in a rolling buffer, which the capture key saves.
+    let capture = resolved.capture.as_ref().map(|capture| {
+        let seconds = capture.length.as_secs_f64();
+        log::info!(
+            "Capture key saves the last {:.1}s of output to {}",
+            seconds,
+            capture.dir.display()
+        );
+        let frames = (seconds * resolved.output_sample_rate as f64).round() as usize;
+        capture::OutputCapture {
+            buffer: Arc::new(capture::CaptureBuffer::new(frames)),
+            dir: capture.dir.clone(),
+            sample_rate: resolved.output_sample_rate,
+        }
+    });
+    let capture_buffer = capture.as_ref().map(|capture| Arc::clone(&capture.buffer));
+
+    // Press counts of every bound key, reported on exit unless --no-stats.
+    // A key bound in several banks is labelled with its first binding.
+    let key_hits = (!cli.no_stats).then(|| {
+        let mut keys = HashMap::new();
+        let key_maps = std::iter::once(&resolved.key_map)
+            .chain(resolved.banks.iter().map(|bank| &bank.key_map));
+        for (&code, binding) in key_maps.flatten() {
+            keys.entry(code)
+                .or_insert_with(|| binding_samples(binding, &resolved.sample_names));
+        }
+        Arc::new(stats::KeyHits::new(keys))
+    });
+
+    // Mute and solo state, toggled by the input thread and read by the
+    // audio callback.
+    let sample_toggles = Arc::new(audio::SampleToggles::new(resolved.sample_names.len()));
+
+    // Bound sample names, republished by config reloads for the preview
+    // server.
+    let sample_names = Arc::new(ArcSwap::from_pointee(library.sample_names.clone()));
+
+    // Metronome on/off switch, flipped by its toggle key and read by the
+    // audio callback.
+    let metronome_switch = resolved.metronome.as_ref().map(|metronome| {
+        log::info!(
+            "Metronome: {} BPM, {} beats per bar{}",
+            metronome.tempo,
+            metronome.beats_per_bar,
+            if metronome.enabled {
+                ""
+            } else {
+                " (off until toggled)"
+            }
+        );
+        Arc::new(AtomicBool::new(metronome.enabled))
+    });
+
+    let bindings = input::InputBindings {
+        banks,
+        cycling_keys: resolved.cycling_keys,
+        expression: resolved.expression,
+        key_travel: resolved.key_travel,
+        velocity_timing_window: resolved.velocity_timing_window,
+        simultaneous_hits: resolved.simultaneous_hits,
+        auto_advance: resolved.auto_advance,
+        audition: resolved.audition,
+        capture,
+        momentary: resolved.momentary,
+        kit_select: resolved.kit_select,
+        mute: resolved.mute,
+        solo: resolved.solo,
+        sample_toggles: Arc::clone(&sample_toggles),
+        metronome: metronome_switch.clone(),
+        error_cue: error_cue.clone(),
+        key_hits: key_hits.clone(),
+        master_volume: Arc::clone(&master_volume),
+        sample_names: Arc::clone(&sample_names),
+    };
+
     // Set up signal handlers.
     let shutdown = Arc::new(AtomicBool::new(false));
     let shutdown_signal = shutdown.clone();
@@ -116,51 +430,328 @@ fn run(cli: Cli) -> Result<()> {
     signal_hook::flag::register(signal_hook::consts::SIGINT, Arc::clone(&shutdown_signal))
         .context("Failed to register SIGINT handler")?;
 
-    log::info!("Signal handlers registered (SIGTERM, SIGINT)");
+    // SIGUSR1 asks for a status snapshot, logged from the main loop.
+    let status_request = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(signal_hook::consts::SIGUSR1, Arc::clone(&status_request))
+        .context("Failed to register SIGUSR1 handler")?;
+
+    log::info!("Signal handlers registered (SIGTERM, SIGINT, SIGUSR1)");
+
+    // Trigger latency statistics, reported periodically from the main loop.
+    let latency_stats = resolved
+        .latency_stats
+        .then(|| Arc::new(latency::LatencyStats::new()));
+
+    // Output peak before limiting, reported periodically from the main loop.
+    let peak_meter = resolved
+        .report_clipping
+        .then(|| Arc::new(audio::PeakMeter::new()));
+
+    // Voice steals and callback timing, reported periodically with --stats,
+    // and the voice count shown by --tui.
+    let engine_stats = (cli.stats || cli.tui)
+        .then(|| Arc::new(stats::EngineStats::new(resolved.output_sample_rate)));
+
+    // Recording: the audio callback feeds a ring drained by a writer thread.
+    let (recorder_producer, recorder) = match &cli.record {
+        Some(path) => {
+            let (producer, recorder) =
+                record::create_recorder(&config::expand_tilde(path), resolved.output_sample_rate)?;
+            (Some(producer), Some(recorder))
+        }
+        None => (None, None),
+    };
 
     // Start the audio engine.
+    let metronome = resolved
+        .metronome
+        .as_ref()
+        .zip(metronome_switch)
+        .map(|(metronome, switch)| {
+            metronome::Metronome::new(metronome, resolved.output_sample_rate, switch)
+        });
     let audio_config = audio::AudioEngineConfig {
         sample_bank: Arc::clone(&sample_bank),
+        device_name: resolved.audio_device.clone(),
+        sample_rate: resolved.output_sample_rate,
         max_voices: resolved.max_voices,
-        master_volume: resolved.master_volume,
+        master_volume: Arc::clone(&master_volume),
+        volume_smoothing: resolved.volume_smoothing,
+        reverb: resolved.reverb,
+        latency_stats: latency_stats.clone(),
+        error_cue,
+        max_triggers_per_callback: resolved.max_triggers_per_callback,
+        trigger_dedup: resolved.trigger_dedup,
+        bank_crossfade: resolved.bank_crossfade,
+        sample_toggles,
+        limiter: resolved.limiter,
+        extra_output_channels: resolved.extra_output_channels,
+        output_mode: resolved.output_mode,
+        latency: resolved.latency,
+        recorder: recorder_producer,
+        capture: capture_buffer,
+        metronome,
+        peak_meter: peak_meter.clone(),
+        engine_stats: engine_stats.clone(),
     };
 
-    let _audio_stream = audio::start_audio_stream(audio_config, consumer)?;
+    let mut audio_engine = audio::start_audio_stream(audio_config, consumer)?;
+    let reconnect_audio = resolved.reconnect_audio;
 
-    // Open the input device.
-    let device = input::open_device(std::path::Path::new(&device_path))?;
+    // What the SIGUSR1 status snapshot names as the input.
+    let input_name = match resolved.input {
+        config::InputKind::Evdev => device_paths.join(", "),
+        config::InputKind::Midi => format!(
+            "MIDI port {}",
+            resolved.midi.port.as_deref().unwrap_or("(first)")
+        ),
+    };
 
-    // Create a virtual device mirroring the physical keyboard's capabilities
-    // to forward non-bound events (keys, mouse axes, etc.).
-    let virtual_device = input::create_virtual_device(&device)?;
+    // Open the input devices, or connect to the MIDI port.
+    let input_source = match resolved.input {
+        config::InputKind::Evdev => {
+            let mut devices = Vec::with_capacity(device_paths.len());
+            for path in device_paths {
+                let device = input::open_device(std::path::Path::new(&path))?;
+
+                // Create a virtual device mirroring the physical keyboard's
+                // capabilities to forward non-bound events (keys, mouse axes, etc.).
+                let virtual_device =
+                    input::create_virtual_device(&device, &resolved.virtual_device)?;
+                devices.push(input::EvdevInput {
+                    path: path.into(),
+                    device,
+                    virtual_device,
+                });
+            }
+            input::InputSource::Evdev(devices)
+        }
+        config::InputKind::Midi => {
+            input::InputSource::Midi(input::open_midi_port(resolved.midi.port.as_deref())?)
+        }
+    };
+
+    // Control actions from the terminal, the preview server, OSC and the
+    // control socket share one channel into the input thread.
+    let terminal_commands =
+        resolved.terminal_commands && std::io::stdin().is_terminal() && !cli.tui;
+    if resolved.terminal_commands && cli.tui {
+        log::info!("The terminal UI owns the terminal, terminal commands disabled");
+    } else if resolved.terminal_commands && !terminal_commands {
+        log::info!("stdin is not a terminal, terminal commands disabled");
+    }
+    let remote_control = cli.osc.is_some() || cli.control_socket.is_some();
+    let (command_sender, commands) =
+        if terminal_commands || resolved.preview_server.is_some() || remote_control {
+            let (sender, receiver) = std::sync::mpsc::channel();
+            (Some(sender), Some(receiver))
+        } else {
+            (None, None)
+        };
+
+    // Terminal commands: a plain thread reading stdin lines. It is not
+    // joined on shutdown since it may be blocked in a read.
+    if terminal_commands && let Some(sender) = command_sender.clone() {
+        std::thread::Builder::new()
+            .name("commands".to_string())
+            .spawn(move || commands::run_command_reader(std::io::stdin().lock(), sender))
+            .context("Failed to spawn terminal command thread")?;
+    }
+
+    // OSC listener: bound now, like the preview server, so a taken port
+    // fails at startup. Kits are selected by name from the discovered list.
+    let osc = match (cli.osc, command_sender.clone()) {
+        (Some(port), Some(sender)) => Some((
+            osc::bind(port)?,
+            library.kits.iter().map(|kit| kit.name.clone()).collect(),
+            sender,
+        )),
+        _ => None,
+    };
+
+    // Control socket: opened now too. It answers status requests from the
+    // playing bank itself, and removes its file when the thread ends.
+    let control_socket = match (&cli.control_socket, command_sender.clone()) {
+        (Some(path), Some(sender)) => Some((
+            control::bind(&config::expand_tilde(path))?,
+            library.kits.iter().map(|kit| kit.name.clone()).collect(),
+            Arc::clone(&sample_bank),
+            sender,
+        )),
+        _ => None,
+    };
+
+    // Preview server: bound now so an unavailable address fails at startup.
+    // The kit list is fixed once discovered; the bound samples follow
+    // config reloads.
+    let preview = match (resolved.preview_server, command_sender) {
+        (Some(addr), Some(sender)) => Some((
+            preview::bind(addr)?,
+            library.kits.clone(),
+            Arc::clone(&sample_names),
+            sender,
+        )),
+        _ => None,
+    };
 
     // Run input loop on a dedicated thread using crossbeam scoped threads.
     // This ensures the thread is joined before we exit.
     log::info!("keyboard-drums ready. Press bound keys to play samples.");
 
+    // Heartbeat from the input loop, monitored by the optional watchdog.
+    // The input loop records the keyboard it grabs in it.
+    let heartbeat = watchdog::Heartbeat::new();
+    let grabs_keyboard = matches!(input_source, input::InputSource::Evdev(_));
+
+    // The terminal UI replaces the log output on the main thread.
+    let mut tui = match &engine_stats {
+        Some(stats) if cli.tui => Some(tui::Tui::start(
+            Arc::clone(&sample_bank),
+            Arc::clone(stats),
+            resolved.max_voices,
+            bound_keys,
+            log_buffer,
+        )?),
+        _ => None,
+    };
+
+    let show_stats = cli.stats;
+    let playing_bank = Arc::clone(&sample_bank);
     crossbeam::thread::scope(|s| {
         let shutdown_ref = &shutdown;
-        let cycling_keys = &resolved.cycling_keys;
+        let heartbeat = &heartbeat;
+
+        match (resolved.watchdog_timeout, grabs_keyboard) {
+            (Some(timeout), true) => {
+                let exit_on_hang = resolved.watchdog_exit;
+                s.spawn(move |_| {
+                    watchdog::run_watchdog(heartbeat, timeout, exit_on_hang, shutdown_ref)
+                });
+            }
+            (Some(_), false) => {
+                log::info!("Watchdog disabled: MIDI input does not grab a keyboard");
+            }
+            (None, _) => {}
+        }
+
+        if let Some((server, kits, sample_names, sender)) = preview {
+            s.spawn(move |_| {
+                preview::run_preview_server(server, &kits, &sample_names, sender, shutdown_ref)
+            });
+        }
+
+        if let Some((socket, kit_names, sender)) = osc {
+            s.spawn(move |_| osc::run_osc_listener(socket, kit_names, sender, shutdown_ref));
+        }
+
+        if let Some((socket, kit_names, bank, sender)) = control_socket {
+            s.spawn(move |_| {
+                control::run_control_socket(socket, kit_names, bank, sender, shutdown_ref)
+            });
+        }
+
+        let record_handle = recorder.map(|recorder| s.spawn(move |_| recorder.run(shutdown_ref)));
 
-        let input_handle = s.spawn(move |_| {
-            input::run_input_loop(
-                device,
-                &key_map,
+        let midi = &resolved.midi;
+        let virtual_identity = &resolved.virtual_device;
+        let input_handle = s.spawn(move |_| match input_source {
+            input::InputSource::Evdev(devices) => input::run_input_loop(
+                devices,
+                virtual_identity,
+                bindings,
                 producer,
                 shutdown_ref,
-                cycling_keys,
+                heartbeat,
                 library,
                 sample_bank,
-                &suppressed_keys,
-                virtual_device,
-            )
+                commands,
+                config_reloads,
+            ),
+            input::InputSource::Midi(port) => input::run_midi_loop(
+                port,
+                midi,
+                bindings,
+                producer,
+                shutdown_ref,
+                heartbeat,
+                library,
+                sample_bank,
+                commands,
+                config_reloads,
+            ),
         });
 
-        // Main thread: wait for shutdown signal.
+        // Main thread: wait for shutdown signal (drawing the terminal UI if
+        // enabled), reporting latency and clipping, reopening a lost audio
+        // device and reloading the config if enabled.
+        let mut next_latency_report = Instant::now() + LATENCY_REPORT_INTERVAL;
+        let mut next_clip_report = Instant::now() + CLIP_REPORT_INTERVAL;
+        let mut next_config_check = Instant::now() + CONFIG_CHECK_INTERVAL;
+        let mut next_stats_report = Instant::now() + STATS_REPORT_INTERVAL;
+        let mut reported_drops = 0;
         while !shutdown.load(Ordering::Relaxed) {
-            std::thread::sleep(std::time::Duration::from_millis(100));
+            match &mut tui {
+                Some(screen) => match screen.frame() {
+                    Ok(true) => {}
+                    Ok(false) => shutdown.store(true, Ordering::Relaxed),
+                    Err(e) => {
+                        tui = None;
+                        log::error!("Terminal UI failed, continuing without it: {:#}", e);
+                    }
+                },
+                None => std::thread::sleep(Duration::from_millis(100)),
+            }
+
+            if reconnect_audio {
+                audio_engine.supervise(Instant::now());
+            }
+
+            if let Some(stats) = &latency_stats
+                && Instant::now() >= next_latency_report
+            {
+                next_latency_report += LATENCY_REPORT_INTERVAL;
+                report_latency(stats);
+            }
+
+            if let Some(meter) = &peak_meter
+                && Instant::now() >= next_clip_report
+            {
+                next_clip_report += CLIP_REPORT_INTERVAL;
+                report_clipping(meter);
+            }
+
+            if let Some(stats) = &engine_stats
+                && show_stats
+            {
+                if Instant::now() >= next_stats_report {
+                    next_stats_report += STATS_REPORT_INTERVAL;
+                    report_stats(stats, &dropped_triggers, &mut reported_drops);
+                }
+            } else {
+                warn_dropped_triggers(&dropped_triggers, &mut reported_drops);
+            }
+
+            if let Some(watcher) = &mut config_watcher
+                && Instant::now() >= next_config_check
+            {
+                next_config_check += CONFIG_CHECK_INTERVAL;
+                watcher.poll();
+            }
+
+            if status_request.swap(false, Ordering::Relaxed) {
+                report_status(
+                    &playing_bank.load(),
+                    engine_stats.as_deref(),
+                    &dropped_triggers,
+                    master_volume.get(),
+                    &input_name,
+                    resolved.audio_device.as_deref(),
+                );
+            }
         }
 
+        // Restore the terminal before the shutdown logs.
+        drop(tui);
         log::info!("Shutdown signal received, stopping...");
 
         // Wait for the input thread to exit.
@@ -169,11 +760,171 @@ fn run(cli: Cli) -> Result<()> {
             Ok(Err(e)) => log::error!("Input thread error: {:#}", e),
             Err(_) => log::error!("Input thread panicked"),
         }
+
+        // Wait for the recording to be flushed and finalized.
+        if let Some(handle) = record_handle {
+            match handle.join() {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => log::error!("Recording error: {:#}", e),
+                Err(_) => log::error!("Recording thread panicked"),
+            }
+        }
     })
     .map_err(|_| anyhow::anyhow!("Thread scope panicked"))?;
 
+    if let Some(hits) = &key_hits {
+        report_key_hits(hits);
+    }
+
+    if let Some(path) = &resolved.state_file {
+        let saved = state::SavedState::from_bank(&playing_bank.load(), master_volume.get());
+        match state::save_state(path, &saved) {
+            Ok(()) => log::info!(
+                "Saved kit '{}' variant '{}' to {}",
+                saved.kit,
+                saved.variant,
+                path.display()
+            ),
+            Err(e) => log::error!("Failed to save state: {:#}", e),
+        }
+    }
+
     // Audio stream is dropped here, stopping playback.
     log::info!("keyboard-drums stopped.");
 
     Ok(())
 }
+
+/// Log trigger latency statistics for the last reporting window.
+fn report_latency(stats: &latency::LatencyStats) {
+    match stats.take_summary() {
+        Some(summary) => log::info!(
+            "Trigger latency ({} triggers): min {:.2}ms, avg {:.2}ms, max {:.2}ms, jitter {:.2}ms",
+            summary.count,
+            summary.min.as_secs_f64() * 1000.0,
+            summary.avg.as_secs_f64() * 1000.0,
+            summary.max.as_secs_f64() * 1000.0,
+            summary.jitter().as_secs_f64() * 1000.0,
+        ),
+        None => log::debug!("Trigger latency: no triggers in the last window"),
+    }
+}
+
+/// The default bank's bound keys and their sample names, in key code order,
+/// for the terminal UI.
+fn tui_bindings(resolved: &config::ResolvedConfig) -> Vec<(String, String)> {
+    let mut codes: Vec<&u16> = resolved.key_map.keys().collect();
+    codes.sort();
+    codes
+        .into_iter()
+        .map(|code| {
+            (
+                format!("{:?}", evdev::KeyCode::new(*code)),
+                binding_samples(&resolved.key_map[code], &resolved.sample_names),
+            )
+        })
+        .collect()
+}
+
+/// The sample names a binding plays, comma-separated.
+fn binding_samples(binding: &config::ResolvedBinding, sample_names: &[String]) -> String {
+    let samples: Vec<&str> = binding
+        .sample_indices
+        .iter()
+        .map(|&i| sample_names[i].as_str())
+        .collect();
+    samples.join(", ")
+}
+
+/// Log how many times each bound key was hit this session, most hit first.
+fn report_key_hits(hits: &stats::KeyHits) {
+    let summary = hits.summary();
+    if summary.is_empty() {
+        log::info!("No bound keys were hit this session");
+        return;
+    }
+    log::info!("Key hits this session:");
+    for (code, samples, count) in summary {
+        log::info!(
+            "  {:<16} {:<24} {}",
+            format!("{:?}", evdev::KeyCode::new(code)),
+            samples,
+            count
+        );
+    }
+}
+
+/// Log engine statistics for the last reporting window, including the
+/// triggers dropped since the previous report.
+fn report_stats(stats: &stats::EngineStats, dropped: &AtomicU64, reported_drops: &mut u64) {
+    let summary = stats.take_summary();
+    let total_drops = dropped.load(Ordering::Relaxed);
+    log::info!(
+        "Audio stats: {} dropped triggers, {} voice steals, {} callbacks \
+         (avg {:.2}ms, max {:.2}ms, {} late)",
+        total_drops - *reported_drops,
+        summary.voice_steals,
+        summary.callbacks,
+        summary.avg_callback.as_secs_f64() * 1000.0,
+        summary.max_callback.as_secs_f64() * 1000.0,
+        summary.late_callbacks,
+    );
+    *reported_drops = total_drops;
+}
+
+/// Log a snapshot of what is playing, for SIGUSR1. The voice count is only
+/// measured with --stats or --tui.
+fn report_status(
+    bank: &samples::SampleBank,
+    engine_stats: Option<&stats::EngineStats>,
+    dropped: &AtomicU64,
+    master_volume: f32,
+    input_name: &str,
+    audio_device: Option<&str>,
+) {
+    let active_voices = match engine_stats {
+        Some(stats) => stats.active_voices().to_string(),
+        None => "unknown (run with --stats)".to_string(),
+    };
+    log::info!(
+        "Status: kit '{}' variant '{}', {} active voices, {} dropped triggers, \
+         master volume {:.2}, input {}, audio device {}",
+        bank.kit_name,
+        bank.variant_name,
+        active_voices,
+        dropped.load(Ordering::Relaxed),
+        master_volume,
+        input_name,
+        audio_device.unwrap_or("(default)"),
+    );
+}
+
+/// Warn when triggers were dropped since the last check, because the ring
+/// was full. Without `--stats` this is the only place drops show up.
+fn warn_dropped_triggers(dropped: &AtomicU64, reported_drops: &mut u64) {
+    let total_drops = dropped.load(Ordering::Relaxed);
+    if total_drops > *reported_drops {
+        log::warn!(
+            "Trigger ring buffer full! Dropped {} trigger(s). \
+             This may indicate the audio thread is not consuming fast enough.",
+            total_drops - *reported_drops
+        );
+        *reported_drops = total_drops;
+    }
+}
+
+/// Log the output peak for the last reporting window if it went over full
+/// scale, i.e. if the limiter had to clamp it.
+fn report_clipping(meter: &audio::PeakMeter) {
+    let peak = meter.take();
+    if peak > 1.0 {
+        log::warn!(
+            "Output clipped: peak {:.2} ({:+.1} dB over full scale) in the last {}s",
+            peak,
+            20.0 * peak.log10(),
+            CLIP_REPORT_INTERVAL.as_secs()
+        );
+    } else {
+        log::debug!("Output peak {:.2}, no clipping in the last window", peak);
+    }
+}
@@ -0,0 +1,183 @@
+use crate::config::ResolvedMetronome;
+use crate::samples::SampleData;
+use crate::synth::{self, SynthSpec, Waveform};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Pitch of the click on the first beat of a bar, in Hz.
+const ACCENT_HZ: f32 = 1600.0;
+
+/// Pitch of the click on the other beats, in Hz.
+const BEAT_HZ: f32 = 1000.0;
+
+/// Length of each click, in milliseconds.
+const CLICK_MS: f32 = 25.0;
+
+/// Built-in click track, mixed by the audio callback.
+///
+/// Beats are scheduled on a frame counter: beat `n` starts at frame
+/// `round(n * frames_per_beat)` after the metronome was switched on, so
+/// the tempo does not drift however the output is split into buffers.
+/// Both clicks are rendered in `new`, so `render` is real-time safe.
+pub struct Metronome {
+    /// On/off switch, flipped by the toggle key on the input thread.
+    enabled: Arc<AtomicBool>,
+
+    /// Whether the switch was on during the previous buffer.
+    was_enabled: bool,
+
+    /// Click played on the first beat of each bar.
+    accent: SampleData,
+
+    /// Click played on the other beats.
+    beat: SampleData,
+
+    frames_per_beat: f64,
+    beats_per_bar: u64,
+    volume: f32,
+
+    /// Frames rendered since the metronome was switched on.
+    frame: u64,
+
+    /// Index of the next beat since the metronome was switched on.
+    next_beat: u64,
+
+    /// Frame `next_beat` starts at.
+    next_beat_frame: u64,
+
+    /// The click being played, if any: whether it is the accent, and the
+    /// next frame of it to play.
+    playing: Option<(bool, usize)>,
+}
+
+impl Metronome {
+    pub fn new(settings: &ResolvedMetronome, sample_rate: u32, enabled: Arc<AtomicBool>) -> Self {
+        let click = |freq| {
+            synth::render(
+                &SynthSpec {
+                    waveform: Waveform::Sine(freq),
+                    length_ms: CLICK_MS,
+                },
+                sample_rate,
+            )
+        };
+        Self {
+            enabled,
+            was_enabled: false,
+            accent: click(ACCENT_HZ),
+            beat: click(BEAT_HZ),
+            frames_per_beat: sample_rate as f64 * 60.0 / settings.tempo,
+            beats_per_bar: settings.beats_per_bar as u64,
+            volume: settings.volume,
+            frame: 0,
+            next_beat: 0,
+            next_beat_frame: 0,
+            playing: None,
+        }
+    }
+
+    /// Add the clicks due in this buffer to the first `mixed_channels` of
+    /// the interleaved `data`. Switching the metronome on starts a bar
+    /// right away; switching it off lets the current click finish.
+    pub fn render(&mut self, data: &mut [f32], channels: usize, mixed_channels: usize) {
+        let enabled = self.enabled.load(Ordering::Relaxed);
+        if enabled && !self.was_enabled {
+            self.frame = 0;
+            self.next_beat = 0;
+            self.next_beat_frame = 0;
+        }
+        self.was_enabled = enabled;
+        if !enabled && self.playing.is_none() {
+            return;
+        }
+
+        for samples in data.chunks_exact_mut(channels) {
+            if enabled && self.frame == self.next_beat_frame {
+                let accent = self.next_beat.is_multiple_of(self.beats_per_bar);
+                self.playing = Some((accent, 0));
+                self.next_beat += 1;
+                self.next_beat_frame =
+                    (self.next_beat as f64 * self.frames_per_beat).round() as u64;
+            }
+            self.frame += 1;
+
+            let Some((accent, position)) = &mut self.playing else {
+                continue;
+            };
+            let click = if *accent { &self.accent } else { &self.beat };
+            let value = click.data[*position] * self.volume;
+            for sample in samples.iter_mut().take(mixed_channels) {
+                *sample += value;
+            }
+            *position += 1;
+            if *position >= click.data.len() {
+                self.playing = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Frames at which a click starts in `data`, a mono render.
+    fn click_onsets(data: &[f32]) -> Vec<usize> {
+        // The clicks fade in from silence, so the first frame of each is
+        // zero; a click starts on the frame before its first non-zero one
+        // after a gap.
+        let mut onsets = Vec::new();
+        let mut last_sound: Option<usize> = None;
+        for (i, &sample) in data.iter().enumerate() {
+            if sample == 0.0 {
+                continue;
+            }
+            if last_sound.is_none_or(|last| i - last > 100) {
+                onsets.push(i - 1);
+            }
+            last_sound = Some(i);
+        }
+        onsets
+    }
+
+    #[test]
+    fn test_clicks_land_on_beat_frames_across_buffers() {
+        // 7 beats per 2 seconds at 48kHz: 13714.28... frames per beat, so
+        // any per-buffer rounding would drift.
+        let settings = ResolvedMetronome {
+            tempo: 210.0,
+            beats_per_bar: 3,
+            volume: 1.0,
+            enabled: true,
+        };
+        let enabled = Arc::new(AtomicBool::new(true));
+        let mut metronome = Metronome::new(&settings, 48000, Arc::clone(&enabled));
+
+        // Render in odd-sized buffers, as a device might ask for them.
+        let mut data = vec![0.0f32; 96_000];
+        let mut start = 0;
+        for size in [441, 1000, 37, 4096].into_iter().cycle() {
+            let end = (start + size).min(data.len());
+            metronome.render(&mut data[start..end], 1, 1);
+            start = end;
+            if start == data.len() {
+                break;
+            }
+        }
+
+        let expected: Vec<usize> = (0..7)
+            .map(|n| (n as f64 * 48000.0 * 60.0 / 210.0).round() as usize)
+            .collect();
+        assert_eq!(click_onsets(&data), expected);
+
+        // Every third beat is the higher, accented click.
+        assert_eq!(data[expected[3] + 5], data[expected[0] + 5]);
+        assert!(data[expected[3] + 5] > data[expected[1] + 5]);
+
+        // Switched off, no new clicks start.
+        enabled.store(false, Ordering::Relaxed);
+        let mut silent = vec![0.0f32; 48000];
+        metronome.render(&mut silent, 1, 1);
+        assert!(silent.iter().all(|&s| s == 0.0));
+    }
+}
@@ -0,0 +1,341 @@
+use crate::commands::{ControlAction, KitAction};
+use anyhow::{Context, Result, bail};
+use std::net::UdpSocket;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+/// How long the listener waits for a packet before re-checking shutdown.
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Largest packet read at once. Control messages are a few dozen bytes.
+const MAX_PACKET_SIZE: usize = 4096;
+
+/// Addresses understood, logged when a message is not.
+const OSC_HELP: &str = "OSC addresses: /kit/next, /kit/prev, /kit/select <name or number>, /variant/next, /variant/prev, /master <0.0 to 1.0>";
+
+/// An argument of an OSC message.
+#[derive(Debug, Clone, PartialEq)]
+enum OscArg {
+    Int(i32),
+    Float(f32),
+    Str(String),
+}
+
+impl OscArg {
+    fn as_f32(&self) -> Option<f32> {
+        match self {
+            OscArg::Int(i) => Some(*i as f32),
+            OscArg::Float(f) => Some(*f),
+            OscArg::Str(_) => None,
+        }
+    }
+}
+
+/// One OSC message: an address such as `/kit/next` and its arguments.
+#[derive(Debug, Clone, PartialEq)]
+struct OscMessage {
+    address: String,
+    args: Vec<OscArg>,
+}
+
+/// Reads the 4-byte aligned fields of an OSC packet in order.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if len > self.bytes.len() {
+            bail!("OSC packet is truncated");
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// A NUL-terminated string, padded with NULs to a multiple of 4 bytes.
+    fn string(&mut self) -> Result<&'a str> {
+        let len = self
+            .bytes
+            .iter()
+            .position(|&b| b == 0)
+            .context("OSC string is not terminated")?;
+        let padded = (len + 4) & !3;
+        let bytes = self.take(padded.min(self.bytes.len()))?;
+        std::str::from_utf8(&bytes[..len]).context("OSC string is not valid UTF-8")
+    }
+}
+
+/// Decode the messages in `packet`, a single message or a bundle of them,
+/// into `messages`. Bundle time tags are ignored: everything acts at once.
+fn decode_packet(packet: &[u8], messages: &mut Vec<OscMessage>) -> Result<()> {
+    let mut reader = Reader { bytes: packet };
+    if packet.starts_with(b"#bundle\0") {
+        reader.take(16)?; // The "#bundle" string and its time tag.
+        while !reader.is_empty() {
+            let size = reader.u32()? as usize;
+            decode_packet(reader.take(size)?, messages)?;
+        }
+        return Ok(());
+    }
+
+    let address = reader.string()?;
+    if !address.starts_with('/') {
+        bail!("'{}' is not an OSC address", address);
+    }
+    // Old senders leave out the type tags of a message without arguments.
+    let tags = if reader.is_empty() {
+        ","
+    } else {
+        reader.string()?
+    };
+    let Some(tags) = tags.strip_prefix(',') else {
+        bail!("OSC message {} has no type tags", address);
+    };
+    let mut args = Vec::with_capacity(tags.len());
+    for tag in tags.chars() {
+        args.push(match tag {
+            'i' => OscArg::Int(reader.u32()? as i32),
+            'f' => OscArg::Float(f32::from_bits(reader.u32()?)),
+            's' => OscArg::Str(reader.string()?.to_string()),
+            // True and false carry no data.
+            'T' => OscArg::Int(1),
+            'F' => OscArg::Int(0),
+            other => bail!("Unsupported OSC argument type '{}' in {}", other, address),
+        });
+    }
+    messages.push(OscMessage {
+        address: address.to_string(),
+        args,
+    });
+    Ok(())
+}
+
+/// The control action `message` asks for. Returns None for a button
+/// release: controllers send 1 when a button is pressed and 0 when it is
+/// let go, and only the press should switch kits.
+///
+/// Kit numbers are one-based, as in terminal commands; `kit_names` are the
+/// discovered kits, in order.
+fn message_action(message: &OscMessage, kit_names: &[String]) -> Result<Option<ControlAction>> {
+    let first = message.args.first();
+    let released = first.and_then(OscArg::as_f32) == Some(0.0);
+    let kit_action = match message.address.as_str() {
+        "/kit/next" => KitAction::NextKit,
+        "/kit/prev" => KitAction::PrevKit,
+        "/variant/next" => KitAction::NextVariant,
+        "/variant/prev" => KitAction::PrevVariant,
+        "/kit/select" => match first {
+            Some(OscArg::Str(name)) => match kit_names.iter().position(|k| k == name) {
+                Some(index) => KitAction::SelectKit(index),
+                None => bail!("Unknown kit '{}'", name),
+            },
+            Some(OscArg::Int(n)) if *n >= 1 => KitAction::SelectKit(*n as usize - 1),
+            Some(OscArg::Int(_)) => bail!("Kit numbers start at 1"),
+            _ => bail!("/kit/select needs a kit name or number"),
+        },
+        "/master" => {
+            let volume = first
+                .and_then(OscArg::as_f32)
+                .context("/master needs a volume")?;
+            if !(0.0..=1.0).contains(&volume) {
+                bail!("Volume must be between 0.0 and 1.0, got {}", volume);
+            }
+            return Ok(Some(ControlAction::SetVolume(volume)));
+        }
+        other => bail!("Unknown OSC address '{}'", other),
+    };
+    if released && !matches!(kit_action, KitAction::SelectKit(_)) {
+        return Ok(None);
+    }
+    Ok(Some(ControlAction::Kit(kit_action)))
+}
+
+/// Listen for OSC messages on UDP `port`, on every interface so a
+/// controller on another machine can reach it. Fails early if the port is
+/// taken.
+pub fn bind(port: u16) -> Result<UdpSocket> {
+    let socket = UdpSocket::bind(("0.0.0.0", port))
+        .with_context(|| format!("Failed to listen for OSC on UDP port {}", port))?;
+    socket
+        .set_read_timeout(Some(SHUTDOWN_POLL_INTERVAL))
+        .context("Failed to set the OSC socket timeout")?;
+    log::info!("Listening for OSC on UDP port {}", port);
+    Ok(socket)
+}
+
+/// Receive OSC messages until `shutdown` is set and forward what they ask
+/// for to the input thread as control actions.
+pub fn run_osc_listener(
+    socket: UdpSocket,
+    kit_names: Vec<String>,
+    sender: Sender<ControlAction>,
+    shutdown: &AtomicBool,
+) {
+    let mut packet = [0u8; MAX_PACKET_SIZE];
+    let mut messages = Vec::new();
+    while !shutdown.load(Ordering::Relaxed) {
+        let (len, from) = match socket.recv_from(&mut packet) {
+            Ok(received) => received,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                ) =>
+            {
+                continue;
+            }
+            Err(e) => {
+                log::warn!("Failed to receive an OSC packet: {}", e);
+                continue;
+            }
+        };
+
+        messages.clear();
+        if let Err(e) = decode_packet(&packet[..len], &mut messages) {
+            log::warn!("Ignoring OSC packet from {}: {:#}", from, e);
+            continue;
+        }
+        for message in &messages {
+            match message_action(message, &kit_names) {
+                Ok(Some(action)) => {
+                    log::debug!("OSC {} {:?} -> {:?}", message.address, message.args, action);
+                    if sender.send(action).is_err() {
+                        return;
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => log::warn!("OSC from {}: {:#}. {}", from, e, OSC_HELP),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Append `s` as an OSC string: NUL-terminated and padded to 4 bytes.
+    fn push_string(packet: &mut Vec<u8>, s: &str) {
+        packet.extend_from_slice(s.as_bytes());
+        packet.resize((packet.len() + 4) & !3, 0);
+    }
+
+    fn encode(address: &str, args: &[OscArg]) -> Vec<u8> {
+        let mut packet = Vec::new();
+        push_string(&mut packet, address);
+        let tags: String = args
+            .iter()
+            .map(|arg| match arg {
+                OscArg::Int(_) => 'i',
+                OscArg::Float(_) => 'f',
+                OscArg::Str(_) => 's',
+            })
+            .collect();
+        push_string(&mut packet, &format!(",{}", tags));
+        for arg in args {
+            match arg {
+                OscArg::Int(i) => packet.extend_from_slice(&i.to_be_bytes()),
+                OscArg::Float(f) => packet.extend_from_slice(&f.to_be_bytes()),
+                OscArg::Str(s) => push_string(&mut packet, s),
+            }
+        }
+        packet
+    }
+
+    /// The action of the single message in `packet`.
+    fn action(packet: &[u8]) -> Result<Option<ControlAction>> {
+        let kit_names = vec!["acoustic".to_string(), "electronic".to_string()];
+        let mut messages = Vec::new();
+        decode_packet(packet, &mut messages)?;
+        assert_eq!(messages.len(), 1);
+        message_action(&messages[0], &kit_names)
+    }
+
+    #[test]
+    fn test_osc_messages_become_control_actions() {
+        assert_eq!(
+            action(&encode("/kit/next", &[])).unwrap(),
+            Some(ControlAction::Kit(KitAction::NextKit))
+        );
+        assert_eq!(
+            action(&encode("/variant/prev", &[OscArg::Float(1.0)])).unwrap(),
+            Some(ControlAction::Kit(KitAction::PrevVariant))
+        );
+        assert_eq!(
+            action(&encode(
+                "/kit/select",
+                &[OscArg::Str("electronic".to_string())]
+            ))
+            .unwrap(),
+            Some(ControlAction::Kit(KitAction::SelectKit(1)))
+        );
+        assert_eq!(
+            action(&encode("/kit/select", &[OscArg::Int(1)])).unwrap(),
+            Some(ControlAction::Kit(KitAction::SelectKit(0)))
+        );
+        assert_eq!(
+            action(&encode("/master", &[OscArg::Float(0.25)])).unwrap(),
+            Some(ControlAction::SetVolume(0.25))
+        );
+
+        // A button release does nothing.
+        assert_eq!(
+            action(&encode("/variant/next", &[OscArg::Float(0.0)])).unwrap(),
+            None
+        );
+
+        assert!(
+            action(&encode(
+                "/kit/select",
+                &[OscArg::Str("vintage".to_string())]
+            ))
+            .is_err()
+        );
+        assert!(action(&encode("/kit/select", &[OscArg::Int(0)])).is_err());
+        assert!(action(&encode("/master", &[OscArg::Float(1.5)])).is_err());
+        assert!(action(&encode("/master", &[])).is_err());
+        assert!(action(&encode("/tempo", &[OscArg::Int(120)])).is_err());
+        assert!(action(&encode("/master", &[OscArg::Float(0.5)])[..12]).is_err());
+    }
+
+    #[test]
+    fn test_bundle_decodes_every_message() {
+        let mut bundle = Vec::new();
+        push_string(&mut bundle, "#bundle");
+        bundle.extend_from_slice(&1u64.to_be_bytes()); // "Immediately".
+        for message in [
+            encode("/kit/next", &[]),
+            encode("/master", &[OscArg::Float(0.5)]),
+        ] {
+            bundle.extend_from_slice(&(message.len() as u32).to_be_bytes());
+            bundle.extend_from_slice(&message);
+        }
+
+        let mut messages = Vec::new();
+        decode_packet(&bundle, &mut messages).unwrap();
+        assert_eq!(
+            messages,
+            vec![
+                OscMessage {
+                    address: "/kit/next".to_string(),
+                    args: Vec::new(),
+                },
+                OscMessage {
+                    address: "/master".to_string(),
+                    args: vec![OscArg::Float(0.5)],
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,228 @@
+use crate::commands::ControlAction;
+use crate::samples::KitInfo;
+use anyhow::{Result, anyhow};
+use arc_swap::ArcSwap;
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// How long the server waits for a request before re-checking shutdown.
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// JSON body of `GET /kits`.
+#[derive(Debug, Serialize)]
+struct KitListing<'a> {
+    kits: Vec<KitEntry<'a>>,
+
+    /// Sample filenames, triggerable with `POST /trigger/<sample>`.
+    samples: &'a [String],
+}
+
+#[derive(Debug, Serialize)]
+struct KitEntry<'a> {
+    name: &'a str,
+    variants: &'a [String],
+}
+
+/// What a request asks the server to do.
+#[derive(Debug, PartialEq)]
+enum Route {
+    ListKits,
+
+    /// Play the bound sample with this name.
+    Trigger(String),
+
+    /// A trigger for a sample name that is not bound.
+    UnknownSample(String),
+
+    NotFound,
+}
+
+/// Serialize the discovered kits and bound samples as JSON.
+fn kit_listing_json(kits: &[KitInfo], sample_names: &[String]) -> String {
+    let listing = KitListing {
+        kits: kits
+            .iter()
+            .map(|kit| KitEntry {
+                name: &kit.name,
+                variants: &kit.variants,
+            })
+            .collect(),
+        samples: sample_names,
+    };
+    serde_json::to_string(&listing).expect("kit listing is always serializable")
+}
+
+/// Decode `%XX` escapes in a URL path segment. Returns None for malformed
+/// escapes or invalid UTF-8.
+fn percent_decode(segment: &str) -> Option<String> {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// Match a request method and URL against the server's endpoints.
+fn route(method: &Method, url: &str, sample_names: &[String]) -> Route {
+    let path = url.split('?').next().unwrap_or_default();
+    match (method, path) {
+        (Method::Get, "/kits") => Route::ListKits,
+        (Method::Post, path) => {
+            let Some(name) = path.strip_prefix("/trigger/").and_then(percent_decode) else {
+                return Route::NotFound;
+            };
+            if sample_names.contains(&name) {
+                Route::Trigger(name)
+            } else {
+                Route::UnknownSample(name)
+            }
+        }
+        _ => Route::NotFound,
+    }
+}
+
+/// Start listening on `addr`. Fails early if the address is unavailable.
+pub fn bind(addr: SocketAddr) -> Result<Server> {
+    let server = Server::http(addr)
+        .map_err(|e| anyhow!("Failed to start preview server on {}: {}", addr, e))?;
+    log::info!("Preview server listening on http://{}", addr);
+    Ok(server)
+}
+
+/// Serve kit listings and sample triggers until `shutdown` is set.
+///
+/// Triggers are forwarded to the input thread as control actions, by
+/// sample name, and played through its ring producer. `sample_names` are
+/// the bound samples, kept current by config reloads.
+pub fn run_preview_server(
+    server: Server,
+    kits: &[KitInfo],
+    sample_names: &ArcSwap<Vec<String>>,
+    sender: Sender<ControlAction>,
+    shutdown: &AtomicBool,
+) {
+    while !shutdown.load(Ordering::Relaxed) {
+        let request = match server.recv_timeout(SHUTDOWN_POLL_INTERVAL) {
+            Ok(Some(request)) => request,
+            Ok(None) => continue,
+            Err(e) => {
+                log::warn!("Preview server failed to receive a request: {}", e);
+                continue;
+            }
+        };
+
+        let sample_names = sample_names.load();
+        let route = route(request.method(), request.url(), &sample_names);
+        log::debug!(
+            "Preview server: {} {} -> {:?}",
+            request.method(),
+            request.url(),
+            route
+        );
+        match route {
+            Route::ListKits => respond_json(request, &kit_listing_json(kits, &sample_names)),
+            Route::Trigger(name) => match sender.send(ControlAction::TriggerSampleNamed(name)) {
+                Ok(()) => respond(request, 204, ""),
+                Err(_) => respond(request, 503, "Input thread is not running"),
+            },
+            Route::UnknownSample(name) => {
+                respond(request, 404, &format!("Unknown sample '{}'", name))
+            }
+            Route::NotFound => respond(request, 404, "Not found"),
+        }
+    }
+    log::debug!("Preview server stopped");
+}
+
+fn respond(request: Request, status: u16, body: &str) {
+    let response = Response::from_string(body).with_status_code(status);
+    if let Err(e) = request.respond(response) {
+        log::debug!("Preview server failed to send response: {}", e);
+    }
+}
+
+fn respond_json(request: Request, body: &str) {
+    let content_type =
+        Header::from_bytes("Content-Type", "application/json").expect("static header is valid");
+    let response = Response::from_string(body).with_header(content_type);
+    if let Err(e) = request.respond(response) {
+        log::debug!("Preview server failed to send response: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kit_listing_json() {
+        let kits = vec![
+            KitInfo {
+                name: "acoustic".to_string(),
+                variants: vec!["dry".to_string(), "room".to_string()],
+            },
+            KitInfo {
+                name: "electronic".to_string(),
+                variants: vec!["808".to_string()],
+            },
+        ];
+        let sample_names = vec!["kick.wav".to_string(), "snare.wav".to_string()];
+
+        let json: serde_json::Value =
+            serde_json::from_str(&kit_listing_json(&kits, &sample_names)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "kits": [
+                    { "name": "acoustic", "variants": ["dry", "room"] },
+                    { "name": "electronic", "variants": ["808"] },
+                ],
+                "samples": ["kick.wav", "snare.wav"],
+            })
+        );
+    }
+
+    #[test]
+    fn test_route() {
+        let samples = vec!["kick.wav".to_string(), "open hat.wav".to_string()];
+
+        assert_eq!(route(&Method::Get, "/kits", &samples), Route::ListKits);
+        assert_eq!(route(&Method::Get, "/kits?x=1", &samples), Route::ListKits);
+        assert_eq!(
+            route(&Method::Post, "/trigger/kick.wav", &samples),
+            Route::Trigger("kick.wav".to_string())
+        );
+        assert_eq!(
+            route(&Method::Post, "/trigger/open%20hat.wav", &samples),
+            Route::Trigger("open hat.wav".to_string())
+        );
+        assert_eq!(
+            route(&Method::Post, "/trigger/tom.wav", &samples),
+            Route::UnknownSample("tom.wav".to_string())
+        );
+
+        // Triggering needs POST, and malformed escapes are rejected.
+        assert_eq!(
+            route(&Method::Get, "/trigger/kick.wav", &samples),
+            Route::NotFound
+        );
+        assert_eq!(
+            route(&Method::Post, "/trigger/kick%2", &samples),
+            Route::NotFound
+        );
+        assert_eq!(route(&Method::Post, "/kits", &samples), Route::NotFound);
+    }
+}
@@ -0,0 +1,181 @@
+use anyhow::{Context, Result};
+use ringbuf::HeapRb;
+use ringbuf::traits::{Consumer, Observer, Producer, Split};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// Channel count of recordings.
+const RECORD_CHANNELS: u16 = 2;
+
+/// Seconds of audio the recording ring holds, so a slow disk write does
+/// not drop output.
+const RECORD_BUFFER_SECS: usize = 4;
+
+/// Samples the writer thread pops per write.
+const WRITE_CHUNK_SAMPLES: usize = 8192;
+
+/// How long the writer thread sleeps when the ring is empty.
+const WRITER_IDLE_SLEEP: Duration = Duration::from_millis(10);
+
+/// Producer half of the recording ring (used by the audio callback).
+pub struct RecordProducer {
+    inner: ringbuf::HeapProd<f32>,
+
+    /// Set while samples are being dropped, so an overflow logs once
+    /// rather than every callback.
+    overflowing: bool,
+}
+
+/// Writer half of a recording: drains the ring into a WAV file.
+pub struct Recorder {
+    inner: ringbuf::HeapCons<f32>,
+    writer: hound::WavWriter<BufWriter<File>>,
+}
+
+/// Interleaved samples in the recording ring at `sample_rate`.
+fn ring_size(sample_rate: u32) -> usize {
+    sample_rate as usize * RECORD_CHANNELS as usize * RECORD_BUFFER_SECS
+}
+
+/// Create the WAV file at `path` and the ring feeding it, recording at the
+/// output `sample_rate`.
+///
+/// The producer is meant for the audio callback, the recorder for a
+/// dedicated writer thread.
+pub fn create_recorder(path: &Path, sample_rate: u32) -> Result<(RecordProducer, Recorder)> {
+    let spec = hound::WavSpec {
+        channels: RECORD_CHANNELS,
+        sample_rate,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+    };
+    let writer = hound::WavWriter::create(path, spec)
+        .with_context(|| format!("Failed to create recording: {}", path.display()))?;
+
+    let rb = HeapRb::<f32>::new(ring_size(sample_rate));
+    let (prod, cons) = rb.split();
+
+    log::info!(
+        "Recording output to {} ({}s buffer)",
+        path.display(),
+        RECORD_BUFFER_SECS
+    );
+
+    Ok((
+        RecordProducer {
+            inner: prod,
+            overflowing: false,
+        },
+        Recorder {
+            inner: cons,
+            writer,
+        },
+    ))
+}
+
+impl RecordProducer {
+    /// Push one callback's interleaved output into the ring as stereo.
+    /// Extra output channels are dropped and mono is duplicated.
+    ///
+    /// Called from the audio callback: lock-free and allocation-free.
+    /// Returns false if the ring was full and samples were dropped.
+    pub fn push(&mut self, data: &[f32], channels: usize) -> bool {
+        let complete = if channels == RECORD_CHANNELS as usize {
+            self.inner.push_slice(data) == data.len()
+        } else {
+            let mut complete = true;
+            for frame in data.chunks_exact(channels) {
+                let stereo = [frame[0], frame[1.min(channels - 1)]];
+                complete &= self.inner.push_slice(&stereo) == stereo.len();
+            }
+            complete
+        };
+
+        if !complete && !self.overflowing {
+            log::warn!(
+                "Recording buffer full! Dropped output samples. \
+                 This may indicate the disk is not keeping up."
+            );
+        }
+        self.overflowing = !complete;
+        complete
+    }
+}
+
+impl Recorder {
+    /// Write recorded samples to the file until `shutdown` is set, then
+    /// drain what is left and finalize the WAV header.
+    pub fn run(mut self, shutdown: &AtomicBool) -> Result<()> {
+        let mut buf = vec![0.0f32; WRITE_CHUNK_SAMPLES];
+
+        loop {
+            // Read the flag before draining so samples pushed just before
+            // shutdown are still written.
+            let stopping = shutdown.load(Ordering::Relaxed);
+            while !self.inner.is_empty() {
+                let count = self.inner.pop_slice(&mut buf);
+                for &sample in &buf[..count] {
+                    self.writer
+                        .write_sample(sample)
+                        .context("Failed to write recording")?;
+                }
+            }
+            if stopping {
+                break;
+            }
+            std::thread::sleep(WRITER_IDLE_SLEEP);
+        }
+
+        let frames = self.writer.duration();
+        let sample_rate = self.writer.spec().sample_rate;
+        self.writer
+            .finalize()
+            .context("Failed to finalize recording")?;
+        log::info!(
+            "Recording finished: {:.1}s written",
+            frames as f64 / sample_rate as f64
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recording_round_trip() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.wav");
+        let (mut prod, recorder) = create_recorder(&path, 48_000).unwrap();
+
+        // One stereo buffer, then a 4-channel buffer reduced to its front pair.
+        assert!(prod.push(&[0.1, -0.1, 0.2, -0.2], 2));
+        assert!(prod.push(&[0.3, -0.3, 0.9, 0.9], 4));
+
+        recorder.run(&AtomicBool::new(true)).unwrap();
+
+        let mut reader = hound::WavReader::open(&path).unwrap();
+        let spec = reader.spec();
+        assert_eq!(spec.channels, 2);
+        assert_eq!(spec.sample_rate, 48_000);
+        let samples: Vec<f32> = reader.samples::<f32>().map(|s| s.unwrap()).collect();
+        assert_eq!(samples, vec![0.1, -0.1, 0.2, -0.2, 0.3, -0.3]);
+    }
+
+    #[test]
+    fn test_full_ring_drops_samples() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = tempfile::tempdir().unwrap();
+        let (mut prod, _recorder) = create_recorder(&dir.path().join("full.wav"), 48_000).unwrap();
+
+        let chunk = vec![0.0f32; ring_size(48_000) / 2];
+        assert!(prod.push(&chunk, 2));
+        assert!(prod.push(&chunk, 2));
+        assert!(!prod.push(&[0.0, 0.0], 2));
+    }
+}
@@ -0,0 +1,265 @@
+use crate::audio::MasterVolume;
+use crate::config::{
+    self, ResolvedCapture, ResolvedConfig, ResolvedCyclingKeys, ResolvedKitKey, ResolvedSampleKey,
+};
+use crate::input::{self, BindingBank};
+use crate::samples::SampleSettings;
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::mpsc::Sender;
+use std::time::{Duration, SystemTime};
+
+/// Bindings and per-sample settings from a changed config file, applied by
+/// the input thread.
+pub struct ConfigReload {
+    pub banks: Vec<BindingBank>,
+    pub cycling_keys: ResolvedCyclingKeys,
+    pub momentary: Vec<ResolvedKitKey>,
+    pub kit_select: Vec<ResolvedKitKey>,
+    pub mute: Vec<ResolvedSampleKey>,
+    pub solo: Vec<ResolvedSampleKey>,
+    pub velocity_timing_window: Duration,
+
+    /// Sample filenames the new bindings expect, indexed by sample id.
+    pub sample_names: Vec<String>,
+
+    /// Per-sample settings for `sample_names`.
+    pub settings: SampleSettings,
+}
+
+/// Settings that are only read at startup. Reloading cannot change them,
+/// so a config asking for different values gets a restart warning.
+#[derive(Debug, PartialEq)]
+struct StartupSettings {
+    devices: Vec<String>,
+    samples_dir: PathBuf,
+    output_sample_rate: u32,
+    max_voices: usize,
+    capture: Option<ResolvedCapture>,
+}
+
+impl StartupSettings {
+    fn new(resolved: &ResolvedConfig) -> Self {
+        Self {
+            devices: resolved.devices.clone(),
+            samples_dir: resolved.samples_dir.clone(),
+            output_sample_rate: resolved.output_sample_rate,
+            max_voices: resolved.max_voices,
+            capture: resolved.capture.clone(),
+        }
+    }
+
+    /// Log which of these settings `other` changes.
+    fn warn_changes(&self, other: &StartupSettings) {
+        let changed = [
+            ("devices", self.devices != other.devices),
+            ("samples_dir", self.samples_dir != other.samples_dir),
+            (
+                "output_sample_rate",
+                self.output_sample_rate != other.output_sample_rate,
+            ),
+            ("max_voices", self.max_voices != other.max_voices),
+            ("capture", self.capture != other.capture),
+        ];
+        for (name, _) in changed.iter().filter(|(_, changed)| *changed) {
+            log::warn!(
+                "Config reload: {} changed, restart keyboard-drums to apply it",
+                name
+            );
+        }
+    }
+}
+
+/// Watches the user and system config files and sends their bindings to
+/// the input thread when either changes.
+pub struct ConfigWatcher {
+    path: PathBuf,
+    system_path: PathBuf,
+
+    /// `--profile` from the command line, selected again on every reload.
+    profile: Option<String>,
+
+    /// `--device` paths from the command line, which override the config
+    /// file when given.
+    device_override: Vec<String>,
+
+    /// Modification times of both files at the last check (None if missing).
+    modified: [Option<SystemTime>; 2],
+
+    startup: StartupSettings,
+
+    /// Master volume of the config in effect. Only a change to it is
+    /// applied, so a volume set at the terminal survives unrelated edits.
+    config_master_volume: f32,
+
+    master_volume: Arc<MasterVolume>,
+    sender: Sender<ConfigReload>,
+}
+
+impl ConfigWatcher {
+    pub fn new(
+        path: &Path,
+        system_path: &Path,
+        profile: Option<String>,
+        device_override: Vec<String>,
+        resolved: &ResolvedConfig,
+        master_volume: Arc<MasterVolume>,
+        sender: Sender<ConfigReload>,
+    ) -> Self {
+        let mut watcher = Self {
+            path: path.to_path_buf(),
+            system_path: system_path.to_path_buf(),
+            profile,
+            device_override,
+            modified: [None, None],
+            startup: StartupSettings::new(resolved),
+            config_master_volume: resolved.master_volume,
+            master_volume,
+            sender,
+        };
+        watcher.modified = watcher.modification_times();
+        watcher
+    }
+
+    fn modification_times(&self) -> [Option<SystemTime>; 2] {
+        [&self.path, &self.system_path]
+            .map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+    }
+
+    /// Return true if either config file changed since the last check.
+    fn changed(&mut self) -> bool {
+        let modified = self.modification_times();
+        let changed = modified != self.modified;
+        self.modified = modified;
+        changed
+    }
+
+    /// Reload the config if either file changed, logging any error and
+    /// keeping the previous config in that case.
+    pub fn poll(&mut self) {
+        if !self.changed() {
+            return;
+        }
+        log::info!("Config file changed, reloading");
+        match self.reload() {
+            Ok(reload) => {
+                if self.sender.send(reload).is_err() {
+                    log::warn!("Config reload: input thread is not running");
+                }
+            }
+            Err(e) => log::error!("Config reload failed, keeping the previous config: {:#}", e),
+        }
+    }
+
+    /// Load and resolve the config files again, apply a changed master
+    /// volume, and return the new bindings. Nothing is applied on error.
+    fn reload(&mut self) -> Result<ConfigReload> {
+        let mut resolved =
+            config::load_config(&self.path, &self.system_path, self.profile.as_deref())?;
+        if !self.device_override.is_empty() {
+            resolved.devices = self.device_override.clone();
+        }
+        self.startup.warn_changes(&StartupSettings::new(&resolved));
+
+        if resolved.master_volume != self.config_master_volume {
+            self.config_master_volume = resolved.master_volume;
+            self.master_volume.set(resolved.master_volume);
+            log::info!(
+                "Config reload: master volume set to {:.2}",
+                resolved.master_volume
+            );
+        }
+
+        Ok(ConfigReload {
+            banks: input::build_binding_banks(&resolved),
+            settings: resolved.sample_settings(),
+            cycling_keys: resolved.cycling_keys,
+            momentary: resolved.momentary,
+            kit_select: resolved.kit_select,
+            mute: resolved.mute,
+            solo: resolved.solo,
+            velocity_timing_window: resolved.velocity_timing_window,
+            sample_names: resolved.sample_names,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_reload_applies_new_bindings_and_keeps_old_on_error() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("samples")).unwrap();
+        let path = dir.path().join("config.toml");
+        let write_config = |body: &str| {
+            let config = format!(
+                "samples_dir = \"{}\"\n{}",
+                dir.path().join("samples").display(),
+                body
+            );
+            fs::write(&path, config).unwrap();
+        };
+
+        write_config(
+            r#"
+            master_volume = 0.5
+            [[bindings]]
+            key = "KEY_A"
+            sample = "kick.wav"
+            "#,
+        );
+        let no_system = dir.path().join("missing-system.toml");
+        let resolved = config::load_config(&path, &no_system, None).unwrap();
+        let master_volume = Arc::new(MasterVolume::new(resolved.master_volume));
+        let (sender, _receiver) = std::sync::mpsc::channel();
+        let mut watcher = ConfigWatcher::new(
+            &path,
+            &no_system,
+            None,
+            Vec::new(),
+            &resolved,
+            Arc::clone(&master_volume),
+            sender,
+        );
+
+        // Rebind KEY_A, add KEY_S, and change gain and volume.
+        write_config(
+            r#"
+            master_volume = 0.7
+            [[bindings]]
+            key = "KEY_A"
+            sample = "snare.wav"
+            gain = 0.6
+
+            [[bindings]]
+            key = "KEY_S"
+            sample = "kick.wav"
+            "#,
+        );
+        let reload = watcher.reload().unwrap();
+        assert_eq!(reload.sample_names, vec!["snare.wav", "kick.wav"]);
+        let key_map = &reload.banks[0].key_map;
+        assert_eq!(key_map[&30].sample_indices, [0]); // KEY_A
+        assert_eq!(key_map[&31].sample_indices, [1]); // KEY_S
+        assert!(reload.banks[0].suppressed_keys.contains(&31));
+        assert_eq!(reload.settings.gains, vec![0.6, 1.0]);
+        assert_eq!(master_volume.get(), 0.7);
+
+        // An invalid config is rejected and changes nothing.
+        write_config(
+            r#"
+            master_volume = 0.2
+            [[bindings]]
+            key = "KEY_NOT_A_KEY"
+            sample = "kick.wav"
+            "#,
+        );
+        assert!(watcher.reload().is_err());
+        assert_eq!(master_volume.get(), 0.7);
+    }
+}
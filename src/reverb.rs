@@ -0,0 +1,165 @@
+use crate::config::ReverbConfig;
+
+/// Comb filter delay lengths in frames at 44.1kHz (Freeverb tunings).
+const COMB_TUNINGS: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+
+/// Allpass filter delay lengths in frames at 44.1kHz (Freeverb tunings).
+const ALLPASS_TUNINGS: [usize; 4] = [556, 441, 341, 225];
+
+/// Extra delay added to the right channel's filters to decorrelate L and R.
+const STEREO_SPREAD: usize = 23;
+
+/// Input attenuation so the summed comb outputs don't overload.
+const FIXED_GAIN: f32 = 0.015;
+
+/// Mapping from `room_size` (0.0 to 1.0) to comb feedback.
+const SCALE_ROOM: f32 = 0.28;
+const OFFSET_ROOM: f32 = 0.7;
+
+/// Mapping from `damping` (0.0 to 1.0) to the comb low-pass coefficient.
+const SCALE_DAMP: f32 = 0.4;
+
+/// Makeup gain applied to the wet output.
+const SCALE_WET: f32 = 3.0;
+
+/// Feedback coefficient for the allpass diffusers.
+const ALLPASS_FEEDBACK: f32 = 0.5;
+
+/// Lowpass-feedback comb filter.
+struct Comb {
+    buffer: Vec<f32>,
+    index: usize,
+    filter_store: f32,
+}
+
+impl Comb {
+    fn new(len: usize) -> Self {
+        Self {
+            buffer: vec![0.0; len.max(1)],
+            index: 0,
+            filter_store: 0.0,
+        }
+    }
+
+    #[inline]
+    fn process(&mut self, input: f32, feedback: f32, damp: f32) -> f32 {
+        let output = self.buffer[self.index];
+        self.filter_store = output * (1.0 - damp) + self.filter_store * damp;
+        self.buffer[self.index] = input + self.filter_store * feedback;
+        self.index += 1;
+        if self.index == self.buffer.len() {
+            self.index = 0;
+        }
+        output
+    }
+}
+
+/// Schroeder allpass diffuser.
+struct Allpass {
+    buffer: Vec<f32>,
+    index: usize,
+}
+
+impl Allpass {
+    fn new(len: usize) -> Self {
+        Self {
+            buffer: vec![0.0; len.max(1)],
+            index: 0,
+        }
+    }
+
+    #[inline]
+    fn process(&mut self, input: f32) -> f32 {
+        let buffered = self.buffer[self.index];
+        self.buffer[self.index] = input + buffered * ALLPASS_FEEDBACK;
+        self.index += 1;
+        if self.index == self.buffer.len() {
+            self.index = 0;
+        }
+        buffered - input
+    }
+}
+
+/// Freeverb-style stereo reverb: eight parallel lowpass-feedback combs
+/// followed by four series allpasses per channel.
+///
+/// All delay lines are allocated in `new`, so `process` is real-time safe.
+pub struct Reverb {
+    combs_l: Vec<Comb>,
+    combs_r: Vec<Comb>,
+    allpasses_l: Vec<Allpass>,
+    allpasses_r: Vec<Allpass>,
+    feedback: f32,
+    damp: f32,
+    wet: f32,
+}
+
+impl Reverb {
+    /// Build a reverb with delay lines scaled for `sample_rate`.
+    pub fn new(config: &ReverbConfig, sample_rate: u32) -> Self {
+        let scale = |len: usize| (len as f64 * sample_rate as f64 / 44_100.0).round() as usize;
+
+        let mut reverb = Self {
+            combs_l: COMB_TUNINGS.iter().map(|&t| Comb::new(scale(t))).collect(),
+            combs_r: COMB_TUNINGS
+                .iter()
+                .map(|&t| Comb::new(scale(t + STEREO_SPREAD)))
+                .collect(),
+            allpasses_l: ALLPASS_TUNINGS
+                .iter()
+                .map(|&t| Allpass::new(scale(t)))
+                .collect(),
+            allpasses_r: ALLPASS_TUNINGS
+                .iter()
+                .map(|&t| Allpass::new(scale(t + STEREO_SPREAD)))
+                .collect(),
+            feedback: 0.0,
+            damp: 0.0,
+            wet: 0.0,
+        };
+        reverb.set_params(config);
+        reverb
+    }
+
+    /// Switch to the room size, damping and wet level of `config`, keeping
+    /// the delay lines. Real-time safe.
+    pub fn set_params(&mut self, config: &ReverbConfig) {
+        self.feedback = config.room_size * SCALE_ROOM + OFFSET_ROOM;
+        self.damp = config.damping * SCALE_DAMP;
+        self.wet = config.wet * SCALE_WET;
+    }
+
+    /// Silence the delay lines, dropping any tail. Real-time safe.
+    pub fn clear(&mut self) {
+        for comb in self.combs_l.iter_mut().chain(&mut self.combs_r) {
+            comb.buffer.fill(0.0);
+            comb.filter_store = 0.0;
+        }
+        for allpass in self.allpasses_l.iter_mut().chain(&mut self.allpasses_r) {
+            allpass.buffer.fill(0.0);
+        }
+    }
+
+    /// Process one mono input frame, returning the wet (left, right) output.
+    #[inline]
+    pub fn process(&mut self, input: f32) -> (f32, f32) {
+        let input = input * FIXED_GAIN;
+
+        let mut out_l = 0.0;
+        let mut out_r = 0.0;
+        for comb in &mut self.combs_l {
+            out_l += comb.process(input, self.feedback, self.damp);
+        }
+        for comb in &mut self.combs_r {
+            out_r += comb.process(input, self.feedback, self.damp);
+        }
+        for allpass in &mut self.allpasses_l {
+            out_l = allpass.process(out_l);
+        }
+        for allpass in &mut self.allpasses_r {
+            out_r = allpass.process(out_r);
+        }
+
+        (out_l * self.wet, out_r * self.wet)
+    }
+}
@@ -1,26 +1,148 @@
+use anyhow::{Result, bail};
 use ringbuf::traits::{Consumer, Producer, Split};
 use ringbuf::HeapRb;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Instant;
 
-/// Number of trigger slots in the ring buffer.
+/// Default number of trigger slots in the ring buffer.
 /// 128 is more than enough for even the fastest human drumming.
-const RING_BUFFER_SIZE: usize = 128;
+pub const DEFAULT_RING_BUFFER_SIZE: usize = 128;
+
+/// Whether the per-trigger debug logs are silenced. Set at startup.
+static QUIET_TRIGGER_LOGS: AtomicBool = AtomicBool::new(false);
+
+/// Silence (or restore) the debug logs written for every key press and
+/// trigger, independently of the log level. Warnings are unaffected.
+pub fn set_quiet_trigger_logs(quiet: bool) {
+    QUIET_TRIGGER_LOGS.store(quiet, Ordering::Relaxed);
+}
+
+/// Whether the per-trigger debug logs are written (at debug level).
+pub fn trigger_logs_enabled() -> bool {
+    !QUIET_TRIGGER_LOGS.load(Ordering::Relaxed)
+}
+
+/// `log::debug!` for the per-trigger hot path. When trigger logs are
+/// silenced nothing is done, not even formatting the arguments.
+macro_rules! trigger_debug {
+    ($($arg:tt)+) => {
+        if $crate::ring::trigger_logs_enabled() {
+            log::debug!($($arg)+);
+        }
+    };
+}
+pub(crate) use trigger_debug;
+
+/// What a trigger asks the audio thread to do with its sample.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerKind {
+    /// Play the sample once.
+    OneShot,
+
+    /// Start the sample looping until a matching `StopLoop`.
+    StartLoop,
+
+    /// Fade out the sample's looping voices.
+    StopLoop,
+
+    /// Fade out every playing voice, whatever its sample (panic key).
+    StopAll,
+
+    /// Release the sample's voices with a short fade (gated key let go).
+    Release,
+
+    /// A new sample bank was swapped in. Voices still playing the old one
+    /// fade out if bank crossfades are on.
+    BankSwap,
+}
+
+/// Where a trigger came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerSource {
+    /// A key on the evdev keyboard.
+    Keyboard,
+
+    /// A MIDI note.
+    Midi,
+
+    /// A request to the preview server.
+    Preview,
+}
 
 /// A trigger message sent from the input thread to the audio thread.
 /// Kept small to fit in a cache line and avoid allocations.
 #[derive(Debug, Clone, Copy)]
 pub struct Trigger {
     /// Index into the preloaded samples array.
-    pub sample_id: u8,
+    pub sample_id: u16,
 
-    /// Velocity/volume multiplier (0.0 to 1.0).
-    /// Currently always 1.0 (key-down only), but extensible for
-    /// future velocity-sensitive input.
+    /// Volume multiplier: the binding's gain times the hit strength (see
+    /// `hit`).
     pub velocity: f32,
+
+    /// How hard the hit was (0.0 to 1.0), before the binding's gain. Drives
+    /// velocity-dependent playback such as the attack ramp.
+    pub hit: f32,
+
+    /// Which take to play of a sample bound to a directory (see
+    /// `SampleBank::sample_for_turn`), chosen by the input thread. 0 for
+    /// other samples.
+    pub take: u16,
+
+    /// When the input thread created this trigger, for latency measurement.
+    pub enqueued_at: Instant,
+
+    /// Whether to play the sample once or start or stop a loop.
+    pub kind: TriggerKind,
+
+    /// What produced the trigger, for collapsing duplicates from several
+    /// sources.
+    pub source: TriggerSource,
+}
+
+impl Trigger {
+    /// Create a one-shot trigger stamped with the current time.
+    pub fn new(sample_id: u16, velocity: f32) -> Self {
+        Self::with_kind(sample_id, velocity, TriggerKind::OneShot)
+    }
+
+    /// Create a trigger of the given kind stamped with the current time.
+    pub fn with_kind(sample_id: u16, velocity: f32, kind: TriggerKind) -> Self {
+        Self {
+            sample_id,
+            velocity,
+            hit: velocity,
+            take: 0,
+            enqueued_at: Instant::now(),
+            kind,
+            source: TriggerSource::Keyboard,
+        }
+    }
+
+    /// The same trigger, tagged as coming from `source`.
+    pub fn tagged(self, source: TriggerSource) -> Self {
+        Self { source, ..self }
+    }
+
+    /// The same trigger, played with hit strength `hit`.
+    pub fn with_hit(self, hit: f32) -> Self {
+        Self { hit, ..self }
+    }
+
+    /// The same trigger, playing take `take` of a directory-bound sample.
+    pub fn with_take(self, take: u16) -> Self {
+        Self { take, ..self }
+    }
 }
 
 /// Producer half of the trigger ring buffer (used by the input thread).
 pub struct TriggerProducer {
     inner: ringbuf::HeapProd<Trigger>,
+
+    /// Triggers dropped because the ring was full, shared with whoever
+    /// reports it (see `drop_counter`).
+    dropped: Arc<AtomicU64>,
 }
 
 /// Consumer half of the trigger ring buffer (used by the audio thread).
@@ -28,35 +150,49 @@ pub struct TriggerConsumer {
     inner: ringbuf::HeapCons<Trigger>,
 }
 
-/// Create a new trigger ring buffer, returning the producer and consumer halves.
+/// Create a new trigger ring buffer with `capacity` slots, returning the
+/// producer and consumer halves.
 ///
 /// The producer is meant for the input thread (evdev reader).
 /// The consumer is meant for the audio callback.
 /// Communication is lock-free SPSC — no mutexes involved.
-pub fn create_trigger_channel() -> (TriggerProducer, TriggerConsumer) {
-    let rb = HeapRb::<Trigger>::new(RING_BUFFER_SIZE);
+///
+/// Returns an error if `capacity` is zero. Any other size works; it does
+/// not need to be a power of two.
+pub fn create_trigger_channel(capacity: usize) -> Result<(TriggerProducer, TriggerConsumer)> {
+    if capacity == 0 {
+        bail!("Trigger ring buffer capacity must be at least 1");
+    }
+    let rb = HeapRb::<Trigger>::new(capacity);
     let (prod, cons) = rb.split();
 
-    log::info!(
-        "Created trigger ring buffer with {} slots",
-        RING_BUFFER_SIZE
-    );
+    log::info!("Created trigger ring buffer with {} slots", capacity);
 
-    (
-        TriggerProducer { inner: prod },
+    Ok((
+        TriggerProducer {
+            inner: prod,
+            dropped: Arc::new(AtomicU64::new(0)),
+        },
         TriggerConsumer { inner: cons },
-    )
+    ))
+}
+
+/// Create a trigger ring buffer with the default capacity.
+#[cfg(test)]
+pub fn default_trigger_channel() -> (TriggerProducer, TriggerConsumer) {
+    create_trigger_channel(DEFAULT_RING_BUFFER_SIZE).expect("default capacity is non-zero")
 }
 
 impl TriggerProducer {
     /// Push a trigger into the ring buffer.
     ///
     /// Returns true if the trigger was successfully enqueued.
-    /// Returns false if the buffer is full (trigger dropped — logged as warning).
+    /// Returns false if the buffer is full (trigger dropped — counted in
+    /// `drop_counter`).
     pub fn send(&mut self, trigger: Trigger) -> bool {
         match self.inner.try_push(trigger) {
             Ok(()) => {
-                log::debug!(
+                trigger_debug!(
                     "Trigger sent: sample_id={}, velocity={:.2}",
                     trigger.sample_id,
                     trigger.velocity
@@ -64,15 +200,17 @@ impl TriggerProducer {
                 true
             }
             Err(_) => {
-                log::warn!(
-                    "Trigger ring buffer full! Dropped trigger for sample_id={}. \
-                     This may indicate the audio thread is not consuming fast enough.",
-                    trigger.sample_id
-                );
+                self.dropped.fetch_add(1, Ordering::Relaxed);
                 false
             }
         }
     }
+
+    /// The number of triggers dropped so far because the ring was full,
+    /// readable from another thread after the producer moves away.
+    pub fn drop_counter(&self) -> Arc<AtomicU64> {
+        Arc::clone(&self.dropped)
+    }
 }
 
 impl TriggerConsumer {
@@ -86,32 +224,60 @@ impl TriggerConsumer {
             out.push(trigger);
         }
     }
+
+    /// Drain at most `max` triggers, oldest first, leaving the rest queued
+    /// for the next call. Lock-free like `drain`.
+    pub fn drain_at_most(&mut self, out: &mut Vec<Trigger>, max: usize) {
+        out.clear();
+        while out.len() < max
+            && let Some(trigger) = self.inner.try_pop()
+        {
+            out.push(trigger);
+        }
+    }
 }
 
 // Mark as Send so they can be moved to different threads.
 // ringbuf's HeapProd/HeapCons are already Send, but our wrappers
 // inherit it automatically. This is just a compile-time assertion.
 const _: () = {
-    fn assert_send<T: Send>() {}
-    fn assert_types() {
-        assert_send::<TriggerProducer>();
-        assert_send::<TriggerConsumer>();
-    }
+    const fn assert_send<T: Send>() {}
+    assert_send::<TriggerProducer>();
+    assert_send::<TriggerConsumer>();
 };
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_quiet_trigger_logs_skip_the_debug_call() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        // Debug logging on, so only the flag can skip the call.
+        log::set_max_level(log::LevelFilter::Debug);
+        let mut formatted = 0;
+
+        set_quiet_trigger_logs(true);
+        trigger_debug!("{}", {
+            formatted += 1;
+            formatted
+        });
+        assert_eq!(formatted, 0);
+
+        set_quiet_trigger_logs(false);
+        trigger_debug!("{}", {
+            formatted += 1;
+            formatted
+        });
+        assert_eq!(formatted, 1);
+    }
+
     #[test]
     fn test_send_and_receive() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let (mut prod, mut cons) = create_trigger_channel();
+        let (mut prod, mut cons) = default_trigger_channel();
 
-        let trigger = Trigger {
-            sample_id: 3,
-            velocity: 0.75,
-        };
+        let trigger = Trigger::new(3, 0.75);
 
         assert!(prod.send(trigger));
 
@@ -126,7 +292,7 @@ mod tests {
     #[test]
     fn test_drain_empty() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let (_prod, mut cons) = create_trigger_channel();
+        let (_prod, mut cons) = default_trigger_channel();
 
         let mut buf = Vec::new();
         cons.drain(&mut buf);
@@ -136,13 +302,10 @@ mod tests {
     #[test]
     fn test_multiple_triggers() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let (mut prod, mut cons) = create_trigger_channel();
+        let (mut prod, mut cons) = default_trigger_channel();
 
         for i in 0..10 {
-            prod.send(Trigger {
-                sample_id: i,
-                velocity: 1.0,
-            });
+            prod.send(Trigger::new(i, 1.0));
         }
 
         let mut buf = Vec::new();
@@ -150,39 +313,41 @@ mod tests {
 
         assert_eq!(buf.len(), 10);
         for (i, trigger) in buf.iter().enumerate() {
-            assert_eq!(trigger.sample_id, i as u8);
+            assert_eq!(trigger.sample_id, i as u16);
         }
     }
 
     #[test]
     fn test_buffer_full() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let (mut prod, _cons) = create_trigger_channel();
-
-        // Fill the buffer completely.
-        let mut sent = 0;
-        for i in 0..200 {
-            if prod.send(Trigger {
-                sample_id: (i % 256) as u8,
-                velocity: 1.0,
-            }) {
-                sent += 1;
+        for capacity in [DEFAULT_RING_BUFFER_SIZE, 16, 100] {
+            let (mut prod, _cons) = create_trigger_channel(capacity).unwrap();
+
+            // Fill the buffer completely.
+            let mut sent = 0;
+            for i in 0..200 {
+                if prod.send(Trigger::new((i % 256) as u16, 1.0)) {
+                    sent += 1;
+                }
             }
+
+            // Should have filled up to the capacity, counting the rest.
+            assert_eq!(sent, capacity);
+            assert_eq!(
+                prod.drop_counter().load(Ordering::Relaxed),
+                (200 - capacity) as u64
+            );
         }
 
-        // Should have filled up to RING_BUFFER_SIZE.
-        assert_eq!(sent, RING_BUFFER_SIZE);
+        assert!(create_trigger_channel(0).is_err());
     }
 
     #[test]
     fn test_drain_clears_for_reuse() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let (mut prod, mut cons) = create_trigger_channel();
+        let (mut prod, mut cons) = default_trigger_channel();
 
-        prod.send(Trigger {
-            sample_id: 1,
-            velocity: 1.0,
-        });
+        prod.send(Trigger::new(1, 1.0));
 
         let mut buf = Vec::new();
         cons.drain(&mut buf);
@@ -193,10 +358,7 @@ mod tests {
         assert!(buf.is_empty());
 
         // Send more and drain again.
-        prod.send(Trigger {
-            sample_id: 2,
-            velocity: 1.0,
-        });
+        prod.send(Trigger::new(2, 1.0));
         cons.drain(&mut buf);
         assert_eq!(buf.len(), 1);
         assert_eq!(buf[0].sample_id, 2);
@@ -205,14 +367,11 @@ mod tests {
     #[test]
     fn test_cross_thread() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let (mut prod, mut cons) = create_trigger_channel();
+        let (mut prod, mut cons) = default_trigger_channel();
 
         let handle = std::thread::spawn(move || {
             for i in 0..50 {
-                prod.send(Trigger {
-                    sample_id: (i % 256) as u8,
-                    velocity: 1.0,
-                });
+                prod.send(Trigger::new((i % 256) as u16, 1.0));
             }
         });
 
@@ -1,10 +1,29 @@
+use crate::config::ReverbConfig;
+use crate::filter::OnePole;
+use crate::levels;
+use crate::resample;
+use crate::synth;
 use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Instant;
 
-/// The expected output sample rate. Samples must match this rate.
-pub const OUTPUT_SAMPLE_RATE: u32 = 48_000;
+/// The default output sample rate. Samples must match the configured rate.
+pub const DEFAULT_SAMPLE_RATE: u32 = 48_000;
+
+/// Consecutive full-scale samples on one channel that mark a sample as
+/// clipped, unless configured otherwise.
+pub const DEFAULT_CLIP_WARNING_RUN: usize = 4;
+
+/// Magnitude at or above which a sample counts as full scale. Integer
+/// formats top out just below 1.0 on the positive side (32767/32768).
+const FULL_SCALE: f32 = 0.9999;
+
+/// Seed of the dither noise, fixed so a file decodes the same way on every
+/// load.
+const DITHER_SEED: u32 = 0x2545_f491;
 
 /// Preloaded sample data stored in memory for zero-latency playback.
 #[derive(Debug)]
@@ -17,7 +36,7 @@ pub struct SampleData {
     /// Number of channels (1 = mono, 2 = stereo).
     pub channels: u16,
 
-    /// Original sample rate (must equal OUTPUT_SAMPLE_RATE).
+    /// Sample rate (equals the output sample rate once loaded).
     pub sample_rate: u32,
 }
 
@@ -46,8 +65,17 @@ pub struct SampleBank {
     /// Loaded sample data indexed by sample_id.
     pub samples: Vec<Arc<SampleData>>,
 
-    /// Per-sample gain values indexed by sample_id.
-    pub sample_gains: Vec<f32>,
+    /// Per-sample playback settings indexed by sample_id.
+    pub settings: SampleSettings,
+
+    /// Every take of each sample bound to a directory, indexed by
+    /// sample_id and played in turn; `samples` holds the first. Empty for
+    /// samples bound to a single file.
+    pub pools: Vec<Vec<Arc<SampleData>>>,
+
+    /// Reverb from the kit manifest, used instead of the config's
+    /// `[reverb]` while this bank plays.
+    pub reverb: Option<ReverbConfig>,
 
     /// Name of the current kit (folder name).
     pub kit_name: String,
@@ -56,6 +84,145 @@ pub struct SampleBank {
     pub variant_name: String,
 }
 
+impl SampleBank {
+    /// Memory held by this bank's sample data, in bytes.
+    pub fn memory_bytes(&self) -> usize {
+        // `samples` holds the first take of each pool.
+        let takes = self.pools.iter().flat_map(|pool| pool.iter().skip(1));
+        self.samples
+            .iter()
+            .chain(takes)
+            .map(|sample| sample.data.len() * size_of::<f32>())
+            .sum()
+    }
+
+    /// The sample to play for `sample_id` on its `turn`th hit: the next
+    /// take of a directory pool, or else the sample itself. `sample_id`
+    /// must be in range.
+    pub fn sample_for_turn(&self, sample_id: usize, turn: usize) -> &Arc<SampleData> {
+        match self.pools.get(sample_id) {
+            Some(pool) if !pool.is_empty() => &pool[turn % pool.len()],
+            _ => &self.samples[sample_id],
+        }
+    }
+}
+
+/// How sample files are checked and converted when they are loaded.
+#[derive(Debug, Clone, Copy)]
+pub struct LoadOptions {
+    /// Output sample rate that loaded samples must match.
+    pub sample_rate: u32,
+
+    /// Resample samples at other rates instead of rejecting them.
+    pub resample: bool,
+
+    /// Keep a sample at its own rate, neither rejecting nor resampling it.
+    /// It plays at the output rate, shifted in pitch. Set per sample from
+    /// its binding.
+    pub ignore_sample_rate: bool,
+
+    /// Warn about a sample with at least this many consecutive full-scale
+    /// samples on a channel, a sign of a clipped export. 0 disables it.
+    pub clip_warning_run: usize,
+
+    /// Cut silence from the start (and optionally the end) of each sample.
+    pub trim_silence: Option<SilenceTrim>,
+
+    /// Scale each sample so its peak sits at this linear level. Per-sample
+    /// gains apply on top when it plays.
+    pub normalize_peak: Option<f32>,
+
+    /// Add triangular (TPDF) dither noise of up to one least significant
+    /// bit when decoding integer samples. Float samples are unchanged.
+    pub dither: bool,
+}
+
+/// Which silent frames are cut from a sample when it loads.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SilenceTrim {
+    /// Level below which a frame counts as silent, as a linear amplitude.
+    pub threshold: f32,
+
+    /// Cut trailing silence as well as leading silence.
+    pub trailing: bool,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        Self {
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            resample: false,
+            ignore_sample_rate: false,
+            clip_warning_run: DEFAULT_CLIP_WARNING_RUN,
+            trim_silence: None,
+            normalize_peak: None,
+            dither: false,
+        }
+    }
+}
+
+/// Per-sample playback settings from config bindings, indexed by sample_id.
+/// Each field is a parallel vector with one entry per sample name.
+#[derive(Debug, Clone, Default)]
+pub struct SampleSettings {
+    /// Gain multipliers.
+    pub gains: Vec<f32>,
+
+    /// Amount sent to the shared reverb bus (0.0 to 1.0).
+    pub reverb_sends: Vec<f32>,
+
+    /// Gain floor applied after velocity scaling.
+    pub min_gains: Vec<f32>,
+
+    /// Whether voices are exempt from stealing while unprotected voices exist.
+    pub protected: Vec<bool>,
+
+    /// Choke group (0 = none). Starting a sample stops playing voices in
+    /// the same group.
+    pub choke_groups: Vec<u8>,
+
+    /// Voices kept for the sample: its newest this-many voices are never
+    /// stolen.
+    pub reserved_voices: Vec<u8>,
+
+    /// Stereo pan position (-1.0 left to 1.0 right, 0.0 center).
+    pub pans: Vec<f32>,
+
+    /// Playback rate multipliers (1.0 = original pitch, 2.0 = an octave up).
+    pub playback_rates: Vec<f32>,
+
+    /// Region (first, last frame) that voices start at a random point in,
+    /// if any.
+    pub start_regions: Vec<Option<(usize, usize)>>,
+
+    /// Attack ramp length (softest hit, hardest hit) in frames, if any.
+    /// Hits in between get a length in between.
+    pub vel_attacks: Vec<Option<(usize, usize)>>,
+
+    /// Whether one-shot hits loop until released, choked or silenced.
+    pub loops: Vec<bool>,
+
+    /// First frame of the looped part.
+    pub loop_starts: Vec<usize>,
+
+    /// Frame the loop wraps back at (exclusive); None for the sample end.
+    pub loop_ends: Vec<Option<usize>>,
+
+    /// Whether the sample loads at its own rate, unchecked and unconverted.
+    pub ignore_sample_rates: Vec<bool>,
+
+    /// Whether voices are added to the output after the master limiter.
+    pub bypass_master_fx: Vec<bool>,
+
+    /// Filter the sample plays through, if any.
+    pub filters: Vec<Option<OnePole>>,
+
+    /// Gain compensating each loaded sample's measured loudness, so equal
+    /// velocities sound equally loud. Empty unless loudness-normalized
+    /// velocity is enabled.
+    pub loudness_gains: Vec<f32>,
+}
+
 /// Discovered drum kit with its variants.
 #[derive(Debug, Clone)]
 pub struct KitInfo {
@@ -78,8 +245,26 @@ pub struct KitLibrary {
     /// Sample filenames that bindings expect (e.g. ["kick.wav", "snare.wav"]).
     pub sample_names: Vec<String>,
 
-    /// Per-sample gains from config bindings, indexed by sample_id.
-    pub sample_gains: Vec<f32>,
+    /// Per-sample settings from config bindings, indexed by sample_id.
+    pub settings: SampleSettings,
+
+    /// Sample rate checks and conversion applied when loading samples.
+    pub load_options: LoadOptions,
+
+    /// Measure each loaded sample's loudness and compensate for it.
+    pub loudness_normalize_velocity: bool,
+
+    /// Keep every kit and variant in memory (see `preload`) instead of
+    /// loading each one when it is switched to.
+    pub preload_all: bool,
+
+    /// Banks loaded up front by `preload`, keyed by (kit index, variant
+    /// index). Empty unless `preload_all` is set.
+    pub preloaded: HashMap<(usize, usize), Arc<SampleBank>>,
+
+    /// Load kits switched to on a worker thread (see `loader::BankLoader`)
+    /// instead of on the input thread.
+    pub background_loading: bool,
 }
 
 impl KitLibrary {
@@ -93,13 +278,108 @@ impl KitLibrary {
         self.kits.get(kit_index).map_or(0, |k| k.variants.len())
     }
 
+    /// Indices of the kit named `kit` and its variant named `variant`, or
+    /// its first variant if None.
+    pub fn find_kit(&self, kit: &str, variant: Option<&str>) -> Option<(usize, usize)> {
+        let kit_index = self.kits.iter().position(|k| k.name == kit)?;
+        let variant_index = match variant {
+            Some(variant) => self.kits[kit_index]
+                .variants
+                .iter()
+                .position(|v| v == variant)?,
+            None => 0,
+        };
+        Some((kit_index, variant_index))
+    }
+
     /// Build the full path to a variant directory.
+    #[cfg(test)]
     pub fn variant_path(&self, kit_index: usize, variant_index: usize) -> Option<PathBuf> {
         let kit = self.kits.get(kit_index)?;
         let variant = kit.variants.get(variant_index)?;
         Some(self.samples_dir.join(&kit.name).join(variant))
     }
 
+    /// Bound sample files found in no variant of any kit. Synth samples
+    /// need no file and are never missing.
+    pub fn missing_samples(&self) -> Result<Vec<String>> {
+        let mut missing: Vec<String> = self
+            .sample_names
+            .iter()
+            .filter(|name| !synth::is_synth(name))
+            .cloned()
+            .collect();
+        for kit in &self.kits {
+            let kit_dir = self.samples_dir.join(&kit.name);
+            for variant in &kit.variants {
+                let variant_dir = kit_dir.join(variant);
+                let renames = variant_manifest(&kit_dir, &variant_dir)?.samples;
+                missing.retain(|name| find_sample_source(&variant_dir, name, &renames).is_none());
+            }
+        }
+        Ok(missing)
+    }
+
+    /// The bank for a kit/variant: the preloaded one if there is one,
+    /// otherwise loaded from disk now (and kept, with `preload_all`).
+    pub fn bank(&mut self, kit_index: usize, variant_index: usize) -> Result<Arc<SampleBank>> {
+        if let Some(bank) = self.preloaded.get(&(kit_index, variant_index)) {
+            return Ok(Arc::clone(bank));
+        }
+        let bank = Arc::new(self.load_bank(kit_index, variant_index)?);
+        self.keep_preloaded(kit_index, variant_index, &bank);
+        Ok(bank)
+    }
+
+    /// With `preload_all`, keep a bank loaded after startup, e.g. once a
+    /// config reload has dropped the preloaded banks, so switching back to
+    /// it reads no files.
+    pub fn keep_preloaded(
+        &mut self,
+        kit_index: usize,
+        variant_index: usize,
+        bank: &Arc<SampleBank>,
+    ) {
+        if self.preload_all {
+            self.preloaded
+                .entry((kit_index, variant_index))
+                .or_insert_with(|| Arc::clone(bank));
+        }
+    }
+
+    /// Load every kit and variant into memory, replacing any loaded
+    /// before, so switching between them reads no files. A variant that
+    /// fails to load is logged and left to load (and fail) when it is
+    /// switched to.
+    pub fn preload(&mut self) {
+        let start = Instant::now();
+        let mut preloaded = HashMap::new();
+        let mut bytes = 0;
+        for (kit_index, kit) in self.kits.iter().enumerate() {
+            for (variant_index, variant) in kit.variants.iter().enumerate() {
+                match self.load_bank(kit_index, variant_index) {
+                    Ok(bank) => {
+                        bytes += bank.memory_bytes();
+                        preloaded.insert((kit_index, variant_index), Arc::new(bank));
+                    }
+                    Err(e) => log::error!(
+                        "Failed to preload kit '{}' variant '{}': {:#}",
+                        kit.name,
+                        variant,
+                        e
+                    ),
+                }
+            }
+        }
+        log::info!(
+            "Preloaded {} kit variants in {:.0}ms, using {:.1} MB of sample data",
+            preloaded.len(),
+            start.elapsed().as_secs_f64() * 1000.0,
+            bytes as f64 / (1024.0 * 1024.0),
+        );
+        self.preloaded = preloaded;
+    }
+
     /// Load all samples for a given kit/variant into a SampleBank.
     ///
     /// Missing sample files are replaced with silent placeholders so that
@@ -112,30 +392,68 @@ impl KitLibrary {
             .get(variant_index)
             .context("Variant index out of range")?;
 
-        let variant_dir = self.samples_dir.join(&kit.name).join(variant);
+        let kit_dir = self.samples_dir.join(&kit.name);
+        let variant_dir = kit_dir.join(variant);
+        let manifest = variant_manifest(&kit_dir, &variant_dir)?;
+        let renames = &manifest.samples;
 
         let start = Instant::now();
         let mut samples = Vec::with_capacity(self.sample_names.len());
+        let mut pools = vec![Vec::new(); self.sample_names.len()];
         let mut loaded_count = 0usize;
 
         for (i, name) in self.sample_names.iter().enumerate() {
-            let path = variant_dir.join(name);
-
-            if path.is_file() {
-                log::debug!(
-                    "Loading sample {} of {}: {}",
-                    i + 1,
-                    self.sample_names.len(),
-                    path.display()
-                );
-                let sample = load_wav(&path).with_context(|| {
-                    format!(
-                        "Failed to load sample '{}' from kit '{}' variant '{}'",
-                        name, kit.name, variant,
-                    )
-                })?;
+            if synth::is_synth(name) {
+                // Validated with the config, so this only fails on a bug.
+                let spec = synth::parse_synth_spec(name)?;
+                let sample = synth::render(&spec, self.load_options.sample_rate);
                 samples.push(Arc::new(sample));
                 loaded_count += 1;
+            } else if let Some(source) = find_sample_source(&variant_dir, name, renames) {
+                let options = LoadOptions {
+                    ignore_sample_rate: self
+                        .settings
+                        .ignore_sample_rates
+                        .get(i)
+                        .copied()
+                        .unwrap_or(false),
+                    ..self.load_options
+                };
+                let load = |path: &Path| {
+                    load_sample(path, options).with_context(|| {
+                        format!(
+                            "Failed to load sample '{}' from kit '{}' variant '{}'",
+                            name, kit.name, variant,
+                        )
+                    })
+                };
+                match source {
+                    SampleSource::File(path) => {
+                        log::debug!(
+                            "Loading sample {} of {}: {}",
+                            i + 1,
+                            self.sample_names.len(),
+                            path.display()
+                        );
+                        samples.push(Arc::new(load(&path)?));
+                    }
+                    SampleSource::Pool(paths) => {
+                        log::debug!(
+                            "Loading sample {} of {}: {} takes from {}/",
+                            i + 1,
+                            self.sample_names.len(),
+                            paths.len(),
+                            variant_dir.join(name).display()
+                        );
+                        let pool = paths
+                            .iter()
+                            .map(|path| load(path).map(Arc::new))
+                            .collect::<Result<Vec<_>>>()?;
+                        samples.push(Arc::clone(&pool[0]));
+                        pools[i] = pool;
+                    }
+                }
+                loaded_count += 1;
             } else {
                 log::debug!(
                     "Sample '{}' not found in kit '{}' variant '{}' — using silence",
@@ -146,7 +464,7 @@ impl KitLibrary {
                 samples.push(Arc::new(SampleData {
                     data: vec![],
                     channels: 1,
-                    sample_rate: OUTPUT_SAMPLE_RATE,
+                    sample_rate: self.load_options.sample_rate,
                 }));
             }
         }
@@ -173,9 +491,24 @@ impl KitLibrary {
             );
         }
 
+        let mut settings = self.settings.clone();
+        apply_sample_overrides(&mut settings, &self.sample_names, &manifest.overrides);
+        if let Some(gain) = manifest.gain {
+            // Unset gains are empty and read as 1.0.
+            settings.gains.resize(self.sample_names.len(), 1.0);
+            for sample_gain in &mut settings.gains {
+                *sample_gain *= gain.max(0.0);
+            }
+        }
+        if self.loudness_normalize_velocity {
+            settings.loudness_gains = levels::loudness_compensation(&samples);
+        }
+
         Ok(SampleBank {
             samples,
-            sample_gains: self.sample_gains.clone(),
+            settings,
+            pools,
+            reverb: manifest.reverb.map(ReverbConfig::clamped),
             kit_name: kit.name.clone(),
             variant_name: variant.clone(),
         })
@@ -195,13 +528,16 @@ impl KitLibrary {
 /// ```
 ///
 /// Kits and variants are sorted alphabetically. Each variant must contain
-/// all of the sample files specified in `sample_names`.
+/// all of the sample files specified in `sample_names`. Synth samples need
+/// no files; if every sample is one, an empty samples directory gets a
+/// single built-in kit.
 pub fn discover_kits(
     samples_dir: &Path,
     sample_names: &[String],
-    sample_gains: &[f32],
+    settings: &SampleSettings,
 ) -> Result<KitLibrary> {
     let mut kits: Vec<KitInfo> = Vec::new();
+    let all_synth = sample_names.iter().all(|name| synth::is_synth(name));
 
     let entries = std::fs::read_dir(samples_dir).with_context(|| {
         format!(
@@ -232,14 +568,14 @@ pub fn discover_kits(
             }
 
             let variant_name = ventry.file_name().to_string_lossy().to_string();
+            let renames = variant_manifest(&path, &vpath)?.samples;
 
             // Check which sample files are present in this variant.
             // Variants are accepted even if some samples are missing —
             // missing samples will be silent placeholders at load time.
             let mut present_count = 0;
-            for sample_name in sample_names {
-                let sample_path = vpath.join(sample_name);
-                if sample_path.is_file() {
+            for sample_name in sample_names.iter().filter(|name| !synth::is_synth(name)) {
+                if find_sample_source(&vpath, sample_name, &renames).is_some() {
                     present_count += 1;
                 } else {
                     log::info!(
@@ -251,7 +587,7 @@ pub fn discover_kits(
                 }
             }
 
-            if present_count > 0 {
+            if present_count > 0 || all_synth {
                 variants.push(variant_name);
             } else {
                 log::warn!(
@@ -277,6 +613,14 @@ pub fn discover_kits(
 
     kits.sort_by(|a, b| a.name.cmp(&b.name));
 
+    if kits.is_empty() && all_synth {
+        log::info!("No kit folders found; every binding is a synth sound, so using a built-in kit");
+        kits.push(KitInfo {
+            name: SYNTH_KIT_NAME.to_string(),
+            variants: vec![SYNTH_KIT_NAME.to_string()],
+        });
+    }
+
     if kits.is_empty() {
         bail!(
             "No valid drum kits found in {}. Expected structure: \
@@ -305,75 +649,452 @@ pub fn discover_kits(
         samples_dir: samples_dir.to_path_buf(),
         kits,
         sample_names: sample_names.to_vec(),
-        sample_gains: sample_gains.to_vec(),
+        settings: settings.clone(),
+        load_options: LoadOptions::default(),
+        loudness_normalize_velocity: false,
+        preload_all: false,
+        preloaded: HashMap::new(),
+        background_loading: false,
     })
 }
 
-/// Load a single WAV file into a SampleData struct.
+/// Kit and variant name of the built-in kit used when only synth samples
+/// are bound and the samples directory has no kits.
+const SYNTH_KIT_NAME: &str = "synth";
+
+/// Sample file extensions that can be loaded, lowercase.
+const SAMPLE_EXTENSIONS: &[&str] = &["wav", "ogg", "flac"];
+
+/// Optional manifest in a kit or variant folder.
+pub const KIT_MANIFEST_FILE: &str = "kit.toml";
+
+/// Contents of a `kit.toml` manifest.
+#[derive(Debug, Default, Deserialize)]
+struct KitManifest {
+    /// Actual filenames for sample names used in bindings, for kits whose
+    /// files are named differently, e.g. "kick.wav" = "bd.wav".
+    #[serde(default)]
+    samples: HashMap<String, String>,
+
+    /// Per-sample settings for this kit, taking precedence over the
+    /// bindings', e.g. `[overrides."clap.wav"]` with `gain = 0.5`.
+    #[serde(default)]
+    overrides: HashMap<String, SampleOverride>,
+
+    /// Reverb for this kit, used instead of the config's `[reverb]` while
+    /// the kit plays.
+    reverb: Option<ReverbConfig>,
+
+    /// Gain for every sample of this kit, on top of their own gains, to
+    /// balance kits against each other (see `--analyze-levels`).
+    gain: Option<f32>,
+}
+
+/// Binding settings a kit manifest can override for one sample.
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+struct SampleOverride {
+    gain: Option<f32>,
+    pan: Option<f32>,
+}
+
+/// Read the manifest in `dir`, or an empty one if there is none.
+fn read_kit_manifest(dir: &Path) -> Result<KitManifest> {
+    let path = dir.join(KIT_MANIFEST_FILE);
+    if !path.is_file() {
+        return Ok(KitManifest::default());
+    }
+    let content = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read kit manifest: {}", path.display()))?;
+    toml::from_str(&content)
+        .with_context(|| format!("Failed to parse kit manifest: {}", path.display()))
+}
+
+/// The manifest for a variant: the kit manifest's, with the variant
+/// manifest's on top. Overrides merge field by field, so a variant can
+/// change a sample's pan and keep the kit's gain for it.
+fn variant_manifest(kit_dir: &Path, variant_dir: &Path) -> Result<KitManifest> {
+    let mut manifest = read_kit_manifest(kit_dir)?;
+    let variant = read_kit_manifest(variant_dir)?;
+    manifest.samples.extend(variant.samples);
+    manifest.reverb = variant.reverb.or(manifest.reverb);
+    manifest.gain = variant.gain.or(manifest.gain);
+    for (name, over) in variant.overrides {
+        let merged = manifest.overrides.entry(name).or_default();
+        merged.gain = over.gain.or(merged.gain);
+        merged.pan = over.pan.or(merged.pan);
+    }
+    Ok(manifest)
+}
+
+/// Apply a kit manifest's per-sample overrides on top of the bindings'
+/// settings. Overrides for samples no binding plays are ignored.
+fn apply_sample_overrides(
+    settings: &mut SampleSettings,
+    sample_names: &[String],
+    overrides: &HashMap<String, SampleOverride>,
+) {
+    for (name, over) in overrides {
+        let Some(index) = sample_names.iter().position(|n| n == name) else {
+            log::debug!("Kit override for unbound sample '{}' ignored", name);
+            continue;
+        };
+        // Unset settings are empty and read as their defaults.
+        if let Some(gain) = over.gain {
+            if settings.gains.len() <= index {
+                settings.gains.resize(index + 1, 1.0);
+            }
+            settings.gains[index] = gain.max(0.0);
+        }
+        if let Some(pan) = over.pan {
+            if settings.pans.len() <= index {
+                settings.pans.resize(index + 1, 0.0);
+            }
+            settings.pans[index] = pan.clamp(-1.0, 1.0);
+        }
+    }
+}
+
+/// Find sample `name` in `dir`, under the filename `renames` maps it to if
+/// any: a sample file, or else a directory of them.
+fn find_sample_source(
+    dir: &Path,
+    name: &str,
+    renames: &HashMap<String, String>,
+) -> Option<SampleSource> {
+    let name = renames.get(name).map_or(name, String::as_str);
+    if let Some(path) = find_sample_file(dir, name) {
+        return Some(SampleSource::File(path));
+    }
+    let pool = sample_pool_files(&dir.join(name));
+    (!pool.is_empty()).then_some(SampleSource::Pool(pool))
+}
+
+/// Where a bound sample comes from in a variant.
+enum SampleSource {
+    /// A single sample file.
+    File(PathBuf),
+
+    /// A directory of takes, played in turn (round-robin).
+    Pool(Vec<PathBuf>),
+}
+
+/// The sample files directly inside `dir`, sorted by name so a pool plays
+/// in the same order every time. Empty if `dir` is not a directory.
+fn sample_pool_files(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.is_file()
+                && path.extension().is_some_and(|ext| {
+                    let ext = ext.to_string_lossy().to_ascii_lowercase();
+                    SAMPLE_EXTENSIONS.contains(&ext.as_str())
+                })
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+/// Find the file for sample `name` in `dir`.
 ///
-/// The WAV must be 48kHz. Supports 16-bit and 24-bit integer formats,
-/// as well as 32-bit float. Mono and stereo are supported.
-pub fn load_wav(path: &Path) -> Result<SampleData> {
-    let reader = hound::WavReader::open(path)
-        .with_context(|| format!("Failed to open WAV file: {}", path.display()))?;
+/// An exact filename match wins. Otherwise a file with the same stem and
+/// another supported extension is used, so a binding for `kick.wav` also
+/// picks up `kick.flac` or `kick.ogg`.
+pub fn find_sample_file(dir: &Path, name: &str) -> Option<PathBuf> {
+    let exact = dir.join(name);
+    if exact.is_file() {
+        return Some(exact);
+    }
+    SAMPLE_EXTENSIONS
+        .iter()
+        .map(|ext| exact.with_extension(ext))
+        .find(|path| path.is_file())
+}
 
-    let spec = reader.spec();
+/// Load a sample file, choosing the decoder from its extension: `.ogg`
+/// (Vorbis), `.flac`, or WAV for anything else.
+///
+/// Samples must match the output sample rate in `options`, unless
+/// resampling is enabled.
+pub fn load_sample(path: &Path, options: LoadOptions) -> Result<SampleData> {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("ogg") => load_ogg(path, options),
+        Some("flac") => load_flac(path, options),
+        _ => load_wav(path, options),
+    }
+}
 
-    // Validate sample rate.
-    if spec.sample_rate != OUTPUT_SAMPLE_RATE {
+/// Check that a decoded sample is mono or stereo and, unless it is going
+/// to be resampled or its rate is ignored, at the output sample rate.
+fn validate_format(
+    path: &Path,
+    sample_rate: u32,
+    channels: u32,
+    options: LoadOptions,
+) -> Result<()> {
+    let rate_accepted = options.resample || options.ignore_sample_rate;
+    if sample_rate == 0 || (sample_rate != options.sample_rate && !rate_accepted) {
         bail!(
             "Sample rate mismatch in {}: expected {}Hz, got {}Hz. \
-             Please convert your samples to {}Hz.",
+             Please convert your samples to {}Hz, set resample = true \
+             or set ignore_sample_rate = true on the binding.",
             path.display(),
-            OUTPUT_SAMPLE_RATE,
-            spec.sample_rate,
-            OUTPUT_SAMPLE_RATE,
+            options.sample_rate,
+            sample_rate,
+            options.sample_rate,
         );
     }
 
-    // Validate channels.
-    if spec.channels == 0 || spec.channels > 2 {
+    if channels == 0 || channels > 2 {
         bail!(
             "Unsupported channel count in {}: {}. Only mono (1) and stereo (2) are supported.",
             path.display(),
-            spec.channels,
+            channels,
         );
     }
 
-    let channels = spec.channels;
-    let data = decode_samples(reader, &spec, path)?;
+    Ok(())
+}
+
+/// Convert a decoded sample to the output sample rate if needed, then log
+/// a summary.
+///
+/// Resampling can overshoot full scale on sharp transients, so a resampled
+/// sample that peaks above 1.0 is scaled back down to exactly 1.0.
+fn finish_loading(path: &Path, mut sample: SampleData, options: LoadOptions) -> SampleData {
+    // Check the decoded data, as resampling smooths over clipped runs.
+    if options.clip_warning_run > 0 {
+        let clipped = clipped_sample_count(
+            &sample.data,
+            sample.channels as usize,
+            options.clip_warning_run,
+        );
+        if clipped > 0 {
+            log::warn!(
+                "{} looks clipped: {} samples in runs of {} or more at full scale. \
+                 Check how it was exported.",
+                path.display(),
+                clipped,
+                options.clip_warning_run,
+            );
+        }
+    }
+
+    if sample.sample_rate != options.sample_rate && options.ignore_sample_rate {
+        let semitones = 12.0 * (options.sample_rate as f64 / sample.sample_rate as f64).log2();
+        log::info!(
+            "Playing {} ({}Hz) at {}Hz as-is, {:+.2} semitones off pitch (ignore_sample_rate)",
+            path.display(),
+            sample.sample_rate,
+            options.sample_rate,
+            semitones,
+        );
+    } else if sample.sample_rate != options.sample_rate {
+        log::debug!(
+            "Resampling {} from {}Hz to {}Hz",
+            path.display(),
+            sample.sample_rate,
+            options.sample_rate,
+        );
+        sample.data = resample::resample(
+            &sample.data,
+            sample.channels as usize,
+            sample.sample_rate,
+            options.sample_rate,
+        );
+        sample.sample_rate = options.sample_rate;
+
+        let peak = sample.data.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        if peak > 1.0 {
+            sample.data.iter_mut().for_each(|s| *s /= peak);
+        }
+    }
+
+    if let Some(trim) = options.trim_silence {
+        let channels = (sample.channels as usize).max(1);
+        let (leading, trailing) = silent_edges(&sample.data, channels, trim.threshold);
+        let trailing = if trim.trailing { trailing } else { 0 };
+        if leading + trailing > 0 {
+            let frames = sample.data.len() / channels;
+            sample.data.truncate((frames - trailing) * channels);
+            sample.data.drain(..leading * channels);
+            log::debug!(
+                "Trimmed {} silent frames from the start and {} from the end of {}",
+                leading,
+                trailing,
+                path.display(),
+            );
+        }
+    }
+
+    if let Some(target) = options.normalize_peak {
+        let peak = sample.data.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        // Silent placeholders have no level to scale to.
+        if peak > 0.0 {
+            let scale = target / peak;
+            sample.data.iter_mut().for_each(|s| *s *= scale);
+            log::debug!(
+                "Normalized {} by {:+.1} dB",
+                path.display(),
+                20.0 * scale.log10()
+            );
+        }
+    }
 
     log::info!(
         "Loaded sample: {} ({} channels, {}Hz, {:.2}s, {} frames, {:.1} KB)",
         path.file_name()
             .map(|n| n.to_string_lossy().to_string())
             .unwrap_or_else(|| path.display().to_string()),
-        channels,
-        spec.sample_rate,
-        data.len() as f64 / channels as f64 / spec.sample_rate as f64,
-        data.len() / channels as usize,
-        data.len() as f64 * 4.0 / 1024.0,
+        sample.channels,
+        sample.sample_rate,
+        sample.duration_secs(),
+        sample.num_frames(),
+        sample.data.len() as f64 * 4.0 / 1024.0,
     );
+    sample
+}
+
+/// Number of whole frames at the start and at the end of interleaved `data`
+/// in which every channel is below `threshold`. A sample that is silent
+/// throughout has none, so it is never trimmed to nothing.
+fn silent_edges(data: &[f32], channels: usize, threshold: f32) -> (usize, usize) {
+    let channels = channels.max(1);
+    let audible = |frame: &[f32]| frame.iter().any(|s| s.abs() >= threshold);
+    let Some(first) = data.chunks_exact(channels).position(audible) else {
+        return (0, 0);
+    };
+    let last = data
+        .chunks_exact(channels)
+        .rposition(audible)
+        .unwrap_or(first);
+    (first, data.len() / channels - 1 - last)
+}
+
+/// Count the samples in runs of at least `min_run` consecutive full-scale
+/// samples on one channel of interleaved `data`.
+fn clipped_sample_count(data: &[f32], channels: usize, min_run: usize) -> usize {
+    let channels = channels.max(1);
+    let mut clipped = 0;
+    for channel in 0..channels {
+        let mut run = 0;
+        for &sample in data.iter().skip(channel).step_by(channels) {
+            if sample.abs() >= FULL_SCALE {
+                run += 1;
+            } else {
+                if run >= min_run {
+                    clipped += run;
+                }
+                run = 0;
+            }
+        }
+        if run >= min_run {
+            clipped += run;
+        }
+    }
+    clipped
+}
+
+/// Load a single WAV file into a SampleData struct.
+///
+/// The WAV must be at the output sample rate unless resampling. Supports
+/// 16-bit and 24-bit integer formats, as well as 32-bit float. Mono and
+/// stereo are supported.
+pub fn load_wav(path: &Path, options: LoadOptions) -> Result<SampleData> {
+    let reader = hound::WavReader::open(path)
+        .with_context(|| format!("Failed to open WAV file: {}", path.display()))?;
 
-    Ok(SampleData {
+    let spec = reader.spec();
+    validate_format(path, spec.sample_rate, spec.channels as u32, options)?;
+
+    let data = decode_samples(reader, &spec, path, options.dither)?;
+    let sample = SampleData {
         data,
-        channels,
+        channels: spec.channels,
         sample_rate: spec.sample_rate,
-    })
+    };
+    Ok(finish_loading(path, sample, options))
+}
+
+/// Load an OGG Vorbis file into a SampleData struct.
+///
+/// Same requirements as WAV: the output sample rate (unless resampling),
+/// mono or stereo.
+/// Decoded samples are clamped to [-1.0, 1.0], since lossy decoding can
+/// overshoot slightly.
+fn load_ogg(path: &Path, options: LoadOptions) -> Result<SampleData> {
+    let file = std::fs::File::open(path)
+        .with_context(|| format!("Failed to open OGG file: {}", path.display()))?;
+    let mut reader = lewton::inside_ogg::OggStreamReader::new(std::io::BufReader::new(file))
+        .with_context(|| format!("Failed to read OGG Vorbis headers: {}", path.display()))?;
+
+    let sample_rate = reader.ident_hdr.audio_sample_rate;
+    let channels = reader.ident_hdr.audio_channels;
+    validate_format(path, sample_rate, channels as u32, options)?;
+
+    let mut data = Vec::new();
+    while let Some(packet) = reader
+        .read_dec_packet_generic::<lewton::samples::InterleavedSamples<f32>>()
+        .with_context(|| format!("Failed to decode samples from {}", path.display()))?
+    {
+        data.extend(packet.samples.iter().map(|s| s.clamp(-1.0, 1.0)));
+    }
+
+    let sample = SampleData {
+        data,
+        channels: channels as u16,
+        sample_rate,
+    };
+    Ok(finish_loading(path, sample, options))
+}
+
+/// Load a FLAC file into a SampleData struct.
+///
+/// Same requirements as WAV: the output sample rate (unless resampling),
+/// mono or stereo.
+/// Integer samples are normalized by bit depth exactly like integer WAVs.
+fn load_flac(path: &Path, options: LoadOptions) -> Result<SampleData> {
+    let mut reader = claxon::FlacReader::open(path)
+        .with_context(|| format!("Failed to open FLAC file: {}", path.display()))?;
+
+    let info = reader.streaminfo();
+    validate_format(path, info.sample_rate, info.channels, options)?;
+
+    let mut to_f32 = int_to_f32(info.bits_per_sample as u16, options.dither);
+    let data = reader
+        .samples()
+        .map(|s| s.map(&mut to_f32))
+        .collect::<std::result::Result<Vec<f32>, _>>()
+        .with_context(|| format!("Failed to decode samples from {}", path.display()))?;
+
+    let sample = SampleData {
+        data,
+        channels: info.channels as u16,
+        sample_rate: info.sample_rate,
+    };
+    Ok(finish_loading(path, sample, options))
 }
 
 /// Decode WAV samples to normalized f32 based on the sample format and bit depth.
+/// Integer samples are dithered if `dither` is set.
 fn decode_samples(
     reader: hound::WavReader<std::io::BufReader<std::fs::File>>,
     spec: &hound::WavSpec,
     path: &Path,
+    dither: bool,
 ) -> Result<Vec<f32>> {
     match spec.sample_format {
         hound::SampleFormat::Int => {
-            let max_val = (1u32 << (spec.bits_per_sample - 1)) as f32;
+            let mut to_f32 = int_to_f32(spec.bits_per_sample, dither);
             let data: Vec<f32> = reader
                 .into_samples::<i32>()
-                .map(|s| s.map(|v| v as f32 / max_val))
+                .map(|s| s.map(&mut to_f32))
                 .collect::<std::result::Result<Vec<f32>, _>>()
                 .with_context(|| format!("Failed to decode samples from {}", path.display()))?;
             Ok(data)
@@ -390,6 +1111,38 @@ fn decode_samples(
     }
 }
 
+/// Converter from integer samples at `bits_per_sample` to f32 in
+/// -1.0..=1.0, adding TPDF dither noise if `dither` is set.
+fn int_to_f32(bits_per_sample: u16, dither: bool) -> impl FnMut(i32) -> f32 {
+    let max_val = (1u32 << (bits_per_sample - 1)) as f32;
+    let mut noise = dither.then_some(Dither(DITHER_SEED));
+    move |value| match &mut noise {
+        Some(noise) => ((value as f32 + noise.next_lsb()) / max_val).clamp(-1.0, 1.0),
+        None => value as f32 / max_val,
+    }
+}
+
+/// Xorshift noise source for dithering integer samples.
+struct Dither(u32);
+
+impl Dither {
+    /// A uniform value in 0.0..1.0.
+    fn next_unit(&mut self) -> f32 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.0 = x;
+        (x >> 8) as f32 / (1u32 << 24) as f32
+    }
+
+    /// Noise in -1.0..1.0 least significant bits with a triangular
+    /// distribution: the difference of two uniform values.
+    fn next_lsb(&mut self) -> f32 {
+        self.next_unit() - self.next_unit()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -449,6 +1202,210 @@ mod tests {
         path
     }
 
+    /// Create a test FLAC file holding the same ramp as `create_test_wav`
+    /// (16-bit), as one frame of uncompressed (verbatim) subframes.
+    fn create_test_flac(
+        dir: &Path,
+        name: &str,
+        channels: u16,
+        sample_rate: u32,
+        num_frames: usize,
+    ) -> PathBuf {
+        fn crc8(bytes: &[u8]) -> u8 {
+            bytes.iter().fold(0u8, |crc, &b| {
+                (0..8).fold(crc ^ b, |c, _| (c << 1) ^ ((c >> 7) * 0x07))
+            })
+        }
+        fn crc16(bytes: &[u8]) -> u16 {
+            bytes.iter().fold(0u16, |crc, &b| {
+                (0..8).fold(crc ^ ((b as u16) << 8), |c, _| {
+                    (c << 1) ^ ((c >> 15) * 0x8005)
+                })
+            })
+        }
+
+        let block_size = num_frames as u16;
+        let mut out = b"fLaC".to_vec();
+
+        // STREAMINFO, the only (and so last) metadata block.
+        out.extend([0x80, 0, 0, 34]);
+        out.extend(block_size.to_be_bytes());
+        out.extend(block_size.to_be_bytes());
+        out.extend([0; 6]); // Frame sizes unknown.
+        let packed = (sample_rate as u64) << 44
+            | ((channels as u64 - 1) << 41)
+            | (15 << 36)
+            | num_frames as u64;
+        out.extend(packed.to_be_bytes());
+        out.extend([0; 16]); // MD5 unknown.
+
+        // Frame header: fixed blocksize, 16-bit block size at the end,
+        // rate from STREAMINFO, independent channels, 16-bit samples.
+        let frame_start = out.len();
+        out.extend([0xFF, 0xF8, 0x70, ((channels as u8 - 1) << 4) | 0x08, 0]);
+        out.extend((block_size - 1).to_be_bytes());
+        out.push(crc8(&out[frame_start..]));
+
+        for _ch in 0..channels {
+            out.push(0x02); // Verbatim subframe.
+            for frame in 0..num_frames {
+                let value = ((frame as f64 / num_frames as f64) * 32767.0) as i16;
+                out.extend(value.to_be_bytes());
+            }
+        }
+        out.extend(crc16(&out[frame_start..]).to_be_bytes());
+
+        let path = dir.join(name);
+        std::fs::write(&path, out).unwrap();
+        path
+    }
+
+    /// Bit writer for Vorbis packets, which pack values LSB-first.
+    #[derive(Default)]
+    struct VorbisBits {
+        bytes: Vec<u8>,
+        bit: usize,
+    }
+
+    impl VorbisBits {
+        fn put(&mut self, value: u64, bits: usize) {
+            for i in 0..bits {
+                if self.bit.is_multiple_of(8) {
+                    self.bytes.push(0);
+                }
+                if value >> i & 1 != 0 {
+                    *self.bytes.last_mut().unwrap() |= 1 << (self.bit % 8);
+                }
+                self.bit += 1;
+            }
+        }
+    }
+
+    /// Wrap packets in one Ogg page.
+    fn ogg_page(packets: &[Vec<u8>], header_type: u8, granule: u64, sequence: u32) -> Vec<u8> {
+        fn crc32(bytes: &[u8]) -> u32 {
+            bytes.iter().fold(0u32, |crc, &b| {
+                (0..8).fold(crc ^ ((b as u32) << 24), |c, _| {
+                    (c << 1) ^ ((c >> 31) * 0x04C1_1DB7)
+                })
+            })
+        }
+
+        let mut lacing = Vec::new();
+        for packet in packets {
+            lacing.extend(std::iter::repeat_n(255u8, packet.len() / 255));
+            lacing.push((packet.len() % 255) as u8);
+        }
+        let mut page = b"OggS".to_vec();
+        page.extend([0, header_type]);
+        page.extend(granule.to_le_bytes());
+        page.extend(1u32.to_le_bytes()); // Stream serial.
+        page.extend(sequence.to_le_bytes());
+        page.extend([0; 4]); // CRC, filled in below.
+        page.push(lacing.len() as u8);
+        page.extend(lacing);
+        for packet in packets {
+            page.extend(packet);
+        }
+        let crc = crc32(&page);
+        page[22..26].copy_from_slice(&crc.to_le_bytes());
+        page
+    }
+
+    /// Create a small mono OGG Vorbis file: 256-frame blocks, a flat floor
+    /// and one spectral line, giving a steady tone. `audio_packets`
+    /// packets decode to `(audio_packets - 1) * 128` frames.
+    fn create_test_ogg(dir: &Path, name: &str, sample_rate: u32, audio_packets: usize) -> PathBuf {
+        let mut ident = b"\x01vorbis".to_vec();
+        ident.extend(0u32.to_le_bytes()); // Version.
+        ident.push(1); // Channels.
+        ident.extend(sample_rate.to_le_bytes());
+        ident.extend([0; 12]); // Bitrates.
+        ident.extend([0x88, 1]); // Both block sizes 256, framing bit.
+
+        let mut comment = b"\x03vorbis".to_vec();
+        comment.extend(4u32.to_le_bytes());
+        comment.extend(b"test");
+        comment.extend(0u32.to_le_bytes());
+        comment.push(1);
+
+        let mut setup = VorbisBits::default();
+        for &b in b"\x05vorbis" {
+            setup.put(b as u64, 8);
+        }
+        // One codebook: two 1-bit entries mapping to 0.0 and 1.0.
+        setup.put(0, 8);
+        setup.put(0x564342, 24);
+        setup.put(1, 16); // Dimensions.
+        setup.put(2, 24); // Entries.
+        setup.put(0, 2); // Not ordered, not sparse.
+        setup.put(0, 5); // Entry 0 length 1.
+        setup.put(0, 5); // Entry 1 length 1.
+        setup.put(1, 4); // Lookup type 1.
+        setup.put(0, 32); // Minimum 0.0.
+        setup.put(768 << 21 | 1 << 20, 32); // Delta 1.0.
+        setup.put(0, 4); // 1-bit multiplicands.
+        setup.put(0, 1); // Not a sequence.
+        setup.put(0, 1);
+        setup.put(1, 1);
+        // Time domain transforms (placeholder).
+        setup.put(0, 6);
+        setup.put(0, 16);
+        // Floor 1 with no partitions: posts at x = 0 and 128 only.
+        setup.put(0, 6);
+        setup.put(1, 16);
+        setup.put(0, 5);
+        setup.put(0, 2); // Multiplier 1.
+        setup.put(7, 4); // Range bits.
+        // Residue 1 over bins 0..128 in 32-bin partitions, one class
+        // decoded with the codebook in the first pass.
+        setup.put(0, 6);
+        setup.put(1, 16);
+        setup.put(0, 24);
+        setup.put(128, 24);
+        setup.put(31, 24);
+        setup.put(0, 6);
+        setup.put(0, 8); // Classbook.
+        setup.put(1, 3); // Cascade: pass 0.
+        setup.put(0, 1);
+        setup.put(0, 8); // Pass 0 book.
+        // Mapping 0: one submap, no coupling.
+        setup.put(0, 6);
+        setup.put(0, 16);
+        setup.put(0, 4);
+        setup.put(0, 8);
+        setup.put(0, 8); // Floor 0.
+        setup.put(0, 8); // Residue 0.
+        // Mode 0: short blocks.
+        setup.put(0, 6);
+        setup.put(0, 1);
+        setup.put(0, 32);
+        setup.put(0, 8);
+        setup.put(1, 1); // Framing bit.
+
+        let mut audio = VorbisBits::default();
+        audio.put(0, 1); // Audio packet.
+        audio.put(1, 1); // Floor in use.
+        audio.put(255, 8); // Floor at full scale at both posts.
+        audio.put(255, 8);
+        for partition in 0..4 {
+            audio.put(0, 1); // Classword.
+            for bin in 0..32 {
+                audio.put((partition == 0 && bin == 4) as u64, 1);
+            }
+        }
+        let audio_packets = vec![audio.bytes; audio_packets];
+
+        let frames = (audio_packets.len() as u64 - 1) * 128;
+        let mut out = ogg_page(&[ident], 0x02, 0, 0);
+        out.extend(ogg_page(&[comment, setup.bytes], 0, 0, 1));
+        out.extend(ogg_page(&audio_packets, 0x04, frames, 2));
+
+        let path = dir.join(name);
+        std::fs::write(&path, out).unwrap();
+        path
+    }
+
     /// Helper to create a kit/variant directory structure with WAV files.
     fn setup_kit_dir(root: &Path, kit_name: &str, variant_name: &str, sample_names: &[&str]) {
         let variant_dir = root.join(kit_name).join(variant_name);
@@ -464,7 +1421,7 @@ mod tests {
         let dir = tempfile::tempdir().unwrap();
         let path = create_test_wav(dir.path(), "mono16.wav", 1, 48000, 16, 1000);
 
-        let sample = load_wav(&path).unwrap();
+        let sample = load_wav(&path, LoadOptions::default()).unwrap();
         assert_eq!(sample.channels, 1);
         assert_eq!(sample.sample_rate, 48000);
         assert_eq!(sample.num_frames(), 1000);
@@ -477,7 +1434,7 @@ mod tests {
         let dir = tempfile::tempdir().unwrap();
         let path = create_test_wav(dir.path(), "stereo16.wav", 2, 48000, 16, 500);
 
-        let sample = load_wav(&path).unwrap();
+        let sample = load_wav(&path, LoadOptions::default()).unwrap();
         assert_eq!(sample.channels, 2);
         assert_eq!(sample.sample_rate, 48000);
         assert_eq!(sample.num_frames(), 500);
@@ -490,7 +1447,7 @@ mod tests {
         let dir = tempfile::tempdir().unwrap();
         let path = create_test_wav(dir.path(), "mono24.wav", 1, 48000, 24, 100);
 
-        let sample = load_wav(&path).unwrap();
+        let sample = load_wav(&path, LoadOptions::default()).unwrap();
         assert_eq!(sample.channels, 1);
         assert_eq!(sample.num_frames(), 100);
     }
@@ -501,7 +1458,7 @@ mod tests {
         let dir = tempfile::tempdir().unwrap();
         let path = create_test_wav_f32(dir.path(), "float32.wav", 1, 48000, 200);
 
-        let sample = load_wav(&path).unwrap();
+        let sample = load_wav(&path, LoadOptions::default()).unwrap();
         assert_eq!(sample.channels, 1);
         assert_eq!(sample.num_frames(), 200);
         // First sample should be 0.0, last should be close to 1.0.
@@ -509,19 +1466,206 @@ mod tests {
         assert!((sample.data[199] - 199.0 / 200.0).abs() < 0.01);
     }
 
+    #[test]
+    fn test_dither_stays_within_one_lsb_of_the_plain_decode() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = tempfile::tempdir().unwrap();
+        let path = create_test_wav(dir.path(), "ramp.wav", 1, 48000, 16, 1000);
+        let dithered = LoadOptions {
+            dither: true,
+            ..Default::default()
+        };
+
+        let plain = load_wav(&path, LoadOptions::default()).unwrap();
+        let noisy = load_wav(&path, dithered).unwrap();
+        assert_eq!(noisy.data.len(), plain.data.len());
+        let lsb = 1.0 / 32768.0;
+        let diffs: Vec<f32> = noisy
+            .data
+            .iter()
+            .zip(&plain.data)
+            .map(|(a, b)| a - b)
+            .collect();
+        assert!(diffs.iter().all(|d| d.abs() <= lsb * 1.001));
+        assert!(diffs.iter().filter(|d| d.abs() > lsb * 0.01).count() > 900);
+
+        // The noise averages out, so the ramp keeps its shape.
+        let mean = diffs.iter().sum::<f32>() / diffs.len() as f32;
+        assert!(mean.abs() < lsb * 0.1);
+
+        // The seed is fixed: loading again gives the same samples.
+        assert_eq!(load_wav(&path, dithered).unwrap().data, noisy.data);
+
+        // Float samples are left alone.
+        let path = create_test_wav_f32(dir.path(), "float32.wav", 1, 48000, 200);
+        let plain = load_wav(&path, LoadOptions::default()).unwrap();
+        assert_eq!(load_wav(&path, dithered).unwrap().data, plain.data);
+    }
+
     #[test]
     fn test_wrong_sample_rate() {
         let _ = env_logger::builder().is_test(true).try_init();
         let dir = tempfile::tempdir().unwrap();
         let path = create_test_wav(dir.path(), "wrong_rate.wav", 1, 44100, 16, 100);
 
-        let result = load_wav(&path);
+        let result = load_wav(&path, LoadOptions::default());
         assert!(result.is_err());
         let err = result.unwrap_err().to_string();
         assert!(err.contains("Sample rate mismatch"));
         assert!(err.contains("44100"));
     }
 
+    #[test]
+    fn test_clipped_runs_are_counted_per_channel() {
+        // A clean ramp touches full scale only at its ends.
+        let ramp: Vec<f32> = (0..=100).map(|i| i as f32 / 50.0 - 1.0).collect();
+        assert_eq!(clipped_sample_count(&ramp, 1, 4), 0);
+
+        // Stereo: the left channel sits at full scale (16-bit max and
+        // -1.0) for 5 frames; the right channel's short run is too brief.
+        let mut stereo = Vec::new();
+        for frame in 0..10 {
+            let left = match frame {
+                4 => -1.0,
+                2..7 => 32767.0 / 32768.0,
+                _ => 0.1,
+            };
+            let right = if (2..5).contains(&frame) { 1.0 } else { 0.2 };
+            stereo.extend([left, right]);
+        }
+        assert_eq!(clipped_sample_count(&stereo, 2, 4), 5);
+        assert_eq!(clipped_sample_count(&stereo, 2, 3), 8);
+    }
+
+    #[test]
+    fn test_trim_silence_cuts_leading_frames_and_keeps_transient() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("padded.wav");
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 48000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        // 1000 silent frames (with a little noise below the threshold),
+        // a transient on the right channel only, its decay, then silence.
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for frame in 0..1000 {
+            writer.write_sample((frame % 3) as i16).unwrap();
+            writer.write_sample(0i16).unwrap();
+        }
+        for value in [30000i16, 12000, 4000] {
+            writer.write_sample(value / 10).unwrap();
+            writer.write_sample(value).unwrap();
+        }
+        for _ in 0..500 {
+            writer.write_sample(0i16).unwrap();
+            writer.write_sample(0i16).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let trim = |trailing| LoadOptions {
+            trim_silence: Some(SilenceTrim {
+                threshold: 0.001,
+                trailing,
+            }),
+            ..Default::default()
+        };
+        let sample = load_wav(&path, trim(false)).unwrap();
+        assert_eq!(sample.num_frames(), 3 + 500);
+        assert_eq!(sample.data[1], 30000.0 / 32768.0);
+        assert_eq!(sample.data[0], 3000.0 / 32768.0);
+
+        let sample = load_wav(&path, trim(true)).unwrap();
+        assert_eq!(sample.num_frames(), 3);
+
+        let sample = load_wav(&path, LoadOptions::default()).unwrap();
+        assert_eq!(sample.num_frames(), 1503);
+    }
+
+    #[test]
+    fn test_normalize_scales_quiet_ramp_to_target_peak() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("quiet.wav");
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 48000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        // A ramp up to about -24 dBFS.
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for frame in 0..1000 {
+            writer.write_sample((frame * 2) as i16).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let target = 10f32.powf(-1.0 / 20.0);
+        let options = LoadOptions {
+            normalize_peak: Some(target),
+            ..Default::default()
+        };
+        let sample = load_wav(&path, options).unwrap();
+        let peak = sample.data.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        assert!((peak - target).abs() < 1e-5, "peak {}", peak);
+        // The shape is kept: still a ramp from silence.
+        assert_eq!(sample.data[0], 0.0);
+        assert!((sample.data[500] - target * 1000.0 / 1998.0).abs() < 1e-5);
+
+        // A silent placeholder is left alone.
+        let silent = create_test_wav(dir.path(), "silent.wav", 1, 48000, 16, 1);
+        let sample = load_wav(&silent, options).unwrap();
+        assert_eq!(sample.data, vec![0.0]);
+    }
+
+    #[test]
+    fn test_ignore_sample_rate_loads_only_flagged_samples_as_is() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = tempfile::tempdir().unwrap();
+        let variant_dir = dir.path().join("acoustic").join("dry");
+        std::fs::create_dir_all(&variant_dir).unwrap();
+        create_test_wav(&variant_dir, "kick.wav", 1, 44100, 16, 100);
+        create_test_wav(&variant_dir, "snare.wav", 1, 44100, 16, 100);
+
+        let sample_names = vec!["kick.wav".to_string(), "snare.wav".to_string()];
+        let settings = SampleSettings {
+            ignore_sample_rates: vec![true, false],
+            ..Default::default()
+        };
+        let library = discover_kits(dir.path(), &sample_names, &settings).unwrap();
+        let err = library.load_bank(0, 0).unwrap_err();
+        assert!(format!("{:#}", err).contains("snare.wav"));
+
+        // With the snare at the output rate, the flagged kick keeps its own
+        // rate and length: it is stored as-is, not resampled.
+        create_test_wav(&variant_dir, "snare.wav", 1, 48000, 16, 100);
+        let bank = library.load_bank(0, 0).unwrap();
+        assert_eq!(bank.samples[0].sample_rate, 44100);
+        assert_eq!(bank.samples[0].num_frames(), 100);
+    }
+
+    #[test]
+    fn test_resample_44100_wav() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = tempfile::tempdir().unwrap();
+        let path = create_test_wav(dir.path(), "cd_rate.wav", 2, 44100, 16, 44100);
+
+        let sample = load_wav(
+            &path,
+            LoadOptions {
+                resample: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(sample.sample_rate, 48000);
+        assert_eq!(sample.channels, 2);
+        assert!((sample.num_frames() as i64 - 48000).abs() <= 1);
+        assert!(sample.data.iter().all(|s| s.abs() <= 1.0));
+    }
+
     #[test]
     fn test_normalization_16bit() {
         let _ = env_logger::builder().is_test(true).try_init();
@@ -541,7 +1685,7 @@ mod tests {
         writer.write_sample(0i16).unwrap();
         writer.finalize().unwrap();
 
-        let sample = load_wav(&path).unwrap();
+        let sample = load_wav(&path, LoadOptions::default()).unwrap();
         // i16::MAX / 32768.0 should be very close to 1.0
         assert!((sample.data[0] - (i16::MAX as f32 / 32768.0)).abs() < 0.001);
         // i16::MIN / 32768.0 = -1.0
@@ -557,14 +1701,14 @@ mod tests {
         // 48000 frames at 48kHz = exactly 1.0 second.
         let path = create_test_wav(dir.path(), "onesec.wav", 1, 48000, 16, 48000);
 
-        let sample = load_wav(&path).unwrap();
+        let sample = load_wav(&path, LoadOptions::default()).unwrap();
         assert!((sample.duration_secs() - 1.0).abs() < 0.001);
     }
 
     #[test]
     fn test_nonexistent_file() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let result = load_wav(Path::new("/nonexistent/foo.wav"));
+        let result = load_wav(Path::new("/nonexistent/foo.wav"), LoadOptions::default());
         assert!(result.is_err());
     }
 
@@ -574,12 +1718,75 @@ mod tests {
         let dir = tempfile::tempdir().unwrap();
         let path = create_test_wav(dir.path(), "empty.wav", 1, 48000, 16, 0);
 
-        let sample = load_wav(&path).unwrap();
+        let sample = load_wav(&path, LoadOptions::default()).unwrap();
         assert_eq!(sample.num_frames(), 0);
         assert_eq!(sample.data.len(), 0);
         assert_eq!(sample.duration_secs(), 0.0);
     }
 
+    #[test]
+    fn test_load_flac_matches_wav() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = tempfile::tempdir().unwrap();
+        let wav = create_test_wav(dir.path(), "ramp.wav", 2, 48000, 16, 500);
+        let flac = create_test_flac(dir.path(), "ramp.flac", 2, 48000, 500);
+
+        let from_wav = load_sample(&wav, LoadOptions::default()).unwrap();
+        let from_flac = load_sample(&flac, LoadOptions::default()).unwrap();
+        assert_eq!(from_flac.channels, 2);
+        assert_eq!(from_flac.sample_rate, 48000);
+        assert_eq!(from_flac.num_frames(), from_wav.num_frames());
+        assert_eq!(from_flac.data, from_wav.data);
+
+        let wrong_rate = create_test_flac(dir.path(), "wrong_rate.flac", 1, 44100, 100);
+        let err = load_sample(&wrong_rate, LoadOptions::default())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Sample rate mismatch"));
+        assert!(err.contains("44100"));
+    }
+
+    #[test]
+    fn test_load_ogg() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = tempfile::tempdir().unwrap();
+        let path = create_test_ogg(dir.path(), "tone.ogg", 48000, 9);
+
+        let sample = load_sample(&path, LoadOptions::default()).unwrap();
+        assert_eq!(sample.channels, 1);
+        assert_eq!(sample.sample_rate, 48000);
+        assert_eq!(sample.num_frames(), 8 * 128);
+        assert!(sample.data.iter().all(|s| (-1.0..=1.0).contains(s)));
+        assert!(sample.data.iter().any(|&s| s != 0.0));
+
+        let wrong_rate = create_test_ogg(dir.path(), "wrong_rate.ogg", 44100, 3);
+        let err = load_sample(&wrong_rate, LoadOptions::default())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Sample rate mismatch"));
+        assert!(err.contains("44100"));
+    }
+
+    #[test]
+    fn test_find_sample_file_falls_back_to_other_extensions() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = tempfile::tempdir().unwrap();
+        create_test_flac(dir.path(), "kick.flac", 1, 48000, 100);
+        create_test_wav(dir.path(), "snare.wav", 1, 48000, 16, 100);
+        create_test_ogg(dir.path(), "snare.ogg", 48000, 3);
+
+        assert_eq!(
+            find_sample_file(dir.path(), "kick.wav"),
+            Some(dir.path().join("kick.flac"))
+        );
+        // An exact match wins over other extensions.
+        assert_eq!(
+            find_sample_file(dir.path(), "snare.wav"),
+            Some(dir.path().join("snare.wav"))
+        );
+        assert_eq!(find_sample_file(dir.path(), "tom.wav"), None);
+    }
+
     #[test]
     fn test_discover_kits() {
         let _ = env_logger::builder().is_test(true).try_init();
@@ -591,8 +1798,11 @@ mod tests {
         setup_kit_dir(root, "electric", "variant1", &["kick.wav", "snare.wav"]);
 
         let sample_names = vec!["kick.wav".to_string(), "snare.wav".to_string()];
-        let sample_gains = vec![1.0, 0.9];
-        let library = discover_kits(root, &sample_names, &sample_gains).unwrap();
+        let settings = SampleSettings {
+            gains: vec![1.0, 0.9],
+            ..Default::default()
+        };
+        let library = discover_kits(root, &sample_names, &settings).unwrap();
 
         assert_eq!(library.kits.len(), 2);
         assert_eq!(library.kits[0].name, "acoustic");
@@ -612,14 +1822,36 @@ mod tests {
         setup_kit_dir(root, "acoustic", "variant2", &["kick.wav"]);
 
         let sample_names = vec!["kick.wav".to_string(), "snare.wav".to_string()];
-        let sample_gains = vec![1.0, 0.9];
-        let library = discover_kits(root, &sample_names, &sample_gains).unwrap();
+        let settings = SampleSettings {
+            gains: vec![1.0, 0.9],
+            ..Default::default()
+        };
+        let library = discover_kits(root, &sample_names, &settings).unwrap();
 
         // Both variants should be accepted — variant2 has partial coverage.
         assert_eq!(library.kits.len(), 1);
         assert_eq!(library.kits[0].variants, vec!["variant1", "variant2"]);
     }
 
+    #[test]
+    fn test_missing_samples_are_those_in_no_variant() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+
+        setup_kit_dir(root, "acoustic", "variant1", &["kick.wav"]);
+        setup_kit_dir(root, "electronic", "variant1", &["snare.wav"]);
+
+        let sample_names = vec![
+            "kick.wav".to_string(),
+            "snare.wav".to_string(),
+            "ride.wav".to_string(),
+            "synth:click".to_string(),
+        ];
+        let library = discover_kits(root, &sample_names, &SampleSettings::default()).unwrap();
+        assert_eq!(library.missing_samples().unwrap(), vec!["ride.wav"]);
+    }
+
     #[test]
     fn test_discover_kits_skips_empty_variants() {
         let _ = env_logger::builder().is_test(true).try_init();
@@ -631,13 +1863,47 @@ mod tests {
         setup_kit_dir(root, "acoustic", "variant2", &[]);
 
         let sample_names = vec!["kick.wav".to_string(), "snare.wav".to_string()];
-        let sample_gains = vec![1.0, 0.9];
-        let library = discover_kits(root, &sample_names, &sample_gains).unwrap();
+        let settings = SampleSettings {
+            gains: vec![1.0, 0.9],
+            ..Default::default()
+        };
+        let library = discover_kits(root, &sample_names, &settings).unwrap();
 
         assert_eq!(library.kits.len(), 1);
         assert_eq!(library.kits[0].variants, vec!["variant1"]);
     }
 
+    #[test]
+    fn test_directory_binding_loads_round_robin_pool() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+
+        // "kick" is a directory of takes, snare.wav a single file.
+        setup_kit_dir(root, "acoustic", "dry", &["snare.wav"]);
+        let pool_dir = root.join("acoustic").join("dry").join("kick");
+        std::fs::create_dir_all(&pool_dir).unwrap();
+        create_test_wav(&pool_dir, "kick-3.wav", 1, 48000, 16, 300);
+        create_test_wav(&pool_dir, "kick-1.wav", 1, 48000, 16, 100);
+        create_test_wav(&pool_dir, "kick-2.wav", 1, 48000, 16, 200);
+        std::fs::write(pool_dir.join("notes.txt"), "not a sample").unwrap();
+
+        let sample_names = vec!["kick".to_string(), "snare.wav".to_string()];
+        let library = discover_kits(root, &sample_names, &SampleSettings::default()).unwrap();
+        assert!(library.missing_samples().unwrap().is_empty());
+
+        // Every WAV in the directory is in the pool, sorted by name.
+        let bank = library.load_bank(0, 0).unwrap();
+        let frames: Vec<usize> = bank.pools[0].iter().map(|s| s.num_frames()).collect();
+        assert_eq!(frames, vec![100, 200, 300]);
+        assert_eq!(bank.samples[0].num_frames(), 100);
+        assert!(bank.pools[1].is_empty());
+
+        // Hits rotate through the takes; the file sample always plays.
+        assert_eq!(bank.sample_for_turn(0, 4).num_frames(), 200);
+        assert_eq!(bank.sample_for_turn(1, 4).num_frames(), 100);
+    }
+
     #[test]
     fn test_load_bank_with_missing_sample() {
         let _ = env_logger::builder().is_test(true).try_init();
@@ -648,8 +1914,11 @@ mod tests {
         setup_kit_dir(root, "acoustic", "variant1", &["kick.wav"]);
 
         let sample_names = vec!["kick.wav".to_string(), "snare.wav".to_string()];
-        let sample_gains = vec![1.0, 0.8];
-        let library = discover_kits(root, &sample_names, &sample_gains).unwrap();
+        let settings = SampleSettings {
+            gains: vec![1.0, 0.8],
+            ..Default::default()
+        };
+        let library = discover_kits(root, &sample_names, &settings).unwrap();
 
         let bank = library.load_bank(0, 0).unwrap();
 
@@ -670,8 +1939,11 @@ mod tests {
         let dir = tempfile::tempdir().unwrap();
 
         let sample_names = vec!["kick.wav".to_string()];
-        let sample_gains = vec![1.0];
-        let result = discover_kits(dir.path(), &sample_names, &sample_gains);
+        let settings = SampleSettings {
+            gains: vec![1.0],
+            ..Default::default()
+        };
+        let result = discover_kits(dir.path(), &sample_names, &settings);
         assert!(result.is_err());
         assert!(result
             .unwrap_err()
@@ -679,6 +1951,32 @@ mod tests {
             .contains("No valid drum kits"));
     }
 
+    #[test]
+    fn test_synth_only_bindings_get_a_builtin_kit() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = tempfile::tempdir().unwrap();
+
+        let sample_names = vec![
+            "synth:sine:100hz:50ms".to_string(),
+            "synth:noise:10ms".to_string(),
+        ];
+        let settings = SampleSettings {
+            gains: vec![1.0, 1.0],
+            ..Default::default()
+        };
+        let library = discover_kits(dir.path(), &sample_names, &settings).unwrap();
+        assert_eq!(library.kit_count(), 1);
+
+        let bank = library.load_bank(0, 0).unwrap();
+        assert_eq!(bank.samples[0].num_frames(), 2400);
+        assert_eq!(bank.samples[1].num_frames(), 480);
+
+        // With a file binding too, kit folders are needed as usual.
+        let mut sample_names = sample_names;
+        sample_names.push("kick.wav".to_string());
+        assert!(discover_kits(dir.path(), &sample_names, &settings).is_err());
+    }
+
     #[test]
     fn test_load_bank() {
         let _ = env_logger::builder().is_test(true).try_init();
@@ -688,15 +1986,206 @@ mod tests {
         setup_kit_dir(root, "acoustic", "variant1", &["kick.wav", "snare.wav"]);
 
         let sample_names = vec!["kick.wav".to_string(), "snare.wav".to_string()];
-        let sample_gains = vec![1.0, 0.8];
-        let library = discover_kits(root, &sample_names, &sample_gains).unwrap();
+        let settings = SampleSettings {
+            gains: vec![1.0, 0.8],
+            ..Default::default()
+        };
+        let library = discover_kits(root, &sample_names, &settings).unwrap();
 
         let bank = library.load_bank(0, 0).unwrap();
         assert_eq!(bank.samples.len(), 2);
-        assert_eq!(bank.sample_gains.len(), 2);
+        assert_eq!(bank.settings.gains.len(), 2);
         assert_eq!(bank.kit_name, "acoustic");
         assert_eq!(bank.variant_name, "variant1");
-        assert!((bank.sample_gains[1] - 0.8).abs() < f32::EPSILON);
+        assert!((bank.settings.gains[1] - 0.8).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn test_preloaded_banks_switch_without_reading_files() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+
+        setup_kit_dir(root, "acoustic", "dry", &["kick.wav", "snare.wav"]);
+        setup_kit_dir(root, "acoustic", "room", &["kick.wav", "snare.wav"]);
+        setup_kit_dir(root, "electronic", "clean", &["kick.wav", "snare.wav"]);
+
+        let sample_names = vec!["kick.wav".to_string(), "snare.wav".to_string()];
+        let mut library = discover_kits(root, &sample_names, &SampleSettings::default()).unwrap();
+        library.preload();
+        assert_eq!(library.preloaded.len(), 3);
+        assert_eq!(library.preloaded[&(0, 0)].memory_bytes(), 2 * 100 * 4);
+
+        // With the files gone, every switch is served from memory.
+        std::fs::remove_dir_all(root.join("acoustic")).unwrap();
+        std::fs::remove_dir_all(root.join("electronic")).unwrap();
+        for (kit, variant) in [(0, 1), (1, 0), (0, 0)] {
+            let bank = library.bank(kit, variant).unwrap();
+            assert!(Arc::ptr_eq(&bank, &library.preloaded[&(kit, variant)]));
+        }
+        assert_eq!(library.bank(1, 0).unwrap().kit_name, "electronic");
+    }
+
+    #[test]
+    fn test_kit_manifest_maps_binding_names_to_variant_files() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = tempfile::tempdir().unwrap();
+        let kit_dir = dir.path().join("electronic");
+        for (variant, files) in [
+            ("dry", ["bd.wav", "sd.wav"]),
+            ("wet", ["bd.wav", "sd2.wav"]),
+        ] {
+            let variant_dir = kit_dir.join(variant);
+            std::fs::create_dir_all(&variant_dir).unwrap();
+            create_test_wav(&variant_dir, files[0], 1, 48000, 16, 100);
+            create_test_wav(&variant_dir, files[1], 1, 48000, 16, 200);
+        }
+
+        // The kit renames both samples; "wet" overrides the snare again.
+        std::fs::write(
+            kit_dir.join(KIT_MANIFEST_FILE),
+            "[samples]\n\"kick.wav\" = \"bd.wav\"\n\"snare.wav\" = \"sd.wav\"\n",
+        )
+        .unwrap();
+        std::fs::write(
+            kit_dir.join("wet").join(KIT_MANIFEST_FILE),
+            "[samples]\n\"snare.wav\" = \"sd2.wav\"\n",
+        )
+        .unwrap();
+
+        let sample_names = vec!["kick.wav".to_string(), "snare.wav".to_string()];
+        let library = discover_kits(dir.path(), &sample_names, &SampleSettings::default()).unwrap();
+        assert_eq!(library.kits[0].variants, vec!["dry", "wet"]);
+
+        for variant_index in 0..2 {
+            let bank = library.load_bank(0, variant_index).unwrap();
+            assert_eq!(bank.samples[0].num_frames(), 100);
+            assert_eq!(bank.samples[1].num_frames(), 200);
+        }
+
+        // A broken manifest is reported, not ignored.
+        std::fs::write(kit_dir.join(KIT_MANIFEST_FILE), "samples = 3").unwrap();
+        let err = library.load_bank(0, 0).unwrap_err();
+        assert!(format!("{:#}", err).contains("kit manifest"));
+    }
+
+    #[test]
+    fn test_kit_manifest_overrides_binding_gain_and_pan() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        setup_kit_dir(root, "electronic", "dry", &["kick.wav", "clap.wav"]);
+        setup_kit_dir(root, "electronic", "wet", &["kick.wav", "clap.wav"]);
+        setup_kit_dir(root, "acoustic", "dry", &["kick.wav", "clap.wav"]);
+
+        // The kit halves the clap; "wet" pans it and keeps the kit's gain.
+        let kit_dir = root.join("electronic");
+        std::fs::write(
+            kit_dir.join(KIT_MANIFEST_FILE),
+            "[overrides.\"clap.wav\"]\ngain = 0.4\n",
+        )
+        .unwrap();
+        std::fs::write(
+            kit_dir.join("wet").join(KIT_MANIFEST_FILE),
+            "[overrides.\"clap.wav\"]\npan = -0.5\n",
+        )
+        .unwrap();
+
+        let sample_names = vec!["kick.wav".to_string(), "clap.wav".to_string()];
+        let settings = SampleSettings {
+            gains: vec![1.0, 0.8],
+            pans: vec![0.0, 0.25],
+            ..Default::default()
+        };
+        let library = discover_kits(root, &sample_names, &settings).unwrap();
+
+        // Kits are sorted: acoustic has no manifest and keeps the bindings'.
+        let bank = library.load_bank(0, 0).unwrap();
+        assert_eq!(bank.settings.gains, vec![1.0, 0.8]);
+        assert_eq!(bank.settings.pans, vec![0.0, 0.25]);
+
+        let bank = library.load_bank(1, 0).unwrap();
+        assert_eq!(bank.settings.gains, vec![1.0, 0.4]);
+        assert_eq!(bank.settings.pans, vec![0.0, 0.25]);
+
+        let bank = library.load_bank(1, 1).unwrap();
+        assert_eq!(bank.settings.gains, vec![1.0, 0.4]);
+        assert_eq!(bank.settings.pans, vec![0.0, -0.5]);
+    }
+
+    #[test]
+    fn test_kit_manifest_gain_scales_every_sample() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        setup_kit_dir(root, "electronic", "dry", &["kick.wav", "clap.wav"]);
+        setup_kit_dir(root, "electronic", "wet", &["kick.wav", "clap.wav"]);
+
+        // The kit is turned down; "wet" sets its own gain instead.
+        let kit_dir = root.join("electronic");
+        std::fs::write(
+            kit_dir.join(KIT_MANIFEST_FILE),
+            "gain = 0.5\n[overrides.\"clap.wav\"]\ngain = 0.4\n",
+        )
+        .unwrap();
+        std::fs::write(kit_dir.join("wet").join(KIT_MANIFEST_FILE), "gain = 0.25\n").unwrap();
+
+        let sample_names = vec!["kick.wav".to_string(), "clap.wav".to_string()];
+        let library = discover_kits(root, &sample_names, &SampleSettings::default()).unwrap();
+
+        let bank = library.load_bank(0, 0).unwrap();
+        assert_eq!(bank.settings.gains, vec![0.5, 0.2]);
+
+        let bank = library.load_bank(0, 1).unwrap();
+        assert_eq!(bank.settings.gains, vec![0.25, 0.1]);
+    }
+
+    #[test]
+    fn test_kit_manifest_reverb_rides_with_the_bank() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        setup_kit_dir(root, "acoustic", "room", &["kick.wav"]);
+        setup_kit_dir(root, "acoustic", "hall", &["kick.wav"]);
+        setup_kit_dir(root, "electronic", "dry", &["kick.wav"]);
+
+        // The kit sets a room; "room" keeps it, "hall" replaces it.
+        let kit_dir = root.join("acoustic");
+        std::fs::write(
+            kit_dir.join(KIT_MANIFEST_FILE),
+            "[reverb]\nroom_size = 0.4\nwet = 0.2\n",
+        )
+        .unwrap();
+        std::fs::write(
+            kit_dir.join("hall").join(KIT_MANIFEST_FILE),
+            "[reverb]\nroom_size = 1.5\ndamping = 0.1\n",
+        )
+        .unwrap();
+
+        let sample_names = vec!["kick.wav".to_string()];
+        let library = discover_kits(root, &sample_names, &SampleSettings::default()).unwrap();
+
+        // Variants are sorted: hall, then room.
+        let hall = library.load_bank(0, 0).unwrap();
+        assert_eq!(
+            hall.reverb,
+            Some(ReverbConfig {
+                room_size: 1.0,
+                damping: 0.1,
+                wet: ReverbConfig::default().wet,
+            })
+        );
+        let room = library.load_bank(0, 1).unwrap();
+        assert_eq!(
+            room.reverb,
+            Some(ReverbConfig {
+                room_size: 0.4,
+                wet: 0.2,
+                ..ReverbConfig::default()
+            })
+        );
+        let electronic = library.load_bank(1, 0).unwrap();
+        assert_eq!(electronic.reverb, None);
     }
 
     #[test]
@@ -708,8 +2197,11 @@ mod tests {
         setup_kit_dir(root, "acoustic", "variant1", &["kick.wav"]);
 
         let sample_names = vec!["kick.wav".to_string()];
-        let sample_gains = vec![1.0];
-        let library = discover_kits(root, &sample_names, &sample_gains).unwrap();
+        let settings = SampleSettings {
+            gains: vec![1.0],
+            ..Default::default()
+        };
+        let library = discover_kits(root, &sample_names, &settings).unwrap();
 
         let path = library.variant_path(0, 0).unwrap();
         assert_eq!(path, root.join("acoustic").join("variant1"));
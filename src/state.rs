@@ -0,0 +1,116 @@
+use crate::samples::{KitLibrary, SampleBank};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// What `remember_state` keeps across restarts: the kit and variant that
+/// were playing and the master volume.
+///
+/// The kit and variant are kept by name, so adding or removing kits does
+/// not shift the selection to a different one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedState {
+    pub kit: String,
+    pub variant: String,
+    pub master_volume: f32,
+}
+
+impl SavedState {
+    /// The state to save while `bank` is playing at `master_volume`.
+    pub fn from_bank(bank: &SampleBank, master_volume: f32) -> Self {
+        Self {
+            kit: bank.kit_name.clone(),
+            variant: bank.variant_name.clone(),
+            master_volume,
+        }
+    }
+
+    /// Indices of the saved kit and variant in `library`, if both still
+    /// exist.
+    pub fn kit_indices(&self, library: &KitLibrary) -> Option<(usize, usize)> {
+        library.find_kit(&self.kit, Some(&self.variant))
+    }
+}
+
+/// Read the state saved at `path`. Returns None if nothing was saved yet.
+pub fn load_state(path: &Path) -> Result<Option<SavedState>> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(e)
+                .with_context(|| format!("Failed to read state file: {}", path.display()));
+        }
+    };
+    let state = toml::from_str(&text)
+        .with_context(|| format!("Failed to parse state file: {}", path.display()))?;
+    Ok(Some(state))
+}
+
+/// Write `state` to `path`, creating its directory if needed.
+pub fn save_state(path: &Path, state: &SavedState) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create state directory: {}", dir.display()))?;
+    }
+    let text = toml::to_string(state).context("Failed to serialize state")?;
+    std::fs::write(path, text)
+        .with_context(|| format!("Failed to write state file: {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::samples::{KitInfo, LoadOptions, SampleSettings};
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_saved_state_round_trip_restores_kit_indices() {
+        let library = KitLibrary {
+            samples_dir: PathBuf::from("/tmp"),
+            kits: vec![
+                KitInfo {
+                    name: "acoustic".to_string(),
+                    variants: vec!["dry".to_string()],
+                },
+                KitInfo {
+                    name: "electronic".to_string(),
+                    variants: vec!["clean".to_string(), "crushed".to_string()],
+                },
+            ],
+            sample_names: Vec::new(),
+            settings: SampleSettings::default(),
+            load_options: LoadOptions::default(),
+            loudness_normalize_velocity: false,
+            preload_all: false,
+            preloaded: HashMap::new(),
+            background_loading: false,
+        };
+        let bank = SampleBank {
+            samples: Vec::new(),
+            settings: SampleSettings::default(),
+            pools: Vec::new(),
+            reverb: None,
+            kit_name: "electronic".to_string(),
+            variant_name: "crushed".to_string(),
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state").join("state.toml");
+        assert_eq!(load_state(&path).unwrap(), None);
+
+        let saved = SavedState::from_bank(&bank, 0.6);
+        save_state(&path, &saved).unwrap();
+        let loaded = load_state(&path).unwrap().unwrap();
+        assert_eq!(loaded, saved);
+        assert_eq!(loaded.kit_indices(&library), Some((1, 1)));
+
+        // A kit that no longer exists restores nothing.
+        let gone = SavedState {
+            kit: "vintage".to_string(),
+            ..loaded
+        };
+        assert_eq!(gone.kit_indices(&library), None);
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// Lock-free counters for audio engine health: voice steals and how long
+/// each callback took compared with the time its buffer covers, plus the
+/// number of voices playing.
+///
+/// Like `LatencyStats`, the audio thread records with relaxed atomics and
+/// the main thread periodically takes a summary, resetting the window.
+#[derive(Debug)]
+pub struct EngineStats {
+    sample_rate: u32,
+    voice_steals: AtomicU64,
+    callbacks: AtomicU64,
+    late_callbacks: AtomicU64,
+    total_callback_us: AtomicU64,
+    max_callback_us: AtomicU64,
+    active_voices: AtomicUsize,
+}
+
+/// Engine statistics over one reporting window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EngineSummary {
+    /// Voices cut off to make room for new ones at max_voices.
+    pub voice_steals: u64,
+
+    /// Number of audio callbacks.
+    pub callbacks: u64,
+
+    /// Callbacks that took longer than the audio they produced, which
+    /// causes an underrun (xrun) on most devices.
+    pub late_callbacks: u64,
+
+    pub avg_callback: Duration,
+    pub max_callback: Duration,
+}
+
+impl EngineStats {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            voice_steals: AtomicU64::new(0),
+            callbacks: AtomicU64::new(0),
+            late_callbacks: AtomicU64::new(0),
+            total_callback_us: AtomicU64::new(0),
+            max_callback_us: AtomicU64::new(0),
+            active_voices: AtomicUsize::new(0),
+        }
+    }
+
+    /// Record `count` voices stolen. Real-time safe.
+    pub fn record_steals(&self, count: usize) {
+        self.voice_steals.fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    /// Record one callback that filled `frames` frames in `elapsed`.
+    /// Real-time safe.
+    pub fn record_callback(&self, frames: usize, elapsed: Duration) {
+        let us = elapsed.as_micros() as u64;
+        let budget_us = frames as u64 * 1_000_000 / self.sample_rate as u64;
+        self.callbacks.fetch_add(1, Ordering::Relaxed);
+        self.total_callback_us.fetch_add(us, Ordering::Relaxed);
+        self.max_callback_us.fetch_max(us, Ordering::Relaxed);
+        if us > budget_us {
+            self.late_callbacks.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Record how many voices are playing after a callback. Real-time safe.
+    pub fn set_active_voices(&self, count: usize) {
+        self.active_voices.store(count, Ordering::Relaxed);
+    }
+
+    /// Voices playing as of the last callback. Not reset by summaries.
+    pub fn active_voices(&self) -> usize {
+        self.active_voices.load(Ordering::Relaxed)
+    }
+
+    /// Summarize and reset the counters.
+    pub fn take_summary(&self) -> EngineSummary {
+        let callbacks = self.callbacks.swap(0, Ordering::Relaxed);
+        let total_us = self.total_callback_us.swap(0, Ordering::Relaxed);
+        EngineSummary {
+            voice_steals: self.voice_steals.swap(0, Ordering::Relaxed),
+            callbacks,
+            late_callbacks: self.late_callbacks.swap(0, Ordering::Relaxed),
+            avg_callback: Duration::from_micros(total_us.checked_div(callbacks).unwrap_or(0)),
+            max_callback: Duration::from_micros(self.max_callback_us.swap(0, Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Presses of each bound key over the session, counted by the input
+/// thread and summarized when the program exits.
+#[derive(Debug)]
+pub struct KeyHits {
+    /// The samples each key bound at startup plays, and its counter.
+    /// Created up front so counting never allocates; keys bound later by a
+    /// config reload are not counted.
+    counts: HashMap<u16, (String, AtomicU64)>,
+}
+
+impl KeyHits {
+    /// Counters for `keys`, given as key codes with the samples they play.
+    pub fn new(keys: impl IntoIterator<Item = (u16, String)>) -> Self {
+        Self {
+            counts: keys
+                .into_iter()
+                .map(|(key, samples)| (key, (samples, AtomicU64::new(0))))
+                .collect(),
+        }
+    }
+
+    /// Count a press of the key `code`.
+    pub fn record(&self, code: u16) {
+        if let Some((_, count)) = self.counts.get(&code) {
+            count.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Key code, samples and press count of every key pressed at least
+    /// once, most pressed first.
+    pub fn summary(&self) -> Vec<(u16, &str, u64)> {
+        let mut hits: Vec<(u16, &str, u64)> = self
+            .counts
+            .iter()
+            .map(|(&code, (samples, count))| {
+                (code, samples.as_str(), count.load(Ordering::Relaxed))
+            })
+            .filter(|&(_, _, count)| count > 0)
+            .collect();
+        hits.sort_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(&b.0)));
+        hits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_callback_over_its_buffer_time_is_late() {
+        let stats = EngineStats::new(48000);
+
+        // 480 frames at 48kHz is 10ms of audio.
+        stats.record_callback(480, Duration::from_millis(2));
+        stats.record_callback(480, Duration::from_millis(12));
+        stats.record_steals(3);
+
+        let summary = stats.take_summary();
+        assert_eq!(summary.callbacks, 2);
+        assert_eq!(summary.late_callbacks, 1);
+        assert_eq!(summary.voice_steals, 3);
+        assert_eq!(summary.avg_callback, Duration::from_millis(7));
+        assert_eq!(summary.max_callback, Duration::from_millis(12));
+
+        // Taking a summary resets the window.
+        assert_eq!(stats.take_summary().callbacks, 0);
+    }
+}
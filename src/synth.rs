@@ -0,0 +1,205 @@
+use crate::samples::SampleData;
+use anyhow::{Context, Result, bail};
+use std::f32::consts::TAU;
+
+/// Sample names starting with this are rendered instead of loaded from a
+/// file, e.g. "synth:sine:200hz:100ms".
+pub const SYNTH_PREFIX: &str = "synth:";
+
+/// Length of a synthesized sound whose spec gives none, in milliseconds.
+const DEFAULT_LENGTH_MS: f32 = 200.0;
+
+/// Length of a click whose spec gives none, in milliseconds.
+const DEFAULT_CLICK_MS: f32 = 2.0;
+
+/// Fade-in at the start of sine and noise sounds, in milliseconds, so they
+/// start without a click.
+const ATTACK_MS: f32 = 1.0;
+
+/// Peak amplitude of synthesized sounds, leaving headroom for stacking.
+const SYNTH_AMPLITUDE: f32 = 0.8;
+
+/// The shape of a synthesized sound.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Waveform {
+    /// A sine tone at the given frequency in Hz.
+    Sine(f32),
+
+    /// White noise, e.g. for a hi-hat or snare stand-in.
+    Noise,
+
+    /// A short decaying impulse, e.g. for a metronome-style tick.
+    Click,
+}
+
+/// A sound described inline in a sample name instead of a file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SynthSpec {
+    pub waveform: Waveform,
+
+    /// Length in milliseconds. Every sound decays linearly to silence over
+    /// its length.
+    pub length_ms: f32,
+}
+
+/// Whether a sample name is a synth spec rather than a filename.
+pub fn is_synth(name: &str) -> bool {
+    name.starts_with(SYNTH_PREFIX)
+}
+
+/// Parse a synth sample name: "synth:<waveform>" followed by optional
+/// ":<n>hz" (sine only, required there) and ":<n>ms" parts, e.g.
+/// "synth:sine:200hz:100ms", "synth:noise:50ms" or "synth:click".
+pub fn parse_synth_spec(name: &str) -> Result<SynthSpec> {
+    let spec = name
+        .strip_prefix(SYNTH_PREFIX)
+        .with_context(|| format!("'{}' is not a synth spec", name))?;
+    let mut parts = spec.split(':');
+    let waveform = parts.next().unwrap_or_default();
+
+    let mut freq_hz = None;
+    let mut length_ms = None;
+    for part in parts {
+        let lower = part.to_ascii_lowercase();
+        let (value, slot) = if let Some(value) = lower.strip_suffix("hz") {
+            (value, &mut freq_hz)
+        } else if let Some(value) = lower.strip_suffix("ms") {
+            (value, &mut length_ms)
+        } else {
+            bail!(
+                "Invalid part '{}' in synth spec '{}': expected e.g. 200hz or 100ms",
+                part,
+                name
+            );
+        };
+        let value: f32 = value
+            .parse()
+            .ok()
+            .filter(|v: &f32| v.is_finite() && *v > 0.0)
+            .with_context(|| {
+                format!(
+                    "Invalid value '{}' in synth spec '{}': expected a positive number",
+                    part, name
+                )
+            })?;
+        *slot = Some(value);
+    }
+
+    let waveform = match (waveform, freq_hz) {
+        ("sine", Some(freq)) => Waveform::Sine(freq),
+        ("sine", None) => bail!("Synth spec '{}' needs a frequency, e.g. :200hz", name),
+        ("noise", None) => Waveform::Noise,
+        ("click", None) => Waveform::Click,
+        ("noise" | "click", Some(_)) => {
+            bail!("Synth spec '{}': only sine takes a frequency", name)
+        }
+        (other, _) => bail!(
+            "Unknown waveform '{}' in synth spec '{}': expected sine, noise or click",
+            other,
+            name
+        ),
+    };
+    let default_ms = match waveform {
+        Waveform::Click => DEFAULT_CLICK_MS,
+        _ => DEFAULT_LENGTH_MS,
+    };
+
+    Ok(SynthSpec {
+        waveform,
+        length_ms: length_ms.unwrap_or(default_ms),
+    })
+}
+
+/// Render a synth spec as a mono sample at `sample_rate`.
+pub fn render(spec: &SynthSpec, sample_rate: u32) -> SampleData {
+    let rate = sample_rate as f32;
+    let frames = ((spec.length_ms / 1000.0 * rate).round() as usize).max(1);
+    let attack_frames = match spec.waveform {
+        Waveform::Click => 0,
+        _ => (ATTACK_MS / 1000.0 * rate) as usize,
+    };
+
+    // Xorshift noise with a fixed seed, so a kit sounds the same every run.
+    let mut noise_state: u32 = 0x9E37_79B9;
+    let mut noise = move || {
+        noise_state ^= noise_state << 13;
+        noise_state ^= noise_state >> 17;
+        noise_state ^= noise_state << 5;
+        noise_state as f32 / u32::MAX as f32 * 2.0 - 1.0
+    };
+
+    let data = (0..frames)
+        .map(|n| {
+            let value = match spec.waveform {
+                Waveform::Sine(freq) => (TAU * freq * n as f32 / rate).sin(),
+                Waveform::Noise => noise(),
+                Waveform::Click => 1.0,
+            };
+            let decay = 1.0 - n as f32 / frames as f32;
+            let attack = if n < attack_frames {
+                n as f32 / attack_frames as f32
+            } else {
+                1.0
+            };
+            value * decay * attack * SYNTH_AMPLITUDE
+        })
+        .collect();
+
+    SampleData {
+        data,
+        channels: 1,
+        sample_rate,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_synth_spec() {
+        assert_eq!(
+            parse_synth_spec("synth:sine:200hz:100ms").unwrap(),
+            SynthSpec {
+                waveform: Waveform::Sine(200.0),
+                length_ms: 100.0,
+            }
+        );
+        assert_eq!(
+            parse_synth_spec("synth:noise:50ms").unwrap().waveform,
+            Waveform::Noise
+        );
+        assert_eq!(
+            parse_synth_spec("synth:click").unwrap().length_ms,
+            DEFAULT_CLICK_MS
+        );
+
+        for invalid in [
+            "synth:sine:100ms",
+            "synth:noise:200hz",
+            "synth:saw:200hz",
+            "synth:sine:-5hz",
+            "synth:sine:200hz:long",
+        ] {
+            assert!(parse_synth_spec(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_render_sine_length_and_frequency() {
+        let spec = parse_synth_spec("synth:sine:200hz:100ms").unwrap();
+        let sample = render(&spec, 48000);
+        assert_eq!(sample.channels, 1);
+        assert_eq!(sample.num_frames(), 4800);
+
+        // 200Hz over 100ms is 20 cycles, so 40 sign changes (give or take
+        // the first and last one).
+        let crossings = sample
+            .data
+            .windows(2)
+            .filter(|pair| (pair[0] < 0.0) != (pair[1] < 0.0))
+            .count();
+        assert!((39..=41).contains(&crossings), "{} crossings", crossings);
+        assert!(sample.data.iter().all(|s| s.abs() <= SYNTH_AMPLITUDE));
+    }
+}
@@ -0,0 +1,270 @@
+use crate::samples::SampleBank;
+use crate::stats::EngineStats;
+use anyhow::{Context, Result};
+use arc_swap::ArcSwap;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::Stylize;
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// How long each frame waits for terminal input: about 30 redraws per
+/// second, plenty for a status display.
+const FRAME_INTERVAL: Duration = Duration::from_millis(33);
+
+/// Log lines kept for the log panel.
+const LOG_LINES: usize = 100;
+
+/// Log output for the TUI. While the TUI is showing, lines are kept for its
+/// log panel instead of being written over the screen; otherwise they go
+/// straight to stderr.
+#[derive(Clone, Default)]
+pub struct LogBuffer {
+    lines: Arc<Mutex<LogLines>>,
+    showing: Arc<AtomicBool>,
+}
+
+#[derive(Default)]
+struct LogLines {
+    complete: VecDeque<String>,
+
+    /// Text written since the last newline.
+    partial: String,
+}
+
+impl LogBuffer {
+    /// The most recent `count` complete lines, oldest first.
+    fn recent(&self, count: usize) -> Vec<String> {
+        match self.lines.lock() {
+            Ok(lines) => lines
+                .complete
+                .iter()
+                .skip(lines.complete.len().saturating_sub(count))
+                .cloned()
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+impl Write for LogBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.showing.load(Ordering::Relaxed) {
+            return io::stderr().write(buf);
+        }
+        if let Ok(mut lines) = self.lines.lock() {
+            let LogLines { complete, partial } = &mut *lines;
+            partial.push_str(&String::from_utf8_lossy(buf));
+            while let Some(end) = partial.find('\n') {
+                if complete.len() == LOG_LINES {
+                    complete.pop_front();
+                }
+                complete.push_back(partial[..end].to_string());
+                partial.drain(..=end);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+/// Everything one frame shows, gathered before drawing.
+struct TuiView<'a> {
+    kit_name: &'a str,
+    variant_name: &'a str,
+    active_voices: usize,
+    max_voices: usize,
+
+    /// Bound key names and the samples they play, in key order.
+    bindings: &'a [(String, String)],
+
+    log_lines: Vec<String>,
+}
+
+/// Full-screen status display on the main thread: the current kit and
+/// variant, the live voice count, the default bank's bindings and the
+/// latest log lines. The terminal is restored when it is dropped.
+pub struct Tui {
+    terminal: DefaultTerminal,
+    sample_bank: Arc<ArcSwap<SampleBank>>,
+    engine_stats: Arc<EngineStats>,
+    max_voices: usize,
+    bindings: Vec<(String, String)>,
+    logs: LogBuffer,
+}
+
+impl Tui {
+    /// Switch the terminal to the TUI and route logs into its log panel.
+    pub fn start(
+        sample_bank: Arc<ArcSwap<SampleBank>>,
+        engine_stats: Arc<EngineStats>,
+        max_voices: usize,
+        bindings: Vec<(String, String)>,
+        logs: LogBuffer,
+    ) -> Result<Self> {
+        let terminal = ratatui::try_init().context("Failed to start the terminal UI")?;
+        logs.showing.store(true, Ordering::Relaxed);
+        Ok(Self {
+            terminal,
+            sample_bank,
+            engine_stats,
+            max_voices,
+            bindings,
+            logs,
+        })
+    }
+
+    /// Draw one frame, then wait up to one frame interval for terminal
+    /// input. Returns false when the user pressed q or Ctrl+C to quit.
+    ///
+    /// Bound keys are grabbed from the keyboard, so they never reach the
+    /// terminal; only unbound keys show up here.
+    pub fn frame(&mut self) -> Result<bool> {
+        let bank = self.sample_bank.load();
+        let view = TuiView {
+            kit_name: &bank.kit_name,
+            variant_name: &bank.variant_name,
+            active_voices: self.engine_stats.active_voices(),
+            max_voices: self.max_voices,
+            bindings: &self.bindings,
+            log_lines: self.logs.recent(LOG_LINES),
+        };
+        self.terminal
+            .draw(|frame| render(frame, &view))
+            .context("Failed to draw the terminal UI")?;
+
+        if event::poll(FRAME_INTERVAL)?
+            && let Event::Key(key) = event::read()?
+            && key.kind == KeyEventKind::Press
+        {
+            let ctrl_c =
+                key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+            return Ok(!(ctrl_c || key.code == KeyCode::Char('q')));
+        }
+        Ok(true)
+    }
+}
+
+impl Drop for Tui {
+    fn drop(&mut self) {
+        ratatui::restore();
+        self.logs.showing.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Lay out the status line, bindings and log panels.
+fn render(frame: &mut Frame, view: &TuiView) {
+    let [status_area, bindings_area, log_area] = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Fill(1),
+        Constraint::Fill(1),
+    ])
+    .areas(frame.area());
+
+    let status = Line::from(vec![
+        "Kit ".into(),
+        view.kit_name.to_string().bold(),
+        "  Variant ".into(),
+        view.variant_name.to_string().bold(),
+        format!("  Voices {}/{}", view.active_voices, view.max_voices).into(),
+    ]);
+    frame.render_widget(
+        Paragraph::new(status).block(
+            Block::bordered()
+                .title(" keyboard-drums ")
+                .title_bottom(" q or Ctrl+C to quit "),
+        ),
+        status_area,
+    );
+
+    let bindings = view
+        .bindings
+        .iter()
+        .map(|(key, samples)| Line::from(format!("{:<16} {}", key, samples)));
+    frame.render_widget(
+        List::new(bindings).block(Block::bordered().title(" Bindings ")),
+        bindings_area,
+    );
+
+    // Show the newest lines that fit, inside the border.
+    let visible = log_area.height.saturating_sub(2) as usize;
+    let skip = view.log_lines.len().saturating_sub(visible);
+    let log_lines = view.log_lines[skip..]
+        .iter()
+        .map(|line| Line::from(line.as_str()).dim());
+    frame.render_widget(
+        List::new(log_lines).block(Block::bordered().title(" Log ")),
+        log_area,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::Terminal;
+    use ratatui::backend::TestBackend;
+
+    #[test]
+    fn test_render_shows_kit_voices_and_bindings() {
+        let bindings = vec![
+            ("KEY_A".to_string(), "kick.wav".to_string()),
+            ("KEY_S".to_string(), "snare.wav".to_string()),
+        ];
+        let view = TuiView {
+            kit_name: "acoustic",
+            variant_name: "dry",
+            active_voices: 3,
+            max_voices: 32,
+            bindings: &bindings,
+            log_lines: vec!["Loaded kit 'acoustic'".to_string()],
+        };
+
+        let mut terminal = Terminal::new(TestBackend::new(60, 16)).unwrap();
+        terminal.draw(|frame| render(frame, &view)).unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+
+        for expected in [
+            "Kit acoustic",
+            "Variant dry",
+            "Voices 3/32",
+            "KEY_S            snare.wav",
+            "Loaded kit 'acoustic'",
+        ] {
+            assert!(screen.contains(expected), "missing '{}'", expected);
+        }
+    }
+
+    #[test]
+    fn test_log_buffer_keeps_recent_lines_while_showing() {
+        let mut logs = LogBuffer::default();
+        logs.showing.store(true, Ordering::Relaxed);
+        for n in 0..LOG_LINES + 5 {
+            writeln!(logs, "line {}", n).unwrap();
+        }
+
+        let recent = logs.recent(2);
+        assert_eq!(
+            recent,
+            vec![
+                format!("line {}", LOG_LINES + 3),
+                format!("line {}", LOG_LINES + 4)
+            ]
+        );
+        assert_eq!(logs.recent(usize::MAX).len(), LOG_LINES);
+    }
+}
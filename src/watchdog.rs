@@ -0,0 +1,145 @@
+use std::os::fd::RawFd;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// `EVIOCGRAB` ioctl request: `_IOW('E', 0x90, int)`.
+const EVIOCGRAB: libc::Ioctl = 0x4004_4590;
+
+/// Longest the watchdog sleeps between heartbeat checks.
+const MAX_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Liveness signal from the input loop, checked by the watchdog thread.
+#[derive(Debug)]
+pub struct Heartbeat {
+    origin: Instant,
+
+    /// Milliseconds since `origin` at the most recent beat.
+    last_beat_ms: AtomicU64,
+
+    /// Set when the input loop exits on purpose, so the watchdog stands down.
+    stopped: AtomicBool,
+
+    /// Descriptors of the grabbed input devices. Unplugged keyboards that
+    /// have not come back yet are left out.
+    device_fds: Mutex<Vec<RawFd>>,
+}
+
+impl Heartbeat {
+    pub fn new() -> Self {
+        Self {
+            origin: Instant::now(),
+            last_beat_ms: AtomicU64::new(0),
+            stopped: AtomicBool::new(false),
+            device_fds: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Record the grabbed devices the watchdog releases if the loop stalls.
+    pub fn set_device_fds(&self, fds: Vec<RawFd>) {
+        if let Ok(mut device_fds) = self.device_fds.lock() {
+            *device_fds = fds;
+        }
+    }
+
+    fn device_fds(&self) -> Vec<RawFd> {
+        self.device_fds
+            .lock()
+            .map(|fds| fds.clone())
+            .unwrap_or_default()
+    }
+
+    /// Record that the input loop is alive.
+    pub fn beat(&self) {
+        let ms = self.origin.elapsed().as_millis() as u64;
+        self.last_beat_ms.store(ms, Ordering::Relaxed);
+    }
+
+    /// Mark the input loop as finished.
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the last beat is older than `timeout` at `now`.
+    /// A stopped heartbeat is never stale.
+    pub fn is_stale_at(&self, now: Instant, timeout: Duration) -> bool {
+        if self.stopped.load(Ordering::Relaxed) {
+            return false;
+        }
+        let last_beat =
+            self.origin + Duration::from_millis(self.last_beat_ms.load(Ordering::Relaxed));
+        now.saturating_duration_since(last_beat) > timeout
+    }
+}
+
+/// Watch the input loop's heartbeat and release the keyboard if it stalls.
+///
+/// If no beat arrives within `timeout`, the grab on the heartbeat's device
+/// is dropped so the keyboard reaches other applications again, and the process exits
+/// if `exit_on_hang` is set. Returns when `shutdown` is set or the heartbeat
+/// is stopped.
+pub fn run_watchdog(
+    heartbeat: &Heartbeat,
+    timeout: Duration,
+    exit_on_hang: bool,
+    shutdown: &AtomicBool,
+) {
+    let check_interval = (timeout / 4).min(MAX_CHECK_INTERVAL);
+    log::info!(
+        "Watchdog started: releasing the keyboard if input stalls for {:.1}s",
+        timeout.as_secs_f64(),
+    );
+
+    while !shutdown.load(Ordering::Relaxed) && !heartbeat.stopped.load(Ordering::Relaxed) {
+        std::thread::sleep(check_interval);
+
+        if !heartbeat.is_stale_at(Instant::now(), timeout) {
+            continue;
+        }
+
+        log::error!(
+            "Input loop has not responded for {:.1}s — releasing the keyboard grab",
+            timeout.as_secs_f64(),
+        );
+        for fd in heartbeat.device_fds() {
+            // SAFETY: EVIOCGRAB with a zero argument only releases the grab;
+            // a stale fd makes the call fail with EBADF, which we just log.
+            if unsafe { libc::ioctl(fd, EVIOCGRAB, 0) } < 0 {
+                log::warn!(
+                    "Failed to release keyboard grab: {}",
+                    std::io::Error::last_os_error()
+                );
+            }
+        }
+
+        if exit_on_hang {
+            log::error!("Exiting because watchdog_exit is set");
+            std::process::exit(1);
+        }
+        return;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fresh_heartbeat_is_not_stale() {
+        let heartbeat = Heartbeat::new();
+        heartbeat.beat();
+        assert!(!heartbeat.is_stale_at(Instant::now(), Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn test_old_heartbeat_is_stale() {
+        let heartbeat = Heartbeat::new();
+        heartbeat.beat();
+        let later = Instant::now() + Duration::from_secs(3);
+        assert!(heartbeat.is_stale_at(later, Duration::from_secs(2)));
+
+        // Once the input loop has stopped, the watchdog stands down.
+        heartbeat.stop();
+        assert!(!heartbeat.is_stale_at(later, Duration::from_secs(2)));
+    }
+}